@@ -1,4 +1,4 @@
-use cargo_metadata::{MetadataCommand};
+use cargo_metadata::{CargoOpt, MetadataCommand};
 use std::fs::File;
 use std::io::Write;
 use std::path::Path;
@@ -12,8 +12,15 @@ macro_rules! p {
 }
 
 fn main() {
+    // enabled features of this build, so that optional dependencies (e.g. libheif-rs) are listed as well
+    let features: Vec<String> = std::env::vars()
+        .filter_map(|(key, _)| key.strip_prefix("CARGO_FEATURE_").map(|f| f.to_lowercase().replace('_', "-")))
+        .filter(|f| f != "default")
+        .collect();
+
     // Run `cargo metadata` to gather project metadata
     let metadata = MetadataCommand::new()
+        .features(CargoOpt::SomeFeatures(features))
         .exec()
         .expect("Failed to execute cargo metadata");
 
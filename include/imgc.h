@@ -0,0 +1,43 @@
+/* C interface of imgc, available when built with the `ffi` feature. */
+#ifndef IMGC_H
+#define IMGC_H
+
+#include <stddef.h>
+#include <stdint.h>
+
+#define IMGC_FORMAT_WEBP 0
+#define IMGC_FORMAT_WEBP_IMAGE 1
+#define IMGC_FORMAT_AVIF 2
+#define IMGC_FORMAT_PNG 3
+#define IMGC_FORMAT_JPEG 4
+
+#define IMGC_OK 0
+#define IMGC_ERR_INVALID_ARGUMENT -1
+#define IMGC_ERR_DECODE -2
+#define IMGC_ERR_ENCODE -3
+#define IMGC_ERR_PANIC -4
+
+typedef struct ImgcOptions {
+    uint8_t lossless;
+    float quality;
+    uint8_t speed;
+    int32_t png_compression_type;
+    int32_t png_filter_type;
+    int32_t avif_bit_depth;
+    int32_t avif_color_model;
+    int32_t avif_alpha_color_mode;
+    float avif_alpha_quality;
+    uint8_t deterministic;
+    int32_t png_color_type;
+    uint8_t alpha_threshold;
+    uint8_t premultiply_alpha;
+} ImgcOptions;
+
+ImgcOptions imgc_default_options(void);
+int32_t imgc_convert_buffer(int32_t format, const ImgcOptions *options,
+                            const uint8_t *in_ptr, size_t in_len,
+                            uint8_t **out_ptr, size_t *out_len);
+void imgc_free_buffer(uint8_t *ptr, size_t len);
+const char *imgc_last_error(void);
+
+#endif /* IMGC_H */
@@ -0,0 +1,192 @@
+use crate::{converter::EncoderConfig, format::ImageFormat, profile, Error};
+use image::{
+    codecs::{gif::GifDecoder, png::PngDecoder, webp::WebPDecoder},
+    AnimationDecoder, DynamicImage,
+};
+use std::{
+    fs::{self, File},
+    io::BufReader,
+    path::{Path, PathBuf},
+};
+
+// frame step of `FrameMode::EveryNth` if none is set
+const DEFAULT_FRAME_STEP: usize = 2;
+// browsers display frames without a delay (common in gifs) for 100 ms
+const ZERO_DELAY_MS: u32 = 100;
+
+/// Which frames of animated inputs (gif, webp, apng) are converted
+#[cfg_attr(feature = "cli", derive(clap::ValueEnum))]
+#[derive(serde::Serialize, serde::Deserialize, Clone, Copy, PartialEq, Eq, Debug, Default)]
+#[serde(rename_all = "kebab-case")]
+pub enum FrameMode {
+    /// Only the first frame, as a still image.
+    #[default]
+    First,
+    /// All frames, as an animation (webp, png) or a numbered frame sequence (other formats).
+    All,
+    /// Every n-th frame (see --frame-step), each kept frame is shown for the duration of the dropped ones.
+    EveryNth,
+}
+
+/// What happens to animated inputs unless --frames selects their frames
+#[cfg_attr(feature = "cli", derive(clap::ValueEnum))]
+#[derive(serde::Serialize, serde::Deserialize, Clone, Copy, PartialEq, Eq, Debug, Default)]
+#[serde(rename_all = "kebab-case")]
+pub enum AnimatedInputs {
+    /// Convert the first frame as a still image, listing the inputs in the summary.
+    #[default]
+    First,
+    /// Convert all frames into an animation, as webp if the target format has no animations.
+    Animate,
+    /// Copy the inputs unchanged next to the other outputs.
+    Copy,
+}
+
+/// A frame of an animation, composited onto the full canvas
+pub struct Frame {
+    /// Pixels of the whole canvas.
+    pub image: DynamicImage,
+    /// Display duration in milliseconds.
+    pub delay_ms: u32,
+}
+
+/// Whether `img_format` is written as a single animated file (other formats get a numbered frame sequence)
+pub fn supports_animation(img_format: &ImageFormat) -> bool {
+    matches!(img_format, ImageFormat::Webp | ImageFormat::Png)
+}
+
+/// Path of the frame at `index` (0-based) of a frame sequence written instead of `path` (`clip.webp` => `clip.0001.webp`)
+pub fn frame_path(path: &Path, index: usize) -> PathBuf {
+    let ext = path.extension().map(|e| e.to_string_lossy().to_string()).unwrap_or_default();
+    path.with_extension(format!("{:04}.{}", index + 1, ext))
+}
+
+/// Decodes the frames of the animated input at `path` selected by the frame options of `options`.
+///
+/// Returns `None` for the `first` frame mode, still images (incl. single frame animations) and other formats,
+/// which are decoded as usual.
+pub fn read_frames(path: &Path, options: &EncoderConfig) -> Result<Option<Vec<Frame>>, Error> {
+    let mode = options.frames.unwrap_or_default();
+    if mode == FrameMode::First {
+        return Ok(None);
+    }
+    if options.fps.is_some_and(|fps| fps.is_nan() || fps <= 0.) {
+        return Err(Error::from_string("--fps has to be positive".to_string()));
+    }
+    let step = match mode {
+        FrameMode::EveryNth => options.frame_step.unwrap_or(DEFAULT_FRAME_STEP).max(1),
+        _ => 1,
+    };
+    let _span = profile::span("decode");
+    // frames after the last selected one are not decoded at all
+    let limit = options.max_frames.map_or(usize::MAX, |max| max.saturating_mul(step));
+    let Some(frames) = read_animation(path, limit)? else {
+        return Ok(None);
+    };
+
+    let mut selected: Vec<Frame> = Vec::with_capacity(frames.len().div_ceil(step));
+    for (i, frame) in frames.into_iter().enumerate() {
+        match selected.last_mut() {
+            Some(kept) if i % step != 0 => kept.delay_ms += frame.delay_ms,
+            _ => selected.push(frame),
+        }
+    }
+    if let Some(max_frames) = options.max_frames {
+        selected.truncate(max_frames);
+    }
+    if let Some(fps) = options.fps {
+        let delay_ms = (1000. / fps).round().max(1.) as u32;
+        selected.iter_mut().for_each(|frame| frame.delay_ms = delay_ms);
+    }
+    Ok(Some(selected))
+}
+
+/// Whether the gif, webp or png at `path` has several frames, read from its headers without decoding any pixels
+/// (apngs count as animated by their animation chunk, gifs by their image blocks)
+pub fn is_animated(path: &Path) -> bool {
+    let reader = || File::open(path).map(BufReader::new).ok();
+    match ImageFormat::from(path) {
+        ImageFormat::Gif => fs::read(path).is_ok_and(|data| gif_frame_count(&data, 2) > 1),
+        ImageFormat::Webp => reader().and_then(|reader| WebPDecoder::new(reader).ok()).is_some_and(|decoder| decoder.has_animation()),
+        ImageFormat::Png => reader().and_then(|reader| PngDecoder::new(reader).ok()).is_some_and(|decoder| decoder.is_apng().unwrap_or(false)),
+        _ => false,
+    }
+}
+
+// image descriptors of a gif, counting stops at `limit`
+fn gif_frame_count(data: &[u8], limit: usize) -> usize {
+    // skips a chain of data sub-blocks (size byte + data), ended by an empty one
+    fn skip_sub_blocks(data: &[u8], mut pos: usize) -> Option<usize> {
+        loop {
+            let size = *data.get(pos)? as usize;
+            pos += 1 + size;
+            if size == 0 {
+                return Some(pos);
+            }
+        }
+    }
+    let color_table_size = |flags: u8| if flags & 0x80 != 0 { 3 << ((flags & 0x07) + 1) } else { 0 };
+    if data.len() < 13 || !data.starts_with(b"GIF") {
+        return 0;
+    }
+    // header and logical screen descriptor, followed by the global color table
+    let mut pos = 13 + color_table_size(data[10]);
+    let mut count = 0;
+    while count < limit {
+        match data.get(pos) {
+            // extension: label and sub-blocks
+            Some(0x21) => match skip_sub_blocks(data, pos + 2) {
+                Some(next) => pos = next,
+                None => break,
+            },
+            // image descriptor, local color table, lzw code size and the image data sub-blocks
+            Some(0x2C) => {
+                count += 1;
+                let Some(&flags) = data.get(pos + 9) else { break };
+                match skip_sub_blocks(data, pos + 10 + color_table_size(flags) + 1) {
+                    Some(next) => pos = next,
+                    None => break,
+                }
+            }
+            _ => break,
+        }
+    }
+    count
+}
+
+/// Decodes up to `limit` frames of an animated gif, webp or png,
+/// `None` for still images (incl. single frame animations) and other formats
+pub fn read_animation(path: &Path, limit: usize) -> Result<Option<Vec<Frame>>, Error> {
+    let reader = || -> Result<BufReader<File>, Error> { Ok(BufReader::new(File::open(path)?)) };
+    let frames = match ImageFormat::from(path) {
+        ImageFormat::Gif => GifDecoder::new(reader()?)?.into_frames(),
+        ImageFormat::Webp => {
+            let decoder = WebPDecoder::new(reader()?)?;
+            if !decoder.has_animation() {
+                return Ok(None);
+            }
+            decoder.into_frames()
+        }
+        ImageFormat::Png => {
+            let decoder = PngDecoder::new(reader()?)?;
+            if !decoder.is_apng()? {
+                return Ok(None);
+            }
+            decoder.apng()?.into_frames()
+        }
+        _ => return Ok(None),
+    };
+
+    let frames = frames.take(limit)
+        .map(|frame| {
+            let frame = frame?;
+            let (numer, denom) = frame.delay().numer_denom_ms();
+            let delay_ms = match numer / denom.max(1) {
+                0 => ZERO_DELAY_MS,
+                delay_ms => delay_ms,
+            };
+            Ok(Frame { image: DynamicImage::ImageRgba8(frame.into_buffer()), delay_ms })
+        })
+        .collect::<Result<Vec<Frame>, Error>>()?;
+    Ok((frames.len() > 1).then_some(frames))
+}
@@ -0,0 +1,193 @@
+use crate::{
+    converter::{encode_image, EncoderConfig},
+    decode::read_image_from_memory,
+    format::ImageFormat,
+    Error,
+};
+use std::{
+    collections::HashMap,
+    future::Future,
+    path::{Component, Path, PathBuf},
+    pin::Pin,
+    sync::Arc,
+};
+use tokio::{
+    sync::Semaphore,
+    task::{Id, JoinError, JoinSet},
+};
+
+/// Boxed future returned by the async source/sink traits
+pub type BoxFuture<'a, T> = Pin<Box<dyn Future<Output = T> + Send + 'a>>;
+
+/// Provides the raw input bytes for a key (file path, url, object key, ...)
+pub trait AsyncSource: Send + Sync + 'static {
+    /// Fetches the encoded input image for `key`.
+    fn fetch<'a>(&'a self, key: &'a str) -> BoxFuture<'a, Result<Vec<u8>, Error>>;
+}
+
+/// Receives the encoded output bytes for a key
+pub trait AsyncSink: Send + Sync + 'static {
+    /// Stores the encoded output image for `key` (the input key, the sink decides the output name).
+    fn store<'a>(&'a self, key: &'a str, img_format: &'a ImageFormat, data: Vec<u8>) -> BoxFuture<'a, Result<(), Error>>;
+}
+
+/// Local filesystem source/sink using non-blocking tokio file I/O.
+///
+/// Outputs are written next to the input with the new file extension,
+/// or below `output` if set, mirroring the key path (without its root and `..` components).
+#[derive(Clone, Debug, Default)]
+pub struct LocalFs {
+    /// Output directory, empty for writing next to the input.
+    pub output: String,
+}
+
+impl AsyncSource for LocalFs {
+    fn fetch<'a>(&'a self, key: &'a str) -> BoxFuture<'a, Result<Vec<u8>, Error>> {
+        Box::pin(async move { Ok(tokio::fs::read(key).await?) })
+    }
+}
+
+impl AsyncSink for LocalFs {
+    fn store<'a>(&'a self, key: &'a str, img_format: &'a ImageFormat, data: Vec<u8>) -> BoxFuture<'a, Result<(), Error>> {
+        Box::pin(async move {
+            let input_path = PathBuf::from(key);
+            let output_path = if self.output.is_empty() {
+                input_path.with_extension(img_format.extension())
+            } else {
+                let output_path = Path::new(&self.output)
+                    .join(mirrored_path(&input_path))
+                    .with_extension(img_format.extension());
+                if let Some(directory) = output_path.parent() {
+                    tokio::fs::create_dir_all(directory).await?;
+                }
+                output_path
+            };
+            Ok(tokio::fs::write(output_path, data).await?)
+        })
+    }
+}
+
+// `path` without its root (and drive) and without parent components, which would leave the output directory
+fn mirrored_path(path: &Path) -> PathBuf {
+    path.components().filter(|component| matches!(component, Component::Normal(_))).collect()
+}
+
+/// Result of a single async conversion
+#[derive(Debug)]
+pub struct AsyncOutcome {
+    /// The key the input was fetched with.
+    pub key: String,
+    /// (input size, output size) in bytes, or the error that stopped the conversion.
+    pub result: Result<(usize, usize), Error>,
+}
+
+/// Limits for the async pipeline
+#[derive(Clone, Copy, Debug)]
+pub struct AsyncLimits {
+    /// Maximum number of inputs being fetched/stored at the same time.
+    pub io_concurrency: usize,
+    /// Maximum number of concurrent (blocking) encodes, defaults to the number of cpus.
+    pub encode_concurrency: usize,
+}
+
+impl Default for AsyncLimits {
+    fn default() -> Self {
+        let cpus = std::thread::available_parallelism().map_or(1, |n| n.get());
+        AsyncLimits {
+            io_concurrency: cpus * 4,
+            encode_concurrency: cpus,
+        }
+    }
+}
+
+/// Converts all `keys` fetched from `source` and hands the results to `sink`.
+///
+/// Downloads and uploads overlap with the CPU-bound encoders,
+/// which keep running synchronously on tokio's blocking thread pool.
+/// Outcomes are returned in completion order.
+pub async fn convert_async(
+    keys: Vec<String>,
+    source: Arc<dyn AsyncSource>,
+    sink: Arc<dyn AsyncSink>,
+    img_format: ImageFormat,
+    options: EncoderConfig,
+    limits: AsyncLimits,
+) -> Vec<AsyncOutcome> {
+    let io_permits = Arc::new(Semaphore::new(limits.io_concurrency.max(1)));
+    let encode_permits = Arc::new(Semaphore::new(limits.encode_concurrency.max(1)));
+    let img_format = Arc::new(img_format);
+    let options = Arc::new(options);
+    let mut tasks = JoinSet::new();
+    // keys of the running tasks, for the outcome of tasks that panicked
+    let mut running: HashMap<Id, String> = HashMap::new();
+    let mut outcomes = Vec::with_capacity(keys.len());
+
+    for key in keys {
+        // wait for a free io slot before spawning, so that huge key lists do not spawn everything at once
+        let io_permit = match io_permits.clone().acquire_owned().await {
+            Ok(permit) => permit,
+            Err(e) => {
+                outcomes.push(AsyncOutcome { key, result: Err(Error::from_string(format!("io slots closed: {}", e))) });
+                continue;
+            }
+        };
+        let (source, sink) = (source.clone(), sink.clone());
+        let (encode_permits, img_format, options) = (encode_permits.clone(), img_format.clone(), options.clone());
+        let task_key = key.clone();
+        let task = tasks.spawn(async move {
+            let result = convert_one(&task_key, &*source, &*sink, encode_permits, img_format, options).await;
+            drop(io_permit);
+            AsyncOutcome { key: task_key, result }
+        });
+        running.insert(task.id(), key);
+        // collect finished tasks early to keep memory bounded
+        while let Some(done) = tasks.try_join_next_with_id() {
+            outcomes.push(task_outcome(&mut running, done));
+        }
+    }
+    while let Some(done) = tasks.join_next_with_id().await {
+        outcomes.push(task_outcome(&mut running, done));
+    }
+    outcomes
+}
+
+// the outcome of a finished task, or an error outcome for the key of a task that panicked
+fn task_outcome(running: &mut HashMap<Id, String>, done: Result<(Id, AsyncOutcome), JoinError>) -> AsyncOutcome {
+    match done {
+        Ok((id, outcome)) => {
+            running.remove(&id);
+            outcome
+        }
+        Err(e) => AsyncOutcome {
+            key: running.remove(&e.id()).unwrap_or_default(),
+            result: Err(Error::from_string(format!("conversion task failed: {}", e))),
+        },
+    }
+}
+
+async fn convert_one(
+    key: &str,
+    source: &dyn AsyncSource,
+    sink: &dyn AsyncSink,
+    encode_permits: Arc<Semaphore>,
+    img_format: Arc<ImageFormat>,
+    options: Arc<EncoderConfig>,
+) -> Result<(usize, usize), Error> {
+    let input = source.fetch(key).await?;
+    let input_size = input.len();
+
+    let encode_permit = encode_permits.acquire_owned().await
+        .map_err(|e| Error::from_string(format!("encoder slots closed: {}", e)))?;
+    let encode_format = img_format.clone();
+    let encoded = tokio::task::spawn_blocking(move || {
+        let image = read_image_from_memory(&input)?;
+        encode_image(&image, &encode_format, &options)
+    })
+        .await
+        .map_err(|e| Error::from_string(format!("encoding task panicked: {}", e)))??;
+    drop(encode_permit);
+
+    let output_size = encoded.len();
+    sink.store(key, &img_format, encoded).await?;
+    Ok((input_size, output_size))
+}
@@ -1,4 +1,6 @@
-use clap::{ArgAction, Parser, Subcommand};
+use clap::{builder::RangedU64ValueParser, ArgAction, ArgGroup, CommandFactory, Parser, Subcommand};
+use clap_complete::Shell;
+use std::io;
 
 /// Image converter CLI
 #[derive(Parser, Debug)]
@@ -7,6 +9,8 @@ use clap::{ArgAction, Parser, Subcommand};
     about,
     long_about = None
 )]
+// inputs replaced by their outputs
+#[command(group(ArgGroup::new("replacing").multiple(true)))]
 pub struct CliArgs {
     /// The command to execute.
     #[command(subcommand)]
@@ -17,19 +21,64 @@ pub struct CliArgs {
     //#[clap(global = true)]
     // arguments can't be global and required
     // => early exit for no pattern matches
-    pub pattern: String,
+    // optional for commands without inputs (e.g. doctor), checked in main
+    pub pattern: Option<String>,
 
     /// Output directory (flat) of processed images.
     /// Defaults to the same location as the original images with the new file extension.
     /// If set, replaces the fixed base of the pattern directory structure of the input pattern. (before any * in the glob pattern)
-    #[clap(short, long, global = true, default_value = None)]
+    #[clap(short, long, visible_alias = "out", global = true, default_value = None)]
     pub output: Option<String>,
+
+    /// Also write every output into this directory (mapped like --output), e.g. a mounted backup share.
+    /// Repeat for several copies. Existing outputs are skipped or overwritten per directory.
+    #[clap(long, global = true, value_name = "DIR")]
+    pub mirror_output: Vec<String>,
+
+    /// Write all outputs directly into the output directory (e.g. a flat CDN upload folder) instead of mirroring the input tree.
+    /// Outputs of inputs with the same name get a hash of their input path appended (`hero.1a2b3c4d.webp`),
+    /// with `--naming content-hash` their content hash tells them apart.
+    #[clap(long, global = true, action = Some(ArgAction::SetTrue))]
+    pub flatten: Option<bool>,
+
+    /// Strip this directory from the input paths when mapping them into the output directory,
+    /// instead of the fixed base of the pattern (e.g. `--relative-to photos` keeps the `2024/` of `photos/2024/**/*.jpg`).
+    #[clap(long, global = true, value_name = "DIR", conflicts_with = "flatten")]
+    pub relative_to: Option<String>,
+
+    /// Normalize the output filenames to composed (`nfc`) or decomposed (`nfd`) unicode, or transliterate them to `ascii`,
+    /// e.g. `nfc` for trees copied from macOS onto Linux servers.
+    #[clap(long, global = true, value_enum, value_name = "FORM")]
+    pub normalize_filenames: Option<crate::filename::FilenameNormalization>,
     
     /// By default, imgc will process input files in lexicographical order after expanding the pattern.
     /// Setting this starts the process from the back.
     #[clap(long, global = true, action = Some(ArgAction::SetTrue))]
     pub reverse_processing_order: Option<bool>,
 
+    /// Order of the inputs: by `name`, or `random` for sampling runs whose first results should come from all directories.
+    /// The seed of a random order is printed, repeat the order with --seed.
+    #[clap(long, global = true, value_enum, default_value_t = crate::plan::ProcessingOrder::Name)]
+    pub order: crate::plan::ProcessingOrder,
+
+    /// Convert the inputs of one directory after another (also with --order random or --prefer),
+    /// for the read locality of spinning disks and network shares
+    #[clap(long, global = true, action = Some(ArgAction::SetTrue))]
+    pub group_by_directory: Option<bool>,
+
+    /// Seed of `--order random`, the same seed yields the same order of the same inputs.
+    #[clap(long, global = true, value_name = "N")]
+    pub seed: Option<u64>,
+
+    /// Convert inputs matching this glob first, e.g. `--prefer "uploads/2026-10-*/**"`.
+    /// `GLOB=WEIGHT` sets a weight (defaults to 1, higher weights first, negative ones last), repeat for several globs.
+    #[clap(long, global = true, value_name = "GLOB[=WEIGHT]")]
+    pub prefer: Vec<crate::priority::PathWeight>,
+
+    /// Read --prefer globs and weights from a file, one `GLOB = WEIGHT` per line (`#` starts a comment).
+    #[clap(long, global = true, value_name = "PATH")]
+    pub weights: Option<String>,
+
     /// Overwrite the existing output file if the current conversion resulted in a smaller file.
     #[clap(long, global = true, action = Some(ArgAction::SetTrue))]
     pub overwrite_if_smaller: Option<bool>,
@@ -38,9 +87,340 @@ pub struct CliArgs {
     #[clap(long, global = true, action = Some(ArgAction::SetTrue))]
     pub overwrite_existing: Option<bool>,
 
+    /// Sync each output and its directory to disk before counting it as converted (and recording it in the manifest),
+    /// so that a power loss cannot leave truncated outputs reported as converted
+    #[clap(long, global = true, action = Some(ArgAction::SetTrue))]
+    pub fsync: Option<bool>,
+
+    /// Copy the extended attributes of the inputs to their outputs (macOS Finder tags and color labels, `user.*` attributes on Linux)
+    #[clap(long, global = true, action = Some(ArgAction::SetTrue))]
+    pub preserve_xattrs: Option<bool>,
+
+    /// Give the outputs (and the directories created for them) the user and group of their inputs,
+    /// for conversions run as root over the directories of other users (unix only)
+    #[clap(long, global = true, action = Some(ArgAction::SetTrue))]
+    pub preserve_owner: Option<bool>,
+
+    /// Write the outputs into this local directory first and move them to their (slow or remote) destination
+    /// in the background, so the encoders do not wait for the destination
+    #[clap(long, global = true, value_name = "DIR", conflicts_with_all = ["dedupe_outputs", "on_converted", "snippets", "time_budget"])]
+    pub stage_dir: Option<String>,
+
+    /// Number of outputs moved from the --stage-dir to their destination at the same time
+    #[clap(long, global = true, value_name = "N", value_parser = RangedU64ValueParser::<usize>::new().range(1..), default_value_t = 4,
+           requires = "stage_dir")]
+    pub stage_transfers: usize,
+
+    /// Convert inputs again if they were modified after their existing outputs, skip them otherwise (for scheduled mirror jobs)
+    #[clap(long, global = true, action = Some(ArgAction::SetTrue), conflicts_with_all = ["overwrite_existing", "overwrite_if_smaller"])]
+    pub if_stale: Option<bool>,
+
     /// Discards the encoding result if it is larger than the input file (does not create an output file).
     #[clap(long, global = true, action = Some(ArgAction::SetTrue))]
     pub discard_if_larger_than_input: Option<bool>,
+
+    /// Only keep an output (and replace the input with --in-place) if it is at least this much smaller than the input,
+    /// e.g. `10%`. The inputs of smaller savings are left untouched and counted as not worth it.
+    #[clap(long, global = true, value_name = "PERCENT", value_parser = parse_percentage)]
+    pub min_savings: Option<f64>,
+
+    /// Encode still images in these formats (tried in order, e.g. `png` or `png,jpeg`) if the target format is larger
+    /// than the input, the first smaller one is written instead (with its extension, unless replacing the input).
+    #[clap(long, global = true, value_name = "FORMAT", value_enum, value_delimiter = ',')]
+    pub fallback_format: Vec<crate::format::OutputFormat>,
+
+    /// Pick the output format of still images by their content, e.g. `--route has-alpha=>png --route photo=>avif`.
+    /// Classes: has-alpha, opaque, photo (many colors) and graphic (few colors or flat areas), the first matching rule wins,
+    /// other images get the target format. Outputs have the extension of their format.
+    #[clap(long, global = true, value_name = "CLASS=>FORMAT")]
+    pub route: Vec<crate::route::RouteRule>,
+
+    /// Only convert images with transparent pixels (e.g. for a png to webp migration of transparent assets),
+    /// other inputs are skipped. Inputs without alpha channel are skipped before decoding.
+    #[clap(long, global = true, action = Some(ArgAction::SetTrue))]
+    pub only_with_alpha: Option<bool>,
+
+    /// Show a summary (files, input size, format, output directory, overwrite policy) and ask for confirmation before converting,
+    /// a safety net for destructive options like --in-place or --overwrite-existing.
+    #[clap(long, global = true, action = Some(ArgAction::SetTrue))]
+    pub confirm: Option<bool>,
+
+    /// Hide the per-worker progress lines showing the file currently being encoded and its elapsed time.
+    #[clap(long, global = true, action = Some(ArgAction::SetTrue))]
+    pub hide_worker_progress: Option<bool>,
+
+    /// One quality scale for all lossy encoders: `low`, `medium`, `high` or a level from 0 to 100 on the scale of jpeg quality,
+    /// mapped onto the quality of each encoder (and the avif speed). An explicit --quality or --speed takes precedence.
+    #[clap(long, global = true, value_name = "LEVEL")]
+    pub visual_quality: Option<crate::quality::VisualQuality>,
+
+    /// Override encoder settings for inputs of some formats, e.g. `--when png:quality=95 --when jpg,jpeg:quality=80`.
+    /// Keys: quality, visual-quality, speed, lossless and alpha-quality. Later overrides win.
+    #[clap(long, global = true, value_name = "FORMATS:SETTINGS")]
+    pub when: Vec<crate::overrides::InputOverride>,
+
+    /// Pin encoder thread counts and other nondeterministic options,
+    /// so that the same input and settings yield byte-identical output across runs and machines (slower avif encodes).
+    #[clap(long, global = true, action = Some(ArgAction::SetTrue))]
+    pub deterministic: Option<bool>,
+
+    /// Make pixels with an alpha below N fully transparent before encoding (webp and avif),
+    /// nearly invisible fringe pixels otherwise bloat the alpha plane.
+    #[clap(long, global = true, value_name = "N")]
+    pub alpha_threshold: Option<u8>,
+
+    /// Round-trip the colors of semi-transparent pixels through premultiplied alpha before encoding (webp and avif),
+    /// dropping color precision that is invisible after compositing.
+    #[clap(long, global = true, action = Some(ArgAction::SetTrue))]
+    pub premultiply_alpha: Option<bool>,
+
+    /// Which frames of animated gif, webp and png inputs are converted: the first one as a still image,
+    /// or all/every n-th as an animation (webp, png) or a numbered frame sequence (`clip.0001.jpg`, other formats).
+    #[clap(long, global = true, value_enum, value_name = "MODE")]
+    pub frames: Option<crate::animation::FrameMode>,
+
+    /// What happens to animated inputs (detected while planning) without --frames: convert their first frame,
+    /// animate them (as webp if the target format has no animations) or copy them unchanged. The summary lists them.
+    #[clap(long, global = true, value_enum, value_name = "MODE", default_value_t = crate::animation::AnimatedInputs::First)]
+    pub animated: crate::animation::AnimatedInputs,
+
+    /// Decode each still image output before writing it and fail the file if it is not displayed like its input:
+    /// same dimensions and orientation, and an SSIM of at least MIN_SSIM (0 - 1, 0.95 without a value) after converting
+    /// both to sRGB. Avif and heic outputs cannot be decoded and are not validated.
+    #[clap(long, global = true, value_name = "MIN_SSIM", num_args = 0..=1, default_missing_value = "0.95",
+           value_parser = parse_ssim)]
+    pub validate_visual: Option<f64>,
+
+    /// Keep every N-th frame with `--frames every-nth`. Defaults to 2.
+    #[clap(long, global = true, value_name = "N", value_parser = RangedU64ValueParser::<usize>::new().range(1..))]
+    pub frame_step: Option<usize>,
+
+    /// Convert at most N frames of animated inputs.
+    #[clap(long, global = true, value_name = "N", value_parser = RangedU64ValueParser::<usize>::new().range(1..))]
+    pub max_frames: Option<usize>,
+
+    /// Show the converted frames at this frame rate instead of with the frame durations of the input.
+    #[clap(long, global = true, value_name = "FPS", value_parser = parse_fps)]
+    pub fps: Option<f32>,
+
+    /// Write this EXIF `Artist` into the outputs (jpeg, png and webp).
+    #[clap(long, global = true, value_name = "ARTIST")]
+    pub set_artist: Option<String>,
+
+    /// Write this EXIF `Copyright` into the outputs (jpeg, png and webp).
+    #[clap(long, global = true, value_name = "COPYRIGHT")]
+    pub set_copyright: Option<String>,
+
+    /// Write this EXIF `UserComment` into the outputs (jpeg, png and webp).
+    #[clap(long, global = true, value_name = "COMMENT")]
+    pub set_comment: Option<String>,
+
+    /// Copy the EXIF metadata of jpeg, png and webp inputs into the outputs (jpeg, png and webp).
+    #[clap(long, global = true, action = Some(ArgAction::SetTrue))]
+    pub keep_metadata: Option<bool>,
+
+    /// Remove location (GPS) and serial number fields (incl. the vendor MakerNote) from the input metadata,
+    /// keeping everything else like orientation, dates and copyright (implies --keep-metadata).
+    #[clap(long, global = true, action = Some(ArgAction::SetTrue))]
+    pub strip_gps: Option<bool>,
+
+    /// Replace the input files with their converted outputs atomically,
+    /// keeping the paths (and names) referenced elsewhere regardless of the output format.
+    #[clap(long, global = true, action = Some(ArgAction::SetTrue), conflicts_with_all = ["output", "mirror_output", "naming", "dedupe_outputs"])]
+    #[clap(group = "replacing")]
+    pub in_place: Option<bool>,
+
+    /// Re-optimize inputs already in the target format (e.g. `png` for `*.png`),
+    /// replacing them in place if the result is smaller. Other inputs are converted as usual.
+    #[clap(long, global = true, action = Some(ArgAction::SetTrue), group = "replacing")]
+    pub optimize: Option<bool>,
+
+    /// Keep the inputs replaced by --in-place or --optimize with this suffix appended (e.g. `.orig`),
+    /// existing backups are never overwritten.
+    #[clap(long, global = true, value_name = "EXT", requires = "replacing")]
+    pub backup_ext: Option<String>,
+
+    /// Write a shell script reverting the run to this path: new outputs are removed,
+    /// inputs replaced with a --backup-ext and moved to the --quarantine-dir are restored.
+    #[clap(long, global = true, value_name = "PATH")]
+    pub undo_script: Option<String>,
+
+    /// Keep running and convert the files matching the pattern as they are created or changed (until Ctrl+C),
+    /// changed inputs replace their outputs.
+    #[cfg(feature = "watch")]
+    #[clap(long, global = true, action = Some(ArgAction::SetTrue),
+           conflicts_with_all = ["in_place", "optimize", "manifest", "hash_map", "checksums", "time_budget", "confirm", "summary_json", "undo_script"])]
+    pub watch: Option<bool>,
+
+    /// In --watch mode, wait until no file changed for this long before converting the new files as one batch, e.g. 2s.
+    #[cfg(feature = "watch")]
+    #[clap(long, global = true, value_name = "DURATION", value_parser = parse_duration, default_value = "2s", requires = "watch")]
+    pub debounce: std::time::Duration,
+
+    /// In --watch mode, also convert the files existing at the start (like a run without --watch),
+    /// new files are converted first so they are not stuck behind the backlog.
+    #[cfg(feature = "watch")]
+    #[clap(long, global = true, action = Some(ArgAction::SetTrue), requires = "watch")]
+    pub backlog: Option<bool>,
+
+    /// Run this shell command after each converted file, e.g. to upload it or purge a cache.
+    /// It gets IMGC_INPUT, IMGC_OUTPUT, IMGC_INPUT_SIZE and IMGC_OUTPUT_SIZE (bytes) in its environment,
+    /// frame sequences their first frame as output. A failing command fails the file.
+    #[clap(long, global = true, value_name = "COMMAND")]
+    pub on_converted: Option<String>,
+
+    /// Write a json manifest of all converted files to this path
+    /// (input/output path, sha256 of both, encoder, encoder version and settings).
+    #[clap(long, global = true, value_name = "PATH")]
+    pub manifest: Option<String>,
+
+    /// Compute a low quality placeholder of each image for lazy loading and record it in the --manifest.
+    #[clap(long, global = true, value_enum, value_name = "KIND")]
+    pub placeholder: Option<crate::placeholder::PlaceholderKind>,
+
+    /// Compute a perceptual hash of each image and record it in the --manifest (duplicate detection, reverse lookup).
+    #[clap(long, global = true, value_enum, value_name = "ALGORITHM")]
+    pub perceptual_hash: Option<crate::phash::PerceptualHashKind>,
+
+    /// Reject inputs with more pixels than this before decoding them, e.g. 100MP or 50000000 (decompression bombs).
+    #[clap(long, global = true, value_name = "PIXELS", value_parser = parse_pixels)]
+    pub max_pixels: Option<u64>,
+
+    /// Decoder of jpeg inputs: zune-jpeg directly (requires the `zune` feature), the `image` crate,
+    /// or auto (zune if compiled in). Jpegs zune rejects are still decoded by the `image` crate.
+    #[clap(long, global = true, value_enum, value_name = "DECODER", default_value_t = crate::decode::DecoderBackend::Auto)]
+    pub decoder: crate::decode::DecoderBackend,
+
+    /// Only decode inputs of these formats, e.g. jpeg,png,webp. Other inputs are sorted out while planning
+    /// and counted as unconvertible (`disabled-decoder`). Names as listed by `imgc capabilities`, or their extensions.
+    #[clap(long, global = true, value_name = "FORMATS", value_delimiter = ',', value_parser = parse_decoder)]
+    pub enable_decoder: Vec<String>,
+
+    /// Never decode inputs of these formats, e.g. gif,tiff for decoders with known vulnerabilities
+    /// or pathological behavior on a corpus. Like --enable-decoder, these inputs are sorted out while planning.
+    #[clap(long, global = true, value_name = "FORMATS", value_delimiter = ',', value_parser = parse_decoder)]
+    pub disable_decoder: Vec<String>,
+
+    /// Only convert the matched files of these formats, by their extension, e.g. jpg,png
+    /// (the same pattern can be reused by runs targeting different source types).
+    #[clap(long, global = true, value_name = "FORMATS", value_delimiter = ',', value_parser = parse_decoder)]
+    pub only_input_formats: Vec<String>,
+
+    /// Leave the matched files of these formats out, by their extension, e.g. bmp.
+    #[clap(long, global = true, value_name = "FORMATS", value_delimiter = ',', value_parser = parse_decoder)]
+    pub skip_input_formats: Vec<String>,
+
+    /// Abandon (and report) files whose conversion takes longer than this, e.g. 300s, 5m or 1h.
+    #[clap(long, global = true, value_name = "DURATION", value_parser = parse_duration)]
+    pub per_file_timeout: Option<std::time::Duration>,
+
+    /// Convert the salvageable part of truncated or corrupt jpegs (missing part filled gray) instead of failing.
+    #[clap(long, global = true, action = Some(ArgAction::SetTrue))]
+    pub tolerate_truncation: Option<bool>,
+
+    /// Copy (or move, see --quarantine-mode) inputs no decoder can read into this directory, each with a note of its error
+    #[clap(long, global = true, value_name = "DIR")]
+    pub quarantine_dir: Option<String>,
+
+    /// Whether --quarantine-dir receives copies of the undecodable inputs or the inputs themselves
+    #[clap(long, global = true, value_enum, default_value_t = crate::quarantine::QuarantineMode::Copy)]
+    pub quarantine_mode: crate::quarantine::QuarantineMode,
+
+    /// What happens when converting a file panics (a bug in a decoder or encoder): fail the file, stop the run
+    /// or fail it and put the input into the --quarantine-dir. Panics are logged to --panic-log in any case
+    #[clap(long, global = true, value_enum, default_value_t = crate::panics::PanicPolicy::Skip,
+           requires_if("quarantine", "quarantine_dir"))]
+    pub on_panic: crate::panics::PanicPolicy,
+
+    /// Append each panic with the offending input and backtrace to this file [default: imgc-panics.log]
+    #[clap(long, global = true, value_name = "FILE")]
+    pub panic_log: Option<String>,
+
+    /// Write the pixels handed to the encoder (after crop and alpha options) of still images into this directory,
+    /// to compare them with the decoded output when colors shift
+    #[clap(long, global = true, value_name = "DIR")]
+    pub debug_dump_rgba: Option<String>,
+
+    /// File format of the --debug-dump-rgba dumps
+    #[clap(long, global = true, value_enum, default_value_t = crate::dump::DumpFormat::Png, requires = "debug_dump_rgba")]
+    pub debug_dump_format: crate::dump::DumpFormat,
+
+    /// Dump only 1 in N inputs (picked by their path, the same files in every run)
+    #[clap(long, global = true, value_name = "N", value_parser = RangedU64ValueParser::<u32>::new().range(1..), default_value_t = 1,
+           requires = "debug_dump_rgba")]
+    pub debug_dump_sample: u32,
+
+    /// List the matched files that cannot be converted (zero-byte, unknown magic, unsupported format) in this file
+    #[clap(long, global = true, value_name = "FILE")]
+    pub list_unconvertible: Option<String>,
+
+    /// List each input that was not converted with the reason (output exists, existing smaller, unconvertible, ...) after the run
+    #[clap(long, global = true, action = Some(ArgAction::SetTrue))]
+    pub explain_skips: Option<bool>,
+
+    /// Add a table of the counts and sizes (input, output, saved, ratio) per input extension to the encode statistics
+    #[clap(long, global = true, action = Some(ArgAction::SetTrue))]
+    pub by_extension: Option<bool>,
+
+    /// Encode all files fast first, then re-encode the most compressible ones at full effort until this much time has passed, e.g. 2h
+    #[clap(long, global = true, value_name = "DURATION", value_parser = parse_duration,
+           conflicts_with_all = ["in_place", "optimize", "naming"])]
+    pub time_budget: Option<std::time::Duration>,
+
+    /// Record the decode, transform, encode and write spans of each file into this file, a chrome trace
+    /// (chrome://tracing, Perfetto) or folded stacks for flame graphs if it ends in .folded
+    #[clap(long, global = true, value_name = "FILE")]
+    pub profile: Option<String>,
+
+    /// Write a checksum file of all converted outputs into the output directory
+    /// (or the fixed base directory of the pattern if no output directory is set).
+    #[clap(long, global = true, value_enum, value_name = "ALGORITHM")]
+    pub checksums: Option<crate::manifest::ChecksumAlgorithm>,
+
+    /// How output filenames are derived from the input filenames.
+    #[clap(long, global = true, value_enum, default_value_t = crate::converter::OutputNaming::Plain)]
+    pub naming: crate::converter::OutputNaming,
+
+    /// Write a json map of input paths to their output paths (e.g. for bundlers with `--naming content-hash`).
+    #[clap(long, global = true, value_name = "PATH")]
+    pub hash_map: Option<String>,
+
+    /// Write a `<picture>` snippet next to each output (`hero.picture.html` or `.json`), listing the variants
+    /// of the input in all web formats found in the output directory, e.g. after runs into avif, webp and jpeg.
+    #[clap(long, global = true, value_enum, value_name = "FORMAT")]
+    pub snippets: Option<crate::snippet::SnippetFormat>,
+
+    /// Link byte-identical outputs of different inputs (duplicate sources) to a single stored copy.
+    #[clap(long, global = true, value_enum, value_name = "MODE")]
+    pub dedupe_outputs: Option<crate::converter::DedupeMode>,
+
+    /// When to use colors in the output. `auto` respects the `NO_COLOR` environment variable.
+    #[clap(long, global = true, value_enum, default_value_t = crate::output::ColorMode::Auto)]
+    pub color: crate::output::ColorMode,
+
+    /// Only use ASCII characters in messages and progress bars (no ➜ ✔ ✖ glyphs).
+    #[clap(long, global = true, action = Some(ArgAction::SetTrue))]
+    pub ascii: Option<bool>,
+
+    /// Layout of the overall progress bar, an indicatif template, e.g. `{wide_bar} {percent}% {eta} {msg}`
+    /// (position and length are the input bytes, e.g. `{binary_bytes}/{binary_total_bytes}`).
+    #[clap(long, global = true, value_name = "TEMPLATE", value_parser = parse_progress_template)]
+    pub progress_template: Option<String>,
+
+    /// Print this instead of the encode statistics after the run, with placeholders like `{converted}`, `{failed}`,
+    /// `{output_size}`, `{saved}` or `{ratio}` (all of them: time, inputs, converted, skipped, failed, aborted, discarded,
+    /// not_worth_it, rejected, unconvertible, refined, repaired, fallbacks, routed, animated, quarantined, deduplicated, encode_time, input_size, output_size, saved, ratio, reclaimed, potential_savings).
+    #[clap(long, global = true, value_name = "TEMPLATE", value_parser = parse_summary_template)]
+    pub summary_template: Option<String>,
+
+    /// Print exactly one json object with the totals of the run (counts, sizes, ratios, durations) on stdout
+    /// instead of the encode statistics, all other messages go to stderr.
+    #[clap(long, global = true, action = Some(ArgAction::SetTrue), conflicts_with_all = ["confirm", "summary_template"])]
+    pub summary_json: Option<bool>,
+
+    /// Language of the messages and the summary, detected from the system locale (`LANG`) if not set.
+    #[clap(long, global = true, value_enum, value_name = "LANG")]
+    pub lang: Option<crate::i18n::Language>,
 }
 
 /// Image converter actions
@@ -52,9 +432,16 @@ pub enum Command {
         #[clap(long, action = Some(ArgAction::SetTrue))]
         lossless: Option<bool>,
 
+        /// Lossless encoding after adjusting pixel values that are hard to compress (0 - 100, lower adjusts more, 100 is off).
+        /// Much smaller than lossless for screenshots and UI graphics without visible loss. Defaults to 60 without a level.
+        #[clap(long, value_name = "LEVEL", num_args = 0..=1, default_missing_value = "60",
+               value_parser = RangedU64ValueParser::<u8>::new().range(0..=100))]
+        near_lossless: Option<u8>,
+
         /// Control target quality (0 - 100, lower is worse but results in smaller files).
+        /// With lossless encoding, this controls the compression effort instead.
         /// Defaults to 90.0.
-        #[clap(short, long)]
+        #[clap(short, long, value_parser = parse_quality)]
         quality: Option<f32>,
     },
     
@@ -63,14 +450,14 @@ pub enum Command {
     
     /// Convert images to avif format (using ravif crate)
     Avif {
-        /// Control target quality (0 - 100, lower is worse but results in smaller files).
+        /// Control target quality (1 - 100, lower is worse but results in smaller files).
         /// Defaults to 90.0.
-        #[clap(short, long)]
+        #[clap(short, long, value_parser = parse_avif_quality)]
         quality: Option<f32>,
 
         /// Control encoding speed (1 - 10, lower is much slower but has a better quality and lower filesize).
         /// Defaults to 3.
-        #[clap(short, long)]
+        #[clap(short, long, value_parser = RangedU64ValueParser::<u8>::new().range(1..=10))]
         speed: Option<u8>,
         
         /// Choose internal bit depth. (in the generated avif file, nothing to do with the input file)
@@ -86,9 +473,9 @@ pub enum Command {
         #[clap(long, value_enum)]
         alpha_color_mode: Option<crate::converter::avif::AlphaColorMode>,
         
-        /// Control target alpha quality (0 - 100, lower is worse).
+        /// Control target alpha quality (1 - 100, lower is worse).
         /// Defaults to 90.0.
-        #[clap(short, long)]
+        #[clap(short, long, value_parser = parse_avif_quality)]
         alpha_quality: Option<f32>,
     },
     
@@ -105,11 +492,368 @@ pub enum Command {
         /// See: https://docs.rs/image/latest/image/codecs/png/enum.CompressionType.html
         #[clap(long, value_enum)]
         filter_type: Option<crate::converter::png::FilterType>,
+
+        /// Choose the png color type, `auto` picks the smallest lossless one (grayscale or palette if the pixels allow it).
+        /// Defaults to rgb/rgba depending on the alpha channel of the input.
+        #[clap(long, value_enum)]
+        color_type: Option<crate::converter::png::ColorType>,
+
+        /// Drop the text (tEXt, zTXt, iTXt) and time (tIME) chunks of png inputs,
+        /// which are otherwise carried over with --keep-metadata.
+        #[clap(long, action = Some(ArgAction::SetTrue))]
+        strip_chunks: Option<bool>,
+    },
+
+    /// Convert images to pnm format (pbm, pgm, ppm or pam using image crate), e.g. for netpbm pipelines
+    Pnm {
+        /// Choose the pnm subtype. Defaults to `auto` (pgm, ppm or pam depending on the input channels).
+        #[clap(long, value_enum)]
+        subtype: Option<crate::converter::pnm::PnmSubtype>,
+
+        /// Write the plain (ascii) variant of pbm, pgm and ppm instead of the binary one.
+        #[clap(long, action = Some(ArgAction::SetTrue))]
+        plain: Option<bool>,
+    },
+
+    /// Convert images to farbfeld format (16 bit rgba using image crate)
+    Farbfeld {}, // uncompressed, no configuration parameters
+
+    /// Convert images to heic format (using libheif, hevc compressed for Apple devices)
+    #[cfg(feature = "heif")]
+    Heif {
+        /// Use lossless encoding mode. Defaults to false.
+        #[clap(long, action = Some(ArgAction::SetTrue))]
+        lossless: Option<bool>,
+
+        /// Control target quality (0 - 100, lower is worse but results in smaller files).
+        /// Defaults to 90.0.
+        #[clap(short, long, value_parser = parse_quality)]
+        quality: Option<f32>,
     },
 
     /// Convert images to optimized jpeg format (using mozjpeg crate)
-    Jpeg {},
+    Jpeg {
+        /// Control target quality (0 - 100, lower is worse but results in smaller files).
+        /// Defaults to 75.
+        #[clap(short, long, value_parser = parse_quality)]
+        quality: Option<f32>,
+
+        /// Rotate jpeg inputs upright according to their EXIF orientation with a lossless transform
+        /// (like `jpegtran -trim`, partial edge blocks are cut off) instead of re-encoding the pixels.
+        #[clap(long, action = Some(ArgAction::SetTrue))]
+        bake_orientation: Option<bool>,
+
+        /// Insert restart markers every N MCUs (8x8 to 16x16 pixel blocks), so that transmission errors
+        /// only damage a stripe of the image instead of everything after them. Defaults to 0 (none).
+        #[clap(long, value_name = "N")]
+        restart_interval: Option<u16>,
+
+        /// Use the standard progressive scan script instead of searching for the smallest one (faster, larger files).
+        #[clap(long = "no-optimize-scans", action = Some(ArgAction::SetFalse))]
+        optimize_scans: Option<bool>,
+
+        /// Disable trellis quantization (faster, larger files).
+        #[clap(long = "no-trellis", action = Some(ArgAction::SetFalse))]
+        trellis: Option<bool>,
+    },
+
+    /// Extract the frames of animated gif, webp and png inputs as numbered stills (`clip.0001.png`, ...),
+    /// still images are skipped. --frames, --frame-step, --max-frames apply.
+    Frames {
+        /// Glob pattern to match the animated inputs.
+        /// Example: `anims/**/*.gif`
+        input: String,
+
+        /// Format of the extracted frames.
+        #[clap(long, value_enum, default_value_t = crate::format::OutputFormat::Png)]
+        format: crate::format::OutputFormat,
+    },
+
+    /// Grab poster frames of videos (any file matched by the input pattern) with ffmpeg and convert them,
+    /// numbered (`clip.0001.webp`, ...) for several timestamps
+    #[cfg(feature = "ffmpeg")]
+    Poster {
+        /// Glob pattern to match the videos.
+        /// Example: `media/**/*.mp4`
+        input: String,
+
+        /// Timestamp of the frame to grab (ffmpeg syntax, e.g. `00:00:03` or `3.5` seconds), repeat for several frames.
+        #[clap(long, value_name = "TIME", default_value = "0")]
+        at: Vec<String>,
+
+        /// Format of the posters.
+        #[clap(long, value_enum, default_value_t = crate::format::OutputFormat::Webp)]
+        format: crate::format::OutputFormat,
+    },
+
+    /// Pack the images matched by a pattern into sprite sheets (`sprite.0001.png`, ...) with a json atlas of their
+    /// coordinates (`sprite.json`), written into the --output directory or the fixed base of the pattern
+    Sprite {
+        /// Glob pattern to match the images.
+        /// Example: `icons/**/*.png`
+        input: String,
+
+        /// Maximum size of a sheet, more sheets are written if the images do not fit into one.
+        #[clap(long, value_name = "WIDTHxHEIGHT", value_parser = parse_size, default_value = "2048x2048")]
+        max_size: (u32, u32),
+
+        /// Empty pixels between the sprites, against bleeding of neighbours when the sheet is scaled.
+        #[clap(long, value_name = "PIXELS", default_value_t = 0)]
+        padding: u32,
+
+        /// Cut fully transparent borders off the images, the atlas records where each sprite was cut out of its input
+        /// (offset and original size), so engines can reconstruct the original bounds.
+        #[clap(long, action = Some(ArgAction::SetTrue))]
+        trim: Option<bool>,
+
+        /// Repeat the edge pixels of each sprite N times around it, against seams when sampling with filtering.
+        #[clap(long, value_name = "N", default_value_t = 0)]
+        extrude: u32,
+
+        /// Name of the atlas and the sheets.
+        #[clap(long, default_value = "sprite")]
+        name: String,
+
+        /// Format of the sheets.
+        #[clap(long, value_enum, default_value_t = crate::format::OutputFormat::Png)]
+        format: crate::format::OutputFormat,
+    },
+
+    /// Convert the image on the clipboard (e.g. a screenshot) into the file given with --output,
+    /// `clipboard.<ext>` by default (no pattern needed)
+    #[cfg(feature = "clipboard")]
+    Clipboard {
+        /// Format of the output.
+        #[clap(long, value_enum, default_value_t = crate::format::OutputFormat::Webp)]
+        format: crate::format::OutputFormat,
+
+        /// Put the converted image back on the clipboard, e.g. to paste a screenshot at the chosen quality.
+        #[clap(long, action = Some(ArgAction::SetTrue))]
+        copy_back: Option<bool>,
+    },
+
+    /// Open a window converting the images and directories dropped onto it, with a choice of format, quality
+    /// and output directory (no pattern needed, the other global options apply)
+    #[cfg(feature = "gui")]
+    Gui {},
+
+    /// Plan a conversion without converting anything, printing the inputs, outputs, settings and colliding outputs as json
+    /// (or saving them for `imgc apply`), e.g. `imgc "*.png" plan --save plan.json webp --quality 80`
+    Plan {
+        /// Write the plan to this path instead of printing it.
+        #[clap(long, value_name = "PATH")]
+        save: Option<String>,
+
+        /// The conversion to plan.
+        #[command(subcommand)]
+        conversion: Box<Command>,
+    },
+
+    /// Convert the files of a plan saved with `imgc plan --save`, later or on another machine (no pattern needed)
+    Apply {
+        /// Path of the plan json
+        plan: String,
+    },
+
+    /// Remove files matching the glob pattern (and further patterns), optionally only large, old or certain files
+    Clean {
+        /// Further glob patterns of files to remove
+        #[clap(value_name = "PATTERN")]
+        patterns: Vec<String>,
+
+        /// Only remove files larger than this, e.g. 500K, 2M or 1G (binary units)
+        #[clap(long, value_name = "SIZE", value_parser = parse_bytes)]
+        larger_than: Option<u64>,
+
+        /// Only remove files last modified longer ago than this, e.g. 12h or 30d
+        #[clap(long, value_name = "AGE", value_parser = parse_duration)]
+        older_than: Option<std::time::Duration>,
+
+        /// Only remove files with these extensions, e.g. webp,avif
+        #[clap(long, value_name = "EXT", value_delimiter = ',')]
+        format: Vec<String>,
+    },
+
+    /// Self-test all compiled encoders/decoders and print dependency versions (no pattern needed)
+    Doctor {},
+
+    /// Run every compiled encoder over a corpus of own (problematic) files in a temporary directory,
+    /// checking that the outputs decode with matching dimensions, and print a pass/fail matrix (no pattern needed)
+    SelftestCorpus {
+        /// Glob pattern to match the corpus.
+        /// Example: `trouble/**/*`
+        corpus: String,
+    },
+
+    /// Print the compiled encoders, decoders, features and dependency versions (no pattern needed)
+    Capabilities {
+        /// Print json for tooling, stable across versions (fields are only added, see `schema_version`).
+        #[clap(long, action = Some(ArgAction::SetTrue))]
+        json: Option<bool>,
+    },
+
+    /// Re-hash the outputs listed in a manifest written with --manifest, detecting changed or missing files (no pattern needed)
+    VerifyManifest {
+        /// Path of the manifest json
+        manifest: String,
+    },
+
+    /// Add "Convert to WebP/AVIF" entries for images to the context menu of the file manager
+    /// (Windows Explorer, KDE Dolphin, GNOME Files, macOS Finder quick actions) of the current user (no pattern needed)
+    InstallShellIntegration {},
+
+    /// Remove the context menu entries added by install-shell-integration (no pattern needed)
+    UninstallShellIntegration {},
+
+    /// Print a shell completion script to stdout (no pattern needed)
+    Completions {
+        /// Shell to generate the completions for
+        #[clap(value_enum)]
+        shell: Shell,
+    },
+
+    /// Print the man page (roff) to stdout (no pattern needed)
+    Manpage {},
+}
+
+impl Command {
+    /// Whether the command operates on the files matched by the global glob pattern
+    pub fn needs_pattern(&self) -> bool {
+        match self {
+            Command::Frames { .. } | Command::Sprite { .. } | Command::Apply { .. } | Command::Doctor {}
+            | Command::SelftestCorpus { .. } | Command::Capabilities { .. } | Command::VerifyManifest { .. }
+            | Command::InstallShellIntegration {} | Command::UninstallShellIntegration {}
+            | Command::Completions { .. } | Command::Manpage {} => false,
+            Command::Plan { conversion, .. } => conversion.needs_pattern(),
+            #[cfg(feature = "ffmpeg")]
+            Command::Poster { .. } => false,
+            #[cfg(feature = "clipboard")]
+            Command::Clipboard { .. } => false,
+            #[cfg(feature = "gui")]
+            Command::Gui {} => false,
+            _ => true,
+        }
+    }
+}
+
+// quality settings are percentages, the encoders fail with cryptic errors (or panic) outside of them
+fn parse_quality(s: &str) -> Result<f32, String> {
+    match s.parse::<f32>() {
+        Ok(quality) if (0. ..=100.).contains(&quality) => Ok(quality),
+        Ok(_) => Err("out of range, use a quality from 0 to 100 (lower is worse but results in smaller files)".to_string()),
+        Err(_) => Err("not a number, use a quality from 0 to 100".to_string()),
+    }
+}
+
+// ravif has no quality 0, its lowest is 1
+fn parse_avif_quality(s: &str) -> Result<f32, String> {
+    match s.parse::<f32>() {
+        Ok(quality) if (1. ..=100.).contains(&quality) => Ok(quality),
+        Ok(_) => Err("out of range, use an avif quality from 1 to 100 (lower is worse but results in smaller files)".to_string()),
+        Err(_) => Err("not a number, use an avif quality from 1 to 100".to_string()),
+    }
+}
+
+fn parse_percentage(s: &str) -> Result<f64, String> {
+    match s.trim().trim_end_matches('%').trim().parse::<f64>() {
+        Ok(percentage) if (0. ..=100.).contains(&percentage) => Ok(percentage),
+        _ => Err("use a percentage from 0 to 100, e.g. 10%".to_string()),
+    }
+}
+
+fn parse_ssim(s: &str) -> Result<f64, String> {
+    match s.trim().parse::<f64>() {
+        Ok(ssim) if (0. ..=1.).contains(&ssim) => Ok(ssim),
+        _ => Err("use an SSIM from 0 to 1, e.g. 0.95".to_string()),
+    }
+}
+
+fn parse_fps(s: &str) -> Result<f32, String> {
+    match s.parse::<f32>() {
+        Ok(fps) if fps > 0. && fps.is_finite() => Ok(fps),
+        _ => Err("use a positive frame rate, e.g. 12.5".to_string()),
+    }
+}
+
+fn parse_decoder(s: &str) -> Result<String, String> {
+    crate::decode::decoder_name(s)
+        .ok_or_else(|| format!("unknown format `{}`, see `imgc capabilities` for the decoders", s))
+}
+
+fn parse_pixels(s: &str) -> Result<u64, String> {
+    let error = || "use a pixel count, e.g. 100MP, 2.5GP or 50000000".to_string();
+    let number = s.trim().trim_end_matches(['P', 'p']);
+    let (number, factor) = match number.char_indices().last() {
+        Some((i, 'k' | 'K')) => (&number[..i], 1e3),
+        Some((i, 'm' | 'M')) => (&number[..i], 1e6),
+        Some((i, 'g' | 'G')) => (&number[..i], 1e9),
+        _ => (number, 1.),
+    };
+    match number.parse::<f64>() {
+        // counts below one pixel would reject every input
+        Ok(count) if count * factor >= 1. && count.is_finite() => Ok((count * factor) as u64),
+        _ => Err(error()),
+    }
+}
+
+fn parse_bytes(s: &str) -> Result<u64, String> {
+    let error = || "use a size in bytes, e.g. 500K, 2M, 1.5G or 4096".to_string();
+    let number = s.trim().trim_end_matches(['B', 'b']).trim_end_matches('i');
+    let (number, factor) = match number.char_indices().last() {
+        Some((i, 'k' | 'K')) => (&number[..i], 1024.),
+        Some((i, 'm' | 'M')) => (&number[..i], 1024. * 1024.),
+        Some((i, 'g' | 'G')) => (&number[..i], 1024. * 1024. * 1024.),
+        _ => (number, 1.),
+    };
+    match number.trim().parse::<f64>() {
+        Ok(size) if size >= 0. && size.is_finite() => Ok((size * factor) as u64),
+        _ => Err(error()),
+    }
+}
+
+fn parse_size(s: &str) -> Result<(u32, u32), String> {
+    let error = || "use a size of WIDTHxHEIGHT pixels, e.g. 2048x2048".to_string();
+    let (width, height) = s.trim().split_once(['x', 'X']).ok_or_else(error)?;
+    match (width.parse::<u32>(), height.parse::<u32>()) {
+        (Ok(width), Ok(height)) if width > 0 && height > 0 => Ok((width, height)),
+        _ => Err(error()),
+    }
+}
+
+fn parse_progress_template(s: &str) -> Result<String, String> {
+    indicatif::ProgressStyle::with_template(s).map_err(|err| err.to_string())?;
+    Ok(s.to_string())
+}
+
+fn parse_summary_template(s: &str) -> Result<String, String> {
+    crate::outcome::check_summary_template(s)?;
+    Ok(s.to_string())
+}
+
+fn parse_duration(s: &str) -> Result<std::time::Duration, String> {
+    let s = s.trim();
+    let (number, factor) = match s.char_indices().last() {
+        Some((i, 's')) => (&s[..i], 1.),
+        Some((i, 'm')) => (&s[..i], 60.),
+        Some((i, 'h')) => (&s[..i], 3600.),
+        Some((i, 'd')) => (&s[..i], 86400.),
+        _ => (s, 1.),
+    };
+    // too long durations (1e30s) do not fit into a Duration
+    match number.parse::<f64>().ok().filter(|seconds| *seconds > 0.)
+        .and_then(|seconds| std::time::Duration::try_from_secs_f64(seconds * factor).ok()) {
+        Some(duration) => Ok(duration),
+        None => Err("use a positive duration, e.g. 300s, 5m, 1h or 7d".to_string()),
+    }
+}
+
+/// Writes the completion script for `shell` to stdout
+pub fn print_completions(shell: Shell) {
+    clap_complete::generate(shell, &mut CliArgs::command(), "imgc", &mut io::stdout());
+}
 
-    /// Remove files matching a glob pattern
-    Clean {},
+/// Writes the man page to stdout
+pub fn print_manpage() -> Result<(), crate::Error> {
+    clap_mangen::Man::new(CliArgs::command()).render(&mut io::stdout())?;
+    Ok(())
 }
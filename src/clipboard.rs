@@ -0,0 +1,35 @@
+use crate::{
+    converter::{encode_image, EncoderConfig},
+    decode::read_image_from_memory,
+    format::ImageFormat,
+    Error,
+};
+use arboard::{Clipboard, ImageData};
+use image::{DynamicImage, RgbaImage};
+use std::{borrow::Cow, fs, path::Path};
+
+/// Converts the image on the clipboard into `img_format`, writing it to `output`.
+///
+/// With `copy_back`, the converted image replaces the clipboard contents. Clipboards carry pixels, not encoded files,
+/// so the output is decoded again (not possible for avif outputs). Returns the size of the written output.
+pub fn convert_clipboard(output: &Path, img_format: &ImageFormat, options: &EncoderConfig, copy_back: bool) -> Result<usize, Error> {
+    let mut clipboard = Clipboard::new()?;
+    let image = clipboard.get_image()
+        .map_err(|err| Error::from_string(format!("no image on the clipboard: {}", err)))?;
+    let pixels = RgbaImage::from_raw(image.width as u32, image.height as u32, image.bytes.into_owned())
+        .ok_or_else(|| Error::from_string("the clipboard image is truncated".to_string()))?;
+    let data = encode_image(&DynamicImage::ImageRgba8(pixels), img_format, options)?;
+    fs::write(output, &data)?;
+    if copy_back {
+        if *img_format == ImageFormat::Avif {
+            return Err(Error::from_string(format!("{} written, but avif outputs cannot be decoded to copy them back", output.display())));
+        }
+        let converted = read_image_from_memory(&data)?.to_rgba8();
+        clipboard.set_image(ImageData {
+            width: converted.width() as usize,
+            height: converted.height() as usize,
+            bytes: Cow::Owned(converted.into_raw()),
+        })?;
+    }
+    Ok(data.len())
+}
@@ -3,11 +3,14 @@ use image::DynamicImage;
 use ravif::*;
 use rgb::FromSlice;
 use crate::converter::DEPENDENCIES;
+use std::io::Write;
 
 macro_rules! copy_enum_variants {
     ($name:ident, $($variant:ident),*) => {
         #[allow(missing_docs)]
-        #[derive(clap::ValueEnum, Clone, Copy, PartialEq, Eq, Debug)]
+        #[cfg_attr(feature = "cli", derive(clap::ValueEnum))]
+        #[derive(serde::Serialize, serde::Deserialize, Clone, Copy, PartialEq, Eq, Debug)]
+        #[serde(rename_all = "kebab-case")]
         pub enum $name {
             $($variant),*
         }
@@ -51,7 +54,7 @@ pub fn encoder_info(quality: f32, speed: u8,
             ravif_version = version;
         }
         None => {
-            println!("Package '{}' not found", "ravif");
+            println!("Package 'ravif' not found");
         }
     };
     
@@ -65,33 +68,52 @@ pub fn encoder_info(quality: f32, speed: u8,
     )
 }
 
+/// Encodes a `DynamicImage` in avif format, writing the result into `writer`
+///
+/// ravif only produces complete files, so the encoded image is buffered before writing.
+#[allow(clippy::too_many_arguments)]
+pub fn encode_avif_to<W: Write>(image: &DynamicImage, quality: f32, speed: u8,
+                                bit_depth: Option<BitDepth>, color_model: Option<ColorModel>,
+                                alpha_color_mode: Option<AlphaColorMode>, alpha_quality: f32,
+                                threads: Option<usize>, mut writer: W) -> Result<(), Error> {
+    let data = encode_avif(image, quality, speed, bit_depth, color_model, alpha_color_mode, alpha_quality, threads)?;
+    writer.write_all(&data)?;
+    Ok(())
+}
+
 /// Encodes a `DynamicImage` to bytes of avif format
+///
+/// `threads` pins the rav1e thread count, the tile layout (and thereby the output) depends on it.
+/// None uses the global rayon thread pool.
+#[allow(clippy::too_many_arguments)]
 pub fn encode_avif(image: &DynamicImage, quality: f32, speed: u8,
                    bit_depth: Option<BitDepth>, color_model: Option<ColorModel>,
-                   alpha_color_mode: Option<AlphaColorMode>, alpha_quality: f32) -> Result<Vec<u8>, Error> {
-    let avif_res: EncodedImage;
-    if image.color().has_alpha() {
+                   alpha_color_mode: Option<AlphaColorMode>, alpha_quality: f32,
+                   threads: Option<usize>) -> Result<Vec<u8>, Error> {
+    let avif_res = if image.color().has_alpha() {
         let source_image = image.to_rgba8();
         let image = Img::new(source_image.as_rgba(), image.width() as usize, image.height() as usize);
-        avif_res = Encoder::new()
+        Encoder::new()
             .with_quality(quality)
             .with_speed(speed) // speed: 1-10, 10 is fastest, but still slow
             .with_bit_depth(convert_bit_depth_to_ext(bit_depth))
             .with_internal_color_model(convert_color_model_to_ext(color_model))
             .with_alpha_quality(alpha_quality) // TODO: expose parameter
             .with_alpha_color_mode(convert_alpha_color_mode_to_ext(alpha_color_mode)) // internal ravif default
+            .with_num_threads(threads)
             .encode_rgba(image)
-            .map_err(|e| Error::from_string(format!("avif encoding failed: {:?}", e)))?;
+            .map_err(|e| Error::from_string(format!("avif encoding failed: {:?}", e)))?
     } else {
         let source_image = image.to_rgb8();
         let image = Img::new(source_image.as_rgb(), image.width() as usize, image.height() as usize);
-        avif_res = Encoder::new()
+        Encoder::new()
             .with_quality(quality)
             .with_speed(speed) // speed: 1-10, 10 is fastest, but still slow
             .with_bit_depth(convert_bit_depth_to_ext(bit_depth))
             .with_internal_color_model(convert_color_model_to_ext(color_model))
+            .with_num_threads(threads)
             .encode_rgb(image)
-            .map_err(|e| Error::from_string(format!("avif encoding failed: {:?}", e)))?;
-    }
+            .map_err(|e| Error::from_string(format!("avif encoding failed: {:?}", e)))?
+    };
     Ok(avif_res.avif_file)
 }
@@ -0,0 +1,46 @@
+use crate::Error;
+use image::{codecs::farbfeld::FarbfeldEncoder, DynamicImage};
+use crate::converter::DEPENDENCIES;
+use std::io::{BufWriter, Write};
+
+/// Provides encoder information
+pub fn encoder_info() -> String {
+    // we might have multiple versions of the package, use rfind to find the newest one
+    let mut image_version = "";
+    match DEPENDENCIES.iter().rfind(|&&(name, _)| name == "image") {
+        Some((_name, version)) => {
+            image_version = version;
+        }
+        None => {
+            println!("Package 'image' not found");
+        }
+    };
+
+    format!(
+        "Using \"farbfeld (from image crate)\" ({})",
+        image_version
+    )
+}
+
+
+/// Encodes a `DynamicImage` to bytes of farbfeld format
+pub fn encode_farbfeld(image: &DynamicImage) -> Result<Vec<u8>, Error> {
+    let mut output = Vec::new();
+    encode_farbfeld_to(image, &mut output)?;
+    Ok(output)
+}
+
+/// Encodes a `DynamicImage` in farbfeld format (16 bit rgba), streaming the result into `writer`
+///
+/// 16 bit inputs keep their full precision, 8 bit samples are scaled up.
+pub fn encode_farbfeld_to<W: Write>(image: &DynamicImage, writer: W) -> Result<(), Error> {
+    let rgba = image.to_rgba16();
+    let data: Vec<u8> = rgba.as_raw().iter().flat_map(|sample| sample.to_ne_bytes()).collect();
+    // the encoder writes every sample separately
+    let mut writer = BufWriter::new(writer);
+    FarbfeldEncoder::new(&mut writer)
+        .encode(&data, image.width(), image.height())
+        .map_err(|e| Error::from_string(format!("farbfeld encoding failed: {:?}", e)))?;
+    writer.flush()?;
+    Ok(())
+}
@@ -0,0 +1,76 @@
+use crate::Error;
+use image::DynamicImage;
+use libheif_rs::{Channel, ColorSpace, CompressionFormat, EncoderQuality, HeifContext, HeifError, Image, LibHeif, RgbChroma};
+use crate::converter::DEPENDENCIES;
+use std::io::Write;
+
+/// Provides encoder information
+pub fn encoder_info(lossless: bool, quality: f32) -> String {
+    // we might have multiple versions of the package, use rfind to find the newest one
+    let mut libheif_rs_version = "";
+    match DEPENDENCIES.iter().rfind(|&&(name, _)| name == "libheif-rs") {
+        Some((_name, version)) => {
+            libheif_rs_version = version;
+        }
+        None => {
+            println!("Package 'libheif-rs' not found");
+        }
+    };
+    // the encoder itself is the system library
+    let [major, minor, patch] = LibHeif::new().version();
+
+    format!(
+        "Using \"libheif-rs\" ({}, libheif {}.{}.{}) with options (lossless: {}, quality: {})",
+        libheif_rs_version,
+        major, minor, patch,
+        lossless,
+        quality
+    )
+}
+
+
+/// Encodes a `DynamicImage` to bytes of heic format (hevc in a heif container)
+pub fn encode_heif(image: &DynamicImage, lossless: bool, quality: f32) -> Result<Vec<u8>, Error> {
+    let mut output = Vec::new();
+    encode_heif_to(image, lossless, quality, &mut output)?;
+    Ok(output)
+}
+
+/// Encodes a `DynamicImage` in heic format, writing the result into `writer`
+///
+/// libheif encodes the whole file into memory, which is copied into `writer`.
+pub fn encode_heif_to<W: Write>(image: &DynamicImage, lossless: bool, quality: f32, mut writer: W) -> Result<(), Error> {
+    let heif_error = |e: HeifError| Error::from_string(format!("heif encoding failed: {}", e));
+    let (width, height) = (image.width(), image.height());
+    let (chroma, channels, data) = if image.color().has_alpha() {
+        (RgbChroma::Rgba, 4, image.to_rgba8().into_raw())
+    } else {
+        (RgbChroma::Rgb, 3, image.to_rgb8().into_raw())
+    };
+
+    let mut heif_image = Image::new(width, height, ColorSpace::Rgb(chroma)).map_err(heif_error)?;
+    heif_image.create_plane(Channel::Interleaved, width, height, 8).map_err(heif_error)?;
+    {
+        let planes = heif_image.planes_mut();
+        let plane = planes.interleaved
+            .ok_or_else(|| Error::from_string("heif image without interleaved plane".to_string()))?;
+        // rows of the plane may be padded
+        let row_len = width as usize * channels;
+        for (dst, src) in plane.data.chunks_mut(plane.stride).zip(data.chunks_exact(row_len)) {
+            dst[..row_len].copy_from_slice(src);
+        }
+    }
+
+    let lib_heif = LibHeif::new();
+    let mut encoder = lib_heif.encoder_for_format(CompressionFormat::Hevc).map_err(heif_error)?;
+    let encoder_quality = if lossless {
+        EncoderQuality::LossLess
+    } else {
+        EncoderQuality::Lossy(quality.clamp(0., 100.) as u8)
+    };
+    encoder.set_quality(encoder_quality).map_err(heif_error)?;
+    let mut context = HeifContext::new().map_err(heif_error)?;
+    context.encode_image(&heif_image, &mut encoder, None).map_err(heif_error)?;
+    writer.write_all(&context.write_to_bytes().map_err(heif_error)?)?;
+    Ok(())
+}
@@ -6,34 +6,76 @@ pub mod avif;
 pub mod webp_image;
 /// This module provides png conversion via the image crate
 pub mod png;
-mod mozjpeg;
+/// This module provides heic encoding via libheif (libheif-rs crate)
+#[cfg(feature = "heif")]
+pub mod heif;
+/// This module provides pnm (pbm, pgm, ppm, pam) conversion via the image crate
+pub mod pnm;
+/// This module provides farbfeld (16 bit rgba) conversion via the image crate
+pub mod farbfeld;
+/// This module provides jpeg encoding via mozjpeg (libjpeg api of the mozjpeg-sys crate)
+pub mod mozjpeg;
 
 use crate::{
-    converter::avif::encode_avif,
+    animation::{frame_path, read_frames, supports_animation, AnimatedInputs, Frame, FrameMode},
+    converter::avif::encode_avif_to,
     converter::avif::{AlphaColorMode, BitDepth, ColorModel},
-    converter::webp::encode_webp,
-    converter::webp_image::encode_webp_image,
-    converter::png::encode_png,
-    converter::png::{CompressionType, FilterType},
-    converter::mozjpeg::encode_mozjpeg,
+    converter::webp::{encode_webp_animation, encode_webp_to},
+    converter::webp_image::encode_webp_image_to,
+    converter::png::{encode_png_animation, encode_png_to},
+    converter::png::{ColorType, CompressionType, FilterType},
+    converter::mozjpeg::encode_mozjpeg_to,
+    converter::pnm::{encode_pnm_to, PnmSubtype},
+    converter::farbfeld::encode_farbfeld_to,
+    decode::{exceeds_pixel_limit, probe_color_type, probe_dimensions, probe_format_name, read_image_from_memory, read_image_with, salvage_jpeg,
+             DecodeOptions, DecoderBackend, DecoderFilter},
+    dump::PixelDump,
+    filename::FilenameNormalization,
     format::ImageFormat,
+    i18n::tr,
+    manifest::{sha256_hex, ChecksumAlgorithm, Manifest, ManifestEntry, write_checksums},
+    metadata::{copy_png_text, inject_exif, output_exif, read_exif, ExifData, MetadataFields},
+    output,
+    overrides::InputOverride,
+    quality::VisualQuality,
+    phash::{perceptual_hash, PerceptualHashKind},
+    placeholder::{compute_placeholder, PlaceholderKind},
+    plan::{plan_conversion, InputFormatFilter, InputProblem, Plan, PlannedFile, ProcessingOrder},
+    outcome::{ConversionOutcome, RunAggregate, RunStatistics, RunSummary, SkipReason},
+    panics::{append_panic_log, PanicPolicy, PanicReport, DEFAULT_PANIC_LOG},
+    priority::{weight_of, PathWeight},
+    profile::{self, start_profile, write_profile},
+    quarantine::{quarantine_input, QuarantineMode},
+    route::{route_format, ImageTraits, RouteRule},
+    sidecar::{Crop, Sidecar},
+    snippet::{write_snippet, SnippetFormat},
+    stage::{Stage, Transfer},
+    undo::{write_undo_script, UndoStep},
+    utils::{backup_path, create_dir_all_owned, file_owner, replace_file, set_owner, sync_directory, write_file},
+    validate::check_visual,
+    xattr::{read_xattrs, write_xattrs},
     Error,
 };
 use std::{
-    collections::{LinkedList},
+    borrow::Cow,
+    collections::{BTreeMap, HashMap, HashSet},
     fs,
+    io::Write,
     path::{Path, PathBuf},
+    process::{Command, Stdio},
     error::Error as StdError,
-    sync::{Arc, atomic::AtomicBool},
-    panic
+    sync::{Arc, Mutex, Once, OnceLock, atomic::AtomicBool},
+    cell::{Cell, RefCell},
+    time::{Duration, Instant, SystemTime},
+    panic::{self, AssertUnwindSafe},
+    backtrace::Backtrace,
 };
 use std::sync::atomic::{AtomicUsize, Ordering};
 use std::sync::mpsc;
-use image::{ImageReader, ImageFormat as ImageImageFormat, DynamicImage, RgbImage};
+use image::DynamicImage;
 use rayon::prelude::*;
 use humansize::{format_size, FormatSizeOptions, BINARY};
-use indicatif::{HumanDuration, ProgressBar, ProgressStyle};
-use jpeg_decoder::Decoder;
+use crate::progress::{HumanDuration, MultiProgress, ProgressBar, ProgressBatch, ProgressStyle};
 
 // Include dependency version numbers
 include!(concat!(env!("OUT_DIR"), "/versions.rs"));
@@ -49,11 +91,134 @@ pub struct CommonConfig {
     /// Defaults to the same location as the original images with the new file extension.
     pub output: String,
 
+    /// Further output directories receiving a copy of every output (e.g. a mounted backup share),
+    /// mapped like `output`. Existing outputs are skipped or overwritten per directory.
+    /// Defaults to empty (no copies).
+    pub mirror_outputs: Vec<String>,
+
+    /// Write all outputs directly into the output directories, regardless of the input directory structure.
+    /// Outputs of inputs with the same name get a hash of their input path appended (`hero.1a2b3c4d.webp`)
+    /// unless they are named by their content hash.
+    /// Defaults to false.
+    pub flatten: bool,
+
+    /// Directory whose path is stripped from the input paths to map them into the output directories,
+    /// inputs outside of it fail the planning.
+    /// Defaults to None (the fixed base of the pattern, before any wildcard).
+    pub relative_to: Option<String>,
+
+    /// Unicode form of the output filenames (and of the directories created below the output directories).
+    /// Defaults to None (names as given).
+    pub normalize_filenames: Option<FilenameNormalization>,
+
+    /// Show a summary of the planned run (files, input size, format, output directory, overwrite policy)
+    /// and ask for confirmation on stdin before converting anything.
+    /// Defaults to false.
+    pub confirm: bool,
+
+    /// Write a `<picture>` snippet (html or json) next to each output, listing its variants in all web formats
+    /// found in the output directory (e.g. written by earlier runs into other formats).
+    /// Defaults to None (no snippets).
+    pub snippets: Option<SnippetFormat>,
+
+    /// Compute a low quality placeholder of each image and record it in the manifest.
+    /// Defaults to None (no placeholders).
+    pub placeholder: Option<PlaceholderKind>,
+
+    /// Compute a perceptual hash of each image and record it in the manifest, e.g. for duplicate detection.
+    /// Defaults to None (no hashes).
+    pub perceptual_hash: Option<PerceptualHashKind>,
+
+    /// Reject inputs with more pixels than this (read from their header, before decoding),
+    /// e.g. decompression bombs. Rejected inputs are counted separately from errors.
+    /// Defaults to None (no limit besides the ones of the decoders).
+    pub max_pixels: Option<u64>,
+
+    /// Decoder of jpeg inputs.
+    /// Defaults to [`DecoderBackend::Auto`] (zune-jpeg with the `zune` feature, the `image` crate otherwise).
+    pub decoder: DecoderBackend,
+
+    /// Formats whose inputs are decoded, inputs of other formats are sorted out while planning.
+    /// Defaults to empty (all formats).
+    pub decoders: DecoderFilter,
+
+    /// Which of the files matched by the pattern are inputs, by their extension (e.g. only jpegs and pngs).
+    /// Defaults to empty (all files).
+    pub input_formats: InputFormatFilter,
+
+    /// Abandon files whose conversion takes longer than this, reporting them as errors.
+    /// The abandoned conversion cannot be stopped and keeps running in the background until it ends or imgc exits.
+    /// Defaults to None (no timeout).
+    pub per_file_timeout: Option<Duration>,
+
+    /// Convert the salvageable part of truncated or corrupt jpegs instead of failing, the missing part is filled gray.
+    /// Repaired inputs are listed after the run and flagged in the manifest.
+    /// Defaults to false.
+    pub tolerate_truncation: bool,
+
+    /// Directory undecodable inputs are put into (at their path relative to the pattern base),
+    /// each next to a `.error.txt` note of its decoding error.
+    /// Defaults to None (undecodable inputs are only reported).
+    pub quarantine_dir: Option<String>,
+
+    /// Whether quarantined inputs are copied or moved.
+    /// Defaults to copy.
+    pub quarantine_mode: QuarantineMode,
+
+    /// What happens when converting a file panicked (a bug in a decoder or encoder).
+    /// Quarantining requires a `quarantine_dir`.
+    /// Defaults to skip (the file fails).
+    pub on_panic: PanicPolicy,
+
+    /// File each panic is appended to with its input and backtrace, whatever the `on_panic` policy.
+    /// Defaults to None (`imgc-panics.log` in the working directory).
+    pub panic_log: Option<String>,
+
+    /// Dumps of the pixels handed to the encoder for a sample of the still images, to diagnose color shifts of encoders.
+    /// Defaults to None (no dumps).
+    pub pixel_dump: Option<PixelDump>,
+
+    /// File the inputs that cannot be converted (zero-byte, unknown magic, unsupported format) are listed in.
+    /// Defaults to None (only counted).
+    pub list_unconvertible: Option<String>,
+
+    /// List each input that was not converted with the reason after the run.
+    /// Defaults to false.
+    pub explain_skips: bool,
+
+    /// Encode all files at the lowest effort first, then re-encode them at the configured effort
+    /// (preferred and most compressible files first) until this time after the start of the run is used up.
+    /// Defaults to None (a single pass).
+    pub time_budget: Option<Duration>,
+
+    /// File the spans (decode, transform, encode, write) of each converted file are written to after the run,
+    /// as chrome trace or, for paths ending in `.folded`, as folded stacks (see [`crate::profile::write_profile`]).
+    /// Defaults to None (nothing recorded).
+    pub profile: Option<String>,
+
     /// By default, imgc will process input files in lexicographical order after expanding the pattern.
     /// Setting this starts the process from the back.
     /// Defaults to false.
     pub reverse_processing_order: bool,
 
+    /// Order of the inputs (before `priorities`), `Random` shuffles them.
+    /// Defaults to `Name`.
+    pub order: ProcessingOrder,
+
+    /// Convert the inputs of one directory after another (in the order of their first input),
+    /// for the read locality of spinning disks and network shares.
+    /// Defaults to false.
+    pub group_by_directory: bool,
+
+    /// Seed of the `Random` order, to repeat the order of an earlier run.
+    /// Defaults to None (a new seed per run).
+    pub seed: Option<u64>,
+
+    /// Inputs matching these patterns are converted before the others (higher weights first),
+    /// keeping the processing order among inputs of the same weight.
+    /// Defaults to empty (processing order only).
+    pub priorities: Vec<PathWeight>,
+
     /// Overwrite the existing output file if the current conversion resulted in a smaller file.
     /// Defaults to false.
     pub overwrite_if_smaller: bool,
@@ -62,18 +227,784 @@ pub struct CommonConfig {
     /// Defaults to false. (Determined by filename match)
     pub overwrite_existing: bool,
 
+    /// Convert inputs with existing outputs again if the input was modified after the output, skip them otherwise.
+    /// Defaults to false (existing outputs are skipped regardless of their age).
+    pub if_stale: bool,
+
+    /// Sync each output (and its directory entry) to disk before counting it as converted.
+    /// Defaults to false.
+    pub fsync: bool,
+
+    /// Copy the extended attributes of the inputs to their outputs (macOS Finder tags and color labels, `user.*` on Linux).
+    /// Defaults to false.
+    pub preserve_xattrs: bool,
+
+    /// Give the outputs (and the directories created for them) the user and group of their inputs, e.g. when run as root.
+    /// Defaults to false (owned by the user running the conversion).
+    pub preserve_owner: bool,
+
+    /// Local directory the outputs are written to first, `stage_transfers` threads move them to their destination
+    /// while the next files are encoded. Inputs replaced in place and frame sequences are written directly,
+    /// not combined with `dedupe_outputs`, `on_converted`, `snippets` and `time_budget` (they need the outputs in place).
+    /// Defaults to None (outputs are written to their destination).
+    pub stage_dir: Option<String>,
+
+    /// Number of outputs moved from the `stage_dir` at the same time.
+    /// Defaults to 4 (with the cli).
+    pub stage_transfers: usize,
+
+    /// Write a shell script reverting the run to this path: new outputs are removed, replaced (with `backup_ext`)
+    /// and quarantined inputs restored.
+    /// Defaults to None (no undo script).
+    pub undo_script: Option<String>,
+
     /// Discards the encoding result if it is larger than the input file (does not create an output file).
     /// Defaults to false.
     pub discard_if_larger_than_input: bool,
+
+    /// Only keep outputs at least this many percent smaller than their inputs, others are dropped as not worth it.
+    /// Defaults to None (any size is kept).
+    pub min_savings: Option<f64>,
+
+    /// Formats tried in order for still images whose encode in the target format is larger than the input,
+    /// the first smaller encode is written instead.
+    /// Defaults to empty (no fallback).
+    pub fallback_formats: Vec<ImageFormat>,
+
+    /// Output formats of still images by their content, the first matching rule applies.
+    /// Defaults to empty (the target format for all images).
+    pub routes: Vec<RouteRule>,
+
+    /// Only convert images with transparent pixels, others are skipped (inputs without alpha channel before decoding).
+    /// Defaults to false.
+    pub only_with_alpha: bool,
+
+    /// What happens to animated inputs unless `frames` of the encoder settings is set.
+    /// Defaults to [`AnimatedInputs::First`] (the first frame, listed in the summary).
+    pub animated: AnimatedInputs,
+
+    /// Decode each still image output before writing it and fail the file if it is not displayed like its input
+    /// (dimensions, EXIF orientation, ICC profile) with at least this SSIM (0 - 1).
+    /// Defaults to None (no validation).
+    pub validate_visual: Option<f64>,
+
+    /// Hide the per-worker progress lines showing the file currently being processed.
+    /// Defaults to false.
+    pub hide_worker_progress: bool,
+
+    /// Write a json manifest (paths, sha256 of in- and output, encoder, version and settings) of all converted files to this path.
+    /// Defaults to None (no manifest).
+    pub manifest: Option<String>,
+
+    /// Write a checksum file (e.g. `SHA256SUMS`) of all converted outputs into the output directory,
+    /// or into the fixed base directory of the pattern if no output directory is set.
+    /// Defaults to None (no checksum file).
+    pub checksums: Option<ChecksumAlgorithm>,
+
+    /// How output filenames are derived from the input filenames.
+    /// Defaults to `OutputNaming::Plain`.
+    pub naming: OutputNaming,
+
+    /// Write a json map of input paths to their output paths (e.g. content hash names) to this path.
+    /// Defaults to None (no map).
+    pub hash_map: Option<String>,
+
+    /// Link byte-identical outputs of different inputs to the first written copy instead of storing them again.
+    /// Defaults to None (no deduplication).
+    pub dedupe_outputs: Option<DedupeMode>,
+
+    /// Replace the input files with their converted outputs (same path, regardless of the output format).
+    /// Defaults to false.
+    pub in_place: bool,
+
+    /// Keep the replaced input files of `in_place` conversions with this suffix appended (e.g. `.orig`).
+    /// Defaults to None (no backup).
+    pub backup_ext: Option<String>,
+
+    /// Re-optimize inputs already in the target format, replacing them in place if the result is smaller.
+    /// Without it, such inputs are skipped because the output path is the input itself.
+    /// Defaults to false.
+    pub optimize: bool,
+
+    /// Only convert animated inputs, writing their (selected) frames as numbered stills
+    /// even for output formats supporting animations. Still images are skipped.
+    /// Defaults to false.
+    pub extract_frames: bool,
+
+    /// Grab the frames at these timestamps (ffmpeg syntax) of video inputs and convert them instead of images,
+    /// every file matched by the pattern is treated as a video (requires the `ffmpeg` feature).
+    /// Several timestamps are written as numbered frame sequences.
+    /// Defaults to empty (image inputs).
+    pub poster_at: Vec<String>,
+
+    /// Run this shell command after each converted file, with the paths and sizes in the environment
+    /// (`IMGC_INPUT`, `IMGC_OUTPUT`, `IMGC_INPUT_SIZE`, `IMGC_OUTPUT_SIZE`). A failing command fails the file.
+    /// Defaults to None (no command).
+    pub on_converted: Option<String>,
+
+    /// Layout of the overall progress bar, an indicatif template (e.g. `{wide_bar} {percent}% {msg}`),
+    /// whose position and length are the input bytes.
+    /// Defaults to None ([`DEFAULT_PROGRESS_TEMPLATE`]).
+    pub progress_template: Option<String>,
+
+    /// Add a table of the counts and sizes per input extension to the encode statistics.
+    /// Defaults to false.
+    pub by_extension: bool,
+
+    /// Print the summary of the run as one json object on stdout ([`RunSummary::to_json`]), all other messages on stderr.
+    /// Defaults to false.
+    pub summary_json: bool,
+
+    /// Summary printed after the run instead of the encode statistics, with `{field}` placeholders of [`RunSummary`].
+    /// Defaults to None (the encode statistics).
+    pub summary_template: Option<String>,
+
+    /// Called with the outcome of each file after its conversion, e.g. to show the progress in a GUI.
+    /// Defaults to None.
+    pub on_file_done: Option<FileCallback>,
+}
+
+/// Layout of the overall progress bar, an [indicatif template](https://docs.rs/indicatif/latest/indicatif/#templates)
+pub const DEFAULT_PROGRESS_TEMPLATE: &str =
+    "[{elapsed_precise}/~{duration_precise} ({eta_precise} rem.)] {wide_bar:.cyan/blue} {binary_bytes:>10}/{binary_total_bytes:10} | {msg}";
+
+/// Callback of [`CommonConfig::on_file_done`], called from the worker threads
+pub type FileCallback = Arc<dyn Fn(&Path, &ConversionOutcome) + Send + Sync>;
+
+impl CommonConfig {
+    // whether the outputs are numbered frame sequences instead of single files
+    pub(crate) fn writes_frame_sequences(&self) -> bool {
+        self.extract_frames || self.poster_at.len() > 1
+    }
+}
+
+/// How byte-identical outputs are linked to the stored copy
+#[cfg_attr(feature = "cli", derive(clap::ValueEnum))]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DedupeMode {
+    /// Hard link (same filesystem only, indistinguishable from a copy).
+    Hardlink,
+    /// Symbolic link to the absolute path of the stored copy.
+    Symlink,
+}
+
+/// Output filename scheme
+#[cfg_attr(feature = "cli", derive(clap::ValueEnum))]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum OutputNaming {
+    /// Input filename with the new extension (`hero.webp`).
+    #[default]
+    Plain,
+    /// Embed a truncated sha256 of the output for cache busting (`hero.3fa2b1c9.webp`).
+    /// Identical outputs are skipped instead of being written again.
+    ContentHash,
+}
+
+// number of hex characters of the output hash embedded in content hash names
+pub(crate) const CONTENT_HASH_LENGTH: usize = 8;
+
+/// Records collected across all file tasks of a conversion run
+#[derive(Default)]
+struct RunRecords {
+    manifest: Option<Mutex<Vec<ManifestEntry>>>,
+    hash_map: Option<Mutex<BTreeMap<String, String>>>,
+    // output sha256 => first output written with that content
+    dedupe: Option<Mutex<HashMap<String, PathBuf>>>,
+    deduped_files: AtomicUsize,
+    deduped_bytes: AtomicUsize,
+    // inputs salvaged by --tolerate-truncation
+    repaired: Mutex<Vec<PathBuf>>,
+    // inputs written in a --fallback-format
+    fallbacks: Mutex<Vec<(PathBuf, ImageFormat)>>,
+    // extension => inputs written in that format by a --route rule
+    routed: Mutex<BTreeMap<String, usize>>,
+    quarantined: AtomicUsize,
+    undo: Option<Mutex<Vec<UndoStep>>>,
+    // panics raised in decoders and encoders, written to the panic log
+    panics: AtomicUsize,
+    // input whose panic stopped the run with --on-panic abort
+    panic_abort: Mutex<Option<PathBuf>>,
+    // outputs written to the --stage-dir, moved to their destination by the transfer threads
+    stage: Option<Stage>,
+}
+
+// inputs without new output and why, for --explain-skips
+type ExplainedSkips = Mutex<Vec<(PathBuf, String)>>;
+
+/// Replaces `link` with a hard or symbolic link to `original`
+fn link_output(mode: DedupeMode, original: &Path, link: &Path) -> std::io::Result<()> {
+    if fs::symlink_metadata(link).is_ok() {
+        fs::remove_file(link)?;
+    }
+    match mode {
+        DedupeMode::Hardlink => fs::hard_link(original, link),
+        DedupeMode::Symlink => {
+            let original = fs::canonicalize(original)?;
+            #[cfg(unix)]
+            return std::os::unix::fs::symlink(original, link);
+            #[cfg(windows)]
+            return std::os::windows::fs::symlink_file(original, link);
+            #[cfg(not(any(unix, windows)))]
+            return Err(std::io::Error::new(std::io::ErrorKind::Unsupported, "symlinks are not supported"));
+        }
+    }
+}
+
+/// Runs the `--on-converted` command of a converted file via the shell
+fn run_on_converted(command: &str, input_path: &Path, output_path: &Path, input_size: usize, output_size: usize)
+    -> Result<(), Error> {
+    let mut shell = if cfg!(windows) { Command::new("cmd") } else { Command::new("sh") };
+    let status = shell.arg(if cfg!(windows) { "/C" } else { "-c" })
+        .arg(command)
+        .env("IMGC_INPUT", input_path)
+        .env("IMGC_OUTPUT", output_path)
+        .env("IMGC_INPUT_SIZE", input_size.to_string())
+        .env("IMGC_OUTPUT_SIZE", output_size.to_string())
+        .stdin(Stdio::null())
+        .status()?;
+    if !status.success() {
+        return Err(Error::from_string(format!("converted to {}, but --on-converted failed ({})", output_path.display(), status)));
+    }
+    Ok(())
+}
+
+/// Encoder parameters, unset values fall back to the encoder defaults
+#[derive(Clone, Debug, Default, serde::Serialize, serde::Deserialize)]
+pub struct EncoderConfig {
+    /// Use lossless encoding mode (webp).
+    /// Defaults to false.
+    pub lossless: Option<bool>,
+
+    /// Target quality (0 - 100, webp, avif, heic and jpeg).
+    /// Defaults to 90.0 (75 for jpeg).
+    pub quality: Option<f32>,
+
+    /// Encoder independent quality, mapped onto `quality` (and avif `speed`) unless these are set.
+    /// Defaults to None.
+    pub visual_quality: Option<VisualQuality>,
+
+    /// Encoding speed (1 - 10, avif).
+    /// Defaults to 3.
+    pub speed: Option<u8>,
+
+    /// Settings replacing the ones above for inputs of some formats, later entries win.
+    /// Defaults to none.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub input_overrides: Vec<InputOverride>,
+
+    /// Region of the input to convert (the same one for all frames of animations).
+    /// Defaults to None (the whole image).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub crop: Option<Crop>,
+
+    /// Near-lossless preprocessing level of lossless webp (0 - 100, lower adjusts more pixels, 100 is off).
+    /// Implies lossless encoding.
+    /// Defaults to None (off).
+    pub webp_near_lossless: Option<u8>,
+
+    /// Png compression type.
+    pub png_compression_type: Option<CompressionType>,
+
+    /// Png filter type.
+    pub png_filter_type: Option<FilterType>,
+
+    /// Png color type.
+    /// Defaults to rgb/rgba depending on the alpha channel of the input.
+    pub png_color_type: Option<ColorType>,
+
+    /// Drop the text and time chunks of png inputs, which are otherwise carried over if metadata is kept.
+    /// Defaults to false.
+    pub png_strip_chunks: Option<bool>,
+
+    /// Pnm subtype.
+    /// Defaults to pgm, ppm or pam depending on the color and alpha channels of the input.
+    pub pnm_subtype: Option<PnmSubtype>,
+
+    /// Write the plain (ascii) pnm variant instead of the binary one.
+    /// Defaults to false.
+    pub pnm_plain: Option<bool>,
+
+    /// Internal avif bit depth.
+    pub avif_bit_depth: Option<BitDepth>,
+
+    /// Internal avif color model.
+    pub avif_color_model: Option<ColorModel>,
+
+    /// Internal avif alpha color mode.
+    pub avif_alpha_color_mode: Option<AlphaColorMode>,
+
+    /// Target avif alpha quality (0 - 100).
+    /// Defaults to 90.0.
+    pub avif_alpha_quality: Option<f32>,
+
+    /// For jpeg inputs of jpeg conversions, apply the EXIF orientation losslessly to the DCT coefficients
+    /// (and reset the tag) instead of decoding and re-encoding the pixels.
+    /// Defaults to false.
+    pub jpeg_bake_orientation: Option<bool>,
+
+    /// Jpeg restart marker interval in MCUs (0 = none), limiting the damage of transmission errors to a stripe.
+    /// Defaults to 0.
+    pub jpeg_restart_interval: Option<u16>,
+
+    /// Search the smallest progressive jpeg scan script (instead of using the standard one).
+    /// Defaults to true.
+    pub jpeg_optimize_scans: Option<bool>,
+
+    /// Jpeg trellis quantization (smaller files, slower encoding).
+    /// Defaults to true.
+    pub jpeg_trellis: Option<bool>,
+
+    /// Pin encoder thread counts (avif tiling depends on them), so that the same input and settings
+    /// yield byte-identical output across runs and machines.
+    /// Defaults to false.
+    pub deterministic: Option<bool>,
+
+    /// Pixels with an alpha below this are made fully transparent before encoding (webp and avif).
+    /// Defaults to None (unchanged).
+    pub alpha_threshold: Option<u8>,
+
+    /// Round-trip the colors of semi-transparent pixels through premultiplied alpha before encoding (webp and avif).
+    /// Defaults to false.
+    pub premultiply_alpha: Option<bool>,
+
+    /// Which frames of animated inputs (gif, webp, apng) are converted.
+    /// Defaults to the first frame (a still image).
+    pub frames: Option<FrameMode>,
+
+    /// Keep every n-th frame of animated inputs (`FrameMode::EveryNth`).
+    /// Defaults to 2.
+    pub frame_step: Option<usize>,
+
+    /// Maximum number of frames converted from animated inputs (after the frame step).
+    /// Defaults to None (all).
+    pub max_frames: Option<usize>,
+
+    /// Frame rate of converted animations, replacing the frame durations of the input.
+    /// Defaults to None (input durations).
+    pub fps: Option<f32>,
+
+    /// Metadata fields written into the outputs (not supported for avif).
+    pub metadata: MetadataFields,
+
+    /// Copy the EXIF metadata of jpeg, png and webp inputs into the outputs (not supported for avif).
+    /// Defaults to false.
+    pub keep_metadata: Option<bool>,
+
+    /// Remove location (GPS) and serial number fields from the kept metadata, implies `keep_metadata`.
+    /// Defaults to false.
+    pub strip_gps: Option<bool>,
+}
+
+impl EncoderConfig {
+    /// File extension of `img_format` outputs, the one of the forced `pnm_subtype` for pnm outputs
+    ///
+    /// The automatic pnm subtype keeps `.pnm`: it is chosen per image after decoding, output paths are planned before.
+    pub fn output_extension(&self, img_format: &ImageFormat) -> &'static str {
+        match (img_format, self.pnm_subtype) {
+            (ImageFormat::Pnm, Some(PnmSubtype::Pbm)) => "pbm",
+            (ImageFormat::Pnm, Some(PnmSubtype::Pgm)) => "pgm",
+            (ImageFormat::Pnm, Some(PnmSubtype::Ppm)) => "ppm",
+            (ImageFormat::Pnm, Some(PnmSubtype::Pam)) => "pam",
+            _ => img_format.extension(),
+        }
+    }
+
+    /// Quality setting of `img_format`: `quality` if set, otherwise mapped from `visual_quality`
+    ///
+    /// The quality of lossless webp is the compression effort, which is not mapped.
+    /// Out of range qualities are clamped to 0 - 100, 1 - 100 for avif (the cli rejects them already).
+    pub fn target_quality(&self, img_format: &ImageFormat) -> Option<f32> {
+        let lossless_webp = *img_format == ImageFormat::Webp
+            && (self.lossless.unwrap_or(false) || self.webp_near_lossless.is_some());
+        self.quality
+            .or_else(|| self.visual_quality.filter(|_| !lossless_webp)?.quality_for(img_format))
+            .map(|quality| clamp_quality(quality, img_format))
+    }
+
+    /// Avif encoding speed: `speed` if set (clamped to 1 - 10), otherwise mapped from `visual_quality`
+    pub fn target_speed(&self) -> Option<u8> {
+        self.speed.map(|speed| speed.clamp(1, 10))
+            .or_else(|| self.visual_quality.map(VisualQuality::avif_speed))
+    }
+
+    /// Settings for the input at `path`, with the matching `input_overrides` and the settings of its `sidecar` file applied
+    ///
+    /// The returned settings have no overrides left, manifests record the settings actually used.
+    pub fn for_input(&self, path: &Path, sidecar: Option<&Sidecar>) -> Cow<'_, EncoderConfig> {
+        if self.input_overrides.is_empty() && sidecar.is_none() {
+            return Cow::Borrowed(self);
+        }
+        let mut resolved = EncoderConfig { input_overrides: Vec::new(), ..self.clone() };
+        for input_override in self.input_overrides.iter().filter(|input_override| input_override.matches(path)) {
+            input_override.apply_to(&mut resolved);
+        }
+        if let Some(sidecar) = sidecar {
+            sidecar.settings.apply_to(&mut resolved);
+            resolved.crop = sidecar.crop.or(resolved.crop);
+        }
+        Cow::Owned(resolved)
+    }
+
+    /// Whether the EXIF metadata of the input is carried over into the output
+    pub fn keeps_metadata(&self) -> bool {
+        self.keep_metadata.unwrap_or(false) || self.strip_gps.unwrap_or(false)
+    }
+
+    /// The same settings at the lowest encoding effort of `img_format` (quality is kept)
+    ///
+    /// Used by the first pass of `--time-budget`. Encoders without effort setting (lossy webp, pnm, ...) are unchanged.
+    pub fn fast_effort(&self, img_format: &ImageFormat) -> EncoderConfig {
+        let lossless_webp = *img_format == ImageFormat::Webp
+            && (self.lossless.unwrap_or(false) || self.webp_near_lossless.is_some());
+        EncoderConfig {
+            // the quality of lossless webp is the compression effort
+            quality: if lossless_webp { Some(0.) } else { self.quality },
+            speed: Some(10),
+            png_compression_type: Some(CompressionType::Fast),
+            jpeg_optimize_scans: Some(false),
+            jpeg_trellis: Some(false),
+            ..self.clone()
+        }
+    }
+}
+
+// NaN is treated as the lowest quality, ravif asserts a quality of at least 1
+fn clamp_quality(quality: f32, img_format: &ImageFormat) -> f32 {
+    let lowest = if *img_format == ImageFormat::Avif { 1. } else { 0. };
+    if quality.is_nan() { lowest } else { quality.clamp(lowest, 100.) }
+}
+
+/// Encodes a `DynamicImage` to bytes of the given image format.
+pub fn encode_image(image: &DynamicImage, img_format: &ImageFormat, options: &EncoderConfig) -> Result<Vec<u8>, Error> {
+    let mut output = Vec::new();
+    encode_image_to(image, img_format, options, &mut output)?;
+    Ok(output)
+}
+
+/// Encodes a `DynamicImage` to bytes of the given image format,
+/// carrying over the EXIF block of the source file if `options` keep metadata.
+pub fn encode_image_with_exif(image: &DynamicImage, img_format: &ImageFormat, options: &EncoderConfig, source_exif: Option<&[u8]>) -> Result<Vec<u8>, Error> {
+    let mut output = Vec::new();
+    encode_image_with_exif_to(image, img_format, options, source_exif, &mut output)?;
+    Ok(output)
+}
+
+/// Encodes a `DynamicImage` in the given image format, writing the result into `writer`.
+///
+/// The png, webp-image and jpeg encoders stream their output,
+/// the webp and avif encoders write their finished in-memory result.
+/// Outputs with metadata to write are buffered as well.
+pub fn encode_image_to<W: Write>(image: &DynamicImage, img_format: &ImageFormat, options: &EncoderConfig, writer: W) -> Result<(), Error> {
+    encode_image_with_exif_to(image, img_format, options, None, writer)
+}
+
+/// Like [`encode_image_to`], carrying over the EXIF block of the source file if `options` keep metadata.
+pub fn encode_image_with_exif_to<W: Write>(image: &DynamicImage, img_format: &ImageFormat, options: &EncoderConfig,
+                                          source_exif: Option<&[u8]>, mut writer: W) -> Result<(), Error> {
+    let source_exif = source_exif.filter(|_| options.keeps_metadata());
+    let exif = output_exif(source_exif, options.strip_gps.unwrap_or(false), &options.metadata);
+    match exif {
+        Some(exif) if writes_metadata(img_format) => {
+            let mut encoded = Vec::new();
+            encode_pixels_to(image, img_format, options, &mut encoded)?;
+            writer.write_all(&inject_exif(encoded, exif)?)?;
+            Ok(())
+        }
+        _ => encode_pixels_to(image, img_format, options, writer),
+    }
+}
+
+/// Whether EXIF metadata is written into outputs of `img_format`
+///
+/// avif, heic, pnm and farbfeld outputs carry no metadata
+/// (ravif does not support it, img-parts does not know heif, the others have no place for it).
+pub fn writes_metadata(img_format: &ImageFormat) -> bool {
+    matches!(img_format, ImageFormat::Webp | ImageFormat::WebpImage | ImageFormat::Png | ImageFormat::Jpeg)
+}
+
+/// Losslessly transforms a jpeg into a jpeg, rotating it upright according to its EXIF orientation.
+///
+/// The EXIF block is handled like in [`encode_image_with_exif_to`], with the orientation reset.
+pub fn transform_jpeg(data: &[u8], options: &EncoderConfig) -> Result<Vec<u8>, Error> {
+    let source_exif = read_exif(data).and_then(|exif| ExifData::parse(&exif));
+    let orientation = source_exif.as_ref().and_then(ExifData::orientation).unwrap_or(1);
+    let transformed = mozjpeg::transform_mozjpeg(data, orientation)?;
+
+    let source_exif = source_exif.filter(|_| options.keeps_metadata()).map(|mut exif| {
+        exif.reset_orientation();
+        exif.to_bytes()
+    });
+    match output_exif(source_exif.as_deref(), options.strip_gps.unwrap_or(false), &options.metadata) {
+        Some(exif) => inject_exif(transformed, exif),
+        None => Ok(transformed),
+    }
+}
+
+/// Encodes the frames of an animation in the given image format (webp or png, see [`supports_animation`]),
+/// carrying over the EXIF block of the source file if `options` keep metadata.
+pub fn encode_animation(frames: Vec<Frame>, img_format: &ImageFormat, options: &EncoderConfig,
+                        source_exif: Option<&[u8]>) -> Result<Vec<u8>, Error> {
+    let transform = profile::span("transform");
+    let frames: Vec<Frame> = frames.into_iter()
+        .map(|mut frame| {
+            if let Some(crop) = options.crop {
+                frame.image = crop.apply(&frame.image)?.into_owned();
+            }
+            let prepared = match prepare_alpha(&frame.image, img_format, options) {
+                Cow::Owned(image) => Some(image),
+                Cow::Borrowed(_) => None,
+            };
+            Ok(Frame { image: prepared.unwrap_or(frame.image), delay_ms: frame.delay_ms })
+        })
+        .collect::<Result<_, Error>>()?;
+    drop(transform);
+    let _span = profile::span("encode");
+    let encoded = match img_format {
+        ImageFormat::Webp => encode_webp_animation(&frames, options.lossless.unwrap_or(false), options.target_quality(img_format).unwrap_or(90.),
+                                                   options.webp_near_lossless),
+        ImageFormat::Png => encode_png_animation(&frames, options.png_compression_type, options.png_filter_type),
+        _ => Err(Error::from_string("Unsupported animation format".to_string())),
+    }?;
+
+    let source_exif = source_exif.filter(|_| options.keeps_metadata());
+    match output_exif(source_exif, options.strip_gps.unwrap_or(false), &options.metadata) {
+        Some(exif) => inject_exif(encoded, exif),
+        None => Ok(encoded),
+    }
+}
+
+fn encode_pixels_to<W: Write>(image: &DynamicImage, img_format: &ImageFormat, options: &EncoderConfig, writer: W) -> Result<(), Error> {
+    let transform = profile::span("transform");
+    let image = &*encoder_pixels(image, img_format, options)?;
+    drop(transform);
+    let _span = profile::span("encode");
+    let encode_lossless = options.lossless.unwrap_or(false);
+    let encode_quality: f32 = options.target_quality(img_format).unwrap_or(90.);
+    let encode_speed: u8 = options.target_speed().unwrap_or(3);
+
+    match img_format {
+        // TODO: more PNG lossless optimizers, jpeg xl
+        ImageFormat::Webp => encode_webp_to(image, encode_lossless, encode_quality, options.webp_near_lossless, writer),
+        ImageFormat::WebpImage => encode_webp_image_to(image, writer),
+        ImageFormat::Avif => encode_avif_to(
+            image, encode_quality, encode_speed,
+            options.avif_bit_depth, options.avif_color_model,
+            options.avif_alpha_color_mode, options.avif_alpha_quality.map_or(90., |quality| clamp_quality(quality, img_format)),
+            options.deterministic.unwrap_or(false).then_some(1), writer),
+        ImageFormat::Png => encode_png_to(image, options.png_compression_type, options.png_filter_type,
+                                                options.png_color_type, writer),
+        ImageFormat::Jpeg => encode_mozjpeg_to(image, options.target_quality(img_format), options.jpeg_restart_interval,
+                                               options.jpeg_optimize_scans.unwrap_or(true), options.jpeg_trellis.unwrap_or(true), writer),
+        ImageFormat::Pnm => encode_pnm_to(image, options.pnm_subtype, options.pnm_plain.unwrap_or(false), writer),
+        ImageFormat::Farbfeld => encode_farbfeld_to(image, writer),
+        #[cfg(feature = "heif")]
+        ImageFormat::Heif => heif::encode_heif_to(image, encode_lossless, encode_quality, writer),
+        _ => Err(Error::from_string("Unsupported image format".to_string())),
+    }
+}
+
+/// The pixels the encoder of `img_format` receives for `image`: cropped, with the alpha options of webp and avif applied
+pub fn encoder_pixels<'a>(image: &'a DynamicImage, img_format: &ImageFormat, options: &EncoderConfig) -> Result<Cow<'a, DynamicImage>, Error> {
+    let cropped = match options.crop {
+        Some(crop) => crop.apply(image)?,
+        None => Cow::Borrowed(image),
+    };
+    Ok(match cropped {
+        Cow::Borrowed(image) => prepare_alpha(image, img_format, options),
+        Cow::Owned(image) => {
+            let prepared = match prepare_alpha(&image, img_format, options) {
+                Cow::Owned(prepared) => Some(prepared),
+                Cow::Borrowed(_) => None,
+            };
+            Cow::Owned(prepared.unwrap_or(image))
+        }
+    })
+}
+
+// applies the alpha options of webp and avif outputs
+fn prepare_alpha<'a>(image: &'a DynamicImage, img_format: &ImageFormat, options: &EncoderConfig) -> Cow<'a, DynamicImage> {
+    let threshold = options.alpha_threshold.unwrap_or(0);
+    let premultiply = options.premultiply_alpha.unwrap_or(false);
+    let applies = matches!(img_format, ImageFormat::Webp | ImageFormat::WebpImage | ImageFormat::Avif);
+    if !applies || !image.color().has_alpha() || (threshold == 0 && !premultiply) {
+        return Cow::Borrowed(image);
+    }
+    let mut rgba = image.to_rgba8();
+    for pixel in rgba.pixels_mut() {
+        if pixel[3] < threshold {
+            pixel[3] = 0;
+        }
+        // fully transparent colors are left to the encoders (cleaned or predicted)
+        let alpha = pixel[3] as u32;
+        if premultiply && alpha > 0 && alpha < 255 {
+            for channel in &mut pixel.0[..3] {
+                // colors that are equal after premultiplication are displayed identically
+                let premultiplied = (*channel as u32 * alpha + 127) / 255;
+                *channel = ((premultiplied * 255 + alpha / 2) / alpha).min(255) as u8;
+            }
+        }
+    }
+    Cow::Owned(DynamicImage::ImageRgba8(rgba))
+}
+
+/// Name of the package providing the encoder of `img_format`
+pub fn encoder_package(img_format: &ImageFormat) -> &'static str {
+    match img_format {
+        ImageFormat::Webp => "webp",
+        ImageFormat::Avif => "ravif",
+        ImageFormat::Jpeg => "mozjpeg-sys",
+        ImageFormat::Heif => "libheif-rs",
+        _ => "image",
+    }
+}
+
+/// Packages whose versions determine the encoded bytes of `img_format` (for reproducibility records)
+pub fn encoder_libraries(img_format: &ImageFormat) -> &'static [&'static str] {
+    match img_format {
+        ImageFormat::Webp => &["webp", "libwebp-sys"],
+        ImageFormat::WebpImage => &["image", "image-webp"],
+        ImageFormat::Avif => &["ravif", "rav1e"],
+        ImageFormat::Png => &["image", "png", "color_quant"],
+        ImageFormat::Jpeg => &["mozjpeg-sys"],
+        ImageFormat::Pnm | ImageFormat::Farbfeld => &["image"],
+        // the encoder itself is the system libheif
+        ImageFormat::Heif => &["libheif-rs", "libheif-sys"],
+        _ => &[],
+    }
+}
+
+/// Version of the (newest) compiled-in package `name`
+pub fn dependency_version(name: &str) -> Option<&'static str> {
+    // we might have multiple versions of the package, use rfind to find the newest one
+    DEPENDENCIES.iter().rfind(|&&(n, _)| n == name).map(|&(_, version)| version)
+}
+
+thread_local! {
+    // set while a worker runs a file task, panics are then reported per file instead of by the default hook
+    static IN_FILE_TASK: Cell<bool> = const { Cell::new(false) };
+    // panics of the running file task, including the ones caught by the decoder fallbacks
+    static FILE_TASK_PANICS: RefCell<Vec<PanicReport>> = const { RefCell::new(Vec::new()) };
+    // raised once --per-file-timeout abandoned the file task of the thread, which then writes and records nothing more
+    static FILE_TASK_ABANDONED: RefCell<Option<Arc<AtomicBool>>> = const { RefCell::new(None) };
+}
+
+// fails the file task if it was abandoned, checked before each output is written or recorded
+fn check_abandoned() -> Result<(), Error> {
+    let abandoned = FILE_TASK_ABANDONED.with_borrow(|abandoned| abandoned.as_ref().is_some_and(|flag| flag.load(Ordering::SeqCst)));
+    if abandoned {
+        return Err(Error::from_string("abandoned after --per-file-timeout".to_string()));
+    }
+    Ok(())
+}
+
+fn install_file_task_panic_hook() {
+    static HOOK: Once = Once::new();
+    HOOK.call_once(|| {
+        let default_hook = panic::take_hook();
+        panic::set_hook(Box::new(move |info| {
+            if IN_FILE_TASK.with(|t| t.get()) {
+                // keep message, location and backtrace for the panic log, printing here would garble the progress bar
+                let report = PanicReport {
+                    message: info.to_string().replace('\n', " "),
+                    backtrace: Backtrace::force_capture().to_string(),
+                };
+                FILE_TASK_PANICS.with_borrow_mut(|panics| panics.push(report));
+            } else {
+                default_hook(info);
+            }
+        }));
+    });
 }
 
-fn handle_conversion_error(path: PathBuf, err: Box<dyn StdError + Send + Sync>) -> (i32, i32, i32) {
-    // carriage return and clear line contents (do not spam screen content with logger bar states)
-    println!("\r\x1b[2KFile {}: could not be converted, error: {}", path.display() , err);
-    (-2, 0, 0)
+// result of a per-file task and the panics raised while it ran
+struct FileTask<T> {
+    result: Result<T, Box<dyn StdError + Send + Sync>>,
+    panics: Vec<PanicReport>,
+    // set if a panic ended the task (the others were caught by the decoder fallbacks)
+    panicked: bool,
 }
 
-fn base_from_pattern(pattern: &str) -> String {
+/// Runs a per-file task, converting a panic (in decoders/encoders) into an error for that file
+fn run_file_task<T>(task: impl FnOnce() -> Result<T, Box<dyn StdError + Send + Sync>>) -> FileTask<T> {
+    IN_FILE_TASK.with(|t| t.set(true));
+    let result = panic::catch_unwind(AssertUnwindSafe(task));
+    IN_FILE_TASK.with(|t| t.set(false));
+    let panics = FILE_TASK_PANICS.with_borrow_mut(std::mem::take);
+    match result {
+        Ok(result) => FileTask { result, panics, panicked: false },
+        Err(_) => {
+            let msg = panics.last().map_or("unknown panic", |panic| panic.message.as_str());
+            let result = Err(Box::new(Error::from_string(format!("worker {}", msg))) as Box<dyn StdError + Send + Sync>);
+            FileTask { result, panics, panicked: true }
+        }
+    }
+}
+
+/// Runs a per-file task on its own thread, abandoning it after `timeout`
+fn run_file_task_with_timeout<T: Send + 'static>(
+    timeout: Duration,
+    task: impl FnOnce() -> Result<T, Box<dyn StdError + Send + Sync>> + Send + 'static,
+) -> FileTask<T> {
+    let (tx, rx) = mpsc::channel();
+    let abandoned = Arc::new(AtomicBool::new(false));
+    let flag = abandoned.clone();
+    let spawned = std::thread::Builder::new()
+        .name("imgc-file-task".to_string())
+        .spawn(move || {
+            FILE_TASK_ABANDONED.with_borrow_mut(|abandoned| *abandoned = Some(flag));
+            // the receiver is gone if the task was abandoned
+            let _ = tx.send(run_file_task(task));
+        });
+    let error = match spawned.map(|_| rx.recv_timeout(timeout)) {
+        Ok(Ok(task)) => return task,
+        Ok(Err(mpsc::RecvTimeoutError::Timeout)) => {
+            // the task keeps running, its outputs and records would belong to a failed file
+            abandoned.store(true, Ordering::SeqCst);
+            format!("timed out after {}, abandoned (--per-file-timeout)", HumanDuration(timeout))
+        }
+        Ok(Err(mpsc::RecvTimeoutError::Disconnected)) => "worker thread ended without a result".to_string(),
+        Err(err) => format!("cannot start the worker thread: {}", err),
+    };
+    FileTask { result: Err(Box::new(Error::from_string(error))), panics: Vec::new(), panicked: false }
+}
+
+/// Logs the panics raised while converting `path` and applies the --on-panic policy, returning the result of the file
+fn handle_panics<T>(conf: &CommonConfig, records: &RunRecords, multi: &MultiProgress, path: &Path, task: FileTask<T>)
+    -> Result<T, Box<dyn StdError + Send + Sync>> {
+    if task.panics.is_empty() {
+        return task.result;
+    }
+    records.panics.fetch_add(task.panics.len(), Ordering::SeqCst);
+    let log = conf.panic_log.as_deref().unwrap_or(DEFAULT_PANIC_LOG);
+    if let Err(err) = append_panic_log(Path::new(log), path, &task.panics) {
+        multi.suspend(|| print_message(conf, &tr!("run-panic-log-failed", path = path.display().to_string(), log = log, error = err.to_string())));
+    }
+    match conf.on_panic {
+        // panics caught by a decoder fallback stop the run as well, they are bugs all the same
+        PanicPolicy::Abort => {
+            records.panic_abort.lock().unwrap_or_else(|e| e.into_inner()).get_or_insert_with(|| path.to_path_buf());
+            task.result
+        }
+        // inputs no decoder could read are quarantined by the conversion already
+        PanicPolicy::Quarantine if task.panicked => {
+            let panic = &task.panics[task.panics.len() - 1];
+            let error = format!("{}\nbacktrace:\n{}", panic.message, panic.backtrace);
+            match (task.result, quarantine_file(conf, records, path, &error)) {
+                (Err(err), Some(note)) => Err(Box::new(Error::from_string(format!("{} ({})", err, note)))),
+                (result, _) => result,
+            }
+        }
+        PanicPolicy::Skip | PanicPolicy::Quarantine => task.result,
+    }
+}
+
+fn handle_conversion_error(conf: &CommonConfig, multi: &MultiProgress, path: &Path, err: Box<dyn StdError + Send + Sync>) -> ConversionOutcome {
+    // hide the progress bars while printing (do not spam screen content with logger bar states)
+    multi.suspend(|| print_message(conf, &tr!("run-file-failed", path = path.display().to_string(), error = err.to_string())));
+    ConversionOutcome::Failed { error: err.to_string() }
+}
+
+pub(crate) fn base_from_pattern(pattern: &str) -> String {
     let mut base = PathBuf::new();
 
     for part in Path::new(pattern) {
@@ -87,96 +1018,505 @@ fn base_from_pattern(pattern: &str) -> String {
     base.to_string_lossy().to_string()
 }
 
+// raised by Ctrl+C, shared by all runs of the process (watch mode converts many batches)
+static STOP_SIGNAL: OnceLock<Arc<AtomicBool>> = OnceLock::new();
+
+/// Flag raised by Ctrl+C to stop processing further files, the handler is installed by the first call
+pub(crate) fn stop_signal() -> Arc<AtomicBool> {
+    STOP_SIGNAL.get_or_init(|| {
+        let global_stop = Arc::new(AtomicBool::new(false));
+        // applications embedding the library handle signals themselves
+        #[cfg(feature = "cli")]
+        {
+            let global_stop = global_stop.clone();
+            let mut ctrlc_counter = 0;
+            ctrlc::set_handler(move || {
+                if !global_stop.load(std::sync::atomic::Ordering::Relaxed) {
+                    eprintln!("{}", tr!("run-ctrlc"));
+                    global_stop.store(true, std::sync::atomic::Ordering::Relaxed);
+                } else {
+                    eprintln!("{}", tr!("run-ctrlc-again", marks = str::repeat("!", ctrlc_counter)));
+                }
+                ctrlc_counter += 1;
+            }).expect("Error setting Ctrl-C handler");
+        }
+        global_stop
+    }).clone()
+}
+
+// messages of a run, on stderr with --summary-json to keep stdout to the json summary
+fn print_message(conf: &CommonConfig, message: &str) {
+    if conf.summary_json {
+        eprintln!("{}", message);
+    } else {
+        println!("{}", message);
+    }
+}
+
 /// Processes and encodes images in a given directory to the specified image format.
+///
+/// The run is planned with [`plan_conversion`], inputs with existing outputs are set aside
+/// ([`Plan::set_aside_up_to_date`]) and the rest is executed with [`apply_plan`].
 pub fn convert_images(
     conf: CommonConfig,
     img_format: &ImageFormat,
-    option_lossless: &Option<bool>,
-    option_quality: &Option<f32>,
-    option_speed: &Option<u8>,
-    option_png_compression_type: &Option<CompressionType>,
-    option_png_filter_type: &Option<FilterType>,
-    option_avif_bit_depth: &Option<BitDepth>,
-    option_avif_color_model: &Option<ColorModel>,
-    option_avif_alpha_color_mode: &Option<AlphaColorMode>,
-    option_avif_alpha_quality: &Option<f32>,
+    options: &EncoderConfig,
 ) -> Result<(), Error> {
-    let mut paths: Vec<PathBuf> = glob::glob(&*conf.pattern)?
-        .filter_map(|entry| entry.ok())
-        .filter(|path|{
-            let format = ImageFormat::from(path.as_path());
-            format != ImageFormat::Unknown
-                && format != ImageFormat::Avif // disable reading avif (FIXME: re-enable with reliable build+integration for reader)
-        })
-        .collect();
-    // sort paths lexicographically, not only filenames
-    paths.sort_by(|a, b| {
-        let dir_cmp = a.parent().cmp(&b.parent());
-        let cmp = if dir_cmp != std::cmp::Ordering::Equal {
-            dir_cmp
-        } else {
-            a.file_name().cmp(&b.file_name())
-        };
+    let mut plan = plan_conversion(&conf, img_format, options)?;
+    plan.set_aside_up_to_date(&conf);
+    apply_plan(conf, &plan)
+}
 
-        if conf.reverse_processing_order {
-            cmp.reverse()
-        } else {
-            cmp
-        }
-    });
-    // TODO: check for collision candidates (same filename but different extensions => same encoded output filename format...)
-    //  and come up with a solution
+/// Converts the files of a plan.
+///
+/// Inputs, outputs and encoder settings come from the plan,
+/// the overwrite, discard, naming and record options from `conf`.
+pub fn apply_plan(conf: CommonConfig, plan: &Plan) -> Result<(), Error> {
+    let conf = CommonConfig {
+        pattern: plan.pattern.clone(),
+        output: plan.output.clone(),
+        mirror_outputs: plan.mirror_outputs.clone(),
+        extract_frames: plan.extract_frames,
+        poster_at: plan.poster_at.clone(),
+        ..conf
+    };
+    let img_format = &plan.format;
+    let options = &plan.settings;
     let pattern_base = base_from_pattern(&conf.pattern);
 
-    if paths.is_empty() {
-        println!("No images to convert, check input glob pattern and supported input formats.");
+    for collision in &plan.collisions {
+        eprintln!("{}", tr!("run-collision", output = collision.output.display().to_string(),
+                            inputs = collision.inputs.iter().map(|input| input.display().to_string()).collect::<Vec<_>>().join(", ")));
+    }
+    if !plan.skipped.is_empty() {
+        print_message(&conf, &tr!("run-skipping-sidecar", count = plan.skipped.len()));
+    }
+    if !plan.up_to_date.is_empty() {
+        print_message(&conf, &tr!("run-skipping-up-to-date", count = plan.up_to_date.len()));
+    }
+    if !plan.unconvertible.is_empty() {
+        print_message(&conf, &tr!("run-skipping-unconvertible",
+                           count = plan.unconvertible.len(),
+                           zero = plan.count_unconvertible(InputProblem::ZeroByte),
+                           magic = plan.count_unconvertible(InputProblem::UnknownMagic),
+                           unsupported = plan.count_unconvertible(InputProblem::UnsupportedFormat),
+                           disabled = plan.count_unconvertible(InputProblem::DisabledDecoder)));
+        if let Some(listing) = &conf.list_unconvertible {
+            plan.write_unconvertible(Path::new(listing))?;
+            print_message(&conf, &tr!("run-unconvertible-listed", path = listing.as_str()));
+        }
+    }
+    if plan.files.is_empty() && plan.up_to_date.is_empty() {
+        print_message(&conf, &tr!("run-no-images"));
+        if conf.summary_json {
+            println!("{}", RunSummary { unconvertible: plan.unconvertible.len(), ..Default::default() }.to_json());
+        }
+        return Ok(());
+    }
+    if conf.confirm && !confirm_run(&conf, plan)? {
+        print_message(&conf, &tr!("run-aborted"));
         return Ok(());
     }
 
-    // create output directory if it does not exist
-    if ! conf.output.is_empty() {
-        let output_directory = Path::new(&conf.output);
+    // create output directories if they do not exist
+    for output in std::iter::once(&conf.output).chain(&conf.mirror_outputs).filter(|output| !output.is_empty()) {
+        let output_directory = Path::new(output);
         if ! fs::exists(output_directory)? {
             // is it possible to warn in docker if the target output directory is not host mounted?
-            println!("Creating output directory \"{:?}\"", output_directory);
+            print_message(&conf, &tr!("run-creating-output", path = output_directory.display().to_string()));
             fs::create_dir_all(output_directory).unwrap_or_else(|err| {
-                eprintln!("Error creating the output directory: {err}");
+                eprintln!("{}", tr!("run-output-dir-failed", error = err.to_string()));
                 std::process::exit(1);
             });
         }
     }
     // IDEA: create output filename from configurable regex
+    plan.check_writable()?;
 
-    println!("Converting {} files...", paths.len());
+    print_message(&conf, &tr!("run-converting", count = plan.files.len()));
+    if let Some(seed) = plan.order_seed {
+        print_message(&conf, &tr!("run-random-order", seed = seed.to_string()));
+    }
     let encoder_data = match img_format {
-        ImageFormat::Webp => webp::encoder_info(option_lossless.unwrap_or(false), option_quality.unwrap_or(90.)),
+        ImageFormat::Webp => webp::encoder_info(options.lossless.unwrap_or(false), options.target_quality(img_format).unwrap_or(90.),
+                                                options.webp_near_lossless),
         ImageFormat::WebpImage => webp_image::encoder_info(),
-        ImageFormat::Avif => avif::encoder_info(option_quality.unwrap_or(90.), option_speed.unwrap_or(3), None, None),
+        ImageFormat::Avif => avif::encoder_info(options.target_quality(img_format).unwrap_or(90.), options.target_speed().unwrap_or(3), None, None),
         ImageFormat::Png => png::encoder_info(),
-        ImageFormat::Jpeg => mozjpeg::encoder_info(),
+        ImageFormat::Jpeg => mozjpeg::encoder_info(options.target_quality(img_format), options.jpeg_restart_interval,
+                                                   options.jpeg_optimize_scans.unwrap_or(true), options.jpeg_trellis.unwrap_or(true)),
+        ImageFormat::Pnm => pnm::encoder_info(options.pnm_subtype, options.pnm_plain.unwrap_or(false)),
+        ImageFormat::Farbfeld => farbfeld::encoder_info(),
+        #[cfg(feature = "heif")]
+        ImageFormat::Heif => heif::encoder_info(options.lossless.unwrap_or(false), options.target_quality(img_format).unwrap_or(90.)),
         _ => "unknown encoder".parse().unwrap(),
     };
-    println!("{}", encoder_data);
-
-    let global_stop = Arc::new(AtomicBool::new(false));
-    let stop_signal = global_stop.clone();
-    let mut ctrlc_counter = 0;
-    ctrlc::set_handler(move || {
-        if !global_stop.load(std::sync::atomic::Ordering::Relaxed) {
-            println!("received Ctrl+C, stopping further queue processing!");
-            global_stop.store(true, std::sync::atomic::Ordering::Relaxed);
-        } else {
-            println!("an encoding task is still active!{} processing will end afterwards.", str::repeat("!", ctrlc_counter));
+    print_message(&conf, &encoder_data.to_string());
+    for input_override in &options.input_overrides {
+        print_message(&conf, &tr!("run-with-override", override = input_override.to_string()));
+    }
+    if !writes_metadata(img_format) && (!options.metadata.is_empty() || options.keeps_metadata()) {
+        print_message(&conf, &tr!("run-metadata-unsupported", format = img_format.extension()));
+    }
+
+    install_file_task_panic_hook();
+    let stop_signal = stop_signal();
+
+    // inputs skipped while planning count like the ones skipped by the workers
+    let mut aggregate = RunAggregate::default();
+    for input in &plan.up_to_date {
+        let outcome = ConversionOutcome::Skipped {
+            reason: existing_reason(&conf), input_size: input.input_size, output_size: input.output_size,
+        };
+        aggregate.record(&input.path, false, &outcome);
+        if let Some(on_file_done) = &conf.on_file_done {
+            on_file_done(&input.path, &outcome);
+        }
+    }
+    let aggregate = Mutex::new(aggregate);
+    // inputs without new output and why, for --explain-skips
+    let explained_skips: ExplainedSkips = Mutex::new(Vec::new());
+    // shared with the threads of --per-file-timeout, which may outlive their file task
+    let records = Arc::new(RunRecords {
+        manifest: (conf.manifest.is_some() || conf.checksums.is_some()).then(|| Mutex::new(Vec::new())),
+        hash_map: conf.hash_map.as_ref().map(|_| Mutex::new(BTreeMap::new())),
+        dedupe: conf.dedupe_outputs.map(|_| Mutex::new(HashMap::new())),
+        undo: conf.undo_script.as_ref().map(|_| Mutex::new(Vec::new())),
+        stage: conf.stage_dir.as_ref().map(|directory| Stage::start(Path::new(directory), conf.stage_transfers, conf.fsync)).transpose()?,
+        ..Default::default()
+    });
+    let format_option_binary_two_nospace = FormatSizeOptions::from(BINARY)
+        .decimal_places(2).decimal_zeroes(2).space_after_value(false);
+    if conf.profile.is_some() {
+        start_profile();
+    }
+    let run_started = Instant::now();
+    let order: Vec<usize> = (0..plan.files.len()).collect();
+    let explained = conf.explain_skips.then_some(&explained_skips);
+    // files re-encoded in the second pass of --time-budget, the bytes this saved and the files left at low effort
+    let mut refinement = None;
+    match conf.time_budget {
+        None => {
+            run_files(&conf, plan, &order, None, &records, &aggregate, explained, &stop_signal, false);
+        }
+        Some(budget) => {
+            print_message(&conf, &tr!("run-pass-fast"));
+            let fast_plan = Plan {
+                settings: plan.settings.fast_effort(img_format),
+                files: plan.files.iter()
+                    .map(|file| PlannedFile {
+                        settings: file.settings.as_ref().map(|settings| settings.fast_effort(img_format)),
+                        ..file.clone()
+                    })
+                    .collect(),
+                ..plan.clone()
+            };
+            let outcomes = run_files(&conf, &fast_plan, &order, None, &records, &aggregate, explained, &stop_signal, true);
+
+            // the preferred (--prefer), then the most compressible inputs (largest savings of the fast pass) first,
+            // replaced inputs are final already and inputs without effort setting would only be encoded the same way again
+            let mut candidates: Vec<(usize, usize, usize)> = outcomes.iter()
+                .filter_map(|(index, outcome)| match *outcome {
+                    ConversionOutcome::Converted { input_size, output_size, .. } => Some((*index, output_size, input_size.saturating_sub(output_size))),
+                    _ => None,
+                })
+                .filter(|&(index, _, _)| {
+                    let file = &plan.files[index];
+                    !file.replace_input && serde_json::to_value(plan.settings_of(file)).ok()
+                        != serde_json::to_value(fast_plan.settings_of(&fast_plan.files[index])).ok()
+                })
+                .collect();
+            candidates.sort_by_key(|&(index, _, saved)| std::cmp::Reverse((weight_of(&conf.priorities, &plan.files[index].input), saved)));
+            // budgets beyond the range of Instant never run out
+            let deadline = run_started.checked_add(budget);
+            let left = deadline.map_or(budget, |deadline| deadline.saturating_duration_since(Instant::now()));
+            print_message(&conf, &tr!("run-pass-refine", count = candidates.len(), duration = HumanDuration(left).to_string()));
+            let refine_conf = CommonConfig { overwrite_existing: false, overwrite_if_smaller: true, ..conf.clone() };
+            let refine_order: Vec<usize> = candidates.iter().map(|&(index, _, _)| index).collect();
+            let refine_outcomes = run_files(&refine_conf, plan, &refine_order, deadline, &records,
+                                            &Mutex::new(RunAggregate::default()), None, &stop_signal, true);
+            let fast_sizes: HashMap<usize, usize> = candidates.iter().map(|&(index, output_size, _)| (index, output_size)).collect();
+            let (mut refined, mut saved, mut left) = (0, 0, 0);
+            let mut aggregate = aggregate.lock().unwrap_or_else(|e| e.into_inner());
+            for (index, outcome) in refine_outcomes {
+                match outcome {
+                    ConversionOutcome::Converted { output_size, .. } => {
+                        refined += 1;
+                        saved += fast_sizes[&index].saturating_sub(output_size);
+                        // the refined output replaced the one of the fast pass
+                        let file = &plan.files[index];
+                        aggregate.record_smaller_output(&file.input, file.replace_input && conf.backup_ext.is_none(),
+                                                        fast_sizes[&index], output_size);
+                    }
+                    ConversionOutcome::Skipped { reason: SkipReason::TimeBudgetUsedUp, .. } | ConversionOutcome::Aborted => left += 1,
+                    _ => {}
+                }
+            }
+            refinement = Some((refined, saved, left));
+        }
+    }
+    // the transfers of the last outputs are waited for, the records below list outputs at their destination
+    if let (Some(stage), Some(stage_dir)) = (&records.stage, &conf.stage_dir) {
+        let (transferred, failures) = stage.finish();
+        print_message(&conf, &tr!("run-stage-transferred", count = transferred, path = stage_dir.as_str()));
+        for (transfer, err) in &failures {
+            print_message(&conf, &tr!("run-transfer-failed", path = transfer.staged.display().to_string(),
+                                          output = transfer.output.display().to_string(), error = err.to_string()));
+        }
+        // their inputs are counted as failed in the summary
+        drop_lost_outputs(&records, &aggregate, &failures);
+    }
+    if let Some(profile_path) = &conf.profile {
+        let spans = write_profile(Path::new(profile_path))?;
+        print_message(&conf, &tr!("run-profile-written", count = spans, path = profile_path.as_str()));
+    }
+    if records.panics.load(Ordering::Relaxed) > 0 {
+        print_message(&conf, &tr!("run-panics-logged", count = records.panics.load(Ordering::Relaxed),
+                                  path = conf.panic_log.as_deref().unwrap_or(DEFAULT_PANIC_LOG)));
+    }
+    if let (Some(hash_map_path), Some(hash_map)) = (&conf.hash_map, &records.hash_map) {
+        let hash_map = std::mem::take(&mut *hash_map.lock().unwrap_or_else(|e| e.into_inner()));
+        fs::write(hash_map_path, serde_json::to_string_pretty(&hash_map)?)?;
+        print_message(&conf, &tr!("run-hash-map-written", count = hash_map.len(), path = hash_map_path.as_str()));
+    }
+    if let Some(entries) = &records.manifest {
+        let mut entries = std::mem::take(&mut *entries.lock().unwrap_or_else(|e| e.into_inner()));
+        if refinement.is_some() {
+            // re-encoded outputs replace their entries of the fast pass
+            let mut seen = std::collections::HashSet::new();
+            entries.reverse();
+            entries.retain(|entry| seen.insert(entry.output.clone()));
+            entries.reverse();
+        }
+        let manifest = Manifest::new(entries);
+        if let Some(manifest_path) = &conf.manifest {
+            manifest.write(Path::new(manifest_path))?;
+            print_message(&conf, &tr!("run-manifest-written", count = manifest.files.len(), path = manifest_path.as_str()));
+        }
+        if let Some(algorithm) = conf.checksums {
+            let directory = if conf.output.is_empty() { pattern_base.as_str() } else { conf.output.as_str() };
+            let directory = if directory.is_empty() { Path::new(".") } else { Path::new(directory) };
+            let sums_path = write_checksums(algorithm, directory, &manifest.files)?;
+            print_message(&conf, &tr!("run-checksums-written", count = manifest.files.len(), path = sums_path.display().to_string()));
+        }
+    }
+    if let (Some(undo), Some(undo_path)) = (&records.undo, &conf.undo_script) {
+        let steps = std::mem::take(&mut *undo.lock().unwrap_or_else(|e| e.into_inner()));
+        write_undo_script(Path::new(undo_path), &steps)?;
+        print_message(&conf, &tr!("run-undo-written", count = steps.len(), path = undo_path.as_str()));
+    }
+    // the records of the converted files are written, the summary of a stopped run is not
+    if let Some(input) = records.panic_abort.lock().unwrap_or_else(|e| e.into_inner()).take() {
+        return Err(Error::from_string(format!("stopped after a panic converting {} (--on-panic abort), see {}",
+                                              input.display(), conf.panic_log.as_deref().unwrap_or(DEFAULT_PANIC_LOG))));
+    }
+    // \r\x1b[2K is the sequence to clear the current row content (if manual way is intended)
+    let aggregate = aggregate.into_inner().unwrap_or_else(|e| e.into_inner());
+    let stats = aggregate.statistics;
+    // animated inputs handled by --animated, --frames selects the frames of all of them instead
+    let animated_inputs: Vec<&Path> = plan.files.iter()
+        .filter(|file| file.animated && plan.settings_of(file).frames.is_none())
+        .map(|file| file.input.as_path())
+        .collect();
+    let glyphs = output::glyphs();
+    if conf.explain_skips {
+        let mut explained_skips = explained_skips.into_inner().unwrap_or_else(|e| e.into_inner());
+        explained_skips.extend(plan.skipped.iter().map(|path| (path.clone(), tr!("explain-sidecar-skip"))));
+        explained_skips.extend(plan.up_to_date.iter().map(|input| (input.path.clone(), existing_reason(&conf).description())));
+        explained_skips.extend(plan.unconvertible.iter()
+            .map(|input| (input.path.clone(), tr!("explain-unconvertible", problem = input.problem.name()))));
+        explained_skips.sort();
+        if !explained_skips.is_empty() {
+            print_message(&conf, &tr!("summary-not-converted"));
+            for (path, explanation) in &explained_skips {
+                print_message(&conf, &format!("  {}: {}", path.display(), explanation));
+            }
+        }
+    }
+    let summary = RunSummary {
+        statistics: stats.clone(),
+        elapsed: run_started.elapsed(),
+        inputs: plan.files.len() + plan.up_to_date.len(),
+        unconvertible: plan.unconvertible.len(),
+        refined: refinement.map_or(0, |(refined, _, _)| refined),
+        repaired: records.repaired.lock().unwrap_or_else(|e| e.into_inner()).len(),
+        fallbacks: records.fallbacks.lock().unwrap_or_else(|e| e.into_inner()).len(),
+        routed: records.routed.lock().unwrap_or_else(|e| e.into_inner()).values().sum(),
+        animated: animated_inputs.len(),
+        quarantined: records.quarantined.load(Ordering::Relaxed),
+        deduplicated: records.deduped_files.load(Ordering::Relaxed),
+        reclaimed_size: aggregate.reclaimed_size,
+        potential_savings: aggregate.potential_savings,
+        by_extension: aggregate.by_extension,
+    };
+    if conf.summary_json {
+        println!("{}", summary.to_json());
+        return Ok(());
+    }
+    if let Some(template) = &conf.summary_template {
+        println!("{}", summary.render(template));
+        return Ok(());
+    }
+    println!("{}", tr!("summary-title"));
+    println!("{}", tr!("summary-time", duration = HumanDuration(run_started.elapsed()).to_string()));
+    println!("{}", tr!("summary-inputs", count = summary.inputs));
+    println!("{}", tr!("summary-successful", count = stats.converted));
+    println!("{}", tr!("summary-skipped", count = stats.skipped));
+    println!("{}", tr!("summary-errors", count = stats.failed));
+    if stats.aborted > 0 {
+        println!("{}", tr!("summary-aborted", count = stats.aborted));
+    }
+    if let Some((refined, saved, left)) = refinement {
+        println!("{}", tr!("summary-refined", count = refined, saved = format_size(saved, format_option_binary_two_nospace), left = left));
+    }
+    if stats.encoded() > 0 {
+        println!("{}", tr!("summary-encode-time",
+                           duration = HumanDuration(stats.encode_time / stats.encoded() as u32).to_string()));
+    }
+    if !plan.unconvertible.is_empty() {
+        println!("{}", tr!("summary-unconvertible",
+                           count = plan.unconvertible.len(),
+                           zero = plan.count_unconvertible(InputProblem::ZeroByte),
+                           magic = plan.count_unconvertible(InputProblem::UnknownMagic),
+                           unsupported = plan.count_unconvertible(InputProblem::UnsupportedFormat),
+                           disabled = plan.count_unconvertible(InputProblem::DisabledDecoder)));
+    }
+    if stats.rejected > 0 {
+        println!("{}", tr!("summary-rejected", count = stats.rejected));
+    }
+    let repaired = records.repaired.lock().unwrap_or_else(|e| e.into_inner());
+    if !repaired.is_empty() {
+        println!("{}", tr!("summary-repaired", count = repaired.len()));
+        for path in repaired.iter() {
+            println!("  {}", path.display());
+        }
+    }
+    drop(repaired);
+    let fallbacks = records.fallbacks.lock().unwrap_or_else(|e| e.into_inner());
+    if !fallbacks.is_empty() {
+        println!("{}", tr!("summary-fallbacks", count = fallbacks.len()));
+        for (path, format) in fallbacks.iter() {
+            println!("  {} ({})", path.display(), format.extension());
+        }
+    }
+    drop(fallbacks);
+    let routed = records.routed.lock().unwrap_or_else(|e| e.into_inner());
+    if !routed.is_empty() {
+        let formats: Vec<String> = routed.iter().map(|(ext, count)| format!("{} {}", ext, count)).collect();
+        println!("{}", tr!("summary-routed", count = routed.values().sum::<usize>(), formats = formats.join(", ")));
+    }
+    drop(routed);
+    if !animated_inputs.is_empty() {
+        let mode = match conf.animated {
+            AnimatedInputs::First => "first",
+            AnimatedInputs::Animate => "animate",
+            AnimatedInputs::Copy => "copy",
+        };
+        println!("{}", tr!("summary-animated", count = animated_inputs.len(), mode = mode));
+        for path in &animated_inputs {
+            println!("  {}", path.display());
         }
-        ctrlc_counter += 1;
-    }).expect("Error setting Ctrl-C handler");
+    }
+    if records.quarantined.load(Ordering::Relaxed) > 0 {
+        println!("{}", tr!("summary-quarantined", count = records.quarantined.load(Ordering::Relaxed),
+                           mode = if conf.quarantine_mode == QuarantineMode::Move { "moved" } else { "copied" },
+                           path = conf.quarantine_dir.as_deref().unwrap_or_default()));
+    }
+    if records.deduped_files.load(Ordering::Relaxed) > 0 {
+        println!("{}", tr!("summary-deduplicated",
+                           count = records.deduped_files.load(Ordering::Relaxed),
+                           saved = format_size(records.deduped_bytes.load(Ordering::Relaxed), format_option_binary_two_nospace)));
+    }
+    if conf.discard_if_larger_than_input && stats.discarded > 0 {
+        println!("{}", tr!("summary-discarded",
+                           count = stats.discarded,
+                           input = format_size(stats.discarded_input_size, format_option_binary_two_nospace),
+                           arrow = glyphs.arrow,
+                           output = format_size(stats.discarded_output_size, format_option_binary_two_nospace)));
+        println!("{}", tr!("summary-discarded-note"))
+    }
+    if let Some(min_savings) = conf.min_savings && stats.not_worth_it > 0 {
+        println!("{}", tr!("summary-not-worth-it",
+                           count = stats.not_worth_it,
+                           percent = min_savings,
+                           input = format_size(stats.not_worth_it_input_size, format_option_binary_two_nospace),
+                           arrow = glyphs.arrow,
+                           output = format_size(stats.not_worth_it_output_size, format_option_binary_two_nospace)));
+    }
+    if stats.input_size > 0 && stats.output_size > 0 {
+        // show total stats
+        println!("{}", tr!("summary-total-input", size = format_size(stats.input_size, format_option_binary_two_nospace)));
+        println!("{}", tr!("summary-total-output", size = format_size(stats.output_size, format_option_binary_two_nospace)));
+        println!("{}", tr!("summary-total-ratio", ratio = format!("{:.02}", stats.output_size as f64 / stats.input_size as f64 * 100.0)));
+        if stats.preexisting_input_size > 0 && stats.preexisting_output_size > 0 {
+            if stats.input_size - stats.preexisting_input_size > 0 {
+                // if we have new encodes and preexisting images, first show the stats for the new encodes, then for the preexisting ones
+                println!("{}", tr!("summary-new-input", size = format_size(stats.input_size - stats.preexisting_input_size, format_option_binary_two_nospace)));
+                println!("{}", tr!("summary-new-output", size = format_size(stats.output_size - stats.preexisting_output_size, format_option_binary_two_nospace)));
+                println!("{}", tr!("summary-new-ratio", ratio = format!("{:.02}", (stats.output_size - stats.preexisting_output_size) as f64 / (stats.input_size - stats.preexisting_input_size) as f64 * 100.0)));
+            }
+            // if we have preexisting images, show these stats
+            println!("{}", tr!("summary-preexisting-input", size = format_size(stats.preexisting_input_size, format_option_binary_two_nospace)));
+            println!("{}", tr!("summary-preexisting-output", size = format_size(stats.preexisting_output_size, format_option_binary_two_nospace)));
+            println!("{}", tr!("summary-preexisting-ratio", ratio = format!("{:.02}", stats.preexisting_output_size as f64 / stats.preexisting_input_size as f64 * 100.0)));
+        }
+    } else {
+        if (stats.converted + stats.skipped + stats.failed) > 1 {
+            println!("{}", tr!("summary-sizes-unknown"));
+        }
+    }
+    if summary.reclaimed_size > 0 {
+        println!("{}", tr!("summary-reclaimed", size = format_size(summary.reclaimed_size, format_option_binary_two_nospace)));
+    }
+    if summary.potential_savings > 0 {
+        println!("{}", tr!("summary-potential-savings", size = format_size(summary.potential_savings, format_option_binary_two_nospace)));
+    }
+    if conf.by_extension && !summary.by_extension.is_empty() {
+        println!("{}", tr!("summary-by-extension"));
+        println!("{}", tr!("summary-by-extension-header"));
+        for (extension, stats) in &summary.by_extension {
+            println!("  {:<9} {:>7} {:>9} {:>7} {:>6} {:>11} {:>11} {:>11} {:>7}",
+                     if extension.is_empty() { "-" } else { extension },
+                     stats.files_done, stats.converted, stats.skipped, stats.failed,
+                     format_size(stats.input_size, format_option_binary_two_nospace),
+                     format_size(stats.output_size, format_option_binary_two_nospace),
+                     format_size(stats.input_size.saturating_sub(stats.output_size), format_option_binary_two_nospace),
+                     if stats.input_size > 0 { format!("{:.02}%", stats.output_size as f64 / stats.input_size as f64 * 100.0) } else { "-".to_string() });
+        }
+    }
+    Ok(())
+}
 
+/// Converts the files of `plan` at the indices in `order` in parallel, showing the progress
+///
+/// Files not started before the `deadline` are skipped. The outcomes are counted into the `aggregate`,
+/// the outcome of each converted index is only returned with `keep_outcomes` (empty otherwise).
+#[allow(clippy::too_many_arguments)]
+fn run_files(
+    conf: &CommonConfig,
+    plan: &Plan,
+    order: &[usize],
+    deadline: Option<Instant>,
+    records: &Arc<RunRecords>,
+    aggregate: &Mutex<RunAggregate>,
+    explained_skips: Option<&ExplainedSkips>,
+    stop_signal: &AtomicBool,
+    keep_outcomes: bool,
+) -> Vec<(usize, ConversionOutcome)> {
+    let img_format = &plan.format;
+    // pre-sum input sizes, the progress (and thereby the ETA) is weighted by bytes instead of file count
+    let sized_paths: Vec<(usize, u64)> = order.iter()
+        .map(|&index| {
+            (index, plan.files[index].input_size as u64)
+        })
+        .collect();
+    let input_bytes_total: u64 = sized_paths.iter().map(|(_, size)| size).sum();
 
-    let (tx, rx) = mpsc::channel::<PathBuf>();
-    let input_file_count = paths.len() as u64;
+    let (tx, rx) = mpsc::channel::<(usize, u64)>();
+    let input_file_count = sized_paths.len() as u64;
     // producer thread: feed paths in lexicographic order
     std::thread::spawn(move || {
-        for path in paths {
+        for path in sized_paths {
             if tx.send(path).is_err() {
                 break; // consumer dropped, exit
             }
@@ -185,199 +1525,204 @@ pub fn convert_images(
         drop(tx);
     });
 
-    let pb = ProgressBar::new(input_file_count);
-    let style = ProgressStyle::with_template("[{elapsed_precise}/~{duration_precise} ({eta_precise} rem.)] {wide_bar:.cyan/blue} {pos:>7}/{len:7} | {msg}").unwrap();
+    let multi = MultiProgress::new();
+    let pb = multi.add(ProgressBar::new(input_bytes_total));
+    let glyphs = output::glyphs();
+    // templates of the library are not validated like the --progress-template argument, invalid ones fall back to the default
+    let style = conf.progress_template.as_deref()
+        .and_then(|template| ProgressStyle::with_template(template).ok())
+        .unwrap_or_else(|| ProgressStyle::with_template(DEFAULT_PROGRESS_TEMPLATE).unwrap())
+        .progress_chars(glyphs.progress_chars);
     pb.set_style(style);
-    let encode_successful = Arc::new(AtomicUsize::new(0));
-    let encode_skipped = Arc::new(AtomicUsize::new(0));
-    let encode_discarded = Arc::new(AtomicUsize::new(0));
-    let encode_errors = Arc::new(AtomicUsize::new(0));
-    let size_input_total = Arc::new(AtomicUsize::new(0));
-    let size_output_total = Arc::new(AtomicUsize::new(0));
-    let size_input_preexisting = Arc::new(AtomicUsize::new(0));
-    let size_output_preexisting = Arc::new(AtomicUsize::new(0));
-    let size_input_discarded = Arc::new(AtomicUsize::new(0));
-    let size_output_discarded = Arc::new(AtomicUsize::new(0));
+    // one line per rayon worker with the file currently being processed, to spot pathological inputs
+    let worker_bars: Vec<ProgressBar> = if conf.hide_worker_progress {
+        Vec::new()
+    } else {
+        let worker_style = ProgressStyle::with_template("  {spinner} [{elapsed:>4}] {wide_msg}").unwrap()
+            .tick_chars(glyphs.spinner);
+        (0..rayon::current_num_threads()).map(|_| {
+            let bar = multi.add(ProgressBar::new_spinner());
+            bar.set_style(worker_style.clone());
+            bar.set_message(tr!("run-worker-idle"));
+            bar.enable_steady_tick(Duration::from_millis(250));
+            bar
+        }).collect()
+    };
     let format_option_binary_two_nospace = FormatSizeOptions::from(BINARY)
         .decimal_places(2).decimal_zeroes(2).space_after_value(false);
+    let progress_message = |stats: &RunStatistics| {
+        if stats.preexisting_input_size > 0 {
+            format!(
+                "{}/{} files | {} {} {} ({} {} {} preexisting) | {} {} {} {} {} {}",
+                stats.files_done, input_file_count,
+                format_size(stats.input_size, format_option_binary_two_nospace),
+                glyphs.arrow,
+                format_size(stats.output_size, format_option_binary_two_nospace),
+                format_size(stats.preexisting_input_size, format_option_binary_two_nospace),
+                glyphs.arrow,
+                format_size(stats.preexisting_output_size, format_option_binary_two_nospace),
+                glyphs.ok, stats.converted,
+                glyphs.skipped, stats.skipped,
+                glyphs.error, stats.failed
+            )
+        } else {
+            format!(
+                "{}/{} files | {} {} {} | {} {} {} {} {} {}",
+                stats.files_done, input_file_count,
+                format_size(stats.input_size, format_option_binary_two_nospace),
+                glyphs.arrow,
+                format_size(stats.output_size, format_option_binary_two_nospace),
+                glyphs.ok, stats.converted,
+                glyphs.skipped, stats.skipped,
+                glyphs.error, stats.failed
+            )
+        }
+    };
+    let batch = ProgressBatch::new();
+    let outcomes = Mutex::new(Vec::with_capacity(if keep_outcomes { order.len() } else { 0 }));
+    let keep = |index: usize, outcome: ConversionOutcome| if keep_outcomes {
+        outcomes.lock().unwrap_or_else(|e| e.into_inner()).push((index, outcome));
+    };
 
-    let _results: LinkedList<(isize, usize, usize)> = rx.into_iter()
+    rx.into_iter()
         .par_bridge()
-        .map(|path| {
-            let res = if stop_signal.load(std::sync::atomic::Ordering::Relaxed) {
-                return (-2, 0, 0);
+        .for_each(|(index, input_bytes)| {
+            let file = &plan.files[index];
+            let path = &file.input;
+            let worker_bar = rayon::current_thread_index().and_then(|i| worker_bars.get(i));
+            let outcome = if stop_signal.load(std::sync::atomic::Ordering::Relaxed) || records.panic_abort.lock().unwrap_or_else(|e| e.into_inner()).is_some() {
+                ConversionOutcome::Aborted
+            } else if deadline.is_some_and(|deadline| Instant::now() >= deadline) {
+                ConversionOutcome::Skipped { reason: SkipReason::TimeBudgetUsedUp, input_size: 0, output_size: 0 }
             } else {
-                convert_image(
-                    &*path, img_format,
-                    conf.output.clone(), pattern_base.clone(), conf.overwrite_if_smaller,
-                    conf.overwrite_existing, conf.discard_if_larger_than_input,
-                    option_lossless, option_quality, option_speed,
-                    option_png_compression_type, option_png_filter_type,
-                    option_avif_bit_depth, option_avif_color_model, option_avif_alpha_color_mode, option_avif_alpha_quality
-                )
-            }.map_err(|err| handle_conversion_error(path, err)).unwrap_or_else(|_| (-1, 0, 0));
-            pb.inc(1); // increment progress bar counter
-            match res.0 {
-                0 => {
-                    encode_successful.fetch_add(1, Ordering::SeqCst);
-                    size_input_total.fetch_add(res.1, Ordering::SeqCst);
-                    size_output_total.fetch_add(res.2, Ordering::SeqCst);
-                }, // improve: track input/output size here and show interactively
-                1 => {
-                    encode_skipped.fetch_add(1, Ordering::SeqCst);
-                    size_input_total.fetch_add(res.1, Ordering::SeqCst);
-                    size_output_total.fetch_add(res.2, Ordering::SeqCst);
-                    size_input_preexisting.fetch_add(res.1, Ordering::SeqCst);
-                    size_output_preexisting.fetch_add(res.2, Ordering::SeqCst);
-                },
-                2 => {
-                    encode_discarded.fetch_add(1, Ordering::SeqCst);
-                    size_input_discarded.fetch_add(res.1, Ordering::SeqCst);
-                    size_output_discarded.fetch_add(res.2, Ordering::SeqCst);
-                },
-                -1 => {
-                    encode_errors.fetch_add(1, Ordering::SeqCst);
-                },
-                _ => {}
-            }
-            pb.set_message(
-                if size_input_preexisting.load(Ordering::Relaxed) > 0 {
-                    format!(
-                        "{} ➜ {} ({} ➜ {} preexisting) | ✔ {} — {} ✖ {}",
-                        format_size(size_input_total.load(Ordering::Relaxed), format_option_binary_two_nospace),
-                        format_size(size_output_total.load(Ordering::Relaxed), format_option_binary_two_nospace),
-                        format_size(size_input_preexisting.load(Ordering::Relaxed), format_option_binary_two_nospace),
-                        format_size(size_output_preexisting.load(Ordering::Relaxed), format_option_binary_two_nospace),
-                        encode_successful.load(Ordering::Relaxed),
-                        encode_skipped.load(Ordering::Relaxed),
-                        encode_errors.load(Ordering::Relaxed)
-                    )
-                } else {
-                    format!(
-                        "{} ➜ {} | ✔ {} — {} ✖ {}",
-                        format_size(size_input_total.load(Ordering::Relaxed), format_option_binary_two_nospace),
-                        format_size(size_output_total.load(Ordering::Relaxed), format_option_binary_two_nospace),
-                        encode_successful.load(Ordering::Relaxed),
-                        encode_skipped.load(Ordering::Relaxed),
-                        encode_errors.load(Ordering::Relaxed)
-                    )
+                if let Some(bar) = worker_bar {
+                    bar.reset_elapsed();
+                    bar.set_message(path.display().to_string());
                 }
-            );
-            res
-        })
-        .collect();
-
-    // use a return carriage feed to clear the remnants of the progress bar off the screen
-    pb.finish_with_message("finished!");
-    // \r\x1b[2K is the sequence to clear the current row content (if manual way is intended)
-    println!("Encode statistics:");
-    println!("Time taken:  {}", HumanDuration(pb.elapsed()));
-    println!("Input files: {}", input_file_count);
-    println!("Successful:  {}", encode_successful.load(Ordering::Relaxed));
-    println!("Skipped:     {}", encode_skipped.load(Ordering::Relaxed));
-    println!("Errors:      {}", encode_errors.load(Ordering::Relaxed));
-    if conf.discard_if_larger_than_input && encode_discarded.load(Ordering::Relaxed) > 0 {
-        println!("Discarded:   {} (due to the encode being larger than the input; {} ➜ {})",
-                 encode_discarded.load(Ordering::Relaxed),
-                 format_size(size_input_discarded.load(Ordering::Relaxed), format_option_binary_two_nospace),
-                 format_size(size_output_discarded.load(Ordering::Relaxed), format_option_binary_two_nospace));
-        println!("Please note that discarded in- and outputs do not count into the total in-/output statistics below.")
-    }
-    if size_input_total.load(Ordering::Relaxed) > 0 && size_output_total.load(Ordering::Relaxed) > 0 {
-        // show total stats
-        println!("Total input size:  {}", format_size(size_input_total.load(Ordering::Relaxed), format_option_binary_two_nospace));
-        println!("Total output size: {}", format_size(size_output_total.load(Ordering::Relaxed), format_option_binary_two_nospace));
-        println!("Total comp. ratio: {:.02}%", size_output_total.load(Ordering::Relaxed) as f64 / size_input_total.load(Ordering::Relaxed) as f64 * 100.0);
-        if size_input_preexisting.load(Ordering::Relaxed) > 0 && size_output_preexisting.load(Ordering::Relaxed) > 0 {
-            if size_input_total.load(Ordering::Relaxed) - size_input_preexisting.load(Ordering::Relaxed) > 0 {
-                // if we have new encodes and preexisting images, first show the stats for the new encodes, then for the preexisting ones
-                println!("New encodes input size:  {}", format_size(size_input_total.load(Ordering::Relaxed) - size_input_preexisting.load(Ordering::Relaxed), format_option_binary_two_nospace));
-                println!("New encodes output size: {}", format_size(size_output_total.load(Ordering::Relaxed) - size_output_preexisting.load(Ordering::Relaxed), format_option_binary_two_nospace));
-                println!("New encodes comp. ratio: {:.02}%", size_output_preexisting.load(Ordering::Relaxed) as f64 / size_input_preexisting.load(Ordering::Relaxed) as f64 * 100.0);
+                let task = match conf.per_file_timeout {
+                    Some(timeout) => {
+                        let (file, img_format, conf, options, records) =
+                            (file.clone(), *img_format, conf.clone(), plan.settings_of(file).clone(), records.clone());
+                        run_file_task_with_timeout(timeout, move || convert_image(&file, &img_format, &conf, &options, &records))
+                    }
+                    None => run_file_task(|| convert_image(file, img_format, conf, plan.settings_of(file), records)),
+                };
+                handle_panics(conf, records, &multi, path, task)
+                    .unwrap_or_else(|err| handle_conversion_error(conf, &multi, path, err))
+            };
+            if let Some(bar) = worker_bar {
+                bar.set_message(tr!("run-worker-idle"));
             }
-            // if we have preexisting images, show these stats
-            println!("Preexisting input size:  {}", format_size(size_input_preexisting.load(Ordering::Relaxed), format_option_binary_two_nospace));
-            println!("Preexisting output size: {}", format_size(size_output_preexisting.load(Ordering::Relaxed), format_option_binary_two_nospace));
-            println!("Preexisting comp. ratio: {:.02}%", size_output_preexisting.load(Ordering::Relaxed) as f64 / size_input_preexisting.load(Ordering::Relaxed) as f64 * 100.0);
-        }
-    } else {
-        if (encode_successful.load(Ordering::Relaxed) + encode_skipped.load(Ordering::Relaxed) + encode_errors.load(Ordering::Relaxed)) > 1 {
-            println!("Input and output size could not be determined, please try using OS-native binaries.");
-        }
-    }
-    Ok(())
-}
-
-fn fallback_retry_read_image(input_path: &Path, input_error: Box<dyn StdError + Send + Sync>)
-    -> Result<DynamicImage, Box<dyn StdError + Send + Sync>> {
-    let err = input_error;
-    let ext = input_path
-        .extension().and_then(|e| e.to_str())
-        .unwrap_or("").to_ascii_lowercase();
-
-    // try jpeg-decoder to support loading progressive jpegs
-    if ext == "pjpeg" || ext == "jpg" || ext == "jpeg" {
-        if let Ok(file) = fs::File::open(input_path) {
-            let mut decoder = Decoder::new(file);
-            if let Ok(pixels) = decoder.decode() {
-                if let Some(info) = decoder.info() {
-                    // Convert raw pixels to RgbImage
-                    let img = RgbImage::from_raw(
-                        info.width.into(),
-                        info.height.into(),
-                        pixels,
-                    )
-                        .ok_or("Failed to convert jpeg-decoder output to RgbImage")?;
-                    return Ok(DynamicImage::ImageRgb8(img));
+            if let Some(on_file_done) = &conf.on_file_done {
+                on_file_done(path, &outcome);
+            }
+            let replaced = file.replace_input && conf.backup_ext.is_none();
+            if matches!(outcome, ConversionOutcome::Aborted) {
+                aggregate.lock().unwrap_or_else(|e| e.into_inner()).record(path, replaced, &outcome);
+                keep(index, outcome);
+                return;
+            }
+            if let ConversionOutcome::Rejected { .. } = outcome {
+                multi.suspend(|| print_message(conf, &tr!("run-file-rejected", path = path.display().to_string(),
+                                                              limit = conf.max_pixels.unwrap_or_default())));
+            }
+            if let Some(explained_skips) = explained_skips {
+                let explanation = match &outcome {
+                    ConversionOutcome::Skipped { reason, .. } => Some(reason.description()),
+                    ConversionOutcome::Discarded { .. } => Some(if file.reoptimize {
+                        tr!("explain-reoptimized-not-smaller")
+                    } else {
+                        tr!("explain-not-smaller")
+                    }),
+                    ConversionOutcome::NotWorthIt { .. } => Some(tr!("explain-not-worth-it")),
+                    _ => None,
+                };
+                if let Some(explanation) = explanation {
+                    explained_skips.lock().unwrap_or_else(|e| e.into_inner()).push((path.clone(), explanation));
                 }
             }
-        }
-    }
+            // the statistics are complete for the summary, the progress bar only follows in batches
+            let stats = {
+                let mut aggregate = aggregate.lock().unwrap_or_else(|e| e.into_inner());
+                aggregate.record(path, replaced, &outcome);
+                batch.add(input_bytes).map(|bytes| (bytes, aggregate.statistics.clone()))
+            };
+            if let Some((bytes, stats)) = stats {
+                pb.inc(bytes); // advance progress bar by the processed input size
+                pb.set_message(progress_message(&stats));
+            }
+            keep(index, outcome);
+        });
 
-    let mut reader = ImageReader::open(input_path)?;
-    match ext.as_str() {
-        "pjpeg" | "jpg" | "jpeg" => reader.set_format(ImageImageFormat::Jpeg),
-        "x-png" | "png" => reader.set_format(ImageImageFormat::Png),
-        _ => return Err(err), // nothing else to try
+    for bar in &worker_bars {
+        bar.finish_and_clear();
     }
+    pb.inc(batch.flush());
+    // use a return carriage feed to clear the remnants of the progress bar off the screen
+    pb.finish_with_message(tr!("run-finished"));
+    outcomes.into_inner().unwrap_or_else(|e| e.into_inner())
+}
 
-    if let Ok(decoded) = reader.decode() {
-        Ok(decoded)
-    } else {
-        Err(err)
+// takes the outputs of failed transfers from the --stage-dir out of the records, their inputs count as failed
+fn drop_lost_outputs(records: &RunRecords, aggregate: &Mutex<RunAggregate>, failures: &[(Transfer, std::io::Error)]) {
+    let lost: HashSet<&Path> = failures.iter().map(|(transfer, _)| transfer.output.as_path()).collect();
+    if let Some(manifest) = &records.manifest {
+        manifest.lock().unwrap_or_else(|e| e.into_inner()).retain(|entry| !lost.contains(Path::new(&entry.output)));
+    }
+    if let Some(undo) = &records.undo {
+        undo.lock().unwrap_or_else(|e| e.into_inner()).retain(|step| match step {
+            UndoStep::Created { output } | UndoStep::Overwritten { output } => !lost.contains(output.as_path()),
+            _ => true,
+        });
+    }
+    // an input with several lost outputs (mirrors) failed once
+    let mut failed_inputs = HashSet::new();
+    let mut aggregate = aggregate.lock().unwrap_or_else(|e| e.into_inner());
+    for (transfer, _) in failures.iter().filter(|(transfer, _)| failed_inputs.insert(&transfer.input)) {
+        aggregate.record_lost_output(&transfer.input, transfer.input_size, transfer.output_size, transfer.elapsed);
     }
 }
 
-fn try_read_image(input_path: &Path)
-    -> Result<DynamicImage, Box<dyn StdError + Send + Sync>> {
-    // first try with autodetection, unfortunately zune panics on one of the input images...
-    let mut result = panic::catch_unwind(|| {
-        Ok(ImageReader::open(input_path)?.decode()?)
-    });
-
-    if let Ok(inner) = result {
-        if let Ok(img) = inner {
-            return Ok(img); // ✅ move out
-        }
+/// Prints a summary of `plan` and asks whether to run it, anything but `y`/`yes` (or `j`/`ja`) declines
+fn confirm_run(conf: &CommonConfig, plan: &Plan) -> Result<bool, Error> {
+    let input_bytes: u64 = plan.files.iter().map(|file| file.input_size as u64).sum();
+    let replaced = plan.files.iter().filter(|file| file.replace_input).count();
+    println!("{}", tr!("confirm-files", count = plan.files.len(), size = format_size(input_bytes, BINARY)));
+    println!("{}", tr!("confirm-format", format = plan.format.extension()));
+    if conf.output.is_empty() {
+        println!("{}", tr!("confirm-output-next-to-inputs"));
+    } else {
+        println!("{}", tr!("confirm-output", path = conf.output.as_str()));
     }
-
-    // retry with guessed format (we have pngs hiding in jpeg extension files, jpg inside bmp, etc. ...)
-    result = panic::catch_unwind(|| {
-        Ok(ImageReader::open(input_path)?.with_guessed_format()?.decode()?)
-    });
-
-    if let Ok(inner) = result {
-        if let Ok(img) = inner {
-            return Ok(img); // ✅ move out
-        } else {
-            fallback_retry_read_image(input_path, inner.err().unwrap())
-        }
+    for mirror in &conf.mirror_outputs {
+        println!("{}", tr!("confirm-mirror", path = mirror.as_str()));
+    }
+    println!("{}", tr!("confirm-existing", mode = if conf.overwrite_existing {
+        "overwritten"
+    } else if conf.overwrite_if_smaller {
+        "if-smaller"
+    } else if conf.if_stale {
+        "if-stale"
     } else {
-        fallback_retry_read_image(input_path, result.unwrap().err().unwrap())
+        "skipped"
+    }));
+    if replaced > 0 {
+        match &conf.backup_ext {
+            Some(backup_ext) => println!("{}", tr!("confirm-replacing-backups", count = replaced, ext = backup_ext.as_str())),
+            None => println!("{}", tr!("confirm-replacing", count = replaced)),
+        }
     }
+    if !plan.collisions.is_empty() {
+        println!("{}", tr!("confirm-colliding", count = plan.collisions.len()));
+    }
+    print!("{} ", tr!("confirm-prompt"));
+    std::io::stdout().flush()?;
+    let mut answer = String::new();
+    std::io::stdin().read_line(&mut answer)?;
+    Ok(matches!(answer.trim().to_ascii_lowercase().as_str(), "y" | "yes" | "j" | "ja"))
 }
 
-fn normalize_prefix<P: AsRef<Path>>(p: P) -> PathBuf {
+pub(crate) fn normalize_prefix<P: AsRef<Path>>(p: P) -> PathBuf {
     let path = p.as_ref();
 
     let mut components = path.components().peekable();
@@ -399,117 +1744,586 @@ fn normalize_prefix<P: AsRef<Path>>(p: P) -> PathBuf {
     normalized
 }
 
+// path of the input below the pattern base, in the quarantine and pixel dump directories
+fn relative_input(conf: &CommonConfig, input: &Path) -> PathBuf {
+    let base = conf.relative_to.clone().unwrap_or_else(|| base_from_pattern(&conf.pattern));
+    let input_norm = normalize_prefix(input);
+    input_norm.strip_prefix(normalize_prefix(&base)).map(Path::to_path_buf).unwrap_or(input_norm)
+}
+
+/// Puts `input` into the --quarantine-dir next to a note of `error`, returning the note for its error message
+/// (None without a quarantine directory)
+fn quarantine_file(conf: &CommonConfig, records: &RunRecords, input: &Path, error: &str) -> Option<String> {
+    let directory = conf.quarantine_dir.as_ref()?;
+    // abandoned tasks leave their input where it is
+    check_abandoned().ok()?;
+    Some(match quarantine_input(input, &relative_input(conf, input), Path::new(directory), conf.quarantine_mode, error) {
+        Ok(target) => {
+            records.quarantined.fetch_add(1, Ordering::SeqCst);
+            if let (Some(undo), QuarantineMode::Move) = (&records.undo, conf.quarantine_mode) {
+                undo.lock().unwrap_or_else(|e| e.into_inner()).push(UndoStep::Moved { from: input.to_path_buf(), to: target.clone() });
+            }
+            format!("quarantined to {}", target.display())
+        }
+        Err(err) => format!("quarantine failed: {}", err),
+    })
+}
+
 /// Encodes an image to the specified image format and saves it to the specified output directory.
 ///
-/// Returns tuple (isize, usize, usize), (status, input_size (B), output_size (B))
+/// Returns the outcome of the conversion, errors are turned into [`ConversionOutcome::Failed`] by the caller.
 ///
-/// Status codes:
-/// 2 = encode larger than input, output file not saved;
-/// 1 = skipped;
-/// 0 = success;
-/// -1 = error;
-/// -2 = aborted (interrupt / ctrl+c received)
+/// Successful conversions are recorded in the enabled `records`.
 fn convert_image(
-    input_path: &Path,
+    file: &PlannedFile,
     img_format: &ImageFormat,
-    output: String,
-    pattern_base: String,
-    overwrite_if_smaller: bool,
-    overwrite_existing: bool,
-    discard_if_larger_than_input: bool,
-    option_lossless: &Option<bool>,
-    option_quality: &Option<f32>,
-    option_speed: &Option<u8>,
-    option_png_compression_type: &Option<CompressionType>,
-    option_png_filter_type: &Option<FilterType>,
-    option_avif_bit_depth: &Option<BitDepth>,
-    option_avif_color_model: &Option<ColorModel>,
-    option_avif_alpha_color_mode: &Option<AlphaColorMode>,
-    option_avif_alpha_quality: &Option<f32>,
-) -> Result<(isize, usize, usize), Box<dyn StdError + Send + Sync>> {
-    // returns tuple (status, input_size (B), output_size (B))
-    // status:
-    // 2 = would have been larger than input or existing file, output file not saved (show as skipped, but seperate statistics
-    // 1 = skipped,
-    // 0 = success,
-    // -1 = error,
-    // -2 = aborted (interrupt / ctrl+c received)
-    let ext = img_format.extension();
-    let output_path;
-    if output.is_empty() {
-        output_path = input_path.with_extension(ext)
-    } else {
-        let pattern_base_norm = normalize_prefix(&pattern_base);
-        let input_path_norm = normalize_prefix(&input_path);
-        let rel_path = input_path_norm
-            .strip_prefix(&pattern_base_norm)
-            .unwrap_or_else(|_| Path::new(&input_path_norm));
-
-        output_path = Path::new(&output)
-            .join(rel_path.parent().unwrap_or_else(|| Path::new("")))
-            .join(input_path_norm.file_stem().unwrap())
-            .with_extension(ext);
-
-        fs::create_dir_all(Path::new(&output).join(rel_path.parent().unwrap_or_else(|| Path::new(""))))?;
-    };
+    conf: &CommonConfig,
+    options: &EncoderConfig,
+    records: &RunRecords,
+) -> Result<ConversionOutcome, Box<dyn StdError + Send + Sync>> {
+    let started = Instant::now();
+    let input_path = file.input.as_path();
+    let _span = profile::file_span("convert", input_path);
+    let same_format = file.reoptimize;
+    let replace_input = file.replace_input;
+    // the output, followed by its copies in the mirror directories
+    let destinations: Vec<PathBuf> = std::iter::once(file.output.clone()).chain(file.mirrors.iter().cloned()).collect();
+    // outputs and the directories created for them belong to the owner of the input with --preserve-owner
+    let owner = if conf.preserve_owner { file_owner(input_path)? } else { None };
+    if !replace_input && !conf.output.is_empty() {
+        create_dir_all_owned(file.output.parent().unwrap_or_else(|| Path::new("")), owner)?;
+    }
+    for mirror in &file.mirrors {
+        create_dir_all_owned(mirror.parent().unwrap_or_else(|| Path::new("")), owner)?;
+    }
 
-    let input_size = fs::metadata(&input_path)?.len() as usize;
-    if fs::exists(output_path.clone())? && !overwrite_existing && !overwrite_if_smaller {
-        // file exists, and we do not have any overwrite flag on? => return early
-        //println!("skipped because output path exists and overwrite options are unset {}", input_path.display());
-        return Ok((1, input_size, fs::metadata(output_path.clone())?.len() as usize))
+    let input_size = file.input_size;
+    // the input is only looked up again when outputs are compared with its modification time
+    let input_modified = if conf.if_stale { fs::metadata(input_path)?.modified().ok() } else { None };
+    // content hash names are only known after encoding
+    let plain_naming = conf.naming == OutputNaming::Plain;
+    // skipping is decided per destination, frame sequences check their first frame instead;
+    //  each destination is looked up once, which matters on high-latency network filesystems
+    let mut pending: Vec<(PathBuf, bool, Option<fs::Metadata>)> = destinations.iter().enumerate()
+        .map(|(i, output_path)| (output_path.clone(), i > 0, output_metadata(output_path)))
+        .collect();
+    if plain_naming && !replace_input && !conf.writes_frame_sequences() && !conf.overwrite_existing && !conf.overwrite_if_smaller {
+        let output_size = pending[0].2.as_ref().map_or(0, |metadata| metadata.len() as usize);
+        pending.retain(|(_, _, existing)| !output_is_current(conf, input_modified, existing.as_ref()));
+        if pending.is_empty() {
+            // all outputs exist, and we do not have any overwrite flag on? => return early
+            return Ok(ConversionOutcome::Skipped { reason: existing_reason(conf), input_size, output_size });
+        }
     }
 
-    let image = try_read_image(input_path)?;
+    // saved plans may hold inputs of formats whose decoder is disabled in this run
+    if !conf.decoders.is_empty() && let Some(format) = probe_format_name(input_path) && !conf.decoders.allows(&format) {
+        return Err(Box::new(Error::from_string(format!("the {} decoder is disabled (--enable-decoder, --disable-decoder)", format))));
+    }
+    // decompression bombs are rejected by their header, before their pixels are allocated
+    if let Some(max_pixels) = conf.max_pixels
+        && let Some((width, height)) = probe_dimensions(input_path)
+        && width as u64 * height as u64 > max_pixels {
+        return Ok(ConversionOutcome::Rejected { input_size });
+    }
+    // inputs without alpha channel cannot have transparent pixels (not counted into the size statistics)
+    if conf.only_with_alpha && probe_color_type(input_path).is_some_and(|color_type| !color_type.has_alpha()) {
+        return Ok(ConversionOutcome::Skipped { reason: SkipReason::NoAlpha, input_size: 0, output_size: 0 });
+    }
 
-    let encode_lossless = option_lossless.unwrap_or(false);
-    let encode_quality: f32 = option_quality.unwrap_or(90.);
-    let encode_speed: u8 = option_speed.unwrap_or(3);
+    // set if a --route rule (or --animated animate) picked another format than the target format
+    let mut routed = None;
+    // animated inputs without --frames follow --animated
+    let animated = file.animated && options.frames.is_none();
+    if animated && conf.animated == AnimatedInputs::Copy {
+        return copy_animated(file, input_modified, conf, started);
+    }
+    let animated_options;
+    let options = if animated && conf.animated == AnimatedInputs::Animate {
+        if !supports_animation(img_format) {
+            let animated_output = file.output.with_extension(ImageFormat::Webp.extension());
+            if let Some(output_size) = existing_output_size(conf, file, input_modified, &animated_output) {
+                return Ok(ConversionOutcome::Skipped { reason: existing_reason(conf), input_size, output_size });
+            }
+            routed = Some(ImageFormat::Webp);
+        }
+        animated_options = EncoderConfig { frames: Some(FrameMode::All), ..options.clone() };
+        &animated_options
+    } else {
+        options
+    };
 
-    let image_data = match img_format {
-        // TODO: more PNG lossless optimizers, jpeg xl
-        ImageFormat::Webp => encode_webp(&image, encode_lossless, encode_quality),
-        ImageFormat::WebpImage => encode_webp_image(&image),
-        ImageFormat::Avif => encode_avif(
-            &image, encode_quality, encode_speed,
-            *option_avif_bit_depth, *option_avif_color_model,
-            *option_avif_alpha_color_mode, option_avif_alpha_quality.unwrap_or(90.)),
-        ImageFormat::Png => encode_png(&image, *option_png_compression_type, *option_png_filter_type),
-        ImageFormat::Jpeg => encode_mozjpeg(&image),
-        _ => return Err(Box::new(Error::from_string("Unsupported image format".to_string()))),
+    let bake_orientation = options.jpeg_bake_orientation.unwrap_or(false) && options.crop.is_none()
+        && *img_format == ImageFormat::Jpeg && ImageFormat::from(input_path) == ImageFormat::Jpeg;
+    // computed from the decoded input where it is at hand
+    let (mut placeholder, mut image_hash) = (None, None);
+    // decoded with --tolerate-truncation, parts of the image are gray fill
+    let mut repaired = false;
+    // classification of still images, for --route, --only-with-alpha and the manifest
+    let mut content = None;
+    // set if the target format was larger than the input and a --fallback-format was smaller
+    let mut fallback = None;
+    let describe = |image: &DynamicImage| -> Result<_, Error> {
+        Ok((conf.placeholder.map(|kind| compute_placeholder(kind, image, options.crop)).transpose()?,
+            conf.perceptual_hash.map(|kind| perceptual_hash(kind, image, options.crop)).transpose()?))
+    };
+    // inputs no decoder can read are put aside for triage, the error of the file stays
+    let quarantine = |err: Error| -> Error {
+        match quarantine_file(conf, records, input_path, &err.to_string()) {
+            Some(note) => Error::from_string(format!("{} ({})", err, note)),
+            None => err,
+        }
+    };
+    let image_data = if bake_orientation {
+        // lossless, the pixels are never decoded (except for the placeholder and hash of the upright output)
+        let transformed = transform_jpeg(&fs::read(input_path)?, options);
+        if let Ok(data) = &transformed && (conf.placeholder.is_some() || conf.perceptual_hash.is_some()) {
+            (placeholder, image_hash) = describe(&read_image_from_memory(data)?)?;
+        }
+        transformed
+    } else if !conf.poster_at.is_empty() {
+        if replace_input {
+            return Err(Box::new(Error::from_string("a poster cannot replace its video input".to_string())));
+        }
+        let mut posters = read_posters(input_path, &conf.poster_at)?;
+        if posters.len() > 1 {
+            return write_frame_sequence(posters, input_path, input_size, input_modified, &destinations, img_format, conf, options, None, records,
+                                        started);
+        }
+        let poster = posters.remove(0);
+        (placeholder, image_hash) = describe(&poster.image)?;
+        encode_image(&poster.image, img_format, options)
+    } else {
+        let source = if options.keeps_metadata() {
+            Some(fs::read(input_path)?)
+        } else {
+            None
+        };
+        let source_exif = source.as_deref().and_then(read_exif);
+        let encoded = match read_frames(input_path, options).map_err(quarantine)? {
+            Some(frames) if supports_animation(routed.as_ref().unwrap_or(img_format)) && !conf.extract_frames => {
+                (placeholder, image_hash) = describe(&frames[0].image)?;
+                encode_animation(frames, routed.as_ref().unwrap_or(img_format), options, source_exif.as_deref())
+            }
+            Some(frames) => {
+                if replace_input {
+                    return Err(Box::new(Error::from_string(
+                        "a frame sequence cannot replace its input (animations are kept by webp and png outputs)".to_string())));
+                }
+                return write_frame_sequence(frames, input_path, input_size, input_modified, &destinations, img_format, conf, options,
+                                            source_exif.as_deref(), records, started);
+            }
+            // still images have no frames to extract (not counted into the size statistics)
+            None if conf.extract_frames => return Ok(ConversionOutcome::Skipped {
+                reason: SkipReason::NotAnimated, input_size: 0, output_size: 0,
+            }),
+            None => {
+                let image = match read_image_with(input_path, &DecodeOptions { max_pixels: conf.max_pixels, ..DecodeOptions::for_backend(conf.decoder) }) {
+                    // the error of the regular decoders is kept if the input is no salvageable jpeg either,
+                    // oversized inputs are rejected and not salvaged
+                    Err(err) if conf.tolerate_truncation && !exceeds_pixel_limit(&err) => {
+                        let image = salvage_jpeg(&fs::read(input_path)?, conf.max_pixels).map_err(|_| quarantine(err))?;
+                        repaired = true;
+                        image
+                    }
+                    result => result.map_err(quarantine)?,
+                };
+                (placeholder, image_hash) = describe(&image)?;
+                if !conf.routes.is_empty() || conf.only_with_alpha || records.manifest.is_some() {
+                    content = Some(ImageTraits::of(&image));
+                }
+                if conf.only_with_alpha && content.as_ref().is_some_and(|traits| !traits.has_alpha) {
+                    return Ok(ConversionOutcome::Skipped { reason: SkipReason::NoAlpha, input_size: 0, output_size: 0 });
+                }
+                // routed outputs have the extension of their format, the input itself is never one of them
+                if let Some(format) = content.as_ref().and_then(|traits| route_format(&conf.routes, traits))
+                    .filter(|format| format != img_format) {
+                    let routed_output = file.output.with_extension(options.output_extension(&format));
+                    if replace_input || routed_output != file.input {
+                        if let Some(output_size) = existing_output_size(conf, file, input_modified, &routed_output) {
+                            return Ok(ConversionOutcome::Skipped { reason: existing_reason(conf), input_size, output_size });
+                        }
+                        routed = Some(format);
+                    }
+                }
+                let encoded = match encode_image_with_exif(&image, routed.as_ref().unwrap_or(img_format), options, source_exif.as_deref()) {
+                    Ok(data) if data.len() >= input_size && !conf.fallback_formats.is_empty() => {
+                        match encode_fallback(&image, file, input_size, conf, options, source_exif.as_deref()) {
+                            Some((format, data)) => {
+                                fallback = Some(format);
+                                Ok(data)
+                            }
+                            None => Ok(data),
+                        }
+                    }
+                    encoded => encoded,
+                };
+                // outputs that do not look like their input are not written, the input is kept
+                if let (Some(min_ssim), Ok(data)) = (conf.validate_visual, &encoded)
+                    && decodes_output(fallback.or(routed).as_ref().unwrap_or(img_format)) {
+                    let input = match options.crop {
+                        Some(crop) => crop.apply(&image)?,
+                        None => Cow::Borrowed(&image),
+                    };
+                    let input_data = match &source {
+                        Some(source) => Cow::Borrowed(source),
+                        None => Cow::Owned(fs::read(input_path)?),
+                    };
+                    if let Some(problem) = check_visual(&input, &input_data, data, min_ssim)? {
+                        return Err(Box::new(Error::from_string(format!("visual validation failed: {}", problem))));
+                    }
+                }
+                // written whether or not the encoder succeeded, failures are among the reports to diagnose
+                if let Some(dump) = &conf.pixel_dump && dump.samples(input_path) {
+                    let format = fallback.or(routed).unwrap_or(*img_format);
+                    dump.write(&relative_input(conf, input_path), &*encoder_pixels(&image, &format, options)?, &format)?;
+                }
+                encoded
+            }
+        };
+        match source {
+            // text chunks of png inputs are metadata as well
+            Some(source) if *fallback.or(routed).as_ref().unwrap_or(img_format) == ImageFormat::Png && !options.png_strip_chunks.unwrap_or(false)
+                => encoded.and_then(|data| copy_png_text(&source, data, options.strip_gps.unwrap_or(false))),
+            _ => encoded,
+        }
     };
 
+    let img_format = &fallback.or(routed).unwrap_or(*img_format);
+    let ext = options.output_extension(img_format);
+    // replaced inputs keep their name, other outputs get the extension of the routed or fallback format
+    let output = match fallback.or(routed) {
+        Some(_) if !replace_input => {
+            for (output_path, _, existing) in pending.iter_mut() {
+                output_path.set_extension(ext);
+                *existing = output_metadata(output_path);
+            }
+            file.output.with_extension(ext)
+        }
+        _ => file.output.clone(),
+    };
     match image_data {
         Ok(image_data) => {
             let output_size =  image_data.len();
-            if fs::exists(output_path.clone())? &&
-                output_size >= fs::metadata(output_path.clone())?.len() as usize &&
-                overwrite_if_smaller {
-                // overwrite if smaller flag is on, but output exists and is already smaller than our encode
-                //  => abort
-                // TODO: how to propagate this information upwards into statistics? i am not happy with the current handling
-                //println!(
-                //    "skipped because output path exists,\
-                //      overwrite_if_smaller is active,\
-                //      but new output is larger than the existing one {}",
-                //    input_path.display());
-                return Ok((1, input_size, fs::metadata(output_path.clone())?.len() as usize));
-            }
-
-            if discard_if_larger_than_input && output_size >= input_size {
-                // TODO: how to propagate this information upwards into statistics?
-                //println!(
-                //    "skipped because the output is larger than the input,\
-                //      and discard_if_larger_than_input is active {}",
-                //    input_path.display());
-                return Ok((2, input_size, output_size));
-            }
-
-            fs::write(output_path.clone(), image_data)?;
-            Ok((0, input_size, output_size))
+            if plain_naming && conf.overwrite_if_smaller {
+                // overwrite if smaller flag is on, but an output exists and is already smaller than our encode
+                //  => keep it
+                let existing_size = pending[0].2.as_ref().map_or(0, |metadata| metadata.len() as usize);
+                pending.retain(|(_, _, existing)| existing.as_ref()
+                    .is_none_or(|existing| output_size < existing.len() as usize));
+                if pending.is_empty() {
+                    return Ok(ConversionOutcome::Skipped { reason: SkipReason::ExistingSmaller, input_size, output_size: existing_size });
+                }
+            }
+
+            if (conf.discard_if_larger_than_input || same_format) && output_size >= input_size {
+                return Ok(ConversionOutcome::Discarded { input_size, output_size, elapsed: started.elapsed() });
+            }
+            if let Some(min_savings) = conf.min_savings
+                && (output_size as f64) > input_size as f64 * (1. - min_savings / 100.) {
+                return Ok(ConversionOutcome::NotWorthIt { input_size, output_size, elapsed: started.elapsed() });
+            }
+
+            let hash = (!plain_naming || conf.dedupe_outputs.is_some()).then(|| sha256_hex(&image_data));
+            let output_name = |output_path: &Path| match hash.as_ref().filter(|_| !plain_naming) {
+                Some(hash) => output_path.with_extension(format!("{}.{}", &hash[..CONTENT_HASH_LENGTH], ext)),
+                None => output_path.to_path_buf(),
+            };
+            if !plain_naming {
+                for (output_path, _, existing) in pending.iter_mut() {
+                    *output_path = output_name(output_path);
+                    *existing = output_metadata(output_path);
+                }
+            }
+            check_abandoned()?;
+            if let Some(hash_map) = &records.hash_map {
+                hash_map.lock().unwrap_or_else(|e| e.into_inner())
+                    .insert(input_path.display().to_string(), output_name(&output).display().to_string());
+            }
+            if !plain_naming {
+                // same name => same content, nothing to write
+                pending.retain(|(_, _, existing)| existing.is_none());
+                if pending.is_empty() {
+                    return Ok(ConversionOutcome::Skipped { reason: SkipReason::SameContentExists, input_size, output_size });
+                }
+            }
+            // read before an in-place output replaces the input
+            let xattrs = if conf.preserve_xattrs { read_xattrs(input_path)? } else { Vec::new() };
+            for (output_path, is_mirror, existing) in &pending {
+                check_abandoned()?;
+                let mut staged = None;
+                let undo_step = records.undo.as_ref().map(|_| match (replace_input, &conf.backup_ext) {
+                    (true, Some(backup_ext)) => UndoStep::Replaced { path: output_path.clone(), backup: backup_path(output_path, backup_ext) },
+                    (true, None) => UndoStep::ReplacedWithoutBackup { path: output_path.clone() },
+                    _ if existing.is_some() => UndoStep::Overwritten { output: output_path.clone() },
+                    _ => UndoStep::Created { output: output_path.clone() },
+                });
+                let linked = match (conf.dedupe_outputs, &records.dedupe, hash.as_ref()) {
+                    // mirrors are plain copies, they may live on other filesystems than the stored copy
+                    (Some(mode), Some(dedupe), Some(hash)) if !*is_mirror => {
+                        let original = dedupe.lock().unwrap_or_else(|e| e.into_inner()).get(hash).cloned();
+                        match original {
+                            Some(original) if &original != output_path => {
+                                link_output(mode, &original, output_path)?;
+                                if conf.fsync {
+                                    sync_directory(output_path.parent().unwrap_or_else(|| Path::new("")))?;
+                                }
+                                records.deduped_files.fetch_add(1, Ordering::SeqCst);
+                                records.deduped_bytes.fetch_add(output_size, Ordering::SeqCst);
+                                true
+                            }
+                            _ => {
+                                write_file(output_path, &image_data, conf.fsync)?;
+                                // concurrent duplicates may both be written, only later ones are linked
+                                dedupe.lock().unwrap_or_else(|e| e.into_inner()).entry(hash.clone()).or_insert(output_path.clone());
+                                false
+                            }
+                        }
+                    }
+                    _ if replace_input => {
+                        replace_file(output_path, &image_data, conf.backup_ext.as_deref(), conf.fsync)?;
+                        false
+                    }
+                    _ => {
+                        match &records.stage {
+                            Some(stage) => {
+                                let staging_path = stage.staging_path(output_path);
+                                write_file(&staging_path, &image_data, false)?;
+                                staged = Some(staging_path);
+                            }
+                            None => write_file(output_path, &image_data, conf.fsync)?,
+                        }
+                        false
+                    }
+                };
+                if let (Some(undo), Some(undo_step)) = (&records.undo, undo_step) {
+                    undo.lock().unwrap_or_else(|e| e.into_inner()).push(undo_step);
+                }
+                if let (Some(stage), Some(staged)) = (&records.stage, staged) {
+                    // the attributes and owner are set at the destination, copies across filesystems lose them
+                    stage.transfer(Transfer {
+                        staged, output: output_path.clone(), xattrs: xattrs.clone(), owner,
+                        input: input_path.to_path_buf(), input_size, output_size, elapsed: started.elapsed(),
+                    });
+                } else {
+                    // links share the attributes and owner of the stored copy
+                    if !linked && !xattrs.is_empty() {
+                        write_xattrs(output_path, &xattrs)?;
+                    }
+                    if let Some(owner) = owner.filter(|_| !linked) {
+                        set_owner(output_path, owner)?;
+                    }
+                }
+                // recorded once written (and synced with --fsync), failed writes are not in the manifest
+                //  (staged outputs are taken out again if their transfer fails, see `drop_lost_outputs`)
+                if let Some(manifest) = &records.manifest {
+                    let entry = ManifestEntry {
+                        placeholder: placeholder.clone(),
+                        perceptual_hash: image_hash.clone(),
+                        repaired,
+                        fallback_format: fallback,
+                        content: content.clone(),
+                        ..ManifestEntry::new(input_path, output_path, &image_data, img_format, options)?
+                    };
+                    manifest.lock().unwrap_or_else(|e| e.into_inner()).push(entry);
+                }
+            }
+            if let Some(snippets) = conf.snippets.filter(|_| !replace_input) {
+                // imgc does not resize, all variants have the dimensions of the (cropped) input
+                let dimensions = image::ImageReader::new(std::io::Cursor::new(&image_data)).with_guessed_format().ok()
+                    .and_then(|reader| reader.into_dimensions().ok())
+                    .or_else(|| options.crop.map(|crop| (crop.width, crop.height)));
+                let (width, height) = match dimensions {
+                    Some(dimensions) => dimensions,
+                    None => image::image_dimensions(input_path)?,
+                };
+                write_snippet(snippets, input_path, &output, width, height)?;
+            }
+            if repaired {
+                records.repaired.lock().unwrap_or_else(|e| e.into_inner()).push(input_path.to_path_buf());
+            }
+            if let Some(format) = routed.filter(|_| fallback.is_none()) {
+                *records.routed.lock().unwrap_or_else(|e| e.into_inner()).entry(format.extension().to_string()).or_default() += 1;
+            }
+            if let Some(format) = fallback {
+                records.fallbacks.lock().unwrap_or_else(|e| e.into_inner()).push((input_path.to_path_buf(), format));
+            }
+            if let Some(command) = &conf.on_converted {
+                run_on_converted(command, input_path, &output_name(&output), input_size, output_size)?;
+            }
+            Ok(ConversionOutcome::Converted { input_size, output_size, elapsed: started.elapsed() })
         }
         Err(e) => {
             Err(Box::new(Error::from_string(format!("Image encoding failed: {:?}", e))))
         }
     }
 }
+
+// grabs the poster frames of a video input
+#[cfg(feature = "ffmpeg")]
+fn read_posters(path: &Path, times: &[String]) -> Result<Vec<Frame>, Error> {
+    crate::video::read_posters(path, times)
+}
+
+#[cfg(not(feature = "ffmpeg"))]
+fn read_posters(_path: &Path, _times: &[String]) -> Result<Vec<Frame>, Error> {
+    Err(Error::from_string("video inputs require the `ffmpeg` feature".to_string()))
+}
+
+// first --fallback-format whose encode of `image` is smaller than the input, leaving out formats whose output
+//  would be the input itself or an existing file (without overwrite flag)
+fn encode_fallback(
+    image: &DynamicImage,
+    file: &PlannedFile,
+    input_size: usize,
+    conf: &CommonConfig,
+    options: &EncoderConfig,
+    source_exif: Option<&[u8]>,
+) -> Option<(ImageFormat, Vec<u8>)> {
+    conf.fallback_formats.iter()
+        .filter(|format| file.replace_input || {
+            let output = file.output.with_extension(options.output_extension(format));
+            output != file.input && (conf.overwrite_existing || conf.overwrite_if_smaller || !output.exists())
+        })
+        .find_map(|format| encode_image_with_exif(image, format, options, source_exif).ok()
+            .filter(|data| data.len() < input_size)
+            .map(|data| (*format, data)))
+}
+
+// avif and heic outputs cannot be decoded (without the dav1d and libheif decoders), they are not validated
+fn decodes_output(img_format: &ImageFormat) -> bool {
+    !matches!(img_format, ImageFormat::Avif | ImageFormat::Heif)
+}
+
+// size of `output` if it exists and counts as done, for outputs of another format than planned
+fn existing_output_size(conf: &CommonConfig, file: &PlannedFile, input_modified: Option<SystemTime>, output: &Path) -> Option<usize> {
+    if conf.naming != OutputNaming::Plain || file.replace_input || conf.overwrite_existing || conf.overwrite_if_smaller {
+        return None;
+    }
+    let existing = output_metadata(output);
+    output_is_current(conf, input_modified, existing.as_ref()).then(|| existing.map_or(0, |metadata| metadata.len() as usize))
+}
+
+// copies an animated input unchanged to its outputs (with the extension of the input), `--animated copy`
+fn copy_animated(
+    file: &PlannedFile,
+    input_modified: Option<SystemTime>,
+    conf: &CommonConfig,
+    started: Instant,
+) -> Result<ConversionOutcome, Box<dyn StdError + Send + Sync>> {
+    let input_size = file.input_size;
+    let ext = file.input.extension().unwrap_or_default();
+    let destinations: Vec<PathBuf> = std::iter::once(&file.output).chain(&file.mirrors)
+        .map(|output| output.with_extension(ext))
+        .filter(|output| *output != file.input)
+        .collect();
+    if destinations.is_empty() {
+        return Ok(ConversionOutcome::Skipped { reason: SkipReason::AnimatedKept, input_size, output_size: input_size });
+    }
+    let pending: Vec<&PathBuf> = destinations.iter()
+        .filter(|output| existing_output_size(conf, file, input_modified, output).is_none())
+        .collect();
+    if pending.is_empty() {
+        return Ok(ConversionOutcome::Skipped { reason: existing_reason(conf), input_size, output_size: input_size });
+    }
+    let data = fs::read(&file.input)?;
+    for output in pending {
+        check_abandoned()?;
+        write_file(output, &data, conf.fsync)?;
+    }
+    Ok(ConversionOutcome::Converted { input_size, output_size: input_size, elapsed: started.elapsed() })
+}
+
+// an existing output counts as done, with --if-stale only if it was modified after its input (`input_modified`)
+pub(crate) fn output_is_current(conf: &CommonConfig, input_modified: Option<SystemTime>, output: Option<&fs::Metadata>) -> bool {
+    let Some(Ok(output_modified)) = output.map(fs::Metadata::modified) else {
+        return false;
+    };
+    !conf.if_stale || input_modified.is_some_and(|input_modified| input_modified <= output_modified)
+}
+
+// metadata of an existing output with a single lookup, only symlinks (e.g. of --dedupe-outputs) are followed to their target
+pub(crate) fn output_metadata(path: &Path) -> Option<fs::Metadata> {
+    let metadata = fs::symlink_metadata(path).ok()?;
+    if metadata.file_type().is_symlink() { fs::metadata(path).ok() } else { Some(metadata) }
+}
+
+fn existing_reason(conf: &CommonConfig) -> SkipReason {
+    if conf.if_stale { SkipReason::OutputUpToDate } else { SkipReason::OutputExists }
+}
+
+/// Encodes the frames of an animated input (or the posters of a video) as a numbered sequence of stills (`clip.0001.jpg`, ...),
+/// written for output formats without animation support, by `imgc frames` and for several poster timestamps.
+///
+/// The sequence is written to each of `output_paths` (the output and its mirrors),
+/// skipping those whose first frame exists (unless an overwrite flag is set),
+/// the size based discard options only apply to single outputs.
+/// Returns the outcome like [`convert_image`], with the summed size of all frames.
+#[allow(clippy::too_many_arguments)]
+fn write_frame_sequence(
+    frames: Vec<Frame>,
+    input_path: &Path,
+    input_size: usize,
+    input_modified: Option<SystemTime>,
+    output_paths: &[PathBuf],
+    img_format: &ImageFormat,
+    conf: &CommonConfig,
+    options: &EncoderConfig,
+    source_exif: Option<&[u8]>,
+    records: &RunRecords,
+    started: Instant,
+) -> Result<ConversionOutcome, Box<dyn StdError + Send + Sync>> {
+    let mut pending = output_paths.to_vec();
+    if conf.naming == OutputNaming::Plain && !conf.overwrite_existing && !conf.overwrite_if_smaller {
+        let first_frames: Vec<Option<fs::Metadata>> = output_paths.iter().map(|output_path| output_metadata(&frame_path(output_path, 0))).collect();
+        let output_size = first_frames[0].as_ref().map_or(0, |metadata| metadata.len() as usize);
+        let mut first_frames = first_frames.iter();
+        pending.retain(|_| !output_is_current(conf, input_modified, first_frames.next().and_then(Option::as_ref)));
+        if pending.is_empty() {
+            return Ok(ConversionOutcome::Skipped { reason: existing_reason(conf), input_size, output_size });
+        }
+    }
+
+    let xattrs = if conf.preserve_xattrs { read_xattrs(input_path)? } else { Vec::new() };
+    let owner = if conf.preserve_owner { file_owner(input_path)? } else { None };
+    let mut output_size = 0;
+    // the first frame of the output stands for the sequence in --on-converted
+    let mut first_frame = None;
+    for (i, frame) in frames.iter().enumerate() {
+        let image_data = encode_image_with_exif(&frame.image, img_format, options, source_exif)?;
+        let hash = (conf.naming == OutputNaming::ContentHash).then(|| sha256_hex(&image_data));
+        for output_path in &pending {
+            let mut path = frame_path(output_path, i);
+            if let Some(hash) = &hash {
+                path = path.with_extension(format!("{}.{}", &hash[..CONTENT_HASH_LENGTH], options.output_extension(img_format)));
+            }
+            check_abandoned()?;
+            let existed = records.undo.is_some() && path.exists();
+            write_file(&path, &image_data, conf.fsync)?;
+            if let Some(undo) = &records.undo {
+                let output = path.clone();
+                undo.lock().unwrap_or_else(|e| e.into_inner())
+                    .push(if existed { UndoStep::Overwritten { output } } else { UndoStep::Created { output } });
+            }
+            if !xattrs.is_empty() {
+                write_xattrs(&path, &xattrs)?;
+            }
+            if let Some(owner) = owner {
+                set_owner(&path, owner)?;
+            }
+            if let Some(manifest) = &records.manifest {
+                let entry = ManifestEntry::new(input_path, &path, &image_data, img_format, options)?;
+                manifest.lock().unwrap_or_else(|e| e.into_inner()).push(entry);
+            }
+            if i == 0 && first_frame.is_none() {
+                first_frame = Some(path);
+            }
+        }
+        output_size += image_data.len();
+    }
+    if let Some(command) = &conf.on_converted
+        && let Some(first_frame) = first_frame {
+        run_on_converted(command, input_path, &first_frame, input_size, output_size)?;
+    }
+    Ok(ConversionOutcome::Converted { input_size, output_size, elapsed: started.elapsed() })
+}
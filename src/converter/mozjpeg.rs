@@ -1,42 +1,288 @@
 use crate::Error;
-use image::{DynamicImage, EncodableLayout};
+use image::DynamicImage;
 use crate::converter::DEPENDENCIES;
-use std::panic;
+use mozjpeg_sys::*;
+use std::{cell::RefCell, ffi::c_void, io::Write, mem, os::raw::{c_int, c_ulong}, panic::{self, AssertUnwindSafe}, ptr, slice};
+
+// buffers of `jpeg_mem_dest` are allocated by libjpeg with malloc
+unsafe extern "C" {
+    fn free(ptr: *mut c_void);
+}
+
+// `jpeg_transform_info` as declared by the vendored transupp.h,
+//  the mozjpeg-sys binding lacks the drop fields (libjpeg would write past its end)
+#[repr(C)]
+#[allow(dead_code)] // only written by libjpeg
+struct TransformInfo {
+    transform: JXFORM_CODE,
+    perfect: boolean,
+    trim: boolean,
+    force_grayscale: boolean,
+    crop: boolean,
+    slow_hflip: boolean,
+    crop_width: JDIMENSION,
+    crop_width_set: JCROP_CODE,
+    crop_height: JDIMENSION,
+    crop_height_set: JCROP_CODE,
+    crop_xoffset: JDIMENSION,
+    crop_xoffset_set: JCROP_CODE,
+    crop_yoffset: JDIMENSION,
+    crop_yoffset_set: JCROP_CODE,
+    drop_ptr: j_decompress_ptr,
+    drop_coef_arrays: *mut jvirt_barray_ptr,
+    num_components: c_int,
+    workspace_coef_arrays: *mut jvirt_barray_ptr,
+    output_width: JDIMENSION,
+    output_height: JDIMENSION,
+    x_crop_offset: JDIMENSION,
+    y_crop_offset: JDIMENSION,
+    drop_width: JDIMENSION,
+    drop_height: JDIMENSION,
+    imcu_sample_width: c_int,
+    imcu_sample_height: c_int,
+}
+
+// quality set by `jpeg_set_defaults`
+const DEFAULT_QUALITY: f32 = 75.;
+
+// A compress struct kept alive between the images of a worker thread, saving the setup of libjpeg per file.
+//  `jpeg_set_defaults` resets all parameters (including the mozjpeg extensions) before each image,
+//  except for the huffman tables, the ones optimized for the previous image are kept (and change trellis decisions).
+struct CompressContext {
+    cinfo: Box<jpeg_compress_struct>,
+    // the standard dc and ac tables 0 and 1 (as set by `jpeg_set_defaults` on the new struct)
+    standard_tables: [JHUFF_TBL; 4],
+    // referenced by `cinfo`, boxed so it does not move
+    _err: Box<jpeg_error_mgr>,
+}
+
+impl CompressContext {
+    // unwinds on libjpeg errors
+    fn new() -> Self {
+        unsafe {
+            let mut err: Box<jpeg_error_mgr> = Box::new(mem::zeroed());
+            let mut cinfo: Box<jpeg_compress_struct> = Box::new(mem::zeroed());
+            cinfo.common.err = unwinding_error_mgr(&mut err);
+            jpeg_create_compress(&mut *cinfo);
+            cinfo.input_components = 3;
+            cinfo.in_color_space = J_COLOR_SPACE::JCS_RGB;
+            jpeg_set_defaults(&mut cinfo);
+            let mut standard_tables: [JHUFF_TBL; 4] = mem::zeroed();
+            for (table, standard) in huffman_tables(&cinfo).into_iter().zip(&mut standard_tables) {
+                ptr::copy_nonoverlapping(table, standard, 1);
+            }
+            CompressContext { cinfo, standard_tables, _err: err }
+        }
+    }
+
+    // restores the standard huffman tables, to be called before `jpeg_set_defaults`
+    fn reset_huffman_tables(&mut self) {
+        for (table, standard) in huffman_tables(&self.cinfo).into_iter().zip(&self.standard_tables) {
+            unsafe { ptr::copy_nonoverlapping(standard, table, 1) };
+        }
+    }
+}
+
+fn huffman_tables(cinfo: &jpeg_compress_struct) -> [*mut JHUFF_TBL; 4] {
+    [cinfo.dc_huff_tbl_ptrs[0], cinfo.dc_huff_tbl_ptrs[1], cinfo.ac_huff_tbl_ptrs[0], cinfo.ac_huff_tbl_ptrs[1]]
+}
+
+impl Drop for CompressContext {
+    fn drop(&mut self) {
+        unsafe { jpeg_destroy_compress(&mut self.cinfo) };
+    }
+}
+
+thread_local! {
+    static COMPRESS_CONTEXT: RefCell<Option<CompressContext>> = const { RefCell::new(None) };
+}
 
 /// Provides encoder information
-pub fn encoder_info() -> String {
+pub fn encoder_info(quality: Option<f32>, restart_interval: Option<u16>, optimize_scans: bool, trellis: bool) -> String {
     // we might have multiple versions of the package, use rfind to find the newest one
     let mut mozjpeg_version = "";
-    match DEPENDENCIES.iter().rfind(|&&(name, _)| name == "mozjpeg") {
+    match DEPENDENCIES.iter().rfind(|&&(name, _)| name == "mozjpeg-sys") {
         Some((_name, version)) => {
             mozjpeg_version = version;
         }
         None => {
-            println!("Package '{}' not found", "mozjpeg");
+            println!("Package 'mozjpeg-sys' not found");
         }
     };
 
     format!(
-        "Using \"mozjpeg\" ({})",
-        mozjpeg_version
+        "Using \"mozjpeg\" ({}) with options (quality: {}, restart interval: {}, optimize scans: {}, trellis: {})",
+        mozjpeg_version,
+        quality.unwrap_or(DEFAULT_QUALITY),
+        restart_interval.unwrap_or(0),
+        optimize_scans,
+        trellis
     )
 }
 
 
-/// Encodes a `DynamicImage` to bytes of webp format
-pub fn encode_mozjpeg(image: &DynamicImage) -> Result<Vec<u8>, Error> {
-    let result = panic::catch_unwind(|| {
-        let mut comp = mozjpeg::Compress::new(mozjpeg::ColorSpace::JCS_RGB);
-        comp.set_size(image.width() as usize, image.height() as usize);
+/// Encodes a `DynamicImage` to bytes of jpeg format (`quality` 0 - 100, defaults to 75)
+pub fn encode_mozjpeg(image: &DynamicImage, quality: Option<f32>, restart_interval: Option<u16>, optimize_scans: bool,
+                      trellis: bool) -> Result<Vec<u8>, Error> {
+    let rgb = image.to_rgb8();
+    let (width, height) = rgb.dimensions();
+    with_compress_context(|dst, out_buffer, out_size| unsafe {
+        dst.image_width = width;
+        dst.image_height = height;
+        dst.input_components = 3;
+        dst.in_color_space = J_COLOR_SPACE::JCS_RGB;
+        jpeg_set_defaults(dst);
+        if let Some(quality) = quality {
+            // also picks the quantization tables tuned for the quality
+            jpeg_set_quality(dst, quality.clamp(0., 100.).round() as c_int, 1);
+        }
+
+        jpeg_c_set_bool_param(dst, JBOOLEAN_TRELLIS_QUANT, boolean::from(trellis));
+        jpeg_c_set_bool_param(dst, JBOOLEAN_TRELLIS_QUANT_DC, boolean::from(trellis));
+        if !optimize_scans {
+            // still progressive, with the standard scan script instead of searching for the smallest one
+            jpeg_c_set_bool_param(dst, JBOOLEAN_OPTIMIZE_SCANS, 0);
+            jpeg_simple_progression(dst);
+        }
+        // in MCUs, 0 = no restart markers
+        dst.restart_interval = restart_interval.unwrap_or(0).into();
+
+        jpeg_mem_dest(dst, out_buffer, out_size);
+        jpeg_start_compress(dst, 1);
+        let row_len = width as usize * 3;
+        for row in rgb.as_raw().chunks_exact(row_len) {
+            let rows = [row.as_ptr()];
+            jpeg_write_scanlines(dst, rows.as_ptr(), 1);
+        }
+        jpeg_finish_compress(dst);
+    }).map_err(|e| Error::from_string(format!("mozjpeg encoding failed: {}", e)))
+}
+
+/// Encodes a `DynamicImage` in jpeg format, writing the result into `writer`
+///
+/// libjpeg is driven directly (the mozjpeg crate exposes neither restart markers nor trellis quantization),
+/// the encoded image is buffered before writing.
+pub fn encode_mozjpeg_to<W: Write>(image: &DynamicImage, quality: Option<f32>, restart_interval: Option<u16>, optimize_scans: bool,
+                                   trellis: bool, mut writer: W) -> Result<(), Error> {
+    writer.write_all(&encode_mozjpeg(image, quality, restart_interval, optimize_scans, trellis)?)?;
+    Ok(())
+}
+
+/// Losslessly applies the EXIF `orientation` (2 - 8, anything else keeps the orientation) to a jpeg
+///
+/// The DCT coefficients are transformed like `jpegtran -trim` does, so the pixels are never re-encoded.
+/// Partial blocks at the right/bottom edge (less than one MCU) cannot be rotated losslessly and are trimmed.
+/// Only the ICC profile is copied from the input (other metadata is up to the caller).
+pub fn transform_mozjpeg(data: &[u8], orientation: u16) -> Result<Vec<u8>, Error> {
+    let transform = match orientation {
+        2 => JXFORM_CODE_JXFORM_FLIP_H,
+        3 => JXFORM_CODE_JXFORM_ROT_180,
+        4 => JXFORM_CODE_JXFORM_FLIP_V,
+        5 => JXFORM_CODE_JXFORM_TRANSPOSE,
+        6 => JXFORM_CODE_JXFORM_ROT_90,
+        7 => JXFORM_CODE_JXFORM_TRANSVERSE,
+        8 => JXFORM_CODE_JXFORM_ROT_270,
+        _ => JXFORM_CODE_JXFORM_NONE,
+    };
+
+    run_libjpeg(|src, dst, out_buffer, out_size| unsafe {
+        jpeg_create_decompress(src);
+        jpeg_create_compress(dst);
+        jpeg_mem_src(src, data.as_ptr(), data.len() as c_ulong);
+        jcopy_markers_setup(src, JCOPY_OPTION_JCOPYOPT_ICC);
+        jpeg_read_header(src, 1);
+
+        let mut info: TransformInfo = mem::zeroed();
+        info.transform = transform;
+        info.trim = 1;
+        let info = &mut info as *mut TransformInfo as *mut jpeg_transform_info;
+        // only fails for `perfect` transforms
+        jtransform_request_workspace(src, info);
+        let src_coefficients = jpeg_read_coefficients(src);
+        jpeg_copy_critical_parameters(src, dst);
+        let dst_coefficients = jtransform_adjust_parameters(src, dst, src_coefficients, info);
+
+        jpeg_mem_dest(dst, out_buffer, out_size);
+        jpeg_write_coefficients(dst, dst_coefficients);
+        jcopy_markers_execute(src, dst, JCOPY_OPTION_JCOPYOPT_ICC);
+        jtransform_execute_transform(src, dst, src_coefficients, info);
+        jpeg_finish_compress(dst);
+        jpeg_finish_decompress(src);
+    }).map_err(|e| Error::from_string(format!("mozjpeg transform failed: {}", e)))
+}
+
+// Runs `f` with the compress struct of the current thread (created on first use) and the `jpeg_mem_dest` buffer
+//  to write into. The struct is only kept for the next image if `f` did not fail, libjpeg errors leave it in an
+//  unknown state. Returns the written buffer or the libjpeg error message.
+fn with_compress_context<F>(f: F) -> Result<Vec<u8>, String>
+where
+    F: FnOnce(&mut jpeg_compress_struct, &mut *mut u8, &mut c_ulong),
+{
+    let mut context = COMPRESS_CONTEXT.with_borrow_mut(Option::take);
+    let mut out_buffer: *mut u8 = ptr::null_mut();
+    let mut out_size: c_ulong = 0;
+
+    let result = panic::catch_unwind(AssertUnwindSafe(|| {
+        let context = context.get_or_insert_with(CompressContext::new);
+        context.reset_huffman_tables();
+        f(&mut context.cinfo, &mut out_buffer, &mut out_size)
+    }));
+
+    if result.is_ok() {
+        COMPRESS_CONTEXT.with_borrow_mut(|slot| *slot = context);
+    }
+    unsafe { take_output(result, out_buffer, out_size) }
+}
 
-        let mut comp = comp.start_compress(Vec::new())
-            .map_err(|e| Error::from_string(format!("mozjpeg encoding (start_compress) failed: {:?}", e)))?;
+// Runs `f` with zeroed libjpeg structs (with unwinding error handlers) and the `jpeg_mem_dest` buffer to write into,
+//  `f` creates the structs it uses. Returns the written buffer or the libjpeg error message.
+fn run_libjpeg<F>(f: F) -> Result<Vec<u8>, String>
+where
+    F: FnOnce(&mut jpeg_decompress_struct, &mut jpeg_compress_struct, &mut *mut u8, &mut c_ulong),
+{
+    unsafe {
+        let mut src_err: jpeg_error_mgr = mem::zeroed();
+        let mut dst_err: jpeg_error_mgr = mem::zeroed();
+        let mut src: jpeg_decompress_struct = mem::zeroed();
+        let mut dst: jpeg_compress_struct = mem::zeroed();
+        src.common.err = unwinding_error_mgr(&mut src_err);
+        dst.common.err = unwinding_error_mgr(&mut dst_err);
+        let mut out_buffer: *mut u8 = ptr::null_mut();
+        let mut out_size: c_ulong = 0;
 
-        comp.write_scanlines(image.to_rgb8().as_bytes())
-            .map_err(|e| Error::from_string(format!("mozjpeg encoding (write_scanlines) failed: {:?}", e)))?;
+        let result = panic::catch_unwind(AssertUnwindSafe(|| f(&mut src, &mut dst, &mut out_buffer, &mut out_size)));
 
-        comp.finish().map_err(|e| Error::from_string(format!("mozjpeg encoding (finish) failed: {:?}", e)))
-    });
+        // destroying is a no-op for structs that were never created
+        jpeg_destroy_compress(&mut dst);
+        jpeg_destroy_decompress(&mut src);
+        take_output(result, out_buffer, out_size)
+    }
+}
 
-    result.unwrap_or_else(|e| Err(Error::from_string(format!("mozjpeg encoding panicked: {:?}", e))))
-}
\ No newline at end of file
+// Copies the `jpeg_mem_dest` buffer (or the libjpeg error message of `result`) and frees the buffer
+unsafe fn take_output(result: std::thread::Result<()>, out_buffer: *mut u8, out_size: c_ulong) -> Result<Vec<u8>, String> {
+    let output = match result {
+        Ok(()) if !out_buffer.is_null() => Ok(unsafe { slice::from_raw_parts(out_buffer, out_size as usize) }.to_vec()),
+        Ok(()) => Err("no output written".to_string()),
+        Err(e) => Err(e.downcast_ref::<String>().map_or("unknown error", String::as_str).to_string()),
+    };
+    if !out_buffer.is_null() {
+        unsafe { free(out_buffer as *mut c_void) };
+    }
+    output
+}
+
+unsafe fn unwinding_error_mgr(err: &mut jpeg_error_mgr) -> *mut jpeg_error_mgr {
+    let err = unsafe { jpeg_std_error(err) };
+    err.error_exit = Some(unwind_error_exit);
+    err.emit_message = Some(silence_message);
+    err
+}
+
+extern "C-unwind" fn silence_message(_cinfo: &mut jpeg_common_struct, _level: std::os::raw::c_int) {}
+
+extern "C-unwind" fn unwind_error_exit(cinfo: &mut jpeg_common_struct) {
+    let code = unsafe { cinfo.err.as_ref() }.map_or(0, |err| err.msg_code);
+    // like the mozjpeg crate, unwind through libjpeg back into `catch_unwind` (without the panic hook)
+    panic::resume_unwind(Box::new(format!("libjpeg error code {}", code)));
+}
@@ -1,11 +1,14 @@
-use crate::Error;
-use image::{DynamicImage, ImageEncoder};
+use crate::{animation::Frame, Error};
+use image::{DynamicImage, ExtendedColorType, ImageEncoder, RgbaImage};
 use crate::converter::DEPENDENCIES;
+use std::{collections::{HashMap, HashSet}, io::Write};
 
 macro_rules! copy_enum_variants {
     ($name:ident, $($variant:ident),*) => {
         #[allow(missing_docs)]
-        #[derive(clap::ValueEnum, Clone, Copy, PartialEq, Eq, Debug)]
+        #[cfg_attr(feature = "cli", derive(clap::ValueEnum))]
+        #[derive(serde::Serialize, serde::Deserialize, Clone, Copy, PartialEq, Eq, Debug)]
+        #[serde(rename_all = "kebab-case")]
         pub enum $name {
             $($variant),*
         }
@@ -16,6 +19,23 @@ macro_rules! copy_enum_variants {
 copy_enum_variants!(CompressionType, Default, Fast, Best);
 copy_enum_variants!(FilterType, NoFilter, Sub, Up, Avg, Paeth, Adaptive);
 
+/// Color type of the png output
+#[cfg_attr(feature = "cli", derive(clap::ValueEnum))]
+#[derive(serde::Serialize, serde::Deserialize, Clone, Copy, PartialEq, Eq, Debug)]
+#[serde(rename_all = "kebab-case")]
+pub enum ColorType {
+    /// Smallest lossless color type the pixels allow (gray, palette of up to 256 colors, rgb or rgba).
+    Auto,
+    /// Rgb, dropping the alpha channel.
+    Rgb,
+    /// Rgba.
+    Rgba,
+    /// Indexed colors, quantized (lossy) if the image has more than 256 colors.
+    Palette,
+    /// Grayscale (with alpha if the input has an alpha channel), colors are converted to luma.
+    Gray,
+}
+
 fn convert_compression_type_to_ext(compression_type: Option<CompressionType>) -> image::codecs::png::CompressionType {
     match compression_type.unwrap_or(CompressionType::Default) {
         CompressionType::Default => image::codecs::png::CompressionType::Default,
@@ -34,6 +54,27 @@ fn convert_filter_type_to_ext(filter_type: Option<FilterType>) -> image::codecs:
     }
 }
 
+// the png crate is used directly for indexed images (not supported by the image crates encoder),
+//  mapped like the image crate does
+fn convert_compression_type_to_png(compression_type: Option<CompressionType>) -> png::Compression {
+    match compression_type.unwrap_or(CompressionType::Default) {
+        CompressionType::Default => png::Compression::Balanced,
+        CompressionType::Fast => png::Compression::Fast,
+        CompressionType::Best => png::Compression::High,
+    }
+}
+fn convert_filter_type_to_png(filter_type: Option<FilterType>) -> png::Filter {
+    // like libpng, indexed images are not filtered by default
+    match filter_type.unwrap_or(FilterType::NoFilter) {
+        FilterType::NoFilter => png::Filter::NoFilter,
+        FilterType::Sub => png::Filter::Sub,
+        FilterType::Up => png::Filter::Up,
+        FilterType::Avg => png::Filter::Avg,
+        FilterType::Paeth => png::Filter::Paeth,
+        FilterType::Adaptive => png::Filter::Adaptive,
+    }
+}
+
 /// Provides encoder information
 pub fn encoder_info() -> String {
     // we might have multiple versions of the package, use rfind to find the newest one
@@ -43,7 +84,7 @@ pub fn encoder_info() -> String {
             image_version = version;
         }
         None => {
-            println!("Package '{}' not found", "image");
+            println!("Package 'image' not found");
         }
     };
 
@@ -54,31 +95,157 @@ pub fn encoder_info() -> String {
 }
 
 
-/// Encodes a `DynamicImage` to bytes of webp format
-pub fn encode_png(image: &DynamicImage, compression_type: Option<CompressionType>, filter_type: Option<FilterType>) -> Result<Vec<u8>, Error> {
+/// Encodes a `DynamicImage` to bytes of png format
+pub fn encode_png(image: &DynamicImage, compression_type: Option<CompressionType>, filter_type: Option<FilterType>,
+                  color_type: Option<ColorType>) -> Result<Vec<u8>, Error> {
     let mut output = Vec::new();
+    encode_png_to(image, compression_type, filter_type, color_type, &mut output)?;
+    Ok(output)
+}
+
+/// Encodes a `DynamicImage` in png format, streaming the result into `writer`
+///
+/// Without a `color_type`, rgb or rgba is written depending on the alpha channel of the image.
+pub fn encode_png_to<W: Write>(image: &DynamicImage, compression_type: Option<CompressionType>, filter_type: Option<FilterType>,
+                               color_type: Option<ColorType>, mut writer: W) -> Result<(), Error> {
+    let has_alpha = image.color().has_alpha();
+    let (color_type, alpha) = match color_type {
+        None if has_alpha => (ColorType::Rgba, true),
+        None => (ColorType::Rgb, false),
+        Some(ColorType::Auto) => {
+            let rgba = image.to_rgba8();
+            let (color_type, alpha) = lossless_color_type(&rgba);
+            if let Some((palette, indices)) = exact_palette(&rgba) {
+                // a palette does not always win (e.g. for smooth gradients), keep the smaller encoding
+                let mut indexed = Vec::new();
+                encode_indexed_to(&rgba, &palette, &indices, compression_type, filter_type, &mut indexed)?;
+                let mut direct = Vec::new();
+                encode_direct_to(image, color_type, alpha, compression_type, filter_type, &mut direct)?;
+                writer.write_all(if indexed.len() < direct.len() { &indexed } else { &direct })?;
+                return Ok(());
+            }
+            (color_type, alpha)
+        }
+        Some(ColorType::Rgb) => (ColorType::Rgb, false),
+        Some(ColorType::Rgba) => (ColorType::Rgba, true),
+        Some(ColorType::Palette) => {
+            let rgba = image.to_rgba8();
+            let (palette, indices) = exact_palette(&rgba).unwrap_or_else(|| quantized_palette(&rgba));
+            return encode_indexed_to(&rgba, &palette, &indices, compression_type, filter_type, writer);
+        }
+        Some(ColorType::Gray) => (ColorType::Gray, has_alpha),
+    };
+    encode_direct_to(image, color_type, alpha, compression_type, filter_type, writer)
+}
+
+/// Encodes the frames of an animation to bytes of apng format (rgba)
+pub fn encode_png_animation(frames: &[Frame], compression_type: Option<CompressionType>, filter_type: Option<FilterType>)
+    -> Result<Vec<u8>, Error> {
+    let first = frames.first().ok_or_else(|| Error::from_string("animation without frames".to_string()))?;
+    let png_error = |e: png::EncodingError| Error::from_string(format!("apng encoding failed: {:?}", e));
+    let mut output = Vec::new();
+    let mut encoder = png::Encoder::new(&mut output, first.image.width(), first.image.height());
+    encoder.set_color(png::ColorType::Rgba);
+    encoder.set_depth(png::BitDepth::Eight);
+    encoder.set_compression(convert_compression_type_to_png(compression_type));
+    // adaptive like the rgba stills (no filter is only the default of indexed images)
+    encoder.set_filter(convert_filter_type_to_png(filter_type.or(Some(FilterType::Adaptive))));
+    // 0 plays = loop forever
+    encoder.set_animated(frames.len() as u32, 0).map_err(png_error)?;
+    let mut writer = encoder.write_header().map_err(png_error)?;
+    for frame in frames {
+        writer.set_frame_delay(frame.delay_ms.min(u16::MAX.into()) as u16, 1000).map_err(png_error)?;
+        writer.write_image_data(frame.image.to_rgba8().as_raw()).map_err(png_error)?;
+    }
+    writer.finish().map_err(png_error)?;
+    Ok(output)
+}
+
+// smallest lossless non-indexed color type of the pixels and whether it needs an alpha channel
+fn lossless_color_type(image: &RgbaImage) -> (ColorType, bool) {
+    let alpha = image.pixels().any(|p| p[3] != u8::MAX);
+    let gray = image.pixels().all(|p| p[0] == p[1] && p[1] == p[2]);
+    match (gray, alpha) {
+        (true, _) => (ColorType::Gray, alpha),
+        (false, true) => (ColorType::Rgba, true),
+        (false, false) => (ColorType::Rgb, false),
+    }
+}
+
+fn encode_direct_to<W: Write>(image: &DynamicImage, color_type: ColorType, alpha: bool,
+                              compression_type: Option<CompressionType>, filter_type: Option<FilterType>,
+                              writer: W) -> Result<(), Error> {
+    let (data, ext_color_type) = match (color_type, alpha) {
+        (ColorType::Gray, false) => (image.to_luma8().into_raw(), ExtendedColorType::L8),
+        (ColorType::Gray, true) => (image.to_luma_alpha8().into_raw(), ExtendedColorType::La8),
+        (_, false) => (image.to_rgb8().into_raw(), ExtendedColorType::Rgb8),
+        (_, true) => (image.to_rgba8().into_raw(), ExtendedColorType::Rgba8),
+    };
     let ext_compression_type = convert_compression_type_to_ext(compression_type);// default is fast
     let ext_filter_type = convert_filter_type_to_ext(filter_type); // default is adaptive
-    if image.color().has_alpha() {
-        let source_image = image.to_rgba8();
-        image::codecs::png::PngEncoder::new_with_quality(&mut output, ext_compression_type, ext_filter_type)
-            .write_image(
-                source_image.as_ref(),
-                image.width(),
-                image.height(),
-                image::ExtendedColorType::Rgba8,
-            )
-            .map_err(|e| Error::from_string(format!("png encoding failed: {:?}", e)))?;
-    } else {
-        let source_image = image.to_rgb8();
-        image::codecs::png::PngEncoder::new_with_quality(&mut output, ext_compression_type, ext_filter_type)
-            .write_image(
-                source_image.as_ref(),
-                image.width(),
-                image.height(),
-                image::ExtendedColorType::Rgb8,
-            )
-            .map_err(|e| Error::from_string(format!("png encoding failed: {:?}", e)))?;
+    image::codecs::png::PngEncoder::new_with_quality(writer, ext_compression_type, ext_filter_type)
+        .write_image(&data, image.width(), image.height(), ext_color_type)
+        .map_err(|e| Error::from_string(format!("png encoding failed: {:?}", e)))?;
+    Ok(())
+}
+
+// palette and per pixel indices if the image has at most 256 colors,
+//  transparent colors first so that the tRNS chunk can omit the opaque ones
+fn exact_palette(image: &RgbaImage) -> Option<(Vec<[u8; 4]>, Vec<u8>)> {
+    let mut colors = HashSet::new();
+    for pixel in image.pixels() {
+        if colors.insert(pixel.0) && colors.len() > 256 {
+            return None;
+        }
     }
-    Ok(output)
+    let mut palette: Vec<[u8; 4]> = colors.into_iter().collect();
+    palette.sort_unstable_by_key(|c| (c[3] == u8::MAX, *c));
+    let lookup: HashMap<[u8; 4], u8> = palette.iter().enumerate().map(|(i, c)| (*c, i as u8)).collect();
+    let indices = image.pixels().map(|p| lookup[&p.0]).collect();
+    Some((palette, indices))
+}
+
+// lossy 256 color palette (NeuQuant) and per pixel indices
+fn quantized_palette(image: &RgbaImage) -> (Vec<[u8; 4]>, Vec<u8>) {
+    let quantizer = color_quant::NeuQuant::new(10, 256, image.as_raw());
+    let palette = quantizer.color_map_rgba().chunks_exact(4).map(|c| [c[0], c[1], c[2], c[3]]).collect();
+    let indices = image.pixels().map(|p| quantizer.index_of(&p.0) as u8).collect();
+    (palette, indices)
+}
+
+fn encode_indexed_to<W: Write>(image: &RgbaImage, palette: &[[u8; 4]], indices: &[u8],
+                               compression_type: Option<CompressionType>, filter_type: Option<FilterType>,
+                               writer: W) -> Result<(), Error> {
+    // pack the indices with the smallest bit depth fitting the palette
+    let bit_depth = match palette.len() {
+        0..=2 => png::BitDepth::One,
+        3..=4 => png::BitDepth::Two,
+        5..=16 => png::BitDepth::Four,
+        _ => png::BitDepth::Eight,
+    };
+    let bits = bit_depth as usize;
+    let width = image.width() as usize;
+    let row_len = (width * bits).div_ceil(8);
+    let mut data = vec![0u8; row_len * image.height() as usize];
+    for (row, row_indices) in data.chunks_exact_mut(row_len).zip(indices.chunks_exact(width)) {
+        for (x, &index) in row_indices.iter().enumerate() {
+            row[x * bits / 8] |= index << (8 - bits - x * bits % 8);
+        }
+    }
+
+    let mut encoder = png::Encoder::new(writer, image.width(), image.height());
+    encoder.set_color(png::ColorType::Indexed);
+    encoder.set_depth(bit_depth);
+    encoder.set_palette(palette.iter().flat_map(|c| [c[0], c[1], c[2]]).collect::<Vec<u8>>());
+    let transparent = palette.iter().rposition(|c| c[3] != u8::MAX).map_or(0, |i| i + 1);
+    if transparent > 0 {
+        encoder.set_trns(palette[..transparent].iter().map(|c| c[3]).collect::<Vec<u8>>());
+    }
+    encoder.set_compression(convert_compression_type_to_png(compression_type));
+    encoder.set_filter(convert_filter_type_to_png(filter_type));
+    let mut writer = encoder.write_header()
+        .map_err(|e| Error::from_string(format!("png encoding failed: {:?}", e)))?;
+    writer.write_image_data(&data)
+        .and_then(|_| writer.finish())
+        .map_err(|e| Error::from_string(format!("png encoding failed: {:?}", e)))
 }
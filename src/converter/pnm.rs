@@ -0,0 +1,87 @@
+use crate::Error;
+use image::{
+    codecs::pnm::{PnmEncoder, PnmSubtype as ImagePnmSubtype, SampleEncoding},
+    DynamicImage, ExtendedColorType, ImageEncoder,
+};
+use crate::converter::DEPENDENCIES;
+use std::io::Write;
+
+/// Subtype (magic number) of the pnm output, all with 8 bit samples
+#[cfg_attr(feature = "cli", derive(clap::ValueEnum))]
+#[derive(serde::Serialize, serde::Deserialize, Clone, Copy, PartialEq, Eq, Debug)]
+#[serde(rename_all = "kebab-case")]
+pub enum PnmSubtype {
+    /// Pgm for grayscale inputs, ppm for color inputs and pam for inputs with an alpha channel.
+    Auto,
+    /// Portable bitmap (P4/P1), black and white thresholded at half luma.
+    Pbm,
+    /// Portable graymap (P5/P2), colors are converted to luma.
+    Pgm,
+    /// Portable pixmap (P6/P3), dropping the alpha channel.
+    Ppm,
+    /// Portable arbitrary map (P7), keeping the alpha channel.
+    Pam,
+}
+
+/// Provides encoder information
+pub fn encoder_info(subtype: Option<PnmSubtype>, plain: bool) -> String {
+    // we might have multiple versions of the package, use rfind to find the newest one
+    let mut image_version = "";
+    match DEPENDENCIES.iter().rfind(|&&(name, _)| name == "image") {
+        Some((_name, version)) => {
+            image_version = version;
+        }
+        None => {
+            println!("Package 'image' not found");
+        }
+    };
+
+    format!(
+        "Using \"pnm (from image crate)\" ({}) with options (subtype: {:?}, plain: {})",
+        image_version,
+        subtype.unwrap_or(PnmSubtype::Auto),
+        plain
+    )
+}
+
+
+/// Encodes a `DynamicImage` to bytes of pnm format
+pub fn encode_pnm(image: &DynamicImage, subtype: Option<PnmSubtype>, plain: bool) -> Result<Vec<u8>, Error> {
+    let mut output = Vec::new();
+    encode_pnm_to(image, subtype, plain, &mut output)?;
+    Ok(output)
+}
+
+/// Encodes a `DynamicImage` in pnm format, streaming the result into `writer`
+///
+/// `plain` writes the ascii variant of pbm, pgm and ppm, pam is always binary.
+pub fn encode_pnm_to<W: Write>(image: &DynamicImage, subtype: Option<PnmSubtype>, plain: bool, writer: W) -> Result<(), Error> {
+    let encoding = if plain { SampleEncoding::Ascii } else { SampleEncoding::Binary };
+    let subtype = match subtype.unwrap_or(PnmSubtype::Auto) {
+        PnmSubtype::Auto if image.color().has_alpha() => PnmSubtype::Pam,
+        PnmSubtype::Auto if image.color().has_color() => PnmSubtype::Ppm,
+        PnmSubtype::Auto => PnmSubtype::Pgm,
+        subtype => subtype,
+    };
+
+    let (image_subtype, color, data) = match subtype {
+        PnmSubtype::Pbm => {
+            // the encoder takes 0 for black and 1 for white, but writes the plain samples unchanged
+            //  although 1 is black in plain pbm
+            let (black, white) = if plain { (1, 0) } else { (0, 1) };
+            let data = image.to_luma8().into_raw().into_iter()
+                .map(|luma| if luma < 128 { black } else { white })
+                .collect();
+            (ImagePnmSubtype::Bitmap(encoding), ExtendedColorType::L8, data)
+        }
+        PnmSubtype::Pgm => (ImagePnmSubtype::Graymap(encoding), ExtendedColorType::L8, image.to_luma8().into_raw()),
+        PnmSubtype::Ppm => (ImagePnmSubtype::Pixmap(encoding), ExtendedColorType::Rgb8, image.to_rgb8().into_raw()),
+        _ if image.color().has_color() => (ImagePnmSubtype::ArbitraryMap, ExtendedColorType::Rgba8, image.to_rgba8().into_raw()),
+        _ => (ImagePnmSubtype::ArbitraryMap, ExtendedColorType::La8, image.to_luma_alpha8().into_raw()),
+    };
+    PnmEncoder::new(writer)
+        .with_subtype(image_subtype)
+        .write_image(&data, image.width(), image.height(), color)
+        .map_err(|e| Error::from_string(format!("pnm encoding failed: {:?}", e)))?;
+    Ok(())
+}
@@ -1,10 +1,31 @@
-use crate::Error;
+use crate::{animation::Frame, Error};
 use image::DynamicImage;
-use webp::{Encoder};
 use crate::converter::DEPENDENCIES;
+use libwebp_sys::*;
+use std::{cell::RefCell, ffi::CStr, io::Write, mem, ptr, slice};
+
+// outputs growing the buffer beyond this are not kept for the next image
+const MAX_KEPT_BUFFER_SIZE: usize = 16 << 20;
+
+// Output buffer of libwebp kept between the images of a worker thread, its capacity is reused
+struct MemoryWriter(WebPMemoryWriter);
+
+impl Drop for MemoryWriter {
+    fn drop(&mut self) {
+        unsafe { WebPMemoryWriterClear(&mut self.0) };
+    }
+}
+
+thread_local! {
+    static MEMORY_WRITER: RefCell<MemoryWriter> = RefCell::new(MemoryWriter(unsafe {
+        let mut writer = mem::zeroed();
+        WebPMemoryWriterInit(&mut writer);
+        writer
+    }));
+}
 
 /// Provides encoder information
-pub fn encoder_info(lossless: bool, qualify: f32) -> String {
+pub fn encoder_info(lossless: bool, qualify: f32, near_lossless: Option<u8>) -> String {
     // we might have multiple versions of the package, use rfind to find the newest one
     let mut webp_version = "";
     match DEPENDENCIES.iter().rfind(|&&(name, _)| name == "webp") {
@@ -12,21 +33,49 @@ pub fn encoder_info(lossless: bool, qualify: f32) -> String {
             webp_version = version;
         }
         None => {
-            println!("Package '{}' not found", "webp");
+            println!("Package 'webp' not found");
         }
     };
 
     format!(
-        "Using \"webp\" ({}) with options (lossless: {}, qualify: {})",
+        "Using \"webp\" ({}) with options (lossless: {}, qualify: {}, near_lossless: {:?})",
         webp_version,
-        lossless,
-        qualify
+        lossless || near_lossless.is_some(),
+        qualify,
+        near_lossless
     )
 }
 
 
+/// Encoder configuration like `Encoder::encode_simple` of the webp crate, with optional near-lossless preprocessing
+///
+/// `near_lossless` (0 - 100, lower values adjust more pixel values, 100 is off) implies lossless encoding.
+fn webp_config(lossless: bool, quality: f32, near_lossless: Option<u8>) -> Result<WebPConfig, Error> {
+    let mut config = WebPConfig::new()
+        .map_err(|_| Error::from_string("webp config initialization failed".to_string()))?;
+    let lossless = lossless || near_lossless.is_some();
+    config.lossless = lossless.into();
+    config.alpha_compression = (!lossless).into();
+    config.quality = quality;
+    if let Some(level) = near_lossless {
+        config.near_lossless = level.min(100).into();
+    }
+    Ok(config)
+}
+
 /// Encodes a `DynamicImage` to bytes of webp format
-pub fn encode_webp(image: &DynamicImage, lossless: bool, quality: f32) -> Result<Vec<u8>, Error> {
+pub fn encode_webp(image: &DynamicImage, lossless: bool, quality: f32, near_lossless: Option<u8>) -> Result<Vec<u8>, Error> {
+    let mut output = Vec::new();
+    encode_webp_to(image, lossless, quality, near_lossless, &mut output)?;
+    Ok(output)
+}
+
+/// Encodes a `DynamicImage` in webp format, writing the result into `writer`
+///
+/// libwebp encodes into a buffer kept per worker thread, which is copied into `writer` without another intermediate `Vec`.
+pub fn encode_webp_to<W: Write>(image: &DynamicImage, lossless: bool, quality: f32, near_lossless: Option<u8>,
+                              mut writer: W) -> Result<(), Error> {
+    // Luma[A]8 inputs are imported as rgb[a]
     let converted_image: Option<DynamicImage> = match image {
         DynamicImage::ImageLuma8(_) => {
             Some(DynamicImage::ImageRgb8(image.to_rgb8()))
@@ -36,19 +85,92 @@ pub fn encode_webp(image: &DynamicImage, lossless: bool, quality: f32) -> Result
         }
         _ => None,
     };
+    let image = converted_image.as_ref().unwrap_or(image);
+    let config = webp_config(lossless, quality, near_lossless)?;
 
-    let encoder = if let Some(ref img) = converted_image {
-        // Use the converted image (Luma[A]8 paths are unimplemented in the webp lib :D
-        Encoder::from_image(img)
-            .map_err(|e| Error::from_string(format!("Failed to create webp encoder for luma input: {:?}", e)))?
-    } else {
-        Encoder::from_image(image)
-            .map_err(|e| Error::from_string(format!("Failed to create webp encoder: {:?}", e)))?
-    };
+    MEMORY_WRITER.with_borrow_mut(|MemoryWriter(memory)| unsafe {
+        let mut picture = WebPPicture::new()
+            .map_err(|_| Error::from_string("webp picture initialization failed".to_string()))?;
+        picture.use_argb = 1;
+        picture.width = image.width() as i32;
+        picture.height = image.height() as i32;
+        let imported = match image {
+            DynamicImage::ImageRgb8(rgb) => WebPPictureImportRGB(&mut picture, rgb.as_ptr(), rgb.width() as i32 * 3),
+            DynamicImage::ImageRgba8(rgba) => WebPPictureImportRGBA(&mut picture, rgba.as_ptr(), rgba.width() as i32 * 4),
+            _ => {
+                return Err(Error::from_string(format!("Failed to create webp encoder: unsupported color type {:?}",
+                                                      image.color())));
+            }
+        };
+        memory.size = 0;
+        picture.writer = Some(WebPMemoryWrite);
+        picture.custom_ptr = memory as *mut WebPMemoryWriter as *mut _;
+        let encoded = imported != 0 && WebPEncode(&config, &mut picture) != 0;
+        let error_code = picture.error_code;
+        WebPPictureFree(&mut picture);
+
+        let result = if encoded {
+            writer.write_all(slice::from_raw_parts(memory.mem, memory.size)).map_err(Error::from)
+        } else {
+            Err(Error::from_string(format!("webp encoding failed: {:?}", error_code)))
+        };
+        if memory.max_size > MAX_KEPT_BUFFER_SIZE {
+            WebPMemoryWriterClear(memory);
+        }
+        result
+    })
+}
 
-    let webp_data = encoder
-        .encode_simple(lossless, quality)
-        .map_err(|e| Error::from_string(format!("webp encoding failed: {:?}", e)))?;
+/// Encodes the frames of an animation to bytes of (animated) webp format
+///
+/// libwebp is driven directly, the animation encoder of the webp crate loses the duration of the last frame.
+pub fn encode_webp_animation(frames: &[Frame], lossless: bool, quality: f32, near_lossless: Option<u8>) -> Result<Vec<u8>, Error> {
+    let first = frames.first().ok_or_else(|| Error::from_string("animation without frames".to_string()))?;
+    let (width, height) = (first.image.width(), first.image.height());
+    let config = webp_config(lossless, quality, near_lossless)?;
 
-    Ok(webp_data.to_vec())
-}
\ No newline at end of file
+    unsafe {
+        let mut anim_options: WebPAnimEncoderOptions = mem::zeroed();
+        if WebPAnimEncoderOptionsInitInternal(&mut anim_options, WEBP_MUX_ABI_VERSION as _) == 0 {
+            return Err(Error::from_string("webp animation options initialization failed".to_string()));
+        }
+        let encoder = WebPAnimEncoderNewInternal(width as _, height as _, &anim_options, WEBP_MUX_ABI_VERSION as _);
+        if encoder.is_null() {
+            return Err(Error::from_string("webp animation encoder initialization failed".to_string()));
+        }
+        let anim_error = |encoder| {
+            let msg = CStr::from_ptr(WebPAnimEncoderGetError(encoder)).to_string_lossy();
+            Error::from_string(format!("webp animation encoding failed: {}", msg))
+        };
+
+        let result = (|| {
+            let mut timestamp_ms = 0;
+            for frame in frames {
+                let rgba = frame.image.to_rgba8();
+                let mut picture = WebPPicture::new()
+                    .map_err(|_| Error::from_string("webp picture initialization failed".to_string()))?;
+                picture.use_argb = 1;
+                picture.width = width as _;
+                picture.height = height as _;
+                let ok = WebPPictureImportRGBA(&mut picture, rgba.as_ptr(), width as i32 * 4) != 0
+                    && WebPAnimEncoderAdd(encoder, &mut picture, timestamp_ms, &config) != 0;
+                WebPPictureFree(&mut picture);
+                if !ok {
+                    return Err(anim_error(encoder));
+                }
+                timestamp_ms += frame.delay_ms as i32;
+            }
+            // the end timestamp sets the duration of the last frame
+            WebPAnimEncoderAdd(encoder, ptr::null_mut(), timestamp_ms, ptr::null());
+            let mut data = WebPData::default();
+            if WebPAnimEncoderAssemble(encoder, &mut data) == 0 {
+                return Err(anim_error(encoder));
+            }
+            let output = slice::from_raw_parts(data.bytes, data.size).to_vec();
+            WebPDataClear(&mut data);
+            Ok(output)
+        })();
+        WebPAnimEncoderDelete(encoder);
+        result
+    }
+}
@@ -1,6 +1,7 @@
 use crate::Error;
 use image::{DynamicImage, ImageEncoder};
 use crate::converter::DEPENDENCIES;
+use std::io::Write;
 
 /// Provides encoder information
 pub fn encoder_info() -> String {
@@ -11,7 +12,7 @@ pub fn encoder_info() -> String {
             image_version = version;
         }
         None => {
-            println!("Package '{}' not found", "image");
+            println!("Package 'image' not found");
         }
     };
 
@@ -25,9 +26,15 @@ pub fn encoder_info() -> String {
 /// Encodes a `DynamicImage` to bytes of webp format
 pub fn encode_webp_image(image: &DynamicImage) -> Result<Vec<u8>, Error> {
     let mut output = Vec::new();
+    encode_webp_image_to(image, &mut output)?;
+    Ok(output)
+}
+
+/// Encodes a `DynamicImage` in lossless webp format, streaming the result into `writer`
+pub fn encode_webp_image_to<W: Write>(image: &DynamicImage, writer: W) -> Result<(), Error> {
     if image.color().has_alpha() {
         let source_image = image.to_rgba8();
-        image::codecs::webp::WebPEncoder::new_lossless(&mut output)
+        image::codecs::webp::WebPEncoder::new_lossless(writer)
             .write_image(
                 source_image.as_ref(),
                 image.width(),
@@ -36,7 +43,7 @@ pub fn encode_webp_image(image: &DynamicImage) -> Result<Vec<u8>, Error> {
             ).map_err(|e| Error::from_string(format!("webp-image encoding failed: {:?}", e)))?;
     } else {
         let source_image = image.to_rgb8();
-        image::codecs::webp::WebPEncoder::new_lossless(&mut output)
+        image::codecs::webp::WebPEncoder::new_lossless(writer)
             .write_image(
                 source_image.as_ref(),
                 image.width(),
@@ -44,5 +51,5 @@ pub fn encode_webp_image(image: &DynamicImage) -> Result<Vec<u8>, Error> {
                 image::ExtendedColorType::Rgb8,
             ).map_err(|e| Error::from_string(format!("webp-image encoding failed: {:?}", e)))?;
     }
-    Ok(output)
+    Ok(())
 }
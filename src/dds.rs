@@ -0,0 +1,387 @@
+use crate::{decode::check_pixel_limit, utils::linear_to_srgb, Error};
+use image::{DynamicImage, GrayAlphaImage, GrayImage, Luma, Rgba, RgbImage, RgbaImage};
+
+const MAGIC: &[u8] = b"DDS ";
+// magic + header, followed by the optional DX10 header
+const HEADER_LEN: usize = 128;
+const DX10_HEADER_LEN: usize = 20;
+
+// pixel format flags
+const DDPF_ALPHAPIXELS: u32 = 0x1;
+const DDPF_ALPHA: u32 = 0x2;
+const DDPF_FOURCC: u32 = 0x4;
+const DDPF_RGB: u32 = 0x40;
+const DDPF_LUMINANCE: u32 = 0x20000;
+
+/// Pixel formats of the top level surface
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum DdsFormat {
+    Bc1,
+    Bc2,
+    Bc3,
+    Bc4,
+    Bc5,
+    // uncompressed, channel bit masks of red (or luminance), green, blue and alpha
+    Masked { bytes: usize, masks: [u32; 4], luminance: bool },
+    Rgba16Float,
+    Rgba32Float,
+}
+
+/// Whether `ext` (lowercase) is the dds file extension
+pub fn is_dds_extension(ext: &str) -> bool {
+    ext == "dds"
+}
+
+/// Whether `data` starts with the dds signature
+pub fn is_dds(data: &[u8]) -> bool {
+    data.starts_with(MAGIC)
+}
+
+fn u32_at(data: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes([data[offset], data[offset + 1], data[offset + 2], data[offset + 3]])
+}
+
+fn unsupported(what: String) -> Error {
+    Error::from_string(format!("unsupported dds format: {}", what))
+}
+
+fn format_from_dxgi(dxgi_format: u32) -> Result<DdsFormat, Error> {
+    const RGBA: [u32; 4] = [0xFF, 0xFF00, 0xFF_0000, 0xFF00_0000];
+    const BGRA: [u32; 4] = [0xFF_0000, 0xFF00, 0xFF, 0xFF00_0000];
+    const BGRX: [u32; 4] = [0xFF_0000, 0xFF00, 0xFF, 0];
+    Ok(match dxgi_format {
+        2 => DdsFormat::Rgba32Float,
+        10 => DdsFormat::Rgba16Float,
+        27..=29 => DdsFormat::Masked { bytes: 4, masks: RGBA, luminance: false },
+        61 => DdsFormat::Masked { bytes: 1, masks: [0xFF, 0, 0, 0], luminance: true },
+        70..=72 => DdsFormat::Bc1,
+        73..=75 => DdsFormat::Bc2,
+        76..=78 => DdsFormat::Bc3,
+        // signed variants (81, 84) hold normals in -1..1
+        79 | 80 => DdsFormat::Bc4,
+        82 | 83 => DdsFormat::Bc5,
+        87 | 90 | 91 => DdsFormat::Masked { bytes: 4, masks: BGRA, luminance: false },
+        88 | 92 | 93 => DdsFormat::Masked { bytes: 4, masks: BGRX, luminance: false },
+        _ => return Err(unsupported(format!("DXGI format {}", dxgi_format))),
+    })
+}
+
+fn format_from_header(data: &[u8]) -> Result<(DdsFormat, usize), Error> {
+    let flags = u32_at(data, 80);
+    if flags & DDPF_FOURCC != 0 {
+        let fourcc = &data[84..88];
+        let format = match fourcc {
+            b"DX10" => {
+                if data.len() < HEADER_LEN + DX10_HEADER_LEN {
+                    return Err(Error::from_string("truncated dds DX10 header".to_string()));
+                }
+                return Ok((format_from_dxgi(u32_at(data, HEADER_LEN))?, HEADER_LEN + DX10_HEADER_LEN));
+            }
+            b"DXT1" => DdsFormat::Bc1,
+            // DXT2 and DXT4 are premultiplied, which is kept as is
+            b"DXT2" | b"DXT3" => DdsFormat::Bc2,
+            b"DXT4" | b"DXT5" => DdsFormat::Bc3,
+            b"ATI1" | b"BC4U" => DdsFormat::Bc4,
+            b"ATI2" | b"BC5U" => DdsFormat::Bc5,
+            // D3DFMT_A16B16G16R16F and D3DFMT_A32B32G32R32F
+            [113, 0, 0, 0] => DdsFormat::Rgba16Float,
+            [116, 0, 0, 0] => DdsFormat::Rgba32Float,
+            _ => return Err(unsupported(format!("FourCC {:?}", String::from_utf8_lossy(fourcc)))),
+        };
+        return Ok((format, HEADER_LEN));
+    }
+    if flags & (DDPF_RGB | DDPF_LUMINANCE | DDPF_ALPHA) != 0 {
+        let bits = u32_at(data, 88);
+        if !matches!(bits, 8 | 16 | 24 | 32) {
+            return Err(unsupported(format!("{} bits per pixel", bits)));
+        }
+        let alpha_mask = if flags & (DDPF_ALPHAPIXELS | DDPF_ALPHA) != 0 { u32_at(data, 104) } else { 0 };
+        let masks = [u32_at(data, 92), u32_at(data, 96), u32_at(data, 100), alpha_mask];
+        let luminance = flags & DDPF_LUMINANCE != 0;
+        return Ok((DdsFormat::Masked { bytes: bits as usize / 8, masks, luminance }, HEADER_LEN));
+    }
+    Err(unsupported(format!("pixel format flags {:#x}", flags)))
+}
+
+/// Reads the dimensions (width, height) of the top level surface from the header, None if `data` is no dds texture
+pub fn dds_dimensions(data: &[u8]) -> Option<(u32, u32)> {
+    (is_dds(data) && data.len() >= HEADER_LEN).then(|| (u32_at(data, 16), u32_at(data, 12)))
+}
+
+/// Decodes the top level surface (largest mipmap, first array slice or cube face) of an in-memory dds texture.
+///
+/// Supports BC1 - BC5 (DXT1 - DXT5, ATI1/2) compressed, uncompressed 8 to 32 bit and 16/32 bit float textures.
+/// The `_SRGB` and `_UNORM` variants are both passed through unchanged, as color textures hold sRGB values
+/// in practice and data textures (normal maps, masks) are previewed as stored.
+/// Float textures hold linear HDR values, which are clamped and encoded to sRGB.
+/// Textures with more than `max_pixels` pixels are rejected before their pixels are decoded.
+pub fn read_dds_from_memory(data: &[u8], max_pixels: Option<u64>) -> Result<DynamicImage, Error> {
+    if !is_dds(data) || data.len() < HEADER_LEN {
+        return Err(Error::from_string("not a dds file".to_string()));
+    }
+    let (height, width) = (u32_at(data, 12), u32_at(data, 16));
+    if width == 0 || height == 0 {
+        return Err(Error::from_string(format!("invalid dds dimensions {}x{}", width, height)));
+    }
+    check_pixel_limit(width, height, max_pixels)?;
+    let (format, offset) = format_from_header(data)?;
+    let pixels = &data[offset..];
+
+    match format {
+        DdsFormat::Bc1 | DdsFormat::Bc2 | DdsFormat::Bc3 | DdsFormat::Bc4 | DdsFormat::Bc5 => {
+            decode_blocks(pixels, width, height, format)
+        }
+        DdsFormat::Masked { bytes, masks, luminance } => decode_masked(pixels, width, height, bytes, masks, luminance),
+        DdsFormat::Rgba16Float => decode_float(pixels, width, height, 2),
+        DdsFormat::Rgba32Float => decode_float(pixels, width, height, 4),
+    }
+}
+
+fn check_len(pixels: &[u8], needed: usize) -> Result<(), Error> {
+    if pixels.len() < needed {
+        return Err(Error::from_string(format!("truncated dds data ({} of {} bytes)", pixels.len(), needed)));
+    }
+    Ok(())
+}
+
+fn decode_blocks(pixels: &[u8], width: u32, height: u32, format: DdsFormat) -> Result<DynamicImage, Error> {
+    let block_len = if matches!(format, DdsFormat::Bc1 | DdsFormat::Bc4) { 8 } else { 16 };
+    let (blocks_x, blocks_y) = (width.div_ceil(4) as usize, height.div_ceil(4) as usize);
+    check_len(pixels, blocks_x * blocks_y * block_len)?;
+
+    let mut image = RgbaImage::new(width, height);
+    for (i, block) in pixels.chunks_exact(block_len).take(blocks_x * blocks_y).enumerate() {
+        let texels = match format {
+            DdsFormat::Bc1 => decode_color_block(block, true),
+            DdsFormat::Bc2 => {
+                let mut texels = decode_color_block(&block[8..], false);
+                let alpha = u64::from_le_bytes(block[..8].try_into().unwrap());
+                for (j, texel) in texels.iter_mut().enumerate() {
+                    texel[3] = ((alpha >> (4 * j)) & 0xF) as u8 * 17;
+                }
+                texels
+            }
+            DdsFormat::Bc3 => {
+                let mut texels = decode_color_block(&block[8..], false);
+                for (texel, alpha) in texels.iter_mut().zip(decode_channel_block(&block[..8])) {
+                    texel[3] = alpha;
+                }
+                texels
+            }
+            DdsFormat::Bc4 => decode_channel_block(block).map(|red| [red, red, red, 255]),
+            _ => {
+                let (red, green) = (decode_channel_block(&block[..8]), decode_channel_block(&block[8..]));
+                std::array::from_fn(|j| [red[j], green[j], 0, 255])
+            }
+        };
+        let (block_x, block_y) = ((i % blocks_x) as u32 * 4, (i / blocks_x) as u32 * 4);
+        for (j, texel) in texels.into_iter().enumerate() {
+            let (x, y) = (block_x + j as u32 % 4, block_y + j as u32 / 4);
+            if x < width && y < height {
+                image.put_pixel(x, y, Rgba(texel));
+            }
+        }
+    }
+
+    Ok(match format {
+        DdsFormat::Bc4 => DynamicImage::ImageLuma8(GrayImage::from_fn(width, height, |x, y| Luma([image.get_pixel(x, y)[0]]))),
+        DdsFormat::Bc5 => DynamicImage::ImageRgb8(DynamicImage::ImageRgba8(image).to_rgb8()),
+        // most BC1 textures do not use the punch-through alpha
+        DdsFormat::Bc1 if image.pixels().all(|p| p[3] == 255) => DynamicImage::ImageRgb8(DynamicImage::ImageRgba8(image).to_rgb8()),
+        _ => DynamicImage::ImageRgba8(image),
+    })
+}
+
+fn rgb565(color: u16) -> [u8; 3] {
+    let (r, g, b) = ((color >> 11) as u8, ((color >> 5) & 0x3F) as u8, (color & 0x1F) as u8);
+    [(r << 3) | (r >> 2), (g << 2) | (g >> 4), (b << 3) | (b >> 2)]
+}
+
+// BC1 color block, BC2 and BC3 always use the four color mode
+fn decode_color_block(block: &[u8], bc1: bool) -> [[u8; 4]; 16] {
+    let (c0, c1) = (u16::from_le_bytes([block[0], block[1]]), u16::from_le_bytes([block[2], block[3]]));
+    let (a, b) = (rgb565(c0), rgb565(c1));
+    let mix = |wa: u16, wb: u16| -> [u8; 4] {
+        let channel = |i: usize| ((a[i] as u16 * wa + b[i] as u16 * wb) / (wa + wb)) as u8;
+        [channel(0), channel(1), channel(2), 255]
+    };
+    let palette = if c0 > c1 || !bc1 {
+        [mix(1, 0), mix(0, 1), mix(2, 1), mix(1, 2)]
+    } else {
+        [mix(1, 0), mix(0, 1), mix(1, 1), [0, 0, 0, 0]]
+    };
+    let indices = u32_at(block, 4);
+    std::array::from_fn(|j| palette[((indices >> (2 * j)) & 0x3) as usize])
+}
+
+// BC4 block, also the alpha block of BC3 and the channel blocks of BC5
+fn decode_channel_block(block: &[u8]) -> [u8; 16] {
+    let (a, b) = (block[0] as u32, block[1] as u32);
+    let mut palette = [a as u8, b as u8, 0, 0, 0, 0, 0, 255];
+    if a > b {
+        for (i, value) in palette.iter_mut().enumerate().skip(2) {
+            *value = (((8 - i as u32) * a + (i as u32 - 1) * b) / 7) as u8;
+        }
+    } else {
+        for (i, value) in palette.iter_mut().enumerate().take(6).skip(2) {
+            *value = (((6 - i as u32) * a + (i as u32 - 1) * b) / 5) as u8;
+        }
+    }
+    let mut index_bytes = [0; 8];
+    index_bytes[..6].copy_from_slice(&block[2..8]);
+    let indices = u64::from_le_bytes(index_bytes);
+    std::array::from_fn(|j| palette[((indices >> (3 * j)) & 0x7) as usize])
+}
+
+fn decode_masked(pixels: &[u8], width: u32, height: u32, bytes: usize, masks: [u32; 4], luminance: bool) -> Result<DynamicImage, Error> {
+    let count = width as usize * height as usize;
+    check_len(pixels, count * bytes)?;
+    // scales the masked bits of a pixel to 8 bit
+    let extract = |pixel: u32, mask: u32| -> u8 {
+        if mask == 0 {
+            return 0;
+        }
+        let max = mask >> mask.trailing_zeros();
+        (((pixel & mask) >> mask.trailing_zeros()) as u64 * 255 / max as u64) as u8
+    };
+    let has_alpha = masks[3] != 0;
+    // luminance (with alpha), rgb or rgba samples
+    let channels = match (luminance, has_alpha) {
+        (true, false) => 1,
+        (true, true) => 2,
+        (false, false) => 3,
+        (false, true) => 4,
+    };
+    let mut samples = Vec::with_capacity(count * channels);
+    for chunk in pixels.chunks_exact(bytes).take(count) {
+        let mut pixel_bytes = [0; 4];
+        pixel_bytes[..bytes].copy_from_slice(chunk);
+        let pixel = u32::from_le_bytes(pixel_bytes);
+        samples.push(extract(pixel, masks[0]));
+        if !luminance {
+            samples.extend_from_slice(&[extract(pixel, masks[1]), extract(pixel, masks[2])]);
+        }
+        if has_alpha {
+            samples.push(extract(pixel, masks[3]));
+        }
+    }
+    let image = match channels {
+        1 => GrayImage::from_raw(width, height, samples).map(DynamicImage::ImageLuma8),
+        2 => GrayAlphaImage::from_raw(width, height, samples).map(DynamicImage::ImageLumaA8),
+        3 => RgbImage::from_raw(width, height, samples).map(DynamicImage::ImageRgb8),
+        _ => RgbaImage::from_raw(width, height, samples).map(DynamicImage::ImageRgba8),
+    };
+    Ok(image.expect("sample count matches the dimensions"))
+}
+
+fn f16_to_f32(half: u16) -> f32 {
+    let sign = if half & 0x8000 != 0 { -1. } else { 1. };
+    let (exponent, mantissa) = ((half >> 10) & 0x1F, (half & 0x3FF) as f32);
+    sign * match exponent {
+        0 => mantissa * 2f32.powi(-24),
+        0x1F if mantissa == 0. => f32::INFINITY,
+        0x1F => f32::NAN,
+        _ => (1. + mantissa / 1024.) * 2f32.powi(exponent as i32 - 15),
+    }
+}
+
+fn decode_float(pixels: &[u8], width: u32, height: u32, channel_bytes: usize) -> Result<DynamicImage, Error> {
+    let count = width as usize * height as usize;
+    check_len(pixels, count * 4 * channel_bytes)?;
+    let rgba = pixels.chunks_exact(channel_bytes).take(count * 4).enumerate()
+        .map(|(i, bytes)| {
+            let value = match channel_bytes {
+                2 => f16_to_f32(u16::from_le_bytes([bytes[0], bytes[1]])),
+                _ => f32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]),
+            };
+            // alpha is linear coverage
+            if i % 4 == 3 { (value.clamp(0., 1.) * 255. + 0.5) as u8 } else { linear_to_srgb(value) }
+        })
+        .collect();
+    Ok(DynamicImage::ImageRgba8(RgbaImage::from_raw(width, height, rgba).unwrap()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // texture with the pixel format `flags`, `fourcc`, `bits` per pixel and channel `masks`, followed by `pixels`
+    fn texture(width: u32, height: u32, flags: u32, fourcc: &[u8; 4], bits: u32, masks: [u32; 4], pixels: &[u8]) -> Vec<u8> {
+        let mut data = MAGIC.to_vec();
+        data.extend(124u32.to_le_bytes());
+        data.extend(0x1007u32.to_le_bytes()); // caps, height, width, pixel format
+        data.extend(height.to_le_bytes());
+        data.extend(width.to_le_bytes());
+        data.extend([0; 56]); // pitch, depth, mipmap count, reserved
+        data.extend(32u32.to_le_bytes());
+        data.extend(flags.to_le_bytes());
+        data.extend(fourcc);
+        data.extend(bits.to_le_bytes());
+        masks.iter().for_each(|mask| data.extend(mask.to_le_bytes()));
+        data.extend([0; 20]); // caps, reserved
+        assert_eq!(data.len(), HEADER_LEN);
+        data.extend(pixels);
+        data
+    }
+
+    fn compressed(fourcc: &[u8; 4], blocks: &[u8]) -> Vec<u8> {
+        texture(4, 4, DDPF_FOURCC, fourcc, 0, [0; 4], blocks)
+    }
+
+    // 3 bit indices 0 - 7 for the first eight texels of a BC4 block
+    fn channel_block(a: u8, b: u8) -> Vec<u8> {
+        let indices: u64 = (0..8).map(|j| j << (3 * j)).sum();
+        [&[a, b], &indices.to_le_bytes()[..6]].concat()
+    }
+
+    #[test]
+    fn decodes_bc1_punch_through() {
+        // blue before red: the three color mode with a transparent fourth color, indices 0 - 3 in the first row
+        let image = read_dds_from_memory(&compressed(b"DXT1", &[0x1f, 0, 0, 0xf8, 0xe4, 0, 0, 0]), None).unwrap();
+        let DynamicImage::ImageRgba8(image) = image else { panic!("expected 8 bit rgba") };
+        assert_eq!(&image.as_raw()[..16], &[0, 0, 255, 255, 255, 0, 0, 255, 127, 0, 127, 255, 0, 0, 0, 0]);
+        assert_eq!(image.get_pixel(3, 3), &Rgba([0, 0, 255, 255]));
+        // red before blue: four opaque colors, two of them interpolated
+        let image = read_dds_from_memory(&compressed(b"DXT1", &[0, 0xf8, 0x1f, 0, 0xe4, 0, 0, 0]), None).unwrap();
+        let DynamicImage::ImageRgb8(image) = image else { panic!("expected 8 bit rgb") };
+        assert_eq!(&image.as_raw()[..12], &[255, 0, 0, 0, 0, 255, 170, 0, 85, 85, 0, 170]);
+    }
+
+    #[test]
+    fn decodes_bc4_palettes() {
+        // eight values interpolated between the endpoints
+        let image = read_dds_from_memory(&compressed(b"BC4U", &channel_block(255, 0)), None).unwrap();
+        let DynamicImage::ImageLuma8(image) = image else { panic!("expected 8 bit gray") };
+        assert_eq!(&image.as_raw()[..8], &[255, 0, 218, 182, 145, 109, 72, 36]);
+        // six values interpolated, then 0 and 255
+        let image = read_dds_from_memory(&compressed(b"ATI1", &channel_block(0, 255)), None).unwrap();
+        let DynamicImage::ImageLuma8(image) = image else { panic!("expected 8 bit gray") };
+        assert_eq!(&image.as_raw()[..8], &[0, 255, 51, 102, 153, 204, 0, 255]);
+    }
+
+    #[test]
+    fn decodes_masked_565() {
+        // red, green and the middle of each channel, scaled from 5 and 6 bits
+        let pixels: Vec<u8> = [0xf800u16, 0x07e0, 0x8410].iter().flat_map(|pixel| pixel.to_le_bytes()).collect();
+        let data = texture(3, 1, DDPF_RGB, &[0; 4], 16, [0xf800, 0x07e0, 0x001f, 0], &pixels);
+        let DynamicImage::ImageRgb8(image) = read_dds_from_memory(&data, None).unwrap() else { panic!("expected 8 bit rgb") };
+        assert_eq!(image.as_raw(), &[255, 0, 0, 0, 255, 0, 131, 129, 131]);
+        assert!(read_dds_from_memory(&data[..data.len() - 1], None).is_err());
+    }
+
+    #[test]
+    fn decodes_float() {
+        // linear 1, 0.25 and out of range 2 and -1 are encoded to sRGB, the alpha of 0.5 is linear
+        let halves = [0x3c00u16, 0x3400, 0x4000, 0x3800, 0xbc00, 0, 0, 0x3c00];
+        let pixels: Vec<u8> = halves.iter().flat_map(|half| half.to_le_bytes()).collect();
+        let expected = [255, 137, 255, 128, 0, 0, 0, 255];
+        let data = texture(2, 1, DDPF_FOURCC, &[113, 0, 0, 0], 0, [0; 4], &pixels);
+        assert_eq!(read_dds_from_memory(&data, None).unwrap().to_rgba8().as_raw(), &expected);
+        // the same values as 32 bit floats behind a DX10 header
+        let mut pixels = 2u32.to_le_bytes().to_vec();
+        pixels.extend([0; 16]);
+        pixels.extend(halves.iter().flat_map(|&half| f16_to_f32(half).to_le_bytes()));
+        let data = texture(2, 1, DDPF_FOURCC, b"DX10", 0, [0; 4], &pixels);
+        assert_eq!(read_dds_from_memory(&data, None).unwrap().to_rgba8().as_raw(), &expected);
+    }
+}
@@ -0,0 +1,463 @@
+use crate::{
+    dds::{dds_dimensions, is_dds, is_dds_extension, read_dds_from_memory},
+    profile,
+    psd::{is_psd, is_psd_extension, psd_dimensions, read_psd_from_memory},
+    Error,
+};
+use image::{ColorType, DynamicImage, GenericImageView, GrayImage, ImageDecoder, ImageFormat as ImageImageFormat, ImageReader, RgbImage};
+use jpeg_decoder::{Decoder, PixelFormat};
+use std::{
+    fmt, fs,
+    io::{BufRead, Cursor, Read, Seek},
+    panic::{self, AssertUnwindSafe},
+    path::Path,
+};
+
+// bytes read by `probe_dimensions` for the headers of the built-in decoders (jp2 boxes may precede the image header)
+const HEADER_PROBE_LEN: u64 = 64 * 1024;
+
+/// A single decoding attempt of the fallback chain used by [`read_image_with`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DecodeStep {
+    /// Decode with the `image` crate, selecting the decoder by the file extension.
+    Extension,
+    /// Decode with the `image` crate, guessing the format from the file contents
+    /// (we have pngs hiding in jpeg extension files, jpg inside bmp, etc. ...).
+    GuessFormat,
+    /// Decode (progressive) jpegs with the `jpeg-decoder` crate.
+    JpegDecoder,
+    /// Decode with the `image` crate, forcing the format of known extension aliases (`pjpeg`, `x-png`, ...).
+    ForcedExtension,
+    /// Decode dds textures with the built-in decoder (BC1 - BC5, uncompressed and float formats),
+    /// the `image` crate only reads BC1 - BC3.
+    Dds,
+    /// Decode the merged composite of photoshop documents (psd, psb) with the built-in decoder.
+    Psd,
+    /// Decode jpeg 2000 files (which the `image` crate cannot read) with OpenJPEG.
+    #[cfg(feature = "jpeg2000")]
+    Jpeg2000,
+    /// Decode jpegs with `zune-jpeg` directly, without the format detection and pixel conversions of the `image` crate.
+    #[cfg(feature = "zune")]
+    Zune,
+}
+
+/// Decoder of jpeg inputs, the other formats are always decoded by the fallback chain of [`DecodeOptions::default`]
+#[cfg_attr(feature = "cli", derive(clap::ValueEnum))]
+#[derive(serde::Serialize, serde::Deserialize, Clone, Copy, PartialEq, Eq, Debug, Default)]
+#[serde(rename_all = "kebab-case")]
+pub enum DecoderBackend {
+    /// `zune` if compiled in (the `zune` feature), `image` otherwise.
+    #[default]
+    Auto,
+    /// The `image` crate, falling back to `jpeg-decoder`.
+    Image,
+    /// `zune-jpeg` first, falling back to the `image` crate for jpegs it rejects.
+    #[cfg(feature = "zune")]
+    Zune,
+}
+
+/// Configuration of the decoding fallback chain
+#[derive(Clone, Debug)]
+pub struct DecodeOptions {
+    /// Steps tried in order until one of them decodes the image.
+    pub steps: Vec<DecodeStep>,
+    /// Refuse to allocate the pixels of images larger than this (all steps, checked against the header dimensions).
+    /// Defaults to None (the limits of the `image` crate).
+    pub max_pixels: Option<u64>,
+}
+
+impl Default for DecodeOptions {
+    fn default() -> Self {
+        #[allow(unused_mut)]
+        let mut steps = vec![
+            // first, the image crate fails on most other dds formats and cannot read psd files
+            DecodeStep::Dds,
+            DecodeStep::Psd,
+            DecodeStep::Extension,
+            DecodeStep::GuessFormat,
+            DecodeStep::JpegDecoder,
+            DecodeStep::ForcedExtension,
+        ];
+        // first, the other steps can only fail on jpeg 2000 files
+        #[cfg(feature = "jpeg2000")]
+        steps.insert(0, DecodeStep::Jpeg2000);
+        DecodeOptions { steps, max_pixels: None }
+    }
+}
+
+impl DecodeOptions {
+    /// The default fallback chain with the jpeg decoder of `backend`
+    pub fn for_backend(backend: DecoderBackend) -> Self {
+        #[allow(unused_mut)]
+        let mut options = DecodeOptions::default();
+        // right before the `image` crate, so that dds, psd and jpeg 2000 inputs are still told apart first
+        #[cfg(feature = "zune")]
+        if backend != DecoderBackend::Image {
+            let position = options.steps.iter().position(|step| *step == DecodeStep::Extension).unwrap_or(0);
+            options.steps.insert(position, DecodeStep::Zune);
+        }
+        #[cfg(not(feature = "zune"))]
+        let _ = backend;
+        options
+    }
+}
+
+/// Formats whose decoders may be used, by their names in `imgc capabilities` (`jpeg`, `png`, `gif`, `tiff`, `dds`, ...)
+#[derive(Clone, Debug, Default)]
+pub struct DecoderFilter {
+    /// Only inputs of these formats are decoded, all formats if empty.
+    pub enabled: Vec<String>,
+    /// Inputs of these formats are never decoded.
+    pub disabled: Vec<String>,
+}
+
+impl DecoderFilter {
+    /// Whether no format is left out
+    pub fn is_empty(&self) -> bool {
+        self.enabled.is_empty() && self.disabled.is_empty()
+    }
+
+    /// Whether inputs of the format `name` may be decoded
+    pub fn allows(&self, name: &str) -> bool {
+        (self.enabled.is_empty() || self.enabled.iter().any(|enabled| enabled == name))
+            && !self.disabled.iter().any(|disabled| disabled == name)
+    }
+}
+
+/// Name of a decodable format as used by [`DecoderFilter`] for a format name or one of its extensions
+/// (e.g. `jpg` => `jpeg`, `tif` => `tiff`, `jp2` => `jpeg2000`), None for unknown formats
+pub fn decoder_name(name: &str) -> Option<String> {
+    let name = name.trim().to_ascii_lowercase();
+    match name.as_str() {
+        "dds" | "psd" | "jpeg2000" => return Some(name),
+        "psb" => return Some("psd".to_string()),
+        "jp2" | "j2k" | "j2c" | "jpc" => return Some("jpeg2000".to_string()),
+        _ => {}
+    }
+    ImageImageFormat::all()
+        .find(|format| format!("{:?}", format).eq_ignore_ascii_case(&name) || format.extensions_str().contains(&name.as_str()))
+        .map(|format| format!("{:?}", format).to_ascii_lowercase())
+}
+
+/// Name of the format of an encoded image by its signature (see [`DecoderFilter`]), tga has none and is told by `extension`
+pub fn format_name(header: &[u8], extension: &str) -> Option<String> {
+    if is_dds(header) {
+        return Some("dds".to_string());
+    }
+    if is_psd(header) {
+        return Some("psd".to_string());
+    }
+    #[cfg(feature = "jpeg2000")]
+    if crate::jpeg2000::is_jpeg2000(header) {
+        return Some("jpeg2000".to_string());
+    }
+    match image::guess_format(header) {
+        Ok(format) => Some(format!("{:?}", format).to_ascii_lowercase()),
+        Err(_) if extension.eq_ignore_ascii_case("tga") => Some("tga".to_string()),
+        Err(_) => None,
+    }
+}
+
+/// Reads the signature of the image at `path` and names its format like [`format_name`]
+pub fn probe_format_name(path: &Path) -> Option<String> {
+    let mut header = Vec::with_capacity(32);
+    fs::File::open(path).and_then(|file| file.take(32).read_to_end(&mut header)).ok()?;
+    format_name(&header, &path.extension().unwrap_or_default().to_string_lossy())
+}
+
+/// Reads and decodes the image at `path` using the default fallback chain.
+///
+/// Decoder panics (e.g. from zune on malformed inputs) are caught and treated like decoding errors.
+pub fn read_image(path: &Path) -> Result<DynamicImage, Error> {
+    read_image_with(path, &DecodeOptions::default())
+}
+
+/// Reads and decodes the image at `path`, trying the configured steps in order.
+///
+/// If all steps fail, the error of the first failing step is returned.
+pub fn read_image_with(path: &Path, options: &DecodeOptions) -> Result<DynamicImage, Error> {
+    let _span = profile::span("decode");
+    let ext = path
+        .extension().and_then(|e| e.to_str())
+        .unwrap_or("").to_ascii_lowercase();
+
+    run_steps(options, |step| match step {
+        DecodeStep::Extension => decode_reader(ImageReader::open(path)?, options),
+        DecodeStep::GuessFormat => decode_reader(ImageReader::open(path)?.with_guessed_format()?, options),
+        DecodeStep::JpegDecoder => {
+            if !is_jpeg_extension(&ext) {
+                return Ok(None);
+            }
+            decode_jpeg(fs::File::open(path)?, options.max_pixels)
+        }
+        DecodeStep::ForcedExtension => match forced_format(&ext) {
+            Some(format) => {
+                let mut reader = ImageReader::open(path)?;
+                reader.set_format(format);
+                decode_reader(reader, options)
+            }
+            None => Ok(None),
+        },
+        DecodeStep::Dds => {
+            if !is_dds_extension(&ext) {
+                return Ok(None);
+            }
+            Ok(Some(read_dds_from_memory(&fs::read(path)?, options.max_pixels)?))
+        }
+        DecodeStep::Psd => {
+            if !is_psd_extension(&ext) {
+                return Ok(None);
+            }
+            Ok(Some(read_psd_from_memory(&fs::read(path)?, options.max_pixels)?))
+        }
+        #[cfg(feature = "jpeg2000")]
+        DecodeStep::Jpeg2000 => {
+            if !crate::jpeg2000::is_jpeg2000_extension(&ext) {
+                return Ok(None);
+            }
+            Ok(Some(crate::jpeg2000::read_jpeg2000(path, options.max_pixels)?))
+        }
+        #[cfg(feature = "zune")]
+        DecodeStep::Zune => {
+            if !is_jpeg_extension(&ext) {
+                return Ok(None);
+            }
+            decode_zune(&fs::read(path)?, options)
+        }
+    })
+}
+
+/// Decodes an in-memory image using the default fallback chain.
+///
+/// Without a file extension, the extension based steps are skipped.
+pub fn read_image_from_memory(data: &[u8]) -> Result<DynamicImage, Error> {
+    read_image_from_memory_with(data, &DecodeOptions::default())
+}
+
+/// Decodes an in-memory image, trying the configured steps in order.
+pub fn read_image_from_memory_with(data: &[u8], options: &DecodeOptions) -> Result<DynamicImage, Error> {
+    run_steps(options, |step| match step {
+        DecodeStep::GuessFormat => decode_reader(ImageReader::new(Cursor::new(data)).with_guessed_format()?, options),
+        DecodeStep::JpegDecoder => {
+            if image::guess_format(data).ok() != Some(ImageImageFormat::Jpeg) {
+                return Ok(None);
+            }
+            decode_jpeg(Cursor::new(data), options.max_pixels)
+        }
+        DecodeStep::Dds => {
+            if !is_dds(data) {
+                return Ok(None);
+            }
+            Ok(Some(read_dds_from_memory(data, options.max_pixels)?))
+        }
+        DecodeStep::Psd => {
+            if !is_psd(data) {
+                return Ok(None);
+            }
+            Ok(Some(read_psd_from_memory(data, options.max_pixels)?))
+        }
+        #[cfg(feature = "jpeg2000")]
+        DecodeStep::Jpeg2000 => {
+            if !crate::jpeg2000::is_jpeg2000(data) {
+                return Ok(None);
+            }
+            Ok(Some(crate::jpeg2000::read_jpeg2000_from_memory(data, options.max_pixels)?))
+        }
+        #[cfg(feature = "zune")]
+        DecodeStep::Zune => {
+            if image::guess_format(data).ok() != Some(ImageImageFormat::Jpeg) {
+                return Ok(None);
+            }
+            decode_zune(data, options)
+        }
+        DecodeStep::Extension | DecodeStep::ForcedExtension => Ok(None),
+    })
+}
+
+fn run_steps<F>(options: &DecodeOptions, mut attempt: F) -> Result<DynamicImage, Error>
+where
+    F: FnMut(DecodeStep) -> Result<Option<DynamicImage>, Error>,
+{
+    let mut first_error = None;
+    for &step in &options.steps {
+        let result = panic::catch_unwind(AssertUnwindSafe(|| attempt(step)))
+            .unwrap_or_else(|_| Err(Error::from_string(format!("decoder panicked ({:?})", step))));
+        match result {
+            Ok(Some(img)) => return Ok(img),
+            Ok(None) => {} // step not applicable
+            Err(err) => {
+                first_error.get_or_insert(err);
+            }
+        }
+    }
+    Err(first_error.unwrap_or_else(|| Error::from_string("no decoding step applicable".to_string())))
+}
+
+fn decode_reader<R: BufRead + Seek>(mut reader: ImageReader<R>, options: &DecodeOptions) -> Result<Option<DynamicImage>, Error> {
+    if let Some(max_pixels) = options.max_pixels {
+        let mut limits = image::Limits::default();
+        // 16 bytes per pixel fit the widest pixel type (rgba f32)
+        limits.max_alloc = Some(max_pixels.saturating_mul(16));
+        reader.limits(limits);
+    }
+    Ok(Some(reader.decode()?))
+}
+
+/// Reads the dimensions of the image at `path` from its header, without decoding the pixels.
+///
+/// Returns `None` for formats neither the `image` crate nor the built-in dds, psd and jpeg 2000 steps can read.
+pub fn probe_dimensions(path: &Path) -> Option<(u32, u32)> {
+    if let Ok(dimensions) = ImageReader::open(path).ok()?.with_guessed_format().ok()?.into_dimensions() {
+        return Some(dimensions);
+    }
+    let mut header = Vec::new();
+    fs::File::open(path).ok()?.take(HEADER_PROBE_LEN).read_to_end(&mut header).ok()?;
+    #[cfg(feature = "jpeg2000")]
+    if let Some(dimensions) = crate::jpeg2000::jpeg2000_dimensions(&header) {
+        return Some(dimensions);
+    }
+    psd_dimensions(&header).or_else(|| dds_dimensions(&header))
+}
+
+/// Error of [`check_pixel_limit`], an image with more pixels than [`DecodeOptions::max_pixels`]
+#[derive(Debug)]
+pub struct PixelLimitExceeded {
+    /// Width of the rejected image.
+    pub width: u32,
+    /// Height of the rejected image.
+    pub height: u32,
+    /// The exceeded limit.
+    pub max_pixels: u64,
+}
+
+impl fmt::Display for PixelLimitExceeded {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}x{} pixels exceed the limit of {}", self.width, self.height, self.max_pixels)
+    }
+}
+
+impl std::error::Error for PixelLimitExceeded {}
+
+/// Fails if an image of `width` x `height` has more pixels than `max_pixels`, see [`DecodeOptions::max_pixels`]
+pub fn check_pixel_limit(width: u32, height: u32, max_pixels: Option<u64>) -> Result<(), Error> {
+    match max_pixels {
+        Some(max_pixels) if width as u64 * height as u64 > max_pixels =>
+            Err(Error::new(PixelLimitExceeded { width, height, max_pixels })),
+        _ => Ok(()),
+    }
+}
+
+/// Whether `err` rejected an image for its size, by [`check_pixel_limit`] or by the allocation limit of the `image` crate
+pub fn exceeds_pixel_limit(err: &Error) -> bool {
+    let cause = err.cause();
+    cause.is::<PixelLimitExceeded>() || matches!(cause.downcast_ref::<image::ImageError>(), Some(image::ImageError::Limits(_)))
+}
+
+/// Reads the color type (channels and bit depth) of the image at `path` from its header, without decoding the pixels.
+///
+/// Returns `None` for formats the `image` crate cannot read.
+pub fn probe_color_type(path: &Path) -> Option<ColorType> {
+    Some(ImageReader::open(path).ok()?.with_guessed_format().ok()?.into_decoder().ok()?.color_type())
+}
+
+fn decode_jpeg<R: Read>(reader: R, max_pixels: Option<u64>) -> Result<Option<DynamicImage>, Error> {
+    let mut decoder = Decoder::new(reader);
+    if max_pixels.is_some() {
+        decoder.read_info().map_err(|e| Error::from_string(format!("jpeg-decoder failed: {}", e)))?;
+        if let Some(info) = decoder.info() {
+            check_pixel_limit(info.width.into(), info.height.into(), max_pixels)?;
+        }
+    }
+    let pixels = decoder.decode()
+        .map_err(|e| Error::from_string(format!("jpeg-decoder failed: {}", e)))?;
+    let info = decoder.info()
+        .ok_or_else(|| Error::from_string("jpeg-decoder returned no image info".to_string()))?;
+    // Convert raw pixels to RgbImage / GrayImage
+    let img = match info.pixel_format {
+        PixelFormat::L8 => GrayImage::from_raw(info.width.into(), info.height.into(), pixels)
+            .map(DynamicImage::ImageLuma8),
+        _ => RgbImage::from_raw(info.width.into(), info.height.into(), pixels)
+            .map(DynamicImage::ImageRgb8),
+    };
+    img.map(Some)
+        .ok_or_else(|| Error::from_string("Failed to convert jpeg-decoder output to RgbImage".to_string()))
+}
+
+#[cfg(feature = "zune")]
+fn decode_zune(data: &[u8], options: &DecodeOptions) -> Result<Option<DynamicImage>, Error> {
+    use zune_core::{colorspace::ColorSpace, options::DecoderOptions};
+    use zune_jpeg::JpegDecoder;
+
+    let zune_error = |e: zune_jpeg::errors::DecodeErrors| Error::from_string(format!("zune-jpeg failed: {:?}", e));
+    // the size is limited by max_pixels like for the `image` crate, not by the 16384 pixels per side of zune
+    let mut decoder = JpegDecoder::new_with_options(data, DecoderOptions::default()
+        .set_max_width(usize::MAX).set_max_height(usize::MAX));
+    decoder.decode_headers().map_err(zune_error)?;
+    let (width, height) = decoder.dimensions()
+        .ok_or_else(|| Error::from_string("zune-jpeg returned no image info".to_string()))?;
+    check_pixel_limit(width as u32, height as u32, options.max_pixels)?;
+    // gray jpegs stay gray, all other color spaces (YCbCr, CMYK, ...) are converted to rgb
+    let gray = decoder.get_input_colorspace() == Some(ColorSpace::Luma);
+    let colorspace = if gray { ColorSpace::Luma } else { ColorSpace::RGB };
+    decoder.set_options(decoder.get_options().jpeg_set_out_colorspace(colorspace));
+    let pixels = decoder.decode().map_err(zune_error)?;
+    let img = if gray {
+        GrayImage::from_raw(width as u32, height as u32, pixels).map(DynamicImage::ImageLuma8)
+    } else {
+        RgbImage::from_raw(width as u32, height as u32, pixels).map(DynamicImage::ImageRgb8)
+    };
+    img.map(Some)
+        .ok_or_else(|| Error::from_string("Failed to convert zune-jpeg output to RgbImage".to_string()))
+}
+
+/// Decodes as much as possible of a truncated or corrupt jpeg, the missing part is filled gray.
+///
+/// The stream is closed after its last byte and decoded twice, padded once with `0x00` and once with `0x55` bytes
+/// (all one bits are no option, a `0xFF` byte in the entropy-coded data starts a marker).
+/// Rows up to the first one where both decodes differ come from the actual data, the rows after it are filled gray.
+/// Streams with restart markers cannot be closed this way and stay undecodable.
+/// Like the regular decoders, images with more than `max_pixels` pixels are rejected before decoding.
+pub fn salvage_jpeg(data: &[u8], max_pixels: Option<u64>) -> Result<DynamicImage, Error> {
+    if !data.starts_with(&[0xFF, 0xD8]) {
+        return Err(Error::from_string("not a jpeg, cannot be salvaged".to_string()));
+    }
+    // an incomplete trailing marker would swallow the padding
+    let data = data.strip_suffix(&[0xFF]).unwrap_or(data);
+    let closed = |padding: u8| {
+        let mut closed = Vec::with_capacity(data.len() + 4096 + 2);
+        closed.extend_from_slice(data);
+        closed.resize(data.len() + 4096, padding);
+        closed.extend_from_slice(&[0xFF, 0xD9]);
+        decode_jpeg(Cursor::new(closed), max_pixels)?
+            .ok_or_else(|| Error::from_string("jpeg-decoder returned no image".to_string()))
+    };
+    let mut image = closed(0x00)?;
+    let other = closed(0x55)?;
+    if image.color() != other.color() || image.dimensions() != other.dimensions() {
+        return Err(Error::from_string("truncated jpeg could not be salvaged".to_string()));
+    }
+    let row_length = image.as_bytes().len() / image.height().max(1) as usize;
+    let first_invalid_row = image.as_bytes().chunks(row_length.max(1))
+        .zip(other.as_bytes().chunks(row_length.max(1)))
+        .position(|(row, other_row)| row != other_row);
+    if let Some(row) = first_invalid_row {
+        match &mut image {
+            DynamicImage::ImageLuma8(pixels) => pixels.as_mut()[row * row_length..].fill(128),
+            DynamicImage::ImageRgb8(pixels) => pixels.as_mut()[row * row_length..].fill(128),
+            _ => unreachable!("jpeg-decoder outputs gray or rgb images"),
+        }
+    }
+    Ok(image)
+}
+
+fn is_jpeg_extension(ext: &str) -> bool {
+    ext == "pjpeg" || ext == "jpg" || ext == "jpeg"
+}
+
+fn forced_format(ext: &str) -> Option<ImageImageFormat> {
+    match ext {
+        "pjpeg" | "jpg" | "jpeg" => Some(ImageImageFormat::Jpeg),
+        "x-png" | "png" => Some(ImageImageFormat::Png),
+        _ => None,
+    }
+}
@@ -0,0 +1,483 @@
+use crate::{
+    converter::{dependency_version, encode_image, encoder_package, EncoderConfig, DEPENDENCIES},
+    decode::{read_image, read_image_from_memory},
+    format::ImageFormat,
+    output,
+    Error,
+};
+use image::{DynamicImage, GrayAlphaImage, GrayImage, ImageFormat as ImageImageFormat, RgbImage, RgbaImage};
+use glob::glob;
+use std::{
+    collections::BTreeMap,
+    env, fs,
+    io::Cursor,
+    panic::{self, AssertUnwindSafe},
+    path::{Path, PathBuf},
+    process,
+};
+
+// dependencies worth including in bug reports
+const REPORTED_DEPENDENCIES: &[&str] = &[
+    "image", "webp", "libwebp-sys", "ravif", "rav1e", "mozjpeg-sys",
+    "jpeg-decoder", "zune-jpeg", "png", "gif", "tiff", "image-webp", "rayon",
+];
+
+// cargo features (optional backends and frontends) and whether they are compiled in
+const FEATURES: &[(&str, bool)] = &[
+    ("cli", cfg!(feature = "cli")),
+    ("ffi", cfg!(feature = "ffi")),
+    ("async", cfg!(feature = "async")),
+    ("ffmpeg", cfg!(feature = "ffmpeg")),
+    ("heif", cfg!(feature = "heif")),
+    ("jpeg2000", cfg!(feature = "jpeg2000")),
+    ("zune", cfg!(feature = "zune")),
+    ("watch", cfg!(feature = "watch")),
+    ("clipboard", cfg!(feature = "clipboard")),
+    ("gui", cfg!(feature = "gui")),
+];
+
+const ENCODERS: &[ImageFormat] = &[
+    ImageFormat::Webp,
+    ImageFormat::WebpImage,
+    ImageFormat::Avif,
+    ImageFormat::Png,
+    ImageFormat::Jpeg,
+    ImageFormat::Pnm,
+    ImageFormat::Farbfeld,
+    #[cfg(feature = "heif")]
+    ImageFormat::Heif,
+];
+
+/// Small synthetic test images covering the color types handled differently by the encoders
+fn test_images() -> Vec<(&'static str, DynamicImage)> {
+    let (w, h) = (67, 41); // odd sizes catch stride/subsampling issues
+    vec![
+        ("rgb8", DynamicImage::ImageRgb8(RgbImage::from_fn(w, h, |x, y| {
+            image::Rgb([(x * 3) as u8, (y * 5) as u8, ((x + y) * 2) as u8])
+        }))),
+        ("rgba8", DynamicImage::ImageRgba8(RgbaImage::from_fn(w, h, |x, y| {
+            image::Rgba([(x * 3) as u8, (y * 5) as u8, 128, ((x * y) % 256) as u8])
+        }))),
+        ("luma8", DynamicImage::ImageLuma8(GrayImage::from_fn(w, h, |x, y| {
+            image::Luma([((x + y) * 2) as u8])
+        }))),
+        ("lumaa8", DynamicImage::ImageLumaA8(GrayAlphaImage::from_fn(w, h, |x, y| {
+            image::LumaA([(x * 3) as u8, (y * 5) as u8])
+        }))),
+    ]
+}
+
+// subcommand of the encoders, the other formats are only read
+fn encoder_name(img_format: &ImageFormat) -> &'static str {
+    match img_format {
+        ImageFormat::Webp => "webp",
+        ImageFormat::WebpImage => "webp-image",
+        ImageFormat::Avif => "avif",
+        ImageFormat::Png => "png",
+        ImageFormat::Jpeg => "jpeg",
+        ImageFormat::Pnm => "pnm",
+        ImageFormat::Farbfeld => "farbfeld",
+        ImageFormat::Heif => "heif",
+        ImageFormat::Bmp => "bmp",
+        ImageFormat::Dds => "dds",
+        ImageFormat::Gif => "gif",
+        ImageFormat::Hdr => "hdr",
+        ImageFormat::Ico => "ico",
+        ImageFormat::Jpeg2000 => "jpeg2000",
+        ImageFormat::Exr => "exr",
+        ImageFormat::Psd => "psd",
+        ImageFormat::Qoi => "qoi",
+        ImageFormat::Tga => "tga",
+        ImageFormat::Tiff => "tiff",
+        ImageFormat::Unknown => "unknown",
+    }
+}
+
+/// Encodes `image` and decodes the result again, checking the dimensions
+fn roundtrip_encoder(img_format: &ImageFormat, image: &DynamicImage, decodable: bool) -> Result<String, Error> {
+    let options = EncoderConfig {
+        speed: Some(10), // keep avif fast, this is about correctness
+        ..Default::default()
+    };
+    let encoded = encode_image(image, img_format, &options)?;
+    if !decodable {
+        return Ok(format!("{} B, not decoded", encoded.len()));
+    }
+    let decoded = read_image_from_memory(&encoded)?;
+    if (decoded.width(), decoded.height()) != (image.width(), image.height()) {
+        return Err(Error::from_string(format!(
+            "dimension mismatch {}x{} => {}x{}",
+            image.width(), image.height(), decoded.width(), decoded.height()
+        )));
+    }
+    Ok(format!("{} B", encoded.len()))
+}
+
+/// Round-trips `image` through the image crate codec of `format`
+fn roundtrip_decoder(format: ImageImageFormat, image: &DynamicImage) -> Result<(), Error> {
+    let mut encoded = Cursor::new(Vec::new());
+    image.write_to(&mut encoded, format)?;
+    let decoded = image::load_from_memory_with_format(encoded.get_ref(), format)?;
+    if (decoded.width(), decoded.height()) != (image.width(), image.height()) {
+        return Err(Error::from_string("dimension mismatch".to_string()));
+    }
+    Ok(())
+}
+
+// uncompressed 32 bit bgra texture, the layout image editors write without compression
+fn dds_file(image: &RgbaImage) -> Vec<u8> {
+    let mut header = [0u32; 31];
+    header[0] = 124; // header size
+    header[1] = 0x100F; // caps, height, width, pitch and pixel format are set
+    header[2] = image.height();
+    header[3] = image.width();
+    header[4] = image.width() * 4; // pitch
+    header[18] = 32; // pixel format size
+    header[19] = 0x41; // DDPF_RGB | DDPF_ALPHAPIXELS
+    header[21] = 32; // bits per pixel
+    header[22..26].copy_from_slice(&[0xFF_0000, 0xFF00, 0xFF, 0xFF00_0000]);
+    header[26] = 0x1000; // DDSCAPS_TEXTURE
+    let mut data = b"DDS ".to_vec();
+    data.extend(header.iter().flat_map(|value| value.to_le_bytes()));
+    data.extend(image.pixels().flat_map(|pixel| [pixel[2], pixel[1], pixel[0], pixel[3]]));
+    data
+}
+
+// 8 bit rgb document with an uncompressed composite (photoshop mattes transparent composites, which is not lossless)
+fn psd_file(image: &RgbImage) -> Vec<u8> {
+    let mut data = b"8BPS".to_vec();
+    data.extend(1u16.to_be_bytes()); // psd, not psb
+    data.extend([0; 6]);
+    data.extend(3u16.to_be_bytes()); // channels
+    data.extend(image.height().to_be_bytes());
+    data.extend(image.width().to_be_bytes());
+    data.extend(8u16.to_be_bytes()); // depth
+    data.extend(3u16.to_be_bytes()); // rgb mode
+    data.extend([0; 12]); // no color mode data, image resources, layers and masks
+    data.extend(0u16.to_be_bytes()); // raw planes
+    for channel in 0..3 {
+        data.extend(image.pixels().map(|pixel| pixel[channel]));
+    }
+    data
+}
+
+/// Decodes the file written for `image` by a built-in decoder, checking dimensions and pixels
+fn roundtrip_builtin_decoder(data: &[u8], image: &DynamicImage) -> Result<(), Error> {
+    let decoded = read_image_from_memory(data)?;
+    if (decoded.width(), decoded.height()) != (image.width(), image.height()) {
+        return Err(Error::from_string("dimension mismatch".to_string()));
+    }
+    if decoded.to_rgba8() != image.to_rgba8() {
+        return Err(Error::from_string("pixel mismatch".to_string()));
+    }
+    Ok(())
+}
+
+/// Runs the self-test of all compiled encoders and decoders, printing a report.
+///
+/// Returns an error if any check failed, so that scripted (docker) validation can rely on the exit code.
+pub fn run_doctor() -> Result<(), Error> {
+    let mut problems = 0;
+
+    let glyphs = output::glyphs();
+    println!("imgc {}", env!("CARGO_PKG_VERSION"));
+    println!();
+    println!("Optional features:");
+    for &(name, enabled) in FEATURES {
+        if enabled {
+            println!("  {} {:<10} present", glyphs.ok, name);
+        } else {
+            println!("  - {:<10} missing", name);
+        }
+    }
+    println!();
+    println!("Dependencies:");
+    for name in REPORTED_DEPENDENCIES {
+        if let Some(version) = dependency_version(name) {
+            println!("  {:<14} {}", name, version);
+        }
+    }
+
+    println!();
+    println!("Encoders (encode {} decode round trip):", glyphs.arrow);
+    let images = test_images();
+    for img_format in ENCODERS {
+        // avif and heif inputs are not read (see convert_images), outputs can only be checked for success
+        let decodable = !matches!(img_format, ImageFormat::Avif | ImageFormat::Heif);
+        for (name, image) in &images {
+            match roundtrip_encoder(img_format, image, decodable) {
+                Ok(info) => println!("  {} {:<10} {:<7} {}", glyphs.ok, encoder_name(img_format), name, info),
+                Err(err) => {
+                    problems += 1;
+                    println!("  {} {:<10} {:<7} {}", glyphs.error, encoder_name(img_format), name, err);
+                }
+            }
+        }
+    }
+
+    println!();
+    println!("Decoders (image crate):");
+    let rgba = &images[1].1;
+    for format in ImageImageFormat::all() {
+        let name = format.extensions_str().first().copied().unwrap_or("?");
+        if !format.reading_enabled() || format == ImageImageFormat::Avif {
+            println!("  - {:<10} not compiled in", name);
+            continue;
+        }
+        if !format.writing_enabled() {
+            println!("  {} {:<10} available (no encoder for a round trip)", glyphs.ok, name);
+            continue;
+        }
+        // not every codec supports every color type, try until one is accepted
+        let candidates = [
+            rgba.clone(),
+            DynamicImage::ImageRgb8(rgba.to_rgb8()),
+            DynamicImage::ImageRgba16(rgba.to_rgba16()),
+            DynamicImage::ImageRgb32F(rgba.to_rgb32f()),
+            DynamicImage::ImageRgba32F(rgba.to_rgba32f()),
+        ];
+        let mut result = Err(Error::from_string("no color type accepted".to_string()));
+        for candidate in &candidates {
+            result = roundtrip_decoder(format, candidate);
+            if result.is_ok() {
+                break;
+            }
+        }
+        match result {
+            Ok(()) => println!("  {} {:<10} round trip ok", glyphs.ok, name),
+            Err(err) => {
+                problems += 1;
+                println!("  {} {:<10} {}", glyphs.error, name, err);
+            }
+        }
+    }
+
+    println!();
+    println!("Decoders (built-in):");
+    let builtin = [
+        ("dds", dds_file(&rgba.to_rgba8()), rgba.clone()),
+        ("psd", psd_file(&rgba.to_rgb8()), DynamicImage::ImageRgb8(rgba.to_rgb8())),
+    ];
+    for (name, data, image) in &builtin {
+        match roundtrip_builtin_decoder(data, image) {
+            Ok(()) => println!("  {} {:<10} round trip ok", glyphs.ok, name),
+            Err(err) => {
+                problems += 1;
+                println!("  {} {:<10} {}", glyphs.error, name, err);
+            }
+        }
+    }
+
+    println!();
+    println!("Note: avif inputs are not supported (no reliable decoder integration yet).");
+    if problems > 0 {
+        return Err(Error::from_string(format!("doctor found {} problem(s)", problems)));
+    }
+    println!("All checks passed.");
+    Ok(())
+}
+
+/// Encodes `image` into `directory` and decodes the written file again, checking the dimensions.
+///
+/// Encoder panics are caught and reported like encoding errors.
+fn check_corpus_encoder(img_format: &ImageFormat, image: &DynamicImage, input: &Path, directory: &Path) -> Result<(), Error> {
+    let options = EncoderConfig {
+        speed: Some(10),
+        ..Default::default()
+    };
+    let encoded = panic::catch_unwind(AssertUnwindSafe(|| encode_image(image, img_format, &options)))
+        .unwrap_or_else(|_| Err(Error::from_string("encoder panicked".to_string())))?;
+    let stem = input.file_stem().unwrap_or_default().to_string_lossy();
+    let output = directory.join(format!("{}.{}.{}", stem, encoder_name(img_format), img_format.extension()));
+    fs::write(&output, &encoded)?;
+    // avif and heif inputs are not read (see run_doctor), their outputs can only be checked for success
+    if matches!(img_format, ImageFormat::Avif | ImageFormat::Heif) {
+        return Ok(());
+    }
+    let decoded = read_image(&output)?;
+    if (decoded.width(), decoded.height()) != (image.width(), image.height()) {
+        return Err(Error::from_string(format!(
+            "dimension mismatch {}x{} => {}x{}",
+            image.width(), image.height(), decoded.width(), decoded.height()
+        )));
+    }
+    Ok(())
+}
+
+/// Runs every compiled encoder over the files matching `pattern` in a temporary directory,
+/// printing a pass/fail matrix of files and encoders followed by the failures.
+///
+/// Returns an error if any file failed to decode or encode, like [`run_doctor`].
+pub fn run_selftest_corpus(pattern: &str) -> Result<(), Error> {
+    let mut inputs: Vec<PathBuf> = Vec::new();
+    for entry in glob(pattern)? {
+        let path = entry?;
+        if path.is_file() {
+            inputs.push(path);
+        }
+    }
+    if inputs.is_empty() {
+        return Err(Error::from_string(format!("no files match {}", pattern)));
+    }
+    let directory = env::temp_dir().join(format!("imgc-selftest-{}", process::id()));
+    fs::create_dir_all(&directory)?;
+
+    let glyphs = output::glyphs();
+    let name_width = inputs.iter().map(|path| path.display().to_string().chars().count()).max().unwrap_or(0);
+    print!("{:<width$}", "", width = name_width);
+    for img_format in ENCODERS {
+        print!("  {:<10}", encoder_name(img_format));
+    }
+    println!();
+
+    let mut failures = Vec::new();
+    for input in &inputs {
+        print!("{:<width$}", input.display(), width = name_width);
+        match read_image(input) {
+            Ok(image) => {
+                for img_format in ENCODERS {
+                    let glyph = match check_corpus_encoder(img_format, &image, input, &directory) {
+                        Ok(()) => glyphs.ok,
+                        Err(err) => {
+                            failures.push(format!("{} {} {}: {}", glyphs.error, input.display(), encoder_name(img_format), err));
+                            glyphs.error
+                        }
+                    };
+                    print!("  {:<10}", glyph);
+                }
+            }
+            Err(err) => {
+                print!("  {} not decodable", glyphs.error);
+                failures.push(format!("{} {}: {}", glyphs.error, input.display(), err));
+            }
+        }
+        println!();
+    }
+    let _ = fs::remove_dir_all(&directory);
+
+    if !failures.is_empty() {
+        println!();
+        for failure in &failures {
+            println!("{}", failure);
+        }
+        return Err(Error::from_string(format!("selftest-corpus found {} failure(s) in {} files", failures.len(), inputs.len())));
+    }
+    println!();
+    println!("All {} files passed.", inputs.len());
+    Ok(())
+}
+
+/// Encoder of an output format
+#[derive(serde::Serialize, Debug)]
+pub struct EncoderCapability {
+    /// Subcommand selecting the encoder.
+    pub command: &'static str,
+    /// Extension of the outputs.
+    pub extension: &'static str,
+    /// Package providing the encoder.
+    pub package: &'static str,
+    /// Version of the package.
+    pub version: Option<&'static str>,
+}
+
+/// Decoder of an input format
+#[derive(serde::Serialize, Debug)]
+pub struct DecoderCapability {
+    /// Name of the format.
+    pub format: String,
+    /// File extensions of the format.
+    pub extensions: Vec<&'static str>,
+    /// What decodes the format: `image` (crate), `imgc` (built-in), `zune-jpeg` (`zune` feature) or an external binary.
+    pub decoder: &'static str,
+}
+
+/// What this build of imgc can do, for tooling detecting features at runtime (`imgc capabilities --json`)
+#[derive(serde::Serialize, Debug)]
+pub struct Capabilities {
+    /// Version of this format, only increased on incompatible changes (fields are only ever added otherwise).
+    pub schema_version: u32,
+    /// Version of imgc.
+    pub imgc_version: &'static str,
+    /// Compiled cargo features.
+    pub features: Vec<&'static str>,
+    /// Output encoders.
+    pub encoders: Vec<EncoderCapability>,
+    /// Input decoders.
+    pub decoders: Vec<DecoderCapability>,
+    /// Versions of all compiled packages (the newest one of packages present in several versions).
+    pub dependencies: BTreeMap<&'static str, &'static str>,
+}
+
+/// Collects the capabilities of this build
+pub fn capabilities() -> Capabilities {
+    let encoders = ENCODERS.iter()
+        .map(|img_format| EncoderCapability {
+            command: encoder_name(img_format),
+            extension: img_format.extension(),
+            package: encoder_package(img_format),
+            version: dependency_version(encoder_package(img_format)),
+        })
+        .collect();
+
+    // built-in decoders replace the ones of the image crate
+    let mut decoders = vec![
+        DecoderCapability { format: "dds".to_string(), extensions: vec!["dds"], decoder: "imgc" },
+        DecoderCapability { format: "psd".to_string(), extensions: vec!["psd", "psb"], decoder: "imgc" },
+    ];
+    if cfg!(feature = "jpeg2000") {
+        decoders.push(DecoderCapability {
+            format: "jpeg2000".to_string(), extensions: vec!["jp2", "j2k", "j2c", "jpc"], decoder: "opj_decompress",
+        });
+    }
+    if cfg!(feature = "zune") {
+        decoders.push(DecoderCapability {
+            format: "jpeg".to_string(), extensions: vec!["jpg", "jpeg", "pjpeg"], decoder: "zune-jpeg",
+        });
+    }
+    for format in ImageImageFormat::all() {
+        // avif inputs are not supported (see run_doctor)
+        if !format.reading_enabled() || format == ImageImageFormat::Avif {
+            continue;
+        }
+        let name = format!("{:?}", format).to_lowercase();
+        if decoders.iter().any(|decoder| decoder.format == name) {
+            continue;
+        }
+        decoders.push(DecoderCapability { format: name, extensions: format.extensions_str().to_vec(), decoder: "image" });
+    }
+    decoders.sort_by(|a, b| a.format.cmp(&b.format));
+
+    Capabilities {
+        schema_version: 1,
+        imgc_version: env!("CARGO_PKG_VERSION"),
+        features: FEATURES.iter().filter(|(_, enabled)| *enabled).map(|&(name, _)| name).collect(),
+        encoders,
+        decoders,
+        // later entries are newer versions (see dependency_version)
+        dependencies: DEPENDENCIES.iter().copied().collect(),
+    }
+}
+
+/// Prints the capabilities of this build, as json or readable text
+pub fn print_capabilities(json: bool) -> Result<(), Error> {
+    let capabilities = capabilities();
+    if json {
+        println!("{}", serde_json::to_string_pretty(&capabilities)?);
+        return Ok(());
+    }
+    println!("imgc {}", capabilities.imgc_version);
+    println!("Features: {}", capabilities.features.join(", "));
+    println!();
+    println!("Encoders:");
+    for encoder in &capabilities.encoders {
+        println!("  {:<10} .{:<5} {} {}", encoder.command, encoder.extension, encoder.package, encoder.version.unwrap_or("unknown"));
+    }
+    println!();
+    println!("Decoders:");
+    for decoder in &capabilities.decoders {
+        println!("  {:<10} {:<16} {}", decoder.format, decoder.extensions.join(", "), decoder.decoder);
+    }
+    println!();
+    println!("Dependencies: {} packages (see --json)", capabilities.dependencies.len());
+    Ok(())
+}
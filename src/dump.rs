@@ -0,0 +1,66 @@
+use crate::{format::ImageFormat, Error};
+use image::{DynamicImage, ImageFormat as PixelFormat};
+use std::{
+    ffi::OsString,
+    fs,
+    path::{Path, PathBuf},
+};
+
+/// File format of the pixel dumps of `--debug-dump-rgba`
+#[cfg_attr(feature = "cli", derive(clap::ValueEnum))]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum DumpFormat {
+    /// 8 bit rgba png, viewable next to the encoded output.
+    #[default]
+    Png,
+    /// Bare 8 bit rgba bytes, row by row, with the dimensions in the file name (`<width>x<height>.rgba`).
+    Raw,
+}
+
+/// Dumps of the pixels handed to the encoders, for comparing them with the decoded outputs of color-shift reports
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PixelDump {
+    /// Directory the dumps are written into (at the path of their input relative to the pattern base).
+    pub directory: PathBuf,
+    /// File format of the dumps.
+    pub format: DumpFormat,
+    /// Dumps 1 in `sample` inputs, 1 dumps all of them.
+    pub sample: u32,
+}
+
+impl PixelDump {
+    /// Whether `input` is in the sampled subset, picked by a hash of its path (the same inputs in every run)
+    pub fn samples(&self, input: &Path) -> bool {
+        // FNV-1a, stable across builds unlike the std hasher
+        let hash = input.as_os_str().as_encoded_bytes().iter()
+            .fold(0xcbf2_9ce4_8422_2325u64, |hash, &byte| (hash ^ byte as u64).wrapping_mul(0x0100_0000_01b3));
+        hash % self.sample.max(1) as u64 == 0
+    }
+
+    /// Writes the encoder input `image` of the output in `img_format`, returning the dump path
+    ///
+    /// The dump is named after the `relative` input path and the output extension, e.g. `photos/a.jpg.webp.rgba.png`,
+    /// so the dumps of routed and fallback outputs stay apart. Existing dumps are overwritten.
+    pub fn write(&self, relative: &Path, image: &DynamicImage, img_format: &ImageFormat) -> Result<PathBuf, Error> {
+        let target = self.directory.join(relative);
+        fs::create_dir_all(target.parent().unwrap_or(&self.directory))?;
+        let rgba = image.to_rgba8();
+        let mut name = OsString::from(target.file_name().unwrap_or_default());
+        name.push(format!(".{}", img_format.extension()));
+        match self.format {
+            DumpFormat::Png => {
+                name.push(".rgba.png");
+                let target = target.with_file_name(name);
+                rgba.save_with_format(&target, PixelFormat::Png)
+                    .map_err(|e| Error::from_string(format!("cannot write pixel dump {}: {}", target.display(), e)))?;
+                Ok(target)
+            }
+            DumpFormat::Raw => {
+                name.push(format!(".{}x{}.rgba", rgba.width(), rgba.height()));
+                let target = target.with_file_name(name);
+                fs::write(&target, rgba.as_raw())?;
+                Ok(target)
+            }
+        }
+    }
+}
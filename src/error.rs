@@ -67,18 +67,39 @@ impl From<glob::PatternError> for Error {
     }
 }
 
+#[cfg(feature = "cli")]
 impl From<clap::Error> for Error {
     fn from(err: clap::Error) -> Self {
         Self::new(err)
     }
 }
 
+#[cfg(feature = "watch")]
+impl From<notify::Error> for Error {
+    fn from(err: notify::Error) -> Self {
+        Self::new(err)
+    }
+}
+
+#[cfg(feature = "clipboard")]
+impl From<arboard::Error> for Error {
+    fn from(err: arboard::Error) -> Self {
+        Self::new(err)
+    }
+}
+
 impl From<image::ImageError> for Error {
     fn from(err: image::ImageError) -> Self {
         Self::new(err)
     }
 }
 
+impl From<serde_json::Error> for Error {
+    fn from(err: serde_json::Error) -> Self {
+        Self::new(err)
+    }
+}
+
 /// Custom error type for wrapping a string message.
 struct CustomError(String);
 
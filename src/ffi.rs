@@ -0,0 +1,324 @@
+use crate::{
+    converter::{
+        avif::{AlphaColorMode, BitDepth, ColorModel},
+        encode_image,
+        png::{ColorType, CompressionType, FilterType},
+        EncoderConfig,
+    },
+    decode::read_image_from_memory,
+    format::ImageFormat,
+    Error,
+};
+use std::{
+    cell::RefCell,
+    ffi::{c_char, CString},
+    panic, ptr, slice,
+};
+
+/// Output format: webp (webp crate)
+pub const IMGC_FORMAT_WEBP: i32 = 0;
+/// Output format: webp (image crate, lossless)
+pub const IMGC_FORMAT_WEBP_IMAGE: i32 = 1;
+/// Output format: avif (ravif crate)
+pub const IMGC_FORMAT_AVIF: i32 = 2;
+/// Output format: png (image crate)
+pub const IMGC_FORMAT_PNG: i32 = 3;
+/// Output format: jpeg (mozjpeg crate)
+pub const IMGC_FORMAT_JPEG: i32 = 4;
+
+/// Conversion succeeded, the output buffer is set
+pub const IMGC_OK: i32 = 0;
+/// A pointer argument was null or an option was out of range
+pub const IMGC_ERR_INVALID_ARGUMENT: i32 = -1;
+/// The input buffer could not be decoded
+pub const IMGC_ERR_DECODE: i32 = -2;
+/// The encoder failed
+pub const IMGC_ERR_ENCODE: i32 = -3;
+/// The encoder panicked
+pub const IMGC_ERR_PANIC: i32 = -4;
+
+/// Encoder options passed over the C ABI.
+///
+/// Negative values (and `0` for `speed`) select the encoder default,
+/// enum options are indices into the variants listed with each field.
+#[repr(C)]
+#[derive(Clone, Copy, Debug)]
+pub struct ImgcOptions {
+    /// Use lossless encoding mode (webp), `0` = false, everything else = true.
+    pub lossless: u8,
+    /// Target quality (0 - 100, webp, avif, heic and jpeg).
+    pub quality: f32,
+    /// Encoding speed (1 - 10, avif).
+    pub speed: u8,
+    /// Png compression type index (default, fast, best).
+    pub png_compression_type: i32,
+    /// Png filter type index (no-filter, sub, up, avg, paeth, adaptive).
+    pub png_filter_type: i32,
+    /// Avif bit depth index (eight, ten, auto).
+    pub avif_bit_depth: i32,
+    /// Avif color model index (y-cb-cr, rgb).
+    pub avif_color_model: i32,
+    /// Avif alpha color mode index (unassociated-dirty, unassociated-clean, premultiplied).
+    pub avif_alpha_color_mode: i32,
+    /// Target avif alpha quality (0 - 100).
+    pub avif_alpha_quality: f32,
+    /// Byte-identical output across runs and machines, `0` = false, everything else = true.
+    pub deterministic: u8,
+    /// Png color type index (auto, rgb, rgba, palette, gray).
+    pub png_color_type: i32,
+    /// Pixels with an alpha below this are made fully transparent (webp and avif), `0` = off.
+    pub alpha_threshold: u8,
+    /// Round-trip semi-transparent colors through premultiplied alpha (webp and avif), `0` = false, everything else = true.
+    pub premultiply_alpha: u8,
+}
+
+thread_local! {
+    static LAST_ERROR: RefCell<Option<CString>> = const { RefCell::new(None) };
+}
+
+fn set_last_error(msg: String) {
+    let msg = CString::new(msg.replace('\0', "")).unwrap_or_default();
+    LAST_ERROR.with(|e| *e.borrow_mut() = Some(msg));
+}
+
+fn format_from_id(format: i32) -> Option<ImageFormat> {
+    match format {
+        IMGC_FORMAT_WEBP => Some(ImageFormat::Webp),
+        IMGC_FORMAT_WEBP_IMAGE => Some(ImageFormat::WebpImage),
+        IMGC_FORMAT_AVIF => Some(ImageFormat::Avif),
+        IMGC_FORMAT_PNG => Some(ImageFormat::Png),
+        IMGC_FORMAT_JPEG => Some(ImageFormat::Jpeg),
+        _ => None,
+    }
+}
+
+fn index_out_of_range(index: i32) -> Error {
+    Error::from_string(format!("option index {} out of range", index))
+}
+
+// the indices of the enum options are part of the C ABI, they must not follow reorderings of the variants
+
+fn png_compression_type_from_index(index: i32) -> Result<Option<CompressionType>, Error> {
+    Ok(Some(match index {
+        ..0 => return Ok(None),
+        0 => CompressionType::Default,
+        1 => CompressionType::Fast,
+        2 => CompressionType::Best,
+        _ => return Err(index_out_of_range(index)),
+    }))
+}
+
+fn png_filter_type_from_index(index: i32) -> Result<Option<FilterType>, Error> {
+    Ok(Some(match index {
+        ..0 => return Ok(None),
+        0 => FilterType::NoFilter,
+        1 => FilterType::Sub,
+        2 => FilterType::Up,
+        3 => FilterType::Avg,
+        4 => FilterType::Paeth,
+        5 => FilterType::Adaptive,
+        _ => return Err(index_out_of_range(index)),
+    }))
+}
+
+fn png_color_type_from_index(index: i32) -> Result<Option<ColorType>, Error> {
+    Ok(Some(match index {
+        ..0 => return Ok(None),
+        0 => ColorType::Auto,
+        1 => ColorType::Rgb,
+        2 => ColorType::Rgba,
+        3 => ColorType::Palette,
+        4 => ColorType::Gray,
+        _ => return Err(index_out_of_range(index)),
+    }))
+}
+
+fn avif_bit_depth_from_index(index: i32) -> Result<Option<BitDepth>, Error> {
+    Ok(Some(match index {
+        ..0 => return Ok(None),
+        0 => BitDepth::Eight,
+        1 => BitDepth::Ten,
+        2 => BitDepth::Auto,
+        _ => return Err(index_out_of_range(index)),
+    }))
+}
+
+fn avif_color_model_from_index(index: i32) -> Result<Option<ColorModel>, Error> {
+    Ok(Some(match index {
+        ..0 => return Ok(None),
+        0 => ColorModel::YCbCr,
+        1 => ColorModel::RGB,
+        _ => return Err(index_out_of_range(index)),
+    }))
+}
+
+fn avif_alpha_color_mode_from_index(index: i32) -> Result<Option<AlphaColorMode>, Error> {
+    Ok(Some(match index {
+        ..0 => return Ok(None),
+        0 => AlphaColorMode::UnassociatedDirty,
+        1 => AlphaColorMode::UnassociatedClean,
+        2 => AlphaColorMode::Premultiplied,
+        _ => return Err(index_out_of_range(index)),
+    }))
+}
+
+impl ImgcOptions {
+    fn to_encoder_config(self) -> Result<EncoderConfig, Error> {
+        Ok(EncoderConfig {
+            lossless: Some(self.lossless != 0),
+            quality: (self.quality >= 0.).then_some(self.quality),
+            visual_quality: None,
+            speed: (self.speed > 0).then_some(self.speed),
+            input_overrides: Vec::new(),
+            crop: None,
+            webp_near_lossless: None,
+            png_compression_type: png_compression_type_from_index(self.png_compression_type)?,
+            png_filter_type: png_filter_type_from_index(self.png_filter_type)?,
+            png_color_type: png_color_type_from_index(self.png_color_type)?,
+            png_strip_chunks: None,
+            pnm_subtype: None,
+            pnm_plain: None,
+            avif_bit_depth: avif_bit_depth_from_index(self.avif_bit_depth)?,
+            avif_color_model: avif_color_model_from_index(self.avif_color_model)?,
+            avif_alpha_color_mode: avif_alpha_color_mode_from_index(self.avif_alpha_color_mode)?,
+            avif_alpha_quality: (self.avif_alpha_quality >= 0.).then_some(self.avif_alpha_quality),
+            deterministic: Some(self.deterministic != 0),
+            alpha_threshold: (self.alpha_threshold > 0).then_some(self.alpha_threshold),
+            premultiply_alpha: Some(self.premultiply_alpha != 0),
+            frames: None,
+            frame_step: None,
+            max_frames: None,
+            fps: None,
+            metadata: Default::default(),
+            keep_metadata: None,
+            strip_gps: None,
+            jpeg_bake_orientation: None,
+            jpeg_restart_interval: None,
+            jpeg_optimize_scans: None,
+            jpeg_trellis: None,
+        })
+    }
+}
+
+fn convert_buffer(img_format: &ImageFormat, options: &EncoderConfig, input: &[u8]) -> Result<Vec<u8>, i32> {
+    let image = match read_image_from_memory(input) {
+        Ok(image) => image,
+        Err(err) => {
+            set_last_error(format!("decoding failed: {}", err));
+            return Err(IMGC_ERR_DECODE);
+        }
+    };
+
+    match panic::catch_unwind(|| encode_image(&image, img_format, options)) {
+        Ok(Ok(data)) => Ok(data),
+        Ok(Err(err)) => {
+            set_last_error(err.to_string());
+            Err(IMGC_ERR_ENCODE)
+        }
+        Err(_) => {
+            set_last_error("encoder panicked".to_string());
+            Err(IMGC_ERR_PANIC)
+        }
+    }
+}
+
+/// Returns the options struct with every field set to the encoder default.
+#[unsafe(no_mangle)]
+pub extern "C" fn imgc_default_options() -> ImgcOptions {
+    ImgcOptions {
+        lossless: 0,
+        quality: -1.,
+        speed: 0,
+        png_compression_type: -1,
+        png_filter_type: -1,
+        avif_bit_depth: -1,
+        avif_color_model: -1,
+        avif_alpha_color_mode: -1,
+        avif_alpha_quality: -1.,
+        deterministic: 0,
+        png_color_type: -1,
+        alpha_threshold: 0,
+        premultiply_alpha: 0,
+    }
+}
+
+/// Decodes the image in `in_ptr[..in_len]` and encodes it to `format` (one of the `IMGC_FORMAT_*` constants).
+///
+/// On success `IMGC_OK` is returned and `*out_ptr`/`*out_len` describe a newly allocated buffer,
+/// which has to be released with [`imgc_free_buffer`].
+/// On failure a negative `IMGC_ERR_*` code is returned and [`imgc_last_error`] describes the cause.
+///
+/// # Safety
+///
+/// `in_ptr` must point to `in_len` readable bytes, `out_ptr` and `out_len` must be valid for writes.
+/// `options` may be null to use the encoder defaults.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn imgc_convert_buffer(
+    format: i32,
+    options: *const ImgcOptions,
+    in_ptr: *const u8,
+    in_len: usize,
+    out_ptr: *mut *mut u8,
+    out_len: *mut usize,
+) -> i32 {
+    if in_ptr.is_null() || out_ptr.is_null() || out_len.is_null() {
+        set_last_error("null pointer argument".to_string());
+        return IMGC_ERR_INVALID_ARGUMENT;
+    }
+    let Some(img_format) = format_from_id(format) else {
+        set_last_error(format!("unknown output format {}", format));
+        return IMGC_ERR_INVALID_ARGUMENT;
+    };
+    let options = if options.is_null() {
+        imgc_default_options()
+    } else {
+        unsafe { *options }
+    };
+    let encoder_config = match options.to_encoder_config() {
+        Ok(conf) => conf,
+        Err(err) => {
+            set_last_error(err.to_string());
+            return IMGC_ERR_INVALID_ARGUMENT;
+        }
+    };
+
+    let input = unsafe { slice::from_raw_parts(in_ptr, in_len) };
+    match convert_buffer(&img_format, &encoder_config, input) {
+        Ok(data) => {
+            let data = data.into_boxed_slice();
+            unsafe {
+                *out_len = data.len();
+                *out_ptr = Box::into_raw(data) as *mut u8;
+            }
+            IMGC_OK
+        }
+        Err(code) => {
+            unsafe {
+                *out_ptr = ptr::null_mut();
+                *out_len = 0;
+            }
+            code
+        }
+    }
+}
+
+/// Releases a buffer returned by [`imgc_convert_buffer`].
+///
+/// # Safety
+///
+/// `ptr` and `len` must be exactly as returned by [`imgc_convert_buffer`], and must not be freed twice.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn imgc_free_buffer(ptr: *mut u8, len: usize) {
+    if ptr.is_null() {
+        return;
+    }
+    drop(unsafe { Box::from_raw(ptr::slice_from_raw_parts_mut(ptr, len)) });
+}
+
+/// Returns the message of the last error on the calling thread, or null if there was none.
+///
+/// The pointer stays valid until the next failing call on the same thread.
+#[unsafe(no_mangle)]
+pub extern "C" fn imgc_last_error() -> *const c_char {
+    LAST_ERROR.with(|e| e.borrow().as_ref().map_or(ptr::null(), |msg| msg.as_ptr()))
+}
@@ -0,0 +1,87 @@
+use deunicode::deunicode_char;
+use std::path::{Path, PathBuf};
+use unicode_normalization::UnicodeNormalization;
+
+/// Unicode form of output filenames
+///
+/// Trees copied from macOS often have decomposed (NFD) names, which look like the composed (NFC) ones
+/// but are different files (and URLs) elsewhere.
+#[cfg_attr(feature = "cli", derive(clap::ValueEnum))]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FilenameNormalization {
+    /// Composed characters (`é` as one code point), the usual form on Linux and Windows and in URLs.
+    Nfc,
+    /// Decomposed characters (`e` followed by a combining accent), the form of older macOS filesystems.
+    Nfd,
+    /// Accents removed and other characters transliterated (`ß` to `ss`, `東京` to `Dong Jing`),
+    /// characters without transliteration replaced by `_`.
+    Ascii,
+}
+
+impl FilenameNormalization {
+    /// Normalizes the filename (or path component) `name`
+    pub fn normalize(self, name: &str) -> String {
+        match self {
+            FilenameNormalization::Nfc => name.nfc().collect(),
+            FilenameNormalization::Nfd => name.nfd().collect(),
+            // composed first, decomposed marks are transliterated with their letter (`か` + `゙` is `ga`)
+            FilenameNormalization::Ascii => transliterate(&name.nfc().collect::<String>()),
+        }
+    }
+
+    /// Normalizes every component of `path`
+    pub fn normalize_path(self, path: &Path) -> PathBuf {
+        path.components()
+            .map(|component| self.normalize(&component.as_os_str().to_string_lossy()))
+            .collect()
+    }
+}
+
+fn transliterate(name: &str) -> String {
+    let mut ascii = String::with_capacity(name.len());
+    let mut chars = name.chars().peekable();
+    while let Some(c) = chars.next() {
+        if c.is_ascii() {
+            ascii.push(c);
+            continue;
+        }
+        let transliterated = deunicode_char(c).unwrap_or("_");
+        // transliterated syllables end with a space, which is dropped before the extension and at the end
+        let transliterated = match chars.peek() {
+            Some(next) if !next.is_ascii() || next.is_ascii_alphanumeric() => transliterated,
+            _ => transliterated.trim_end(),
+        };
+        // a transliteration must not split the name (`⁄` is `/`)
+        ascii.extend(transliterated.chars().map(|c| if matches!(c, '/' | '\\') { '_' } else { c }));
+    }
+    ascii
+}
+
+#[cfg(test)]
+mod tests {
+    use super::FilenameNormalization::{Ascii, Nfc, Nfd};
+
+    #[test]
+    fn composes_and_decomposes() {
+        assert_eq!(Nfc.normalize("Cafe\u{301}.jpg"), "Café.jpg");
+        assert_eq!(Nfd.normalize("Café.jpg"), "Cafe\u{301}.jpg");
+        // kana with a combining voiced sound mark, hangul jamo
+        assert_eq!(Nfc.normalize("か\u{3099}.png"), "が.png");
+        assert_eq!(Nfd.normalize("が.png"), "か\u{3099}.png");
+        assert_eq!(Nfc.normalize("\u{1112}\u{1161}\u{11ab}"), "한");
+        // singletons (angstrom sign) and CJK compatibility ideographs have no composed form of their own
+        assert_eq!(Nfc.normalize("\u{212b}"), "\u{c5}");
+        assert_eq!(Nfc.normalize("\u{f900}"), "\u{8c48}");
+        // marks are reordered by their combining class
+        assert_eq!(Nfd.normalize("a\u{301}\u{323}"), "a\u{323}\u{301}");
+    }
+
+    #[test]
+    fn transliterates_to_ascii() {
+        assert_eq!(Ascii.normalize("Straße Ærø.jpg"), "Strasse AEro.jpg");
+        assert_eq!(Ascii.normalize("Cafe\u{301}.jpg"), "Cafe.jpg");
+        assert_eq!(Ascii.normalize("か\u{3099}.png"), "ga.png");
+        assert_eq!(Ascii.normalize("東京.jpg"), "Dong Jing.jpg");
+        assert_eq!(Ascii.normalize("a⁄b.png"), "a_b.png");
+    }
+}
@@ -4,18 +4,19 @@ use std::path::Path;
 ///
 /// This enumeration covers a wide range of common and less common image formats.
 /// Each variant represents a different format that an image file can be encoded in.
-/// The `Other` variant is provided to allow for formats not explicitly listed here,
-/// enabling the enumeration to hold any format specified as a `String`.
+/// The `Unknown` variant is used for formats not explicitly listed here.
 ///
 /// # Examples
 ///
 /// ```
-/// use your_crate::ImageFormat;
+/// use imgc::format::ImageFormat;
 ///
 /// let format = ImageFormat::Png;
-/// let unknown_format = ImageFormat::Other("custom-format".to_string());
+/// let unknown_format = ImageFormat::from_extension("custom-format");
+/// assert_eq!(unknown_format, ImageFormat::Unknown);
 /// ```
-#[derive(Debug, PartialEq)]
+#[derive(serde::Serialize, serde::Deserialize, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "kebab-case")]
 pub enum ImageFormat {
     /// AV1 Image File Format, a format designed for high compression efficiency.
     Avif,
@@ -23,7 +24,7 @@ pub enum ImageFormat {
     /// Bitmap, a raster graphics image file format used to store bitmap digital images.
     Bmp,
 
-    /// DirectDraw Surface, a container format for storing data compressed with the S3TC algorithm.
+    /// DirectDraw Surface, a container format for game textures (S3TC/BC compressed, uncompressed or float).
     Dds,
 
     /// Farbfeld, a simple image file format designed to work well for lossless compression.
@@ -35,21 +36,30 @@ pub enum ImageFormat {
     /// High Dynamic Range Image File Format, a raster graphics file format for high dynamic range images.
     Hdr,
 
+    /// High Efficiency Image File Format, hevc compressed images of Apple devices (`.heic`).
+    Heif,
+
     /// Icon, a bitmap image format used for icons in Microsoft Windows.
     Ico,
 
     /// Joint Photographic Experts Group, an image compression standard that supports lossy and lossless compression.
     Jpeg,
 
+    /// JPEG 2000, a wavelet based successor of jpeg common for medical and archival scans.
+    Jpeg2000,
+
     /// OpenEXR, a high dynamic range raster file format.
     Exr,
 
     /// Portable Network Graphics, a raster graphics file format that supports lossless data compression.
     Png,
 
-    /// Portable anymap, a family of file formats to store bitmap images.
+    /// Portable anymap, a family of file formats to store bitmap images (`.pbm`, `.pgm`, `.ppm`, `.pam`).
     Pnm,
 
+    /// Photoshop document, only the merged composite image is read (`.psd`, `.psb`).
+    Psd,
+
     /// QuickTime Image, a raster graphics file format used by Apple's QuickTime framework.
     Qoi,
 
@@ -70,7 +80,7 @@ pub enum ImageFormat {
 
 impl ImageFormat {
     /// Get the file extension associated with the image format
-    pub fn extension(&self) -> &str {
+    pub fn extension(&self) -> &'static str {
         match self {
             ImageFormat::Avif => "avif",
             ImageFormat::Bmp => "bmp",
@@ -78,11 +88,14 @@ impl ImageFormat {
             ImageFormat::Farbfeld => "ff",
             ImageFormat::Gif => "gif",
             ImageFormat::Hdr => "hdr",
+            ImageFormat::Heif => "heic",
             ImageFormat::Ico => "ico",
             ImageFormat::Jpeg => "jpeg",
+            ImageFormat::Jpeg2000 => "jp2",
             ImageFormat::Exr => "exr",
             ImageFormat::Png => "png",
             ImageFormat::Pnm => "pnm",
+            ImageFormat::Psd => "psd",
             ImageFormat::Qoi => "qoi",
             ImageFormat::Tga => "tga",
             ImageFormat::Tiff => "tiff",
@@ -101,11 +114,14 @@ impl ImageFormat {
             "ff" | "farbfeld" => ImageFormat::Farbfeld,
             "gif" => ImageFormat::Gif,
             "hdr" => ImageFormat::Hdr,
+            "heic" | "heif" => ImageFormat::Heif,
             "ico" => ImageFormat::Ico,
             "jpeg" | "jpg" | "pjpeg" => ImageFormat::Jpeg,
+            "jp2" | "j2k" | "j2c" | "jpc" => ImageFormat::Jpeg2000,
             "exr" => ImageFormat::Exr,
             "png" | "x-png" => ImageFormat::Png,
-            "pnm" => ImageFormat::Pnm,
+            "pnm" | "pbm" | "pgm" | "ppm" | "pam" => ImageFormat::Pnm,
+            "psd" | "psb" => ImageFormat::Psd,
             "qoi" => ImageFormat::Qoi,
             "tga" => ImageFormat::Tga,
             "tiff" | "tif" => ImageFormat::Tiff,
@@ -123,3 +139,38 @@ impl From<&Path> for ImageFormat {
         }
     }
 }
+
+/// Output formats selectable by name (subcommands writing a fixed format take their own options instead)
+#[cfg_attr(feature = "cli", derive(clap::ValueEnum))]
+#[derive(serde::Serialize, serde::Deserialize, Clone, Copy, PartialEq, Eq, Debug)]
+#[serde(rename_all = "kebab-case")]
+pub enum OutputFormat {
+    /// webp (webp crate, lossy).
+    Webp,
+    /// webp (image crate, lossless).
+    WebpImage,
+    /// avif (ravif crate).
+    Avif,
+    /// png (image crate).
+    Png,
+    /// jpeg (mozjpeg).
+    Jpeg,
+    /// pnm (image crate, pgm/ppm/pam depending on the input channels).
+    Pnm,
+    /// farbfeld (image crate, 16 bit rgba).
+    Farbfeld,
+}
+
+impl From<OutputFormat> for ImageFormat {
+    fn from(format: OutputFormat) -> Self {
+        match format {
+            OutputFormat::Webp => ImageFormat::Webp,
+            OutputFormat::WebpImage => ImageFormat::WebpImage,
+            OutputFormat::Avif => ImageFormat::Avif,
+            OutputFormat::Png => ImageFormat::Png,
+            OutputFormat::Jpeg => ImageFormat::Jpeg,
+            OutputFormat::Pnm => ImageFormat::Pnm,
+            OutputFormat::Farbfeld => ImageFormat::Farbfeld,
+        }
+    }
+}
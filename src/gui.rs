@@ -0,0 +1,171 @@
+use crate::{
+    converter::{apply_plan, CommonConfig, EncoderConfig},
+    format::{ImageFormat, OutputFormat},
+    outcome::ConversionOutcome,
+    plan::plan_inputs,
+    Error,
+};
+use clap::ValueEnum;
+use eframe::egui;
+use std::{
+    path::{Path, PathBuf},
+    sync::{atomic::{AtomicUsize, Ordering}, Arc, Mutex},
+};
+
+// progress of the running conversion, updated from the worker threads of the batch engine
+#[derive(Default)]
+struct RunProgress {
+    total: AtomicUsize,
+    converted: AtomicUsize,
+    skipped: AtomicUsize,
+    failed: AtomicUsize,
+    last_file: Mutex<String>,
+    // set when the run is over, with its error
+    result: Mutex<Option<Result<(), String>>>,
+}
+
+impl RunProgress {
+    fn done(&self) -> usize {
+        self.converted.load(Ordering::Relaxed) + self.skipped.load(Ordering::Relaxed) + self.failed.load(Ordering::Relaxed)
+    }
+}
+
+struct GuiApp {
+    // settings of the command line, the window only changes format, quality and output directory
+    conf: CommonConfig,
+    options: EncoderConfig,
+    format: OutputFormat,
+    quality: f32,
+    output: String,
+    progress: Option<Arc<RunProgress>>,
+}
+
+// dropped files and the files in dropped directories, with their closest common directory
+fn collect_dropped(paths: Vec<PathBuf>) -> (Vec<PathBuf>, PathBuf) {
+    let mut files = Vec::new();
+    let mut base: Option<PathBuf> = None;
+    for path in paths {
+        let directory = if path.is_dir() { path.clone() } else { path.parent().map(Path::to_path_buf).unwrap_or_default() };
+        base = Some(match base {
+            Some(base) => base.ancestors().find(|ancestor| directory.starts_with(ancestor)).map(Path::to_path_buf).unwrap_or_default(),
+            None => directory,
+        });
+        if path.is_dir() {
+            let pattern = format!("{}/**/*", glob::Pattern::escape(&path.to_string_lossy()));
+            files.extend(glob::glob(&pattern).into_iter().flatten().filter_map(|entry| entry.ok()));
+        } else {
+            files.push(path);
+        }
+    }
+    (files, base.unwrap_or_default())
+}
+
+impl GuiApp {
+    fn start(&mut self, ctx: &egui::Context, dropped: Vec<PathBuf>) {
+        let (files, base) = collect_dropped(dropped);
+        let progress = Arc::new(RunProgress::default());
+        let (run_progress, repaint) = (progress.clone(), ctx.clone());
+        let conf = CommonConfig {
+            output: self.output.trim().to_string(),
+            // directories dropped together keep their structure below their common directory
+            relative_to: Some(base.to_string_lossy().to_string()),
+            hide_worker_progress: true,
+            on_file_done: Some(Arc::new(move |path, outcome| {
+                let counter = match outcome {
+                    ConversionOutcome::Converted { .. } => &run_progress.converted,
+                    ConversionOutcome::Failed { .. } => &run_progress.failed,
+                    _ => &run_progress.skipped,
+                };
+                counter.fetch_add(1, Ordering::Relaxed);
+                *run_progress.last_file.lock().unwrap_or_else(|e| e.into_inner()) = path.display().to_string();
+                repaint.request_repaint();
+            })),
+            ..self.conf.clone()
+        };
+        let options = EncoderConfig { quality: Some(self.quality), ..self.options.clone() };
+        let img_format = ImageFormat::from(self.format);
+        let (run_progress, repaint) = (progress.clone(), ctx.clone());
+        std::thread::spawn(move || {
+            let result = plan_inputs(&conf, &img_format, &options, files).and_then(|mut plan| {
+                plan.set_aside_up_to_date(&conf);
+                run_progress.total.store(plan.files.len() + plan.up_to_date.len(), Ordering::Relaxed);
+                apply_plan(conf, &plan)
+            });
+            *run_progress.result.lock().unwrap_or_else(|e| e.into_inner()) = Some(result.map_err(|err| err.to_string()));
+            repaint.request_repaint();
+        });
+        self.progress = Some(progress);
+    }
+}
+
+impl eframe::App for GuiApp {
+    fn update(&mut self, ctx: &egui::Context, _frame: &mut eframe::Frame) {
+        let running = self.progress.as_ref()
+            .is_some_and(|progress| progress.result.lock().unwrap_or_else(|e| e.into_inner()).is_none());
+        egui::CentralPanel::default().show(ctx, |ui| {
+            ui.add_enabled_ui(!running, |ui| {
+                egui::ComboBox::from_label("Format")
+                    .selected_text(ImageFormat::from(self.format).extension())
+                    .show_ui(ui, |ui| {
+                        for format in OutputFormat::value_variants() {
+                            let name = format.to_possible_value().map(|value| value.get_name().to_string()).unwrap_or_default();
+                            ui.selectable_value(&mut self.format, *format, name);
+                        }
+                    });
+                if matches!(self.format, OutputFormat::Webp | OutputFormat::Avif | OutputFormat::Jpeg) {
+                    ui.add(egui::Slider::new(&mut self.quality, 0.0..=100.0).text("Quality"));
+                }
+                ui.horizontal(|ui| {
+                    ui.label("Output directory");
+                    ui.text_edit_singleline(&mut self.output)
+                        .on_hover_text("Leave empty to write the outputs next to their inputs");
+                });
+            });
+            ui.separator();
+            match &self.progress {
+                None => {
+                    ui.label("Drop images or directories here to convert them");
+                }
+                Some(progress) => {
+                    let (done, total) = (progress.done(), progress.total.load(Ordering::Relaxed));
+                    ui.add(egui::ProgressBar::new(if total > 0 { done as f32 / total as f32 } else { 0. })
+                        .text(format!("{}/{} files", done, total)));
+                    ui.label(format!("converted: {}, skipped: {}, failed: {}", progress.converted.load(Ordering::Relaxed),
+                                     progress.skipped.load(Ordering::Relaxed), progress.failed.load(Ordering::Relaxed)));
+                    ui.label(progress.last_file.lock().unwrap_or_else(|e| e.into_inner()).as_str());
+                    match &*progress.result.lock().unwrap_or_else(|e| e.into_inner()) {
+                        Some(Ok(())) => { ui.label("Done, drop more files to convert them"); }
+                        Some(Err(err)) => { ui.colored_label(ui.visuals().error_fg_color, err); }
+                        None => {}
+                    }
+                }
+            }
+        });
+
+        let dropped: Vec<PathBuf> = ctx.input(|input| input.raw.dropped_files.iter().filter_map(|file| file.path.clone()).collect());
+        if !dropped.is_empty() && !running {
+            self.start(ctx, dropped);
+        }
+    }
+}
+
+/// Opens the drag and drop window of `imgc gui` until it is closed.
+///
+/// Dropped files and the files in dropped directories are converted by the batch engine with the format, quality
+/// and output directory chosen in the window, and the other settings of `conf` and `options` (e.g. overwrite flags).
+pub fn run_gui(conf: CommonConfig, options: EncoderConfig) -> Result<(), Error> {
+    let app = GuiApp {
+        quality: options.quality.unwrap_or(90.),
+        output: conf.output.clone(),
+        conf,
+        options,
+        format: OutputFormat::Webp,
+        progress: None,
+    };
+    let native_options = eframe::NativeOptions {
+        viewport: egui::ViewportBuilder::default().with_inner_size([480., 320.]).with_drag_and_drop(true),
+        ..Default::default()
+    };
+    eframe::run_native("imgc", native_options, Box::new(|_| Ok(Box::new(app))))
+        .map_err(|err| Error::from_string(format!("the window could not be opened: {}", err)))
+}
@@ -0,0 +1,81 @@
+use fluent_bundle::{concurrent::FluentBundle, FluentArgs, FluentResource};
+use std::sync::OnceLock;
+use unic_langid::LanguageIdentifier;
+
+/// Language of the messages of conversion runs
+#[cfg_attr(feature = "cli", derive(clap::ValueEnum))]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Language {
+    /// English.
+    En,
+    /// German.
+    De,
+}
+
+impl Language {
+    /// Language of the system locale (`LC_ALL`, `LC_MESSAGES` or `LANG` on unix), English if it has no catalog
+    pub fn detect() -> Language {
+        match sys_locale::get_locale() {
+            Some(locale) if locale.to_ascii_lowercase().starts_with("de") => Language::De,
+            _ => Language::En,
+        }
+    }
+
+    // language tag and fluent catalog
+    fn catalog(&self) -> (&'static str, &'static str) {
+        match self {
+            Language::En => ("en", include_str!("i18n/en.ftl")),
+            Language::De => ("de", include_str!("i18n/de.ftl")),
+        }
+    }
+}
+
+static BUNDLE: OnceLock<FluentBundle<FluentResource>> = OnceLock::new();
+static ENGLISH: OnceLock<FluentBundle<FluentResource>> = OnceLock::new();
+
+fn load_bundle(language: Language) -> FluentBundle<FluentResource> {
+    let (tag, source) = language.catalog();
+    let id: LanguageIdentifier = tag.parse().expect("valid language tag");
+    let mut bundle = FluentBundle::new_concurrent(vec![id]);
+    // no unicode isolation marks around the arguments, terminals print them
+    bundle.set_use_isolating(false);
+    // the catalogs are compiled in, errors in them are bugs
+    let resource = FluentResource::try_new(source.to_string()).expect("valid message catalog");
+    bundle.add_resource(resource).expect("unique message ids");
+    bundle
+}
+
+/// Selects the language of all following messages, detected from the system locale if `None`.
+///
+/// Only the first call (or message) decides the language.
+pub fn configure(language: Option<Language>) {
+    BUNDLE.get_or_init(|| load_bundle(language.unwrap_or_else(Language::detect)));
+}
+
+/// Message `id` with `args` in the configured language, messages missing in its catalog are english
+pub fn message(id: &str, args: Option<&FluentArgs>) -> String {
+    let bundle = BUNDLE.get_or_init(|| load_bundle(Language::detect()));
+    let english = std::iter::once_with(|| ENGLISH.get_or_init(|| load_bundle(Language::En)));
+    // formatted by the bundle the pattern is taken from, with the plural rules of its language
+    let Some((bundle, pattern)) = std::iter::once(bundle)
+        .chain(english)
+        .find_map(|bundle| Some((bundle, bundle.get_message(id)?.value()?)))
+    else {
+        return id.to_string();
+    };
+    let mut errors = Vec::new();
+    bundle.format_pattern(pattern, args, &mut errors).into_owned()
+}
+
+/// Message of the catalog with named arguments, e.g. `tr!("run-converting", count = 3)`
+macro_rules! tr {
+    ($id:literal) => {
+        $crate::i18n::message($id, None)
+    };
+    ($id:literal, $($name:ident = $value:expr),+ $(,)?) => {{
+        let mut args = fluent_bundle::FluentArgs::new();
+        $(args.set(stringify!($name), $value);)+
+        $crate::i18n::message($id, Some(&args))
+    }};
+}
+pub(crate) use tr;
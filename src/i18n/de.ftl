@@ -0,0 +1,116 @@
+# Meldungen von Konvertierungen, die Bezeichnungen der Zusammenfassung sind aufgefüllt, damit die Werte untereinander stehen.
+
+run-skipping-sidecar = Überspringe { $count } Dateien, die in ihren Sidecar-Dateien mit `skip` markiert sind
+run-skipping-up-to-date = Überspringe { $count } Dateien, deren Ausgaben bereits existieren
+run-skipping-unconvertible = Überspringe { $count } nicht konvertierbare Dateien ({ $zero } leer, { $magic } unbekannte Signatur, { $unsupported } nicht unterstütztes Format, { $disabled } deaktivierter Decoder)
+run-unconvertible-listed = Nicht konvertierbare Dateien aufgelistet in { $path }
+run-no-images = Keine Bilder zu konvertieren, bitte Glob-Muster und unterstützte Eingabeformate prüfen.
+run-aborted = Abgebrochen, es wurden keine Dateien konvertiert.
+run-creating-output = Erstelle Ausgabeverzeichnis "{ $path }"
+run-converting = Konvertiere { $count } Dateien...
+run-random-order = Verarbeitung in zufälliger Reihenfolge (wiederholbar mit --seed { $seed })
+run-pass-fast = Durchgang 1/2: schnelle Kodierung aller Dateien
+run-pass-refine = Durchgang 2/2: bis zu { $count } Dateien mit voller Stufe neu kodieren in den verbleibenden { $duration }
+run-ctrlc = Strg+C empfangen, weitere Dateien werden nicht mehr verarbeitet!
+run-ctrlc-again = eine Kodierung läuft noch!{ $marks } Die Verarbeitung endet danach.
+run-hash-map-written = Hash-Zuordnung mit { $count } Dateien geschrieben nach { $path }
+run-manifest-written = Manifest mit { $count } Dateien geschrieben nach { $path }
+run-checksums-written = Prüfsummen von { $count } Dateien geschrieben nach { $path }
+run-undo-written = Undo-Skript von { $count } Änderungen geschrieben nach { $path }
+run-profile-written = Profil mit { $count } Abschnitten geschrieben nach { $path }
+run-stage-transferred = { $count } Ausgaben aus dem Staging-Verzeichnis { $path } an ihr Ziel verschoben
+run-panics-logged = { $count } Panics in Decodern oder Encodern protokolliert in { $path }, bitte mit den betroffenen Dateien melden
+run-panic-log-failed = Datei { $path }: die Panic konnte nicht in { $log } protokolliert werden: { $error }
+run-file-failed = Datei { $path }: konnte nicht konvertiert werden, Fehler: { $error }
+run-file-rejected = Datei { $path }: abgelehnt, mehr als { $limit } Pixel (--max-pixels)
+run-transfer-failed = Datei { $path }: konnte nicht nach { $output } verschoben werden, Fehler: { $error }
+run-collision = Warnung: { $output } ist die Ausgabe mehrerer Eingaben ({ $inputs }), nur eine davon wird behalten
+run-output-dir-failed = Fehler beim Erstellen des Ausgabeverzeichnisses: { $error }
+run-with-override = {"  "}mit --when { $override }
+run-metadata-unsupported = Warnung: Metadatenfelder werden nicht in { $format }-Ausgaben geschrieben
+run-worker-idle = wartet
+run-finished = fertig!
+
+summary-not-converted = Nicht konvertiert:
+summary-title = Kodierstatistik:
+summary-time = Dauer:           { $duration }
+summary-inputs = Eingabedateien:  { $count }
+summary-successful = Erfolgreich:     { $count }
+summary-skipped = Übersprungen:    { $count }
+summary-errors = Fehler:          { $count }
+summary-aborted = Abgebrochen:     { $count } (nach Strg+C nicht mehr gestartet)
+summary-refined = Verfeinert:      { $count } (mit voller Stufe neu kodiert, { $saved } gespart; { $left } mit niedriger Stufe belassen)
+summary-encode-time = Kodierzeit:      { $duration } pro Datei im Durchschnitt
+summary-unconvertible = Nicht konvertierbar: { $count } ({ $zero } leer, { $magic } unbekannte Signatur, { $unsupported } nicht unterstütztes Format, { $disabled } deaktivierter Decoder)
+summary-rejected = Abgelehnt:       { $count } (mehr Pixel als --max-pixels)
+summary-repaired = Repariert:       { $count } (abgeschnittene jpegs, fehlende Teile grau gefüllt)
+summary-fallbacks = Ausweichformat:  { $count } (Zielformat größer als die Eingabe)
+summary-routed = Umgeleitet:      { $count } ({ $formats })
+summary-animated = Animiert:        { $count } ({ $mode ->
+        [animate] als Animationen konvertiert
+        [copy] unverändert kopiert
+       *[first] nur das erste Bild, siehe --animated
+    })
+summary-quarantined = Quarantäne:      { $count } (nicht dekodierbare Eingaben, { $mode ->
+        [moved] verschoben
+       *[copied] kopiert
+    } nach { $path })
+summary-deduplicated = Dedupliziert:    { $count } (identische Ausgaben verlinkt, { $saved } gespart)
+summary-discarded = Verworfen:       { $count } (Kodierung größer als die Eingabe; { $input } { $arrow } { $output })
+summary-discarded-note = Verworfene Ein- und Ausgaben sind in den folgenden Gesamtgrößen nicht enthalten.
+summary-not-worth-it = Nicht lohnend:   { $count } (weniger als { $percent }% Ersparnis, Eingaben behalten; { $input } { $arrow } { $output })
+summary-total-input = Eingabegröße gesamt:  { $size }
+summary-total-output = Ausgabegröße gesamt:  { $size }
+summary-total-ratio = Kompression gesamt:   { $ratio }%
+summary-new-input = Eingabegröße neu kodiert:  { $size }
+summary-new-output = Ausgabegröße neu kodiert:  { $size }
+summary-new-ratio = Kompression neu kodiert:   { $ratio }%
+summary-preexisting-input = Eingabegröße vorhanden:    { $size }
+summary-preexisting-output = Ausgabegröße vorhanden:    { $size }
+summary-preexisting-ratio = Kompression vorhanden:     { $ratio }%
+summary-sizes-unknown = Ein- und Ausgabegröße konnten nicht bestimmt werden, bitte native Binärdateien des Betriebssystems verwenden.
+summary-reclaimed = Freigegebener Platz:   { $size } (Eingaben durch ihre Ausgaben ersetzt)
+summary-potential-savings = Mögliche Ersparnis:    { $size } (wenn die behaltenen Eingaben gelöscht würden, bis dahin belegen die Ausgaben zusätzlichen Platz)
+summary-by-extension = Nach Eingabe-Endung:
+summary-by-extension-header = {"  "}Endung    Dateien  konvert.  übersp. Fehler     Eingabe     Ausgabe     gespart  Anteil
+
+# Gründe, die --explain-skips auflistet.
+
+explain-sidecar-skip = in der Sidecar-Datei mit `skip` markiert
+explain-unconvertible = nicht konvertierbar ({ $problem })
+explain-output-exists = Ausgabe existiert (siehe --overwrite-existing, --overwrite-if-smaller)
+explain-output-up-to-date = Ausgabe ist neuer als die Eingabe (--if-stale)
+explain-existing-smaller = vorhandene Ausgabe ist kleiner als die neue Kodierung (--overwrite-if-smaller)
+explain-same-content-exists = Ausgabe mit dem gleichen Inhalts-Hash-Namen existiert
+explain-not-animated = Standbild, keine Einzelbilder zu extrahieren
+explain-no-alpha = keine transparenten Pixel (--only-with-alpha)
+explain-animated-kept = animierte Eingabe unverändert behalten (--animated copy)
+explain-time-budget-used-up = Zeitbudget aufgebraucht (--time-budget)
+explain-reoptimized-not-smaller = neu optimierte Kodierung ist nicht kleiner als die Eingabe
+explain-not-smaller = Kodierung ist nicht kleiner als die Eingabe (--discard-if-larger-than-input)
+explain-not-worth-it = Kodierung spart weniger als --min-savings
+
+# Zusammenfassung von --confirm, die Bezeichnungen sind wie die der Zusammenfassung aufgefüllt.
+
+confirm-files = Dateien:   { $count } ({ $size })
+confirm-format = Format:    { $format }
+confirm-output-next-to-inputs = Ausgabe:   neben den Eingaben
+confirm-output = Ausgabe:   { $path }
+confirm-mirror = Spiegel:   { $path }
+confirm-existing = Vorhanden: { $mode ->
+        [overwritten] überschrieben
+        [if-smaller] überschrieben, wenn die neue Ausgabe kleiner ist
+        [if-stale] überschrieben, wenn älter als die Eingabe
+       *[skipped] übersprungen
+    }
+confirm-replacing-backups = Ersetze:   { $count } Eingaben (Sicherungen mit angehängtem { $ext })
+confirm-replacing = Ersetze:   { $count } Eingaben (ohne Sicherungen)
+confirm-colliding = Kollision: { $count } Ausgaben mehrerer Eingaben
+confirm-prompt = Fortfahren? [j/N]
+
+# Meldungen von --watch.
+
+watch-started = Beobachte { $path } auf neue und geänderte Dateien passend zu { $pattern } (Strg+C zum Beenden)
+watch-backlog = Konvertiere einen Rückstand von { $count } Dateien, neue Dateien zuerst
+watch-failed = Warnung: Beobachten von { $path } fehlgeschlagen: { $error }
+watch-backlog-done = Rückstand konvertiert, beobachte neue Dateien
@@ -0,0 +1,116 @@
+# Messages of conversion runs, the summary labels are padded to line up their values.
+
+run-skipping-sidecar = Skipping { $count } files marked with `skip` in their sidecar files
+run-skipping-up-to-date = Skipping { $count } files whose outputs exist already
+run-skipping-unconvertible = Skipping { $count } files that cannot be converted ({ $zero } zero-byte, { $magic } unknown magic, { $unsupported } unsupported format, { $disabled } disabled decoder)
+run-unconvertible-listed = Files that cannot be converted listed in { $path }
+run-no-images = No images to convert, check input glob pattern and supported input formats.
+run-aborted = Aborted, no files were converted.
+run-creating-output = Creating output directory "{ $path }"
+run-converting = Converting { $count } files...
+run-random-order = Processing in random order (repeat it with --seed { $seed })
+run-pass-fast = Pass 1/2: fast encodes of all files
+run-pass-refine = Pass 2/2: re-encoding up to { $count } files at full effort within the remaining { $duration }
+run-ctrlc = received Ctrl+C, stopping further queue processing!
+run-ctrlc-again = an encoding task is still active!{ $marks } processing will end afterwards.
+run-hash-map-written = Hash map with { $count } files written to { $path }
+run-manifest-written = Manifest with { $count } files written to { $path }
+run-checksums-written = Checksums of { $count } files written to { $path }
+run-undo-written = Undo script of { $count } changes written to { $path }
+run-profile-written = Profile of { $count } spans written to { $path }
+run-stage-transferred = Moved { $count } outputs from the staging directory { $path } to their destination
+run-panics-logged = { $count } panics in decoders or encoders logged to { $path }, please report them with the affected files
+run-panic-log-failed = File { $path }: the panic could not be logged to { $log }: { $error }
+run-file-failed = File { $path }: could not be converted, error: { $error }
+run-file-rejected = File { $path }: rejected, more than { $limit } pixels (--max-pixels)
+run-transfer-failed = File { $path }: could not be moved to { $output }, error: { $error }
+run-collision = Warning: { $output } is the output of several inputs ({ $inputs }), only one of them is kept
+run-output-dir-failed = Error creating the output directory: { $error }
+run-with-override = {"  "}with --when { $override }
+run-metadata-unsupported = Warning: metadata fields are not written into { $format } outputs
+run-worker-idle = idle
+run-finished = finished!
+
+summary-not-converted = Not converted:
+summary-title = Encode statistics:
+summary-time = Time taken:  { $duration }
+summary-inputs = Input files: { $count }
+summary-successful = Successful:  { $count }
+summary-skipped = Skipped:     { $count }
+summary-errors = Errors:      { $count }
+summary-aborted = Aborted:     { $count } (not started after ctrl+c)
+summary-refined = Refined:     { $count } (re-encoded at full effort, { $saved } saved; { $left } left at low effort)
+summary-encode-time = Encode time: { $duration } per file on average
+summary-unconvertible = Unconvertible: { $count } ({ $zero } zero-byte, { $magic } unknown magic, { $unsupported } unsupported format, { $disabled } disabled decoder)
+summary-rejected = Rejected:    { $count } (more pixels than --max-pixels)
+summary-repaired = Repaired:    { $count } (truncated jpegs, missing parts filled gray)
+summary-fallbacks = Fallbacks:   { $count } (target format larger than the input)
+summary-routed = Routed:      { $count } ({ $formats })
+summary-animated = Animated:    { $count } ({ $mode ->
+        [animate] converted as animations
+        [copy] copied unchanged
+       *[first] first frame only, see --animated
+    })
+summary-quarantined = Quarantined: { $count } (undecodable inputs, { $mode ->
+        [moved] moved
+       *[copied] copied
+    } to { $path })
+summary-deduplicated = Deduplicated: { $count } (identical outputs linked, { $saved } saved)
+summary-discarded = Discarded:   { $count } (due to the encode being larger than the input; { $input } { $arrow } { $output })
+summary-discarded-note = Please note that discarded in- and outputs do not count into the total in-/output statistics below.
+summary-not-worth-it = Not worth it: { $count } (saving less than { $percent }%, inputs kept; { $input } { $arrow } { $output })
+summary-total-input = Total input size:  { $size }
+summary-total-output = Total output size: { $size }
+summary-total-ratio = Total comp. ratio: { $ratio }%
+summary-new-input = New encodes input size:  { $size }
+summary-new-output = New encodes output size: { $size }
+summary-new-ratio = New encodes comp. ratio: { $ratio }%
+summary-preexisting-input = Preexisting input size:  { $size }
+summary-preexisting-output = Preexisting output size: { $size }
+summary-preexisting-ratio = Preexisting comp. ratio: { $ratio }%
+summary-sizes-unknown = Input and output size could not be determined, please try using OS-native binaries.
+summary-reclaimed = Reclaimed space:   { $size } (inputs replaced by their outputs)
+summary-potential-savings = Potential savings: { $size } (if the kept inputs were deleted, until then the outputs take additional space)
+summary-by-extension = By input extension:
+summary-by-extension-header = {"  "}extension   files converted skipped failed       input      output       saved   ratio
+
+# Reasons listed by --explain-skips.
+
+explain-sidecar-skip = marked with `skip` in its sidecar file
+explain-unconvertible = cannot be converted ({ $problem })
+explain-output-exists = output exists (see --overwrite-existing, --overwrite-if-smaller)
+explain-output-up-to-date = output is newer than the input (--if-stale)
+explain-existing-smaller = existing output is smaller than the new encode (--overwrite-if-smaller)
+explain-same-content-exists = output with the same content hash name exists
+explain-not-animated = still image, no frames to extract
+explain-no-alpha = no transparent pixels (--only-with-alpha)
+explain-animated-kept = animated input kept unchanged (--animated copy)
+explain-time-budget-used-up = time budget used up (--time-budget)
+explain-reoptimized-not-smaller = re-optimized encode is not smaller than the input
+explain-not-smaller = encode is not smaller than the input (--discard-if-larger-than-input)
+explain-not-worth-it = encode saves less than --min-savings
+
+# Summary of --confirm, the labels are padded like the ones of the run summary.
+
+confirm-files = Files:     { $count } ({ $size })
+confirm-format = Format:    { $format }
+confirm-output-next-to-inputs = Output:    next to the inputs
+confirm-output = Output:    { $path }
+confirm-mirror = Mirror:    { $path }
+confirm-existing = Existing:  { $mode ->
+        [overwritten] overwritten
+        [if-smaller] overwritten if the new output is smaller
+        [if-stale] overwritten if older than the input
+       *[skipped] skipped
+    }
+confirm-replacing-backups = Replacing: { $count } inputs (backups with { $ext } appended)
+confirm-replacing = Replacing: { $count } inputs (no backups)
+confirm-colliding = Colliding: { $count } outputs of several inputs
+confirm-prompt = Continue? [y/N]
+
+# Messages of --watch.
+
+watch-started = Watching { $path } for new and changed files matching { $pattern } (Ctrl+C to stop)
+watch-backlog = Converting a backlog of { $count } files, new files first
+watch-failed = Warning: watching { $path } failed: { $error }
+watch-backlog-done = Backlog converted, watching for new files
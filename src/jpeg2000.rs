@@ -0,0 +1,118 @@
+use crate::{
+    decode::{check_pixel_limit, read_image_from_memory_with, DecodeOptions},
+    Error,
+};
+use image::DynamicImage;
+use std::{
+    env,
+    ffi::OsString,
+    fs,
+    io::{Read, Write},
+    path::Path,
+    process::{Command, Stdio},
+};
+use tempfile::TempDir;
+
+// overrides the opj_decompress binary (looked up on the PATH by default)
+const OPJ_DECOMPRESS_ENV: &str = "IMGC_OPJ_DECOMPRESS";
+// jp2 container signature box
+const JP2_SIGNATURE: &[u8] = &[0x00, 0x00, 0x00, 0x0C, b'j', b'P', b' ', b' ', 0x0D, 0x0A, 0x87, 0x0A];
+// start of codestream + image and tile size markers of raw codestreams
+const J2K_SIGNATURE: &[u8] = &[0xFF, 0x4F, 0xFF, 0x51];
+
+// bytes read for the image header, jp2 boxes may precede it
+const HEADER_PROBE_LEN: u64 = 64 * 1024;
+
+// private directory (only accessible by the user on unix) for the files passed to opj_decompress,
+// other users can neither predict their names nor replace them, it is removed on drop
+fn working_directory() -> Result<TempDir, Error> {
+    Ok(tempfile::Builder::new().prefix("imgc-").tempdir()?)
+}
+
+fn opj_decompress_binary() -> OsString {
+    env::var_os(OPJ_DECOMPRESS_ENV).unwrap_or_else(|| OsString::from("opj_decompress"))
+}
+
+/// Whether `ext` (lowercase) is a jpeg 2000 file extension understood by OpenJPEG
+pub fn is_jpeg2000_extension(ext: &str) -> bool {
+    matches!(ext, "jp2" | "j2k" | "j2c" | "jpc")
+}
+
+/// Whether `data` starts with a jpeg 2000 signature (jp2 container or raw codestream)
+pub fn is_jpeg2000(data: &[u8]) -> bool {
+    data.starts_with(JP2_SIGNATURE) || data.starts_with(J2K_SIGNATURE)
+}
+
+/// Reads the dimensions (width, height) from the header of a jpeg 2000 image, None if `data` holds no such header
+pub fn jpeg2000_dimensions(data: &[u8]) -> Option<(u32, u32)> {
+    let u32_at = |offset: usize| Some(u32::from_be_bytes(data.get(offset..offset + 4)?.try_into().unwrap()));
+    if data.starts_with(J2K_SIGNATURE) {
+        // SIZ marker segment: length and capabilities, then the size of the reference grid and the offset of the image
+        return Some((u32_at(8)?.checked_sub(u32_at(16)?)?, u32_at(12)?.checked_sub(u32_at(20)?)?));
+    }
+    if !data.starts_with(JP2_SIGNATURE) {
+        return None;
+    }
+    // top level boxes of the container, the image header box opens the header box
+    let mut pos = 0;
+    loop {
+        let len = u32_at(pos)? as usize;
+        if data.get(pos + 4..pos + 8)? == b"jp2h" {
+            return (data.get(pos + 12..pos + 16)? == b"ihdr").then_some((u32_at(pos + 20)?, u32_at(pos + 16)?));
+        }
+        // lengths 0 (up to the end) and 1 (64 bit) are only used by the codestream box after the header
+        if len < 8 {
+            return None;
+        }
+        pos = pos.checked_add(len)?;
+    }
+}
+
+/// Decodes the jpeg 2000 file at `path` (the codec is chosen by the file extension, see [`is_jpeg2000_extension`]).
+///
+/// OpenJPEG's `opj_decompress` binary writes a lossless png (8 or 16 bit) to a temporary file,
+/// set `IMGC_OPJ_DECOMPRESS` to use another one than on the PATH.
+/// Images with more than `max_pixels` pixels (by their header) are rejected before they are decompressed.
+pub fn read_jpeg2000(path: &Path, max_pixels: Option<u64>) -> Result<DynamicImage, Error> {
+    if max_pixels.is_some() {
+        let mut header = Vec::new();
+        fs::File::open(path)?.take(HEADER_PROBE_LEN).read_to_end(&mut header)?;
+        if let Some((width, height)) = jpeg2000_dimensions(&header) {
+            check_pixel_limit(width, height, max_pixels)?;
+        }
+    }
+    decompress(path, &working_directory()?, max_pixels)
+}
+
+// runs opj_decompress on `path`, writing the png into `directory`
+fn decompress(path: &Path, directory: &TempDir, max_pixels: Option<u64>) -> Result<DynamicImage, Error> {
+    let decoded = directory.path().join("decoded.png");
+    let output = Command::new(opj_decompress_binary())
+        .arg("-quiet")
+        .arg("-i").arg(path)
+        .arg("-o").arg(&decoded)
+        .stdin(Stdio::null())
+        .output()
+        .map_err(|e| Error::from_string(format!("running opj_decompress failed: {}", e)))?;
+    if !output.status.success() {
+        // errors are printed to stdout or stderr depending on the OpenJPEG version
+        let mut message = String::from_utf8_lossy(&output.stderr).trim().to_string();
+        if message.is_empty() {
+            message = String::from_utf8_lossy(&output.stdout).trim().to_string();
+        }
+        return Err(Error::from_string(format!("opj_decompress failed ({}): {}", output.status, message)));
+    }
+    read_image_from_memory_with(&fs::read(&decoded)?, &DecodeOptions { max_pixels, ..DecodeOptions::default() })
+}
+
+/// Decodes an in-memory jpeg 2000 image (see [`is_jpeg2000`]), like [`read_jpeg2000`]
+pub fn read_jpeg2000_from_memory(data: &[u8], max_pixels: Option<u64>) -> Result<DynamicImage, Error> {
+    if let Some((width, height)) = jpeg2000_dimensions(data) {
+        check_pixel_limit(width, height, max_pixels)?;
+    }
+    // opj_decompress only reads files, choosing the codec by the extension
+    let directory = working_directory()?;
+    let input = directory.path().join(if data.starts_with(JP2_SIGNATURE) { "input.jp2" } else { "input.j2k" });
+    fs::OpenOptions::new().write(true).create_new(true).open(&input)?.write_all(data)?;
+    decompress(&input, &directory, max_pixels)
+}
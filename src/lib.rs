@@ -10,16 +10,100 @@
 */
 
 #![deny(missing_docs)]
+/// Animated inputs (frame selection).
+pub mod animation;
+/// Async (tokio) frontend for network-backed inputs and outputs.
+#[cfg(feature = "async")]
+pub mod async_io;
 /// Command-line interface functionality.
+#[cfg(feature = "cli")]
 pub mod cli;
+/// Conversion of the image on the clipboard.
+#[cfg(feature = "clipboard")]
+pub mod clipboard;
 /// Image conversion functionality.
 pub mod converter;
+/// DDS texture decoding (block compressed, uncompressed and float formats).
+pub mod dds;
+/// Self-test of the compiled encoders and decoders.
+pub mod doctor;
+/// Robust image decoding with fallbacks for mislabeled and unusual inputs.
+pub mod decode;
+/// Dumps of the pixels handed to the encoders (`--debug-dump-rgba`).
+pub mod dump;
 /// Error handling for the application.
 mod error;
+/// C ABI for converting in-memory buffers from other languages.
+#[cfg(feature = "ffi")]
+pub mod ffi;
+/// Unicode normalization of output filenames.
+pub mod filename;
 /// Image formats supported by the application.
 pub mod format;
+/// Drag and drop window driving the batch engine.
+#[cfg(feature = "gui")]
+pub mod gui;
+/// Localized messages of conversion runs (fluent catalogs).
+pub mod i18n;
+/// JPEG 2000 decoding (via the opj_decompress binary of OpenJPEG).
+#[cfg(feature = "jpeg2000")]
+pub mod jpeg2000;
+/// Provenance manifest of converted files.
+pub mod manifest;
+/// EXIF metadata written into the outputs.
+pub mod metadata;
 
+/// Outcomes of file conversions and the statistics of a run.
+pub mod outcome;
+/// Terminal output styling (colors and glyphs).
+pub mod output;
+/// Encoder settings overridden per input format.
+pub mod overrides;
+/// Panics of decoders and encoders (`--on-panic`) and their log.
+pub mod panics;
+/// Perceptual image hashes (dHash, pHash).
+pub mod phash;
+/// Low quality image placeholders (blurhash, thumbhash, tiny webp).
+pub mod placeholder;
+/// Planning stage of conversion runs (inputs, outputs and settings).
+pub mod plan;
+/// Conversion order weights per input path.
+pub mod priority;
+/// Spans of the per-file pipeline recorded for `--profile` (chrome trace, folded stacks).
+pub mod profile;
+/// Progress bars of conversion runs, silent without the `cli` feature.
+mod progress;
+/// Photoshop document decoding (merged composite).
+pub mod psd;
+/// Encoder independent quality scale.
+pub mod quality;
+/// Quarantine of undecodable inputs.
+pub mod quarantine;
+/// Output formats picked per image by its content (`--route`).
+pub mod route;
+/// Context menu entries of file managers running imgc.
+pub mod shell_integration;
+/// Per-image settings files next to the inputs.
+pub mod sidecar;
+/// Responsive image snippets (`<picture>` elements) of the outputs.
+pub mod snippet;
+/// Sprite sheets packed from many small images, with a json atlas.
+pub mod sprite;
+/// Staging of outputs for slow destinations (`--stage-dir`).
+mod stage;
+/// Undo scripts reverting conversion runs.
+pub mod undo;
 /// Utility functions and helpers.
 pub mod utils;
+/// Checks that outputs are displayed like their inputs (`--validate-visual`).
+pub mod validate;
+/// Poster frames of videos (via the ffmpeg binary).
+#[cfg(feature = "ffmpeg")]
+pub mod video;
+/// Watch mode, converting new and changed inputs as they appear.
+#[cfg(feature = "watch")]
+pub mod watch;
+/// Extended attributes (Finder tags, `user.*` attributes) copied from the inputs to their outputs.
+pub mod xattr;
 
 pub use error::Error;
@@ -1,37 +1,271 @@
-use clap::Parser;
+use clap::{error::ErrorKind, CommandFactory, Parser};
 use imgc::{
-    cli::{CliArgs, Command},
-    converter::convert_images,
+    animation::FrameMode,
+    cli::{print_completions, print_manpage, CliArgs, Command},
+    converter::{apply_plan, convert_images},
+    decode::DecoderFilter,
+    dump::PixelDump,
     format::ImageFormat,
-    utils::remove_files,
+    i18n,
+    manifest::verify_manifest,
+    metadata::MetadataFields,
+    doctor::{print_capabilities, run_doctor, run_selftest_corpus},
+    output,
+    plan::{plan_conversion, InputFormatFilter, Plan, ProcessingOrder},
+    priority::read_weights,
+    shell_integration::{install_shell_integration, uninstall_shell_integration},
+    sprite::{pack_sprites, SpriteConfig},
+    utils::{remove_files, CleanFilter},
     Error,
 };
-use imgc::converter::CommonConfig;
+#[cfg(feature = "clipboard")]
+use imgc::clipboard::convert_clipboard;
+#[cfg(feature = "gui")]
+use imgc::gui::run_gui;
+#[cfg(feature = "watch")]
+use imgc::watch::{watch_images, WatchConfig};
+use imgc::converter::{CommonConfig, EncoderConfig};
+use std::path::Path;
 
 fn main() -> Result<(), Error> {
     let args = CliArgs::parse();
-    let conf = CommonConfig::from(
-        CommonConfig { 
-            pattern: args.pattern,
-            output: args.output.unwrap_or("".parse().unwrap()),
-            reverse_processing_order: args.reverse_processing_order.unwrap(),
-            overwrite_if_smaller: args.overwrite_if_smaller.unwrap(),
-            overwrite_existing: args.overwrite_existing.unwrap(),
-            discard_if_larger_than_input: args.discard_if_larger_than_input.unwrap(),
+    output::configure(args.color, args.ascii.unwrap());
+    i18n::configure(args.lang);
+    if args.command.needs_pattern() && args.pattern.is_none() {
+        CliArgs::command()
+            .error(ErrorKind::MissingRequiredArgument, "the <PATTERN> argument is required for this command")
+            .exit();
+    }
+    if args.flatten.unwrap() && args.output.is_none() {
+        CliArgs::command()
+            .error(ErrorKind::MissingRequiredArgument, "--flatten requires an --output directory")
+            .exit();
+    }
+    if (args.placeholder.is_some() || args.perceptual_hash.is_some()) && args.manifest.is_none() {
+        CliArgs::command()
+            .error(ErrorKind::MissingRequiredArgument, "--placeholder and --perceptual-hash require a --manifest to record them in")
+            .exit();
+    }
+    if args.seed.is_some() && args.order != ProcessingOrder::Random {
+        CliArgs::command()
+            .error(ErrorKind::MissingRequiredArgument, "--seed requires --order random")
+            .exit();
+    }
+    if args.reverse_processing_order.unwrap() && args.order == ProcessingOrder::Random {
+        CliArgs::command()
+            .error(ErrorKind::ArgumentConflict, "--reverse-processing-order cannot be combined with --order random")
+            .exit();
+    }
+    let mut priorities = match &args.weights {
+        Some(path) => read_weights(Path::new(path))?,
+        None => Vec::new(),
+    };
+    priorities.extend(args.prefer);
+    let conf = CommonConfig {
+        pattern: args.pattern.unwrap_or_default(),
+        output: args.output.unwrap_or_default(),
+        mirror_outputs: args.mirror_output,
+        flatten: args.flatten.unwrap(),
+        relative_to: args.relative_to,
+        normalize_filenames: args.normalize_filenames,
+        reverse_processing_order: args.reverse_processing_order.unwrap(),
+        order: args.order,
+        group_by_directory: args.group_by_directory.unwrap_or(false),
+        seed: args.seed,
+        priorities,
+        overwrite_if_smaller: args.overwrite_if_smaller.unwrap(),
+        overwrite_existing: args.overwrite_existing.unwrap(),
+        if_stale: args.if_stale.unwrap_or(false),
+        fsync: args.fsync.unwrap_or(false),
+        preserve_xattrs: args.preserve_xattrs.unwrap_or(false),
+        preserve_owner: args.preserve_owner.unwrap_or(false),
+        stage_dir: args.stage_dir,
+        stage_transfers: args.stage_transfers,
+        discard_if_larger_than_input: args.discard_if_larger_than_input.unwrap(),
+        min_savings: args.min_savings,
+        fallback_formats: args.fallback_format.into_iter().map(ImageFormat::from).collect(),
+        routes: args.route,
+        only_with_alpha: args.only_with_alpha.unwrap_or(false),
+        animated: args.animated,
+        validate_visual: args.validate_visual,
+        hide_worker_progress: args.hide_worker_progress.unwrap(),
+        confirm: args.confirm.unwrap(),
+        snippets: args.snippets,
+        placeholder: args.placeholder,
+        perceptual_hash: args.perceptual_hash,
+        max_pixels: args.max_pixels,
+        decoder: args.decoder,
+        decoders: DecoderFilter { enabled: args.enable_decoder, disabled: args.disable_decoder },
+        input_formats: InputFormatFilter { only: args.only_input_formats, skip: args.skip_input_formats },
+        per_file_timeout: args.per_file_timeout,
+        tolerate_truncation: args.tolerate_truncation.unwrap_or(false),
+        quarantine_dir: args.quarantine_dir,
+        quarantine_mode: args.quarantine_mode,
+        on_panic: args.on_panic,
+        panic_log: args.panic_log,
+        pixel_dump: args.debug_dump_rgba.map(|directory| PixelDump {
+            directory: directory.into(), format: args.debug_dump_format, sample: args.debug_dump_sample,
+        }),
+        list_unconvertible: args.list_unconvertible,
+        explain_skips: args.explain_skips.unwrap_or(false),
+        time_budget: args.time_budget,
+        profile: args.profile,
+        manifest: args.manifest,
+        checksums: args.checksums,
+        naming: args.naming,
+        hash_map: args.hash_map,
+        dedupe_outputs: args.dedupe_outputs,
+        in_place: args.in_place.unwrap(),
+        backup_ext: args.backup_ext,
+        undo_script: args.undo_script,
+        optimize: args.optimize.unwrap(),
+        extract_frames: false,
+        poster_at: Vec::new(),
+        on_converted: args.on_converted,
+        progress_template: args.progress_template,
+        by_extension: args.by_extension.unwrap_or(false),
+        summary_json: args.summary_json.unwrap_or(false),
+        summary_template: args.summary_template,
+        on_file_done: None,
+    };
+    // settings shared by all encoders
+    let encoder_defaults = EncoderConfig {
+        visual_quality: args.visual_quality,
+        input_overrides: args.when,
+        deterministic: args.deterministic,
+        alpha_threshold: args.alpha_threshold,
+        premultiply_alpha: args.premultiply_alpha,
+        frames: args.frames,
+        frame_step: args.frame_step,
+        max_frames: args.max_frames,
+        fps: args.fps,
+        metadata: MetadataFields {
+            artist: args.set_artist,
+            copyright: args.set_copyright,
+            comment: args.set_comment,
+        },
+        keep_metadata: args.keep_metadata,
+        strip_gps: args.strip_gps,
+        ..Default::default()
+    };
+    #[cfg(feature = "watch")]
+    let watch = args.watch.unwrap_or(false).then_some(WatchConfig {
+        debounce: args.debounce,
+        backlog: args.backlog.unwrap_or(false),
+    });
+    let command = match conversion(args.command, conf.clone(), encoder_defaults.clone()) {
+        #[cfg(feature = "watch")]
+        Ok((conf, img_format, options)) if let Some(watch) = &watch => return watch_images(conf, &img_format, &options, watch),
+        Ok((conf, img_format, options)) => return convert_images(conf, &img_format, &options),
+        Err(command) => command,
+    };
+    #[cfg(feature = "watch")]
+    if watch.is_some() {
+        CliArgs::command()
+            .error(ErrorKind::ArgumentConflict, "--watch requires a conversion command, e.g. `imgc \"*.png\" --watch webp`")
+            .exit();
+    }
+    match command {
+        Command::Plan { save, conversion: planned } => {
+            let Ok((conf, img_format, options)) = conversion(*planned, conf, encoder_defaults) else {
+                CliArgs::command()
+                    .error(ErrorKind::InvalidSubcommand, "plan takes a conversion command, e.g. `imgc \"*.png\" plan webp`")
+                    .exit();
+            };
+            let plan = plan_conversion(&conf, &img_format, &options)?;
+            match save {
+                Some(path) => {
+                    plan.write(Path::new(&path))?;
+                    println!("Plan of {} files ({} colliding outputs) written to {}", plan.files.len(), plan.collisions.len(), path);
+                }
+                None => println!("{}", plan.to_json()?),
+            }
+        }
+        Command::Apply { plan } => apply_plan(conf, &Plan::read(Path::new(&plan))?)?,
+        Command::Sprite { input, max_size, padding, trim, extrude, name, format } => {
+            let sprite_conf = SpriteConfig {
+                pattern: input, output: conf.output, name, max_size, padding, trim: trim.unwrap_or(false), extrude,
+            };
+            pack_sprites(&sprite_conf, &format.into(), &encoder_defaults)?;
         }
-    );
-    match args.command {
-        Command::Webp { lossless, quality}
-            => convert_images(conf, &ImageFormat::Webp, &lossless, &quality, &None, &None, &None, &None, &None, &None, &None)?,
+        #[cfg(feature = "clipboard")]
+        Command::Clipboard { format, copy_back } => {
+            let img_format = ImageFormat::from(format);
+            let output_path = match conf.output.as_str() {
+                "" => format!("clipboard.{}", encoder_defaults.output_extension(&img_format)),
+                output_path => output_path.to_string(),
+            };
+            let output_size = convert_clipboard(Path::new(&output_path), &img_format, &encoder_defaults, copy_back.unwrap_or(false))?;
+            println!("{} {} ({} bytes)", output::glyphs().ok, output_path, output_size);
+        }
+        #[cfg(feature = "gui")]
+        Command::Gui {} => run_gui(conf, encoder_defaults)?,
+        Command::Clean { patterns, larger_than, older_than, format } => {
+            let filter = CleanFilter { larger_than, older_than, extensions: format };
+            remove_files(&[vec![conf.pattern], patterns].concat(), &filter)?;
+        }
+        Command::Doctor {} => run_doctor()?,
+        Command::SelftestCorpus { corpus } => run_selftest_corpus(&corpus)?,
+        Command::Capabilities { json } => print_capabilities(json.unwrap_or(false))?,
+        Command::VerifyManifest { manifest } => verify_manifest(Path::new(&manifest))?,
+        Command::InstallShellIntegration {} => install_shell_integration()?,
+        Command::UninstallShellIntegration {} => uninstall_shell_integration()?,
+        Command::Completions { shell } => print_completions(shell),
+        Command::Manpage {} => print_manpage()?,
+        _ => unreachable!("conversion commands are run above"),
+    }
+    Ok(())
+}
+
+/// Conversion run of `command`, other commands are handed back
+fn conversion(command: Command, conf: CommonConfig, encoder_defaults: EncoderConfig)
+    -> Result<(CommonConfig, ImageFormat, EncoderConfig), Command> {
+    match command {
+        Command::Webp { lossless, near_lossless, quality}
+            => Ok((conf, ImageFormat::Webp, EncoderConfig {
+                lossless, quality, webp_near_lossless: near_lossless, ..encoder_defaults
+            })),
         Command::Avif { quality, speed, bit_depth, color_model, alpha_color_mode, alpha_quality}
-            => convert_images(conf, &ImageFormat::Avif, &None, &quality, &speed, &None, &None, &bit_depth, &color_model, &alpha_color_mode, &alpha_quality)?,
+            => Ok((conf, ImageFormat::Avif, EncoderConfig {
+                quality, speed,
+                avif_bit_depth: bit_depth, avif_color_model: color_model,
+                avif_alpha_color_mode: alpha_color_mode, avif_alpha_quality: alpha_quality,
+                ..encoder_defaults
+            })),
         Command::WebpImage {}
-            => convert_images(conf, &ImageFormat::WebpImage, &None, &None, &None, &None, &None, &None, &None, &None, &None)?,
-        Command::Png { compression_type, filter_type }
-            => convert_images(conf, &ImageFormat::Png, &None, &None, &None, &compression_type, &filter_type, &None, &None, &None, &None)?,
-        Command::Jpeg {}
-            => convert_images(conf, &ImageFormat::Jpeg, &None, &None, &None, &None, &None, &None, &None, &None, &None)?,
-        Command::Clean {} => remove_files(&conf.pattern)?,
+            => Ok((conf, ImageFormat::WebpImage, encoder_defaults)),
+        Command::Png { compression_type, filter_type, color_type, strip_chunks }
+            => Ok((conf, ImageFormat::Png, EncoderConfig {
+                png_compression_type: compression_type, png_filter_type: filter_type,
+                png_color_type: color_type, png_strip_chunks: strip_chunks,
+                ..encoder_defaults
+            })),
+        Command::Pnm { subtype, plain }
+            => Ok((conf, ImageFormat::Pnm, EncoderConfig {
+                pnm_subtype: subtype, pnm_plain: plain, ..encoder_defaults
+            })),
+        Command::Farbfeld {}
+            => Ok((conf, ImageFormat::Farbfeld, encoder_defaults)),
+        #[cfg(feature = "heif")]
+        Command::Heif { lossless, quality }
+            => Ok((conf, ImageFormat::Heif, EncoderConfig {
+                lossless, quality, ..encoder_defaults
+            })),
+        Command::Jpeg { quality, bake_orientation, restart_interval, optimize_scans, trellis }
+            => Ok((conf, ImageFormat::Jpeg, EncoderConfig {
+                quality,
+                jpeg_bake_orientation: bake_orientation, jpeg_restart_interval: restart_interval,
+                jpeg_optimize_scans: optimize_scans, jpeg_trellis: trellis,
+                ..encoder_defaults
+            })),
+        Command::Frames { input, format }
+            => Ok((CommonConfig { pattern: input, extract_frames: true, ..conf }, format.into(), EncoderConfig {
+                frames: Some(encoder_defaults.frames.unwrap_or(FrameMode::All)),
+                ..encoder_defaults
+            })),
+        #[cfg(feature = "ffmpeg")]
+        Command::Poster { input, at, format }
+            => Ok((CommonConfig { pattern: input, poster_at: at, ..conf }, format.into(), encoder_defaults)),
+        command => Err(command),
     }
-    Ok(())
 }
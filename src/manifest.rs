@@ -0,0 +1,192 @@
+use crate::{
+    converter::{dependency_version, encoder_libraries, encoder_package, normalize_prefix, EncoderConfig},
+    format::ImageFormat,
+    output,
+    phash::PerceptualHash,
+    placeholder::Placeholder,
+    route::ImageTraits,
+    Error,
+};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::{collections::BTreeMap, fs, io, path::{Path, PathBuf}};
+
+/// Hash algorithm of the checksum file
+#[cfg_attr(feature = "cli", derive(clap::ValueEnum))]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ChecksumAlgorithm {
+    /// `SHA256SUMS`, verifiable with `sha256sum -c SHA256SUMS`.
+    Sha256,
+}
+
+impl ChecksumAlgorithm {
+    /// File name of the checksum file
+    pub fn file_name(&self) -> &'static str {
+        match self {
+            ChecksumAlgorithm::Sha256 => "SHA256SUMS",
+        }
+    }
+}
+
+/// Provenance record of a single converted file
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct ManifestEntry {
+    /// Path of the input file.
+    pub input: String,
+    /// Path of the written output file.
+    pub output: String,
+    /// Hex encoded sha256 of the input file.
+    pub input_sha256: String,
+    /// Hex encoded sha256 of the output file.
+    pub output_sha256: String,
+    /// Package providing the encoder.
+    pub encoder: String,
+    /// Version of the encoder package.
+    pub encoder_version: String,
+    /// Versions of all packages involved in producing the output bytes (e.g. libwebp-sys, rav1e).
+    #[serde(default)]
+    pub encoder_libraries: BTreeMap<String, String>,
+    /// Encoder settings, unset (`null`) values used the encoder defaults.
+    pub settings: EncoderConfig,
+    /// Low quality placeholder of the image (`--placeholder`).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub placeholder: Option<Placeholder>,
+    /// Perceptual hash of the image (`--perceptual-hash`).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub perceptual_hash: Option<PerceptualHash>,
+    /// The input was a truncated jpeg, its missing part is gray fill (`--tolerate-truncation`).
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub repaired: bool,
+    /// The target format was larger than the input, the output is in this format instead (`--fallback-format`).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub fallback_format: Option<ImageFormat>,
+    /// Color type, transparency and classification of still images (as used by `--route` and `--only-with-alpha`).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub content: Option<ImageTraits>,
+}
+
+impl ManifestEntry {
+    /// Creates the record for `input` being encoded to `output_data`, hashing the input file.
+    pub fn new(
+        input: &Path,
+        output: &Path,
+        output_data: &[u8],
+        img_format: &ImageFormat,
+        options: &EncoderConfig,
+    ) -> Result<Self, Error> {
+        let encoder = encoder_package(img_format);
+        Ok(ManifestEntry {
+            input: input.display().to_string(),
+            output: output.display().to_string(),
+            input_sha256: sha256_file(input)?,
+            output_sha256: sha256_hex(output_data),
+            encoder: encoder.to_string(),
+            encoder_version: dependency_version(encoder).unwrap_or("unknown").to_string(),
+            encoder_libraries: encoder_libraries(img_format).iter()
+                .map(|&name| (name.to_string(), dependency_version(name).unwrap_or("unknown").to_string()))
+                .collect(),
+            settings: options.clone(),
+            placeholder: None,
+            perceptual_hash: None,
+            repaired: false,
+            fallback_format: None,
+            content: None,
+        })
+    }
+}
+
+/// Manifest mapping inputs to outputs of a conversion run
+#[derive(Serialize, Deserialize, Debug)]
+pub struct Manifest {
+    /// Version of imgc that wrote the manifest.
+    pub imgc_version: String,
+    /// Converted files, ordered by input path.
+    pub files: Vec<ManifestEntry>,
+}
+
+impl Manifest {
+    /// Creates a manifest from the entries (in any order, e.g. completion order of the workers).
+    pub fn new(mut files: Vec<ManifestEntry>) -> Self {
+        files.sort_by(|a, b| a.input.cmp(&b.input));
+        Manifest {
+            imgc_version: env!("CARGO_PKG_VERSION").to_string(),
+            files,
+        }
+    }
+
+    /// Writes the manifest as pretty printed json to `path`.
+    pub fn write(&self, path: &Path) -> Result<(), Error> {
+        fs::write(path, serde_json::to_string_pretty(self)?)?;
+        Ok(())
+    }
+
+    /// Reads a manifest previously written by [`Manifest::write`].
+    pub fn read(path: &Path) -> Result<Self, Error> {
+        Ok(serde_json::from_str(&fs::read_to_string(path)?)?)
+    }
+}
+
+/// Writes the checksum file of the outputs of `entries` into `directory`, returning its path.
+///
+/// Outputs below `directory` are listed relative to it, so that e.g. `sha256sum -c` works from there.
+pub fn write_checksums(algorithm: ChecksumAlgorithm, directory: &Path, entries: &[ManifestEntry]) -> Result<PathBuf, Error> {
+    let directory_norm = normalize_prefix(directory);
+    let mut lines: Vec<String> = entries.iter()
+        .map(|entry| {
+            let output = normalize_prefix(&entry.output);
+            let listed = output.strip_prefix(&directory_norm).unwrap_or(&output);
+            let hash = match algorithm {
+                ChecksumAlgorithm::Sha256 => &entry.output_sha256,
+            };
+            format!("{}  {}\n", hash, listed.display())
+        })
+        .collect();
+    lines.sort_by(|a, b| a[64..].cmp(&b[64..]));
+    let path = directory.join(algorithm.file_name());
+    fs::write(&path, lines.concat())?;
+    Ok(path)
+}
+
+/// Re-hashes the outputs listed in the manifest at `path`, printing a line per file.
+///
+/// Relative paths are resolved against the current directory (like during the conversion that wrote the manifest).
+/// Returns an error if any output is missing or its hash changed (e.g. bit-rot).
+pub fn verify_manifest(path: &Path) -> Result<(), Error> {
+    let manifest = Manifest::read(path)?;
+    let glyphs = output::glyphs();
+    let (mut missing, mut mismatched) = (0, 0);
+    for entry in &manifest.files {
+        match sha256_file(Path::new(&entry.output)) {
+            Ok(hash) if hash == entry.output_sha256 => println!("{} {}", glyphs.ok, entry.output),
+            Ok(_) => {
+                mismatched += 1;
+                println!("{} {}: checksum mismatch", glyphs.error, entry.output);
+            }
+            Err(err) => {
+                missing += 1;
+                println!("{} {}: {}", glyphs.error, entry.output, err);
+            }
+        }
+    }
+    println!("Verified {} files: {} mismatched, {} unreadable", manifest.files.len(), mismatched, missing);
+    if mismatched + missing > 0 {
+        return Err(Error::from_string(format!("{} of {} outputs failed verification", mismatched + missing, manifest.files.len())));
+    }
+    Ok(())
+}
+
+/// Hex encoded sha256 of `data`
+pub fn sha256_hex(data: &[u8]) -> String {
+    to_hex(&Sha256::digest(data))
+}
+
+/// Hex encoded sha256 of the file at `path`, read in chunks
+pub fn sha256_file(path: &Path) -> Result<String, Error> {
+    let mut hasher = Sha256::new();
+    io::copy(&mut fs::File::open(path)?, &mut hasher)?;
+    Ok(to_hex(&hasher.finalize()))
+}
+
+fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
@@ -0,0 +1,429 @@
+use crate::Error;
+use img_parts::{
+    png::{Png, PngChunk},
+    riff::{RiffChunk, RiffContent},
+    webp::CHUNK_EXIF,
+    Bytes, DynImage, ImageEXIF,
+};
+use serde::{Deserialize, Serialize};
+
+// exif tags
+const TAG_ORIENTATION: u16 = 0x0112;
+const TAG_ARTIST: u16 = 0x013B;
+const TAG_COPYRIGHT: u16 = 0x8298;
+const TAG_EXIF_IFD: u16 = 0x8769;
+const TAG_GPS_IFD: u16 = 0x8825;
+const TAG_INTEROP_IFD: u16 = 0xA005;
+const TAG_USER_COMMENT: u16 = 0x9286;
+const TAG_MAKER_NOTE: u16 = 0x927C;
+const TAG_CAMERA_OWNER_NAME: u16 = 0xA430;
+const TAG_BODY_SERIAL_NUMBER: u16 = 0xA431;
+const TAG_LENS_SERIAL_NUMBER: u16 = 0xA435;
+const TAG_DNG_CAMERA_SERIAL_NUMBER: u16 = 0xC62F;
+// exif field types
+const TYPE_ASCII: u16 = 2;
+const TYPE_SHORT: u16 = 3;
+const TYPE_LONG: u16 = 4;
+const TYPE_UNDEFINED: u16 = 7;
+
+const EXIF_PREFIX: &[u8] = b"Exif\0\0";
+
+// png chunks carried over by `copy_png_text`
+const PNG_TEXT_CHUNKS: [&[u8; 4]; 4] = [b"tEXt", b"zTXt", b"iTXt", b"tIME"];
+
+/// Metadata fields written into the outputs (jpeg, png and webp, avif outputs carry no metadata)
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MetadataFields {
+    /// EXIF `Artist`.
+    pub artist: Option<String>,
+    /// EXIF `Copyright`.
+    pub copyright: Option<String>,
+    /// EXIF `UserComment`.
+    pub comment: Option<String>,
+}
+
+impl MetadataFields {
+    /// Whether no field is set
+    pub fn is_empty(&self) -> bool {
+        self.artist.is_none() && self.copyright.is_none() && self.comment.is_none()
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+enum ByteOrder {
+    #[default]
+    Little,
+    Big,
+}
+
+impl ByteOrder {
+    fn u16(self, b: &[u8]) -> u16 {
+        match self {
+            ByteOrder::Little => u16::from_le_bytes([b[0], b[1]]),
+            ByteOrder::Big => u16::from_be_bytes([b[0], b[1]]),
+        }
+    }
+
+    fn u32(self, b: &[u8]) -> u32 {
+        match self {
+            ByteOrder::Little => u32::from_le_bytes([b[0], b[1], b[2], b[3]]),
+            ByteOrder::Big => u32::from_be_bytes([b[0], b[1], b[2], b[3]]),
+        }
+    }
+
+    fn u16_bytes(self, v: u16) -> [u8; 2] {
+        match self {
+            ByteOrder::Little => v.to_le_bytes(),
+            ByteOrder::Big => v.to_be_bytes(),
+        }
+    }
+
+    fn u32_bytes(self, v: u32) -> [u8; 4] {
+        match self {
+            ByteOrder::Little => v.to_le_bytes(),
+            ByteOrder::Big => v.to_be_bytes(),
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+struct Field {
+    tag: u16,
+    kind: u16,
+    count: u32,
+    // raw value bytes in the byte order of the block
+    data: Vec<u8>,
+}
+
+fn type_size(kind: u16) -> Option<usize> {
+    match kind {
+        1 | 2 | 6 | 7 => Some(1),
+        3 | 8 => Some(2),
+        4 | 9 | 11 | 13 => Some(4),
+        5 | 10 | 12 => Some(8),
+        _ => None,
+    }
+}
+
+/// Editable EXIF block (the TIFF structure without the `Exif\0\0` prefix).
+///
+/// Keeps IFD0, the Exif, GPS and Interoperability IFDs, the thumbnail IFD is dropped (it is stale after re-encoding).
+/// Vendor MakerNotes are kept as opaque values, offsets inside them may become invalid.
+#[derive(Clone, Debug, Default)]
+pub struct ExifData {
+    order: ByteOrder,
+    ifd0: Vec<Field>,
+    exif: Vec<Field>,
+    gps: Vec<Field>,
+    interop: Vec<Field>,
+}
+
+impl ExifData {
+    /// Parses an EXIF block, returns None for malformed data.
+    pub fn parse(data: &[u8]) -> Option<Self> {
+        let data = data.strip_prefix(EXIF_PREFIX).unwrap_or(data);
+        let order = match data.get(0..4)? {
+            b"II\x2A\0" => ByteOrder::Little,
+            b"MM\0\x2A" => ByteOrder::Big,
+            _ => return None,
+        };
+        let mut exif = ExifData { order, ..Default::default() };
+        let mut ifd0 = exif.read_ifd(data, order.u32(data.get(4..8)?) as usize)?;
+        if let Some(offset) = take_pointer(order, &mut ifd0, TAG_EXIF_IFD) {
+            exif.exif = exif.read_ifd(data, offset)?;
+            if let Some(offset) = take_pointer(order, &mut exif.exif, TAG_INTEROP_IFD) {
+                exif.interop = exif.read_ifd(data, offset)?;
+            }
+        }
+        if let Some(offset) = take_pointer(order, &mut ifd0, TAG_GPS_IFD) {
+            exif.gps = exif.read_ifd(data, offset)?;
+        }
+        exif.ifd0 = ifd0;
+        Some(exif)
+    }
+
+    fn read_ifd(&self, data: &[u8], offset: usize) -> Option<Vec<Field>> {
+        let order = self.order;
+        let count = order.u16(data.get(offset..offset + 2)?) as usize;
+        let mut fields = Vec::with_capacity(count);
+        for i in 0..count {
+            let entry = data.get(offset + 2 + i * 12..offset + 14 + i * 12)?;
+            let (tag, kind, count) = (order.u16(&entry[0..2]), order.u16(&entry[2..4]), order.u32(&entry[4..8]));
+            let Some(size) = type_size(kind).and_then(|s| s.checked_mul(count as usize)) else {
+                continue; // unknown type, skip the entry
+            };
+            let value = if size <= 4 {
+                entry[8..8 + size].to_vec()
+            } else {
+                let start = order.u32(&entry[8..12]) as usize;
+                data.get(start..start.checked_add(size)?)?.to_vec()
+            };
+            fields.push(Field { tag, kind, count, data: value });
+        }
+        Some(fields)
+    }
+
+    /// Whether the block holds no fields
+    pub fn is_empty(&self) -> bool {
+        self.ifd0.is_empty() && self.exif.is_empty() && self.gps.is_empty() && self.interop.is_empty()
+    }
+
+    /// Sets the given metadata fields, replacing existing values
+    pub fn set_fields(&mut self, fields: &MetadataFields) {
+        if let Some(artist) = &fields.artist {
+            set_field(&mut self.ifd0, ascii_field(TAG_ARTIST, artist));
+        }
+        if let Some(copyright) = &fields.copyright {
+            set_field(&mut self.ifd0, ascii_field(TAG_COPYRIGHT, copyright));
+        }
+        if let Some(comment) = &fields.comment {
+            // the user comment is prefixed by its character code
+            let mut data = Vec::new();
+            if comment.is_ascii() {
+                data.extend_from_slice(b"ASCII\0\0\0");
+                data.extend_from_slice(comment.as_bytes());
+            } else {
+                data.extend_from_slice(b"UNICODE\0");
+                data.extend(comment.encode_utf16().flat_map(|c| self.order.u16_bytes(c)));
+            }
+            set_field(&mut self.exif, Field { tag: TAG_USER_COMMENT, kind: TYPE_UNDEFINED, count: data.len() as u32, data });
+        }
+    }
+
+    /// EXIF `Orientation` (1 - 8), None if unset
+    pub fn orientation(&self) -> Option<u16> {
+        self.ifd0.iter()
+            .find(|f| f.tag == TAG_ORIENTATION && f.kind == TYPE_SHORT && f.data.len() == 2)
+            .map(|f| self.order.u16(&f.data))
+    }
+
+    /// Marks the pixels as upright (`Orientation` 1), after the rotation was applied to them
+    pub fn reset_orientation(&mut self) {
+        if self.orientation().is_some_and(|o| o != 1) {
+            set_field(&mut self.ifd0, Field { tag: TAG_ORIENTATION, kind: TYPE_SHORT, count: 1, data: self.order.u16_bytes(1).to_vec() });
+        }
+    }
+
+    /// Removes location (GPS) and device identifying fields (serial numbers, owner name and the vendor MakerNote,
+    /// which commonly embeds the serial number), keeping everything else (orientation, dates, copyright, ...)
+    pub fn strip_private(&mut self) {
+        self.gps.clear();
+        self.ifd0.retain(|f| f.tag != TAG_DNG_CAMERA_SERIAL_NUMBER);
+        self.exif.retain(|f| !matches!(
+            f.tag,
+            TAG_MAKER_NOTE | TAG_CAMERA_OWNER_NAME | TAG_BODY_SERIAL_NUMBER | TAG_LENS_SERIAL_NUMBER
+        ));
+    }
+
+    /// Serializes the block (without the `Exif\0\0` prefix) in its original byte order
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let order = self.order;
+        let (mut ifd0, mut exif) = (self.ifd0.clone(), self.exif.clone());
+        let has_interop = !self.interop.is_empty();
+        let has_exif = !exif.is_empty() || has_interop;
+        let has_gps = !self.gps.is_empty();
+        // pointer placeholders (inline values, the ifd sizes do not depend on them)
+        if has_interop {
+            set_field(&mut exif, pointer_field(order, TAG_INTEROP_IFD, 0));
+        }
+        if has_exif {
+            set_field(&mut ifd0, pointer_field(order, TAG_EXIF_IFD, 0));
+        }
+        if has_gps {
+            set_field(&mut ifd0, pointer_field(order, TAG_GPS_IFD, 0));
+        }
+        // layout: header | ifd0 | exif | interop | gps
+        let exif_offset = 8 + ifd_len(&ifd0);
+        let interop_offset = exif_offset + if has_exif { ifd_len(&exif) } else { 0 };
+        let gps_offset = interop_offset + if has_interop { ifd_len(&self.interop) } else { 0 };
+        if has_interop {
+            set_field(&mut exif, pointer_field(order, TAG_INTEROP_IFD, interop_offset as u32));
+        }
+        if has_exif {
+            set_field(&mut ifd0, pointer_field(order, TAG_EXIF_IFD, exif_offset as u32));
+        }
+        if has_gps {
+            set_field(&mut ifd0, pointer_field(order, TAG_GPS_IFD, gps_offset as u32));
+        }
+
+        let mut out = match order {
+            ByteOrder::Little => b"II\x2A\0".to_vec(),
+            ByteOrder::Big => b"MM\0\x2A".to_vec(),
+        };
+        out.extend_from_slice(&order.u32_bytes(8));
+        write_ifd(order, &mut out, &ifd0);
+        if has_exif {
+            write_ifd(order, &mut out, &exif);
+        }
+        if has_interop {
+            write_ifd(order, &mut out, &self.interop);
+        }
+        if has_gps {
+            write_ifd(order, &mut out, &self.gps);
+        }
+        out
+    }
+}
+
+fn ascii_field(tag: u16, value: &str) -> Field {
+    let mut data = value.as_bytes().to_vec();
+    data.push(0);
+    Field { tag, kind: TYPE_ASCII, count: data.len() as u32, data }
+}
+
+fn pointer_field(order: ByteOrder, tag: u16, offset: u32) -> Field {
+    Field { tag, kind: TYPE_LONG, count: 1, data: order.u32_bytes(offset).to_vec() }
+}
+
+// replaces or inserts the field, keeping the entries sorted by tag (required by the spec)
+fn set_field(fields: &mut Vec<Field>, field: Field) {
+    match fields.binary_search_by_key(&field.tag, |f| f.tag) {
+        Ok(i) => fields[i] = field,
+        Err(i) => fields.insert(i, field),
+    }
+}
+
+// removes a sub-ifd pointer entry, returning the offset it pointed to
+fn take_pointer(order: ByteOrder, fields: &mut Vec<Field>, tag: u16) -> Option<usize> {
+    let i = fields.iter().position(|f| f.tag == tag)?;
+    let field = fields.remove(i);
+    (field.data.len() == 4).then(|| order.u32(&field.data) as usize)
+}
+
+// size of an ifd including its out-of-line values (padded to even offsets)
+fn ifd_len(fields: &[Field]) -> usize {
+    2 + fields.len() * 12 + 4 + fields.iter()
+        .filter(|f| f.data.len() > 4)
+        .map(|f| f.data.len() + f.data.len() % 2)
+        .sum::<usize>()
+}
+
+fn write_ifd(order: ByteOrder, out: &mut Vec<u8>, fields: &[Field]) {
+    let mut data_offset = out.len() + 2 + fields.len() * 12 + 4;
+    out.extend_from_slice(&order.u16_bytes(fields.len() as u16));
+    for field in fields {
+        out.extend_from_slice(&order.u16_bytes(field.tag));
+        out.extend_from_slice(&order.u16_bytes(field.kind));
+        out.extend_from_slice(&order.u32_bytes(field.count));
+        if field.data.len() <= 4 {
+            let mut value = field.data.clone();
+            value.resize(4, 0);
+            out.extend_from_slice(&value);
+        } else {
+            out.extend_from_slice(&order.u32_bytes(data_offset as u32));
+            data_offset += field.data.len() + field.data.len() % 2;
+        }
+    }
+    out.extend_from_slice(&order.u32_bytes(0)); // no next ifd
+    for field in fields.iter().filter(|f| f.data.len() > 4) {
+        out.extend_from_slice(&field.data);
+        if field.data.len() % 2 == 1 {
+            out.push(0);
+        }
+    }
+}
+
+/// Reads the EXIF block of a jpeg, png or webp file
+pub fn read_exif(data: &[u8]) -> Option<Vec<u8>> {
+    let image = DynImage::from_bytes(Bytes::copy_from_slice(data)).ok()??;
+    let exif = match &image {
+        // img-parts only returns `Exif\0\0` prefixed webp chunks, the spec stores the plain TIFF block
+        DynImage::WebP(webp) => webp.chunk_by_id(CHUNK_EXIF)?.content().data()?.clone(),
+        _ => image.exif()?,
+    };
+    Some(exif.strip_prefix(EXIF_PREFIX).unwrap_or(&exif).to_vec())
+}
+
+/// Builds the EXIF block of an output from the `source` block (if metadata is kept) and the fields to set.
+///
+/// Returns None if the output carries no metadata.
+pub fn output_exif(source: Option<&[u8]>, strip_private: bool, fields: &MetadataFields) -> Option<Vec<u8>> {
+    let mut exif = source.and_then(ExifData::parse).unwrap_or_default();
+    if strip_private {
+        exif.strip_private();
+    }
+    exif.set_fields(fields);
+    (!exif.is_empty()).then(|| exif.to_bytes())
+}
+
+/// Replaces the EXIF block of an encoded jpeg, png or webp image
+pub fn inject_exif(data: Vec<u8>, exif: Vec<u8>) -> Result<Vec<u8>, Error> {
+    let mut image = DynImage::from_bytes(Bytes::from(data))
+        .map_err(|e| Error::from_string(format!("could not parse output container for metadata: {}", e)))?
+        .ok_or_else(|| Error::from_string("metadata can only be written into jpeg, png and webp outputs".to_string()))?;
+    let exif = Bytes::from(exif);
+    image.set_exif(Some(exif.clone()));
+    if let DynImage::WebP(webp) = &mut image {
+        // img-parts prefixes `Exif\0\0` like in jpeg, but the webp EXIF chunk holds the plain TIFF block
+        for chunk in webp.chunks_mut().iter_mut().filter(|c| c.id() == CHUNK_EXIF) {
+            *chunk = RiffChunk::new(CHUNK_EXIF, RiffContent::Data(exif.clone()));
+        }
+    }
+    Ok(image.encoder().bytes().to_vec())
+}
+
+/// Copies the text (tEXt, zTXt, iTXt) and time (tIME) chunks of the png `source` into the encoded png `data`.
+///
+/// With `strip_gps`, XMP packets and embedded profiles are dropped, they can hold the location as well.
+/// Sources that are no png files are ignored.
+pub fn copy_png_text(source: &[u8], data: Vec<u8>, strip_gps: bool) -> Result<Vec<u8>, Error> {
+    let Ok(source) = Png::from_bytes(Bytes::copy_from_slice(source)) else {
+        return Ok(data);
+    };
+    let chunks: Vec<_> = source.chunks().iter()
+        .filter(|chunk| PNG_TEXT_CHUNKS.contains(&&chunk.kind()))
+        .filter(|chunk| !strip_gps || !holds_embedded_metadata(chunk))
+        .cloned()
+        .collect();
+    if chunks.is_empty() {
+        return Ok(data);
+    }
+    let mut image = Png::from_bytes(Bytes::from(data))
+        .map_err(|e| Error::from_string(format!("could not parse png output for metadata: {}", e)))?;
+    // in front of IEND, which is always the last chunk
+    let end = image.chunks().len().saturating_sub(1);
+    image.chunks_mut().splice(end..end, chunks);
+    Ok(image.encoder().bytes().to_vec())
+}
+
+// XMP packets (`XML:com.adobe.xmp`) and the exif, iptc and xmp profiles embedded by ImageMagick (`Raw profile type exif`, ...)
+fn holds_embedded_metadata(chunk: &PngChunk) -> bool {
+    let keyword = chunk.contents().split(|&b| b == 0).next().unwrap_or_default();
+    chunk.kind() != *b"tIME" && (keyword == b"XML:com.adobe.xmp" || keyword.starts_with(b"Raw profile type "))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::copy_png_text;
+    use image::{codecs::png::PngEncoder, ExtendedColorType, ImageEncoder};
+    use img_parts::{png::{Png, PngChunk}, Bytes};
+
+    fn png() -> Vec<u8> {
+        let mut data = Vec::new();
+        PngEncoder::new(&mut data).write_image(&[0, 0, 0], 1, 1, ExtendedColorType::Rgb8).unwrap();
+        data
+    }
+
+    fn text_keywords(data: Vec<u8>) -> Vec<Vec<u8>> {
+        Png::from_bytes(Bytes::from(data)).unwrap().chunks().iter()
+            .filter(|chunk| matches!(&chunk.kind(), b"tEXt" | b"iTXt"))
+            .map(|chunk| chunk.contents().split(|&b| b == 0).next().unwrap().to_vec())
+            .collect()
+    }
+
+    #[test]
+    fn strips_xmp_with_gps() {
+        let xmp = concat!("XML:com.adobe.xmp\0\0\0\0\0<x:xmpmeta xmlns:x=\"adobe:ns:meta/\"><rdf:RDF>",
+                          "<rdf:Description xmlns:exif=\"http://ns.adobe.com/exif/1.0/\" exif:GPSLatitude=\"48,8.5N\"",
+                          " exif:GPSLongitude=\"11,34.6E\"/></rdf:RDF></x:xmpmeta>");
+        let mut source = Png::from_bytes(Bytes::from(png())).unwrap();
+        let end = source.chunks().len() - 1;
+        source.chunks_mut().splice(end..end, [
+            PngChunk::new(*b"tEXt", Bytes::from_static(b"Comment\0sunset")),
+            PngChunk::new(*b"iTXt", Bytes::from_static(xmp.as_bytes())),
+        ]);
+        let source = source.encoder().bytes().to_vec();
+        assert_eq!(text_keywords(copy_png_text(&source, png(), false).unwrap()), [&b"Comment"[..], b"XML:com.adobe.xmp"]);
+        assert_eq!(text_keywords(copy_png_text(&source, png(), true).unwrap()), [&b"Comment"[..]]);
+    }
+}
@@ -0,0 +1,421 @@
+use crate::{i18n::tr, progress::HumanDuration};
+use humansize::{format_size, FormatSizeOptions, BINARY};
+use std::{collections::BTreeMap, path::Path, time::Duration};
+
+/// Result of converting one input, the statistics of a run are based on these
+#[derive(Clone, Debug, PartialEq)]
+pub enum ConversionOutcome {
+    /// The output was written.
+    Converted {
+        /// Size of the input (B).
+        input_size: usize,
+        /// Size of the output (B), summed over all frames of frame sequences.
+        output_size: usize,
+        /// Time taken to decode, encode and write the input.
+        elapsed: Duration,
+    },
+    /// Nothing was written, the existing output is kept.
+    Skipped {
+        /// Why the input was skipped.
+        reason: SkipReason,
+        /// Size of the input (B), 0 if it does not count into the statistics.
+        input_size: usize,
+        /// Size of the existing output (B), 0 if it does not count into the statistics.
+        output_size: usize,
+    },
+    /// The encode was not smaller than the input, nothing was written.
+    Discarded {
+        /// Size of the input (B).
+        input_size: usize,
+        /// Size of the discarded encode (B).
+        output_size: usize,
+        /// Time taken to decode and encode the input.
+        elapsed: Duration,
+    },
+    /// The encode saved less than `min_savings`, nothing was written and the input is kept.
+    NotWorthIt {
+        /// Size of the input (B).
+        input_size: usize,
+        /// Size of the dropped encode (B).
+        output_size: usize,
+        /// Time taken to decode and encode the input.
+        elapsed: Duration,
+    },
+    /// The input has more pixels than `max_pixels`, it was not decoded.
+    Rejected {
+        /// Size of the input (B).
+        input_size: usize,
+    },
+    /// The conversion failed.
+    Failed {
+        /// Error of the conversion.
+        error: String,
+    },
+    /// The conversion was not started, the run was interrupted (ctrl+c).
+    Aborted,
+}
+
+/// Why an input was skipped
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SkipReason {
+    /// All outputs exist and no overwrite option is set.
+    OutputExists,
+    /// All outputs exist and were modified after the input (`if_stale`).
+    OutputUpToDate,
+    /// All existing outputs are smaller than the new encode (`overwrite_if_smaller`).
+    ExistingSmaller,
+    /// Outputs with the content hash name of the encode exist already.
+    SameContentExists,
+    /// Still images have no frames to extract.
+    NotAnimated,
+    /// The input has no transparent pixels (`only_with_alpha`).
+    NoAlpha,
+    /// The animated input is its own copy (`animated` copy of a replaced input).
+    AnimatedKept,
+    /// The time budget was used up before the input was started.
+    TimeBudgetUsedUp,
+}
+
+impl SkipReason {
+    /// Explanation of the reason in the configured language, as listed by `--explain-skips`
+    pub fn description(&self) -> String {
+        match self {
+            SkipReason::OutputExists => tr!("explain-output-exists"),
+            SkipReason::OutputUpToDate => tr!("explain-output-up-to-date"),
+            SkipReason::ExistingSmaller => tr!("explain-existing-smaller"),
+            SkipReason::SameContentExists => tr!("explain-same-content-exists"),
+            SkipReason::NotAnimated => tr!("explain-not-animated"),
+            SkipReason::NoAlpha => tr!("explain-no-alpha"),
+            SkipReason::AnimatedKept => tr!("explain-animated-kept"),
+            SkipReason::TimeBudgetUsedUp => tr!("explain-time-budget-used-up"),
+        }
+    }
+}
+
+/// Counts and sizes of the outcomes of a run
+#[derive(Clone, Debug, Default)]
+pub struct RunStatistics {
+    /// Recorded outcomes.
+    pub files_done: usize,
+    /// Converted inputs.
+    pub converted: usize,
+    /// Skipped inputs.
+    pub skipped: usize,
+    /// Inputs whose encode was discarded.
+    pub discarded: usize,
+    /// Inputs whose encode saved less than `min_savings`.
+    pub not_worth_it: usize,
+    /// Inputs rejected by their pixel count.
+    pub rejected: usize,
+    /// Failed conversions.
+    pub failed: usize,
+    /// Inputs not started due to an interruption.
+    pub aborted: usize,
+    /// Input size of converted and skipped inputs (B).
+    pub input_size: usize,
+    /// Output size of converted and skipped inputs (B).
+    pub output_size: usize,
+    /// Input size of skipped inputs with existing outputs (B).
+    pub preexisting_input_size: usize,
+    /// Size of existing outputs of skipped inputs (B).
+    pub preexisting_output_size: usize,
+    /// Input size of discarded encodes (B).
+    pub discarded_input_size: usize,
+    /// Size of discarded encodes (B).
+    pub discarded_output_size: usize,
+    /// Input size of encodes saving less than `min_savings` (B).
+    pub not_worth_it_input_size: usize,
+    /// Size of encodes saving less than `min_savings` (B).
+    pub not_worth_it_output_size: usize,
+    /// Time spent on converted, discarded and not worth it inputs, summed over all workers.
+    pub encode_time: Duration,
+}
+
+impl RunStatistics {
+    /// Inputs that were decoded and encoded, whether the encode was kept or not
+    pub fn encoded(&self) -> usize {
+        self.converted + self.discarded + self.not_worth_it
+    }
+
+    /// Counts `outcome` into the statistics
+    pub fn record(&mut self, outcome: &ConversionOutcome) {
+        self.files_done += 1;
+        match *outcome {
+            ConversionOutcome::Converted { input_size, output_size, elapsed } => {
+                self.converted += 1;
+                self.input_size += input_size;
+                self.output_size += output_size;
+                self.encode_time += elapsed;
+            }
+            ConversionOutcome::Skipped { input_size, output_size, .. } => {
+                self.skipped += 1;
+                self.input_size += input_size;
+                self.output_size += output_size;
+                self.preexisting_input_size += input_size;
+                self.preexisting_output_size += output_size;
+            }
+            ConversionOutcome::Discarded { input_size, output_size, elapsed } => {
+                self.discarded += 1;
+                self.discarded_input_size += input_size;
+                self.discarded_output_size += output_size;
+                self.encode_time += elapsed;
+            }
+            ConversionOutcome::NotWorthIt { input_size, output_size, elapsed } => {
+                self.not_worth_it += 1;
+                self.not_worth_it_input_size += input_size;
+                self.not_worth_it_output_size += output_size;
+                self.encode_time += elapsed;
+            }
+            ConversionOutcome::Rejected { .. } => self.rejected += 1,
+            ConversionOutcome::Failed { .. } => self.failed += 1,
+            ConversionOutcome::Aborted => self.aborted += 1,
+        }
+    }
+
+    // moves a converted input of the given sizes and encode time to the failed ones
+    fn convert_to_failed(&mut self, input_size: usize, output_size: usize, elapsed: Duration) {
+        self.converted = self.converted.saturating_sub(1);
+        self.failed += 1;
+        self.input_size = self.input_size.saturating_sub(input_size);
+        self.output_size = self.output_size.saturating_sub(output_size);
+        self.encode_time = self.encode_time.saturating_sub(elapsed);
+    }
+}
+
+/// Totals of a finished run, shown by its summary
+#[derive(Clone, Debug, Default)]
+pub struct RunSummary {
+    /// Outcomes of the files of the run.
+    pub statistics: RunStatistics,
+    /// Wall time of the run.
+    pub elapsed: Duration,
+    /// Planned inputs.
+    pub inputs: usize,
+    /// Inputs that cannot be converted (zero-byte, unknown magic, unsupported format).
+    pub unconvertible: usize,
+    /// Files re-encoded at full effort by `--time-budget`.
+    pub refined: usize,
+    /// Truncated jpegs salvaged by `--tolerate-truncation`.
+    pub repaired: usize,
+    /// Outputs written in a `--fallback-format` as the target format was larger than the input.
+    pub fallbacks: usize,
+    /// Outputs written in the format of a `--route` rule instead of the target format.
+    pub routed: usize,
+    /// Animated inputs, handled as set by `--animated`.
+    pub animated: usize,
+    /// Undecodable inputs moved or copied to the quarantine directory.
+    pub quarantined: usize,
+    /// Outputs linked to an identical output by `--dedupe-outputs`.
+    pub deduplicated: usize,
+    /// Space freed by outputs replacing their larger inputs (`--in-place`, `--optimize` without `--backup-ext`) (B).
+    pub reclaimed_size: usize,
+    /// Space the outputs of kept inputs would free if the inputs were deleted (B).
+    pub potential_savings: usize,
+    /// Statistics per input extension, see [`RunAggregate`].
+    pub by_extension: BTreeMap<String, RunStatistics>,
+}
+
+impl RunSummary {
+    /// Placeholders of summary templates (`--summary-template`)
+    pub const FIELDS: &'static [&'static str] = &[
+        "time", "inputs", "converted", "skipped", "failed", "aborted", "discarded", "not_worth_it", "rejected", "unconvertible",
+        "refined", "repaired", "fallbacks", "routed", "animated", "quarantined", "deduplicated", "encode_time", "input_size", "output_size", "saved", "ratio",
+        "reclaimed", "potential_savings",
+    ];
+
+    // value of a placeholder, sizes and durations formatted like the default summary
+    fn field(&self, name: &str) -> Option<String> {
+        let stats = &self.statistics;
+        let size = |bytes: usize| format_size(bytes, FormatSizeOptions::from(BINARY)
+            .decimal_places(2).decimal_zeroes(2).space_after_value(false));
+        Some(match name {
+            "time" => HumanDuration(self.elapsed).to_string(),
+            "inputs" => self.inputs.to_string(),
+            "converted" => stats.converted.to_string(),
+            "skipped" => stats.skipped.to_string(),
+            "failed" => stats.failed.to_string(),
+            "aborted" => stats.aborted.to_string(),
+            "discarded" => stats.discarded.to_string(),
+            "not_worth_it" => stats.not_worth_it.to_string(),
+            "rejected" => stats.rejected.to_string(),
+            "unconvertible" => self.unconvertible.to_string(),
+            "refined" => self.refined.to_string(),
+            "repaired" => self.repaired.to_string(),
+            "fallbacks" => self.fallbacks.to_string(),
+            "routed" => self.routed.to_string(),
+            "animated" => self.animated.to_string(),
+            "quarantined" => self.quarantined.to_string(),
+            "deduplicated" => self.deduplicated.to_string(),
+            "encode_time" => HumanDuration(stats.encode_time / stats.encoded().max(1) as u32).to_string(),
+            "input_size" => size(stats.input_size),
+            "output_size" => size(stats.output_size),
+            "saved" => size(stats.input_size.saturating_sub(stats.output_size)),
+            "ratio" if stats.input_size == 0 => "-".to_string(),
+            "ratio" => format!("{:.02}", stats.output_size as f64 / stats.input_size as f64 * 100.0),
+            "reclaimed" => size(self.reclaimed_size),
+            "potential_savings" => size(self.potential_savings),
+            _ => return None,
+        })
+    }
+
+    /// Json object of the totals, sizes in bytes and durations in seconds, e.g. for scripts (`--summary-json`)
+    pub fn to_json(&self) -> serde_json::Value {
+        let stats = &self.statistics;
+        let encoded = stats.encoded();
+        serde_json::json!({
+            "elapsed_secs": self.elapsed.as_secs_f64(),
+            "inputs": self.inputs,
+            "counts": {
+                "converted": stats.converted,
+                "skipped": stats.skipped,
+                "failed": stats.failed,
+                "aborted": stats.aborted,
+                "discarded": stats.discarded,
+                "not_worth_it": stats.not_worth_it,
+                "rejected": stats.rejected,
+                "unconvertible": self.unconvertible,
+                "refined": self.refined,
+                "repaired": self.repaired,
+                "fallbacks": self.fallbacks,
+                "routed": self.routed,
+                "animated": self.animated,
+                "quarantined": self.quarantined,
+                "deduplicated": self.deduplicated,
+            },
+            "sizes": {
+                "input": stats.input_size,
+                "output": stats.output_size,
+                "saved": stats.input_size.saturating_sub(stats.output_size),
+                "preexisting_input": stats.preexisting_input_size,
+                "preexisting_output": stats.preexisting_output_size,
+                "new_input": stats.input_size - stats.preexisting_input_size,
+                "new_output": stats.output_size - stats.preexisting_output_size,
+                "discarded_input": stats.discarded_input_size,
+                "discarded_output": stats.discarded_output_size,
+                "not_worth_it_input": stats.not_worth_it_input_size,
+                "not_worth_it_output": stats.not_worth_it_output_size,
+                "reclaimed": self.reclaimed_size,
+                "potential_savings": self.potential_savings,
+            },
+            "ratios": {
+                "total": ratio(stats.output_size, stats.input_size),
+                "new": ratio(stats.output_size - stats.preexisting_output_size, stats.input_size - stats.preexisting_input_size),
+                "preexisting": ratio(stats.preexisting_output_size, stats.preexisting_input_size),
+            },
+            "encode_time_secs": stats.encode_time.as_secs_f64(),
+            "average_encode_secs": if encoded > 0 { Some(stats.encode_time.as_secs_f64() / encoded as f64) } else { None },
+            "by_extension": self.by_extension.iter().map(|(extension, stats)| (extension.clone(), serde_json::json!({
+                "files": stats.files_done,
+                "converted": stats.converted,
+                "skipped": stats.skipped,
+                "failed": stats.failed,
+                "input_size": stats.input_size,
+                "output_size": stats.output_size,
+                "saved": stats.input_size.saturating_sub(stats.output_size),
+                "ratio": ratio(stats.output_size, stats.input_size),
+            }))).collect::<serde_json::Map<_, _>>(),
+        })
+    }
+
+    /// Fills the `{field}` placeholders of `template` (see [`RunSummary::FIELDS`]), unknown ones are kept as they are
+    pub fn render(&self, template: &str) -> String {
+        let mut rendered = String::with_capacity(template.len());
+        let mut rest = template;
+        while let Some(start) = rest.find('{') {
+            rendered.push_str(&rest[..start]);
+            let placeholder = &rest[start..];
+            match placeholder.find('}').and_then(|end| Some((end, self.field(&placeholder[1..end])?))) {
+                Some((end, value)) => {
+                    rendered.push_str(&value);
+                    rest = &placeholder[end + 1..];
+                }
+                None => {
+                    rendered.push('{');
+                    rest = &placeholder[1..];
+                }
+            }
+        }
+        rendered.push_str(rest);
+        rendered
+    }
+}
+
+/// Totals of the outcomes of a run, folded in file by file so that runs of millions of files do not keep every outcome
+#[derive(Clone, Debug, Default)]
+pub struct RunAggregate {
+    /// Outcomes of all files.
+    pub statistics: RunStatistics,
+    /// Outcomes grouped by the lowercase extension of their inputs (empty for inputs without extension).
+    pub by_extension: BTreeMap<String, RunStatistics>,
+    /// Space freed by outputs replacing their inputs without backup (B).
+    pub reclaimed_size: usize,
+    /// Space the outputs of kept inputs would free if the inputs were deleted (B).
+    pub potential_savings: usize,
+}
+
+impl RunAggregate {
+    /// Counts the `outcome` of `input` into the totals, `replaced` if its output replaced it without keeping a backup
+    pub fn record(&mut self, input: &Path, replaced: bool, outcome: &ConversionOutcome) {
+        self.statistics.record(outcome);
+        self.by_extension.entry(extension_of(input)).or_default().record(outcome);
+        if let ConversionOutcome::Converted { input_size, output_size, .. } | ConversionOutcome::Skipped { input_size, output_size, .. } = *outcome {
+            self.add_savings(replaced, input_size.saturating_sub(output_size));
+        }
+    }
+
+    /// Counts the output of `input` as re-encoded from `previous_size` to the smaller `output_size` (B)
+    pub fn record_smaller_output(&mut self, input: &Path, replaced: bool, previous_size: usize, output_size: usize) {
+        let saved = previous_size.saturating_sub(output_size);
+        self.statistics.output_size -= saved;
+        if let Some(statistics) = self.by_extension.get_mut(&extension_of(input)) {
+            statistics.output_size -= saved;
+        }
+        self.add_savings(replaced, saved);
+    }
+
+    /// Counts the converted `input` as failed, its output of `output_size` (B) did not reach its destination
+    /// (a failed transfer from the `--stage-dir`)
+    pub fn record_lost_output(&mut self, input: &Path, input_size: usize, output_size: usize, elapsed: Duration) {
+        self.statistics.convert_to_failed(input_size, output_size, elapsed);
+        if let Some(statistics) = self.by_extension.get_mut(&extension_of(input)) {
+            statistics.convert_to_failed(input_size, output_size, elapsed);
+        }
+        // staged outputs never replace their inputs
+        self.potential_savings = self.potential_savings.saturating_sub(input_size.saturating_sub(output_size));
+    }
+
+    // outputs replacing their inputs freed the difference on disk, kept inputs only could
+    fn add_savings(&mut self, replaced: bool, saved: usize) {
+        if replaced {
+            self.reclaimed_size += saved;
+        } else {
+            self.potential_savings += saved;
+        }
+    }
+}
+
+// key of an input in the statistics per extension
+fn extension_of(input: &Path) -> String {
+    input.extension().map(|extension| extension.to_string_lossy().to_lowercase()).unwrap_or_default()
+}
+
+// output size in percent of the input size, none without inputs
+fn ratio(output_size: usize, input_size: usize) -> Option<f64> {
+    (input_size > 0).then(|| output_size as f64 / input_size as f64 * 100.0)
+}
+
+/// Checks that all `{field}` placeholders of a summary template are fields of [`RunSummary`]
+pub fn check_summary_template(template: &str) -> Result<(), String> {
+    let mut rest = template;
+    while let Some(start) = rest.find('{') {
+        let Some(end) = rest[start..].find('}') else {
+            return Err(format!("unclosed placeholder at `{}`", &rest[start..]));
+        };
+        let name = &rest[start + 1..start + end];
+        if !RunSummary::FIELDS.contains(&name) {
+            return Err(format!("unknown placeholder `{{{}}}`, available: {}", name, RunSummary::FIELDS.join(", ")));
+        }
+        rest = &rest[start + end + 1..];
+    }
+    Ok(())
+}
@@ -0,0 +1,84 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+
+/// When to use colors in terminal output
+#[cfg_attr(feature = "cli", derive(clap::ValueEnum))]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ColorMode {
+    /// Use colors if the output is a terminal and `NO_COLOR` is not set.
+    #[default]
+    Auto,
+    /// Always use colors.
+    Always,
+    /// Never use colors.
+    Never,
+}
+
+/// Symbols used in progress messages and summaries
+#[derive(Debug)]
+pub struct Glyphs {
+    /// Separates input and output sizes.
+    pub arrow: &'static str,
+    /// Prefixes the successful count / passed checks.
+    pub ok: &'static str,
+    /// Prefixes the skipped count.
+    pub skipped: &'static str,
+    /// Prefixes the error count / failed checks.
+    pub error: &'static str,
+    /// Characters of the progress bar (filled, current, empty).
+    pub progress_chars: &'static str,
+    /// Spinner animation frames of the per-worker lines.
+    pub spinner: &'static str,
+}
+
+const UNICODE_GLYPHS: Glyphs = Glyphs {
+    arrow: "➜",
+    ok: "✔",
+    skipped: "—",
+    error: "✖",
+    progress_chars: "█░",
+    spinner: "⠁⠂⠄⡀⢀⠠⠐⠈ ",
+};
+
+const ASCII_GLYPHS: Glyphs = Glyphs {
+    arrow: "->",
+    ok: "OK",
+    skipped: "--",
+    error: "ERR",
+    progress_chars: "#>-",
+    spinner: "|/-\\ ",
+};
+
+static ASCII_ONLY: AtomicBool = AtomicBool::new(false);
+
+/// Applies the color mode and glyph set for all following output.
+///
+/// In `Auto` mode a non-empty `NO_COLOR` environment variable disables colors (see https://no-color.org).
+pub fn configure(color: ColorMode, ascii_only: bool) {
+    // only the progress bars of the `cli` feature are colored
+    #[cfg(feature = "cli")]
+    {
+        let colors = match color {
+            ColorMode::Always => Some(true),
+            ColorMode::Never => Some(false),
+            ColorMode::Auto => std::env::var_os("NO_COLOR")
+                .filter(|v| !v.is_empty())
+                .map(|_| false),
+        };
+        if let Some(enabled) = colors {
+            console::set_colors_enabled(enabled);
+            console::set_colors_enabled_stderr(enabled);
+        }
+    }
+    #[cfg(not(feature = "cli"))]
+    let _ = color;
+    ASCII_ONLY.store(ascii_only, Ordering::Relaxed);
+}
+
+/// Returns the glyph set selected via [`configure`]
+pub fn glyphs() -> &'static Glyphs {
+    if ASCII_ONLY.load(Ordering::Relaxed) {
+        &ASCII_GLYPHS
+    } else {
+        &UNICODE_GLYPHS
+    }
+}
@@ -0,0 +1,116 @@
+use crate::{converter::EncoderConfig, format::ImageFormat, quality::VisualQuality};
+use std::{fmt, path::Path, str::FromStr};
+
+/// Encoder settings for inputs of some formats, written as `FORMATS:KEY=VALUE,...`,
+/// e.g. `png:quality=95` or `jpg,tiff:visual-quality=high,speed=4`
+#[derive(serde::Serialize, serde::Deserialize, Clone, PartialEq, Debug, Default)]
+pub struct InputOverride {
+    /// Input extensions (lowercase) the settings apply to, aliases of a format match each other (`jpg` and `jpeg`).
+    pub formats: Vec<String>,
+    /// Target quality (0 - 100).
+    pub quality: Option<f32>,
+    /// Encoder independent quality, replacing the quality and speed of the base settings.
+    pub visual_quality: Option<VisualQuality>,
+    /// Avif encoding speed (1 - 10).
+    pub speed: Option<u8>,
+    /// Lossless encoding (webp and heic).
+    pub lossless: Option<bool>,
+    /// Avif alpha quality (0 - 100).
+    pub alpha_quality: Option<f32>,
+}
+
+impl InputOverride {
+    /// Whether the settings apply to the input at `path`
+    pub fn matches(&self, path: &Path) -> bool {
+        let Some(ext) = path.extension().and_then(|ext| ext.to_str()) else {
+            return false;
+        };
+        let input_format = ImageFormat::from_extension(ext);
+        self.formats.iter().any(|format| {
+            format.eq_ignore_ascii_case(ext)
+                || (input_format != ImageFormat::Unknown && ImageFormat::from_extension(format) == input_format)
+        })
+    }
+
+    /// Sets the setting `key` (`quality`, `visual-quality`, `speed`, `lossless` or `alpha-quality`) from its text `value`
+    pub fn set(&mut self, key: &str, value: &str) -> Result<(), String> {
+        match key {
+            "quality" | "q" => self.quality = Some(percentage("quality", value)?),
+            "visual-quality" => self.visual_quality = Some(value.parse()?),
+            "speed" | "s" => self.speed = Some(value.parse::<u8>().ok()
+                .filter(|speed| (1..=10).contains(speed))
+                .ok_or_else(|| format!("speed has to be from 1 to 10, got `{}`", value))?),
+            "lossless" => self.lossless = Some(value.parse::<bool>()
+                .map_err(|_| format!("lossless has to be true or false, got `{}`", value))?),
+            "alpha-quality" => self.alpha_quality = Some(percentage("alpha-quality", value)?),
+            key => return Err(format!(
+                "unknown setting `{}`, expected quality, visual-quality, speed, lossless or alpha-quality", key)),
+        }
+        Ok(())
+    }
+
+    /// Replaces the settings of `options` with the ones set here
+    pub fn apply_to(&self, options: &mut EncoderConfig) {
+        if self.visual_quality.is_some() {
+            // otherwise the base quality and speed would take precedence over the mapped ones
+            options.visual_quality = self.visual_quality;
+            options.quality = None;
+            options.speed = None;
+        }
+        options.quality = self.quality.or(options.quality);
+        options.speed = self.speed.or(options.speed);
+        options.lossless = self.lossless.or(options.lossless);
+        options.avif_alpha_quality = self.alpha_quality.or(options.avif_alpha_quality);
+    }
+}
+
+fn percentage(key: &str, value: &str) -> Result<f32, String> {
+    value.parse::<f32>().ok()
+        .filter(|value| (0. ..=100.).contains(value))
+        .ok_or_else(|| format!("{} has to be from 0 to 100, got `{}`", key, value))
+}
+
+impl FromStr for InputOverride {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (formats, settings) = s.split_once(':')
+            .ok_or_else(|| format!("expected FORMATS:KEY=VALUE,... (e.g. `png:quality=95`), got `{}`", s))?;
+        let mut result = InputOverride {
+            formats: formats.split(',').map(|format| format.trim().trim_start_matches('.').to_ascii_lowercase())
+                .filter(|format| !format.is_empty())
+                .collect(),
+            ..Default::default()
+        };
+        if result.formats.is_empty() {
+            return Err(format!("no input formats given in `{}`", s));
+        }
+        for setting in settings.split(',').map(str::trim).filter(|setting| !setting.is_empty()) {
+            let (key, value) = setting.split_once('=').unwrap_or((setting, "true"));
+            result.set(key.trim(), value.trim())?;
+        }
+        Ok(result)
+    }
+}
+
+impl fmt::Display for InputOverride {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut settings = Vec::new();
+        if let Some(quality) = self.quality {
+            settings.push(format!("quality={}", quality));
+        }
+        if let Some(visual_quality) = self.visual_quality {
+            settings.push(format!("visual-quality={}", visual_quality));
+        }
+        if let Some(speed) = self.speed {
+            settings.push(format!("speed={}", speed));
+        }
+        if let Some(lossless) = self.lossless {
+            settings.push(format!("lossless={}", lossless));
+        }
+        if let Some(alpha_quality) = self.alpha_quality {
+            settings.push(format!("alpha-quality={}", alpha_quality));
+        }
+        write!(f, "{}:{}", self.formats.join(","), settings.join(","))
+    }
+}
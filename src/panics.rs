@@ -0,0 +1,40 @@
+use std::{
+    fs::OpenOptions,
+    io::{self, Write},
+    path::Path,
+};
+
+/// Log file of the panics if no other is configured, in the working directory
+pub const DEFAULT_PANIC_LOG: &str = "imgc-panics.log";
+
+/// What happens to the run when converting a file panicked (a bug in a decoder or encoder)
+#[cfg_attr(feature = "cli", derive(clap::ValueEnum))]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum PanicPolicy {
+    /// Fail the file and continue with the next one.
+    #[default]
+    Skip,
+    /// Stop the run, files not started yet are aborted.
+    Abort,
+    /// Fail the file and put its input into the quarantine directory, next to a note of the panic.
+    Quarantine,
+}
+
+/// Panic raised while converting a file
+#[derive(Clone, Debug)]
+pub struct PanicReport {
+    /// Message and location of the panic.
+    pub message: String,
+    /// Backtrace of the panicking thread.
+    pub backtrace: String,
+}
+
+/// Appends the `panics` raised while converting `input` to the log file at `path`
+pub fn append_panic_log(path: &Path, input: &Path, panics: &[PanicReport]) -> io::Result<()> {
+    // a single write per file, appends of parallel workers do not interleave
+    let entries: String = panics.iter()
+        .map(|panic| format!("input: {}\nimgc: {}\npanic: {}\nbacktrace:\n{}\n\n",
+                             input.display(), env!("CARGO_PKG_VERSION"), panic.message, panic.backtrace))
+        .collect();
+    OpenOptions::new().create(true).append(true).open(path)?.write_all(entries.as_bytes())
+}
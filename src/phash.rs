@@ -0,0 +1,74 @@
+use crate::{sidecar::Crop, Error};
+use image::{imageops::FilterType, DynamicImage};
+use std::f32::consts::PI;
+
+/// Perceptual hash algorithm, similar images have hashes with a small hamming distance
+#[cfg_attr(feature = "cli", derive(clap::ValueEnum))]
+#[derive(serde::Serialize, serde::Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum PerceptualHashKind {
+    /// Difference hash: brightness gradients of a 9x8 thumbnail (fast).
+    Dhash,
+    /// DCT hash: low frequencies of a 32x32 thumbnail compared to their median (robust against recompression and scaling).
+    Phash,
+}
+
+/// 64 bit perceptual hash of an image
+#[derive(serde::Serialize, serde::Deserialize, Clone, Debug, PartialEq)]
+pub struct PerceptualHash {
+    /// Algorithm of the hash.
+    pub kind: PerceptualHashKind,
+    /// Hash as 16 hex digits.
+    pub value: String,
+}
+
+/// Computes the perceptual hash of `image` (of its `crop` region if set)
+pub fn perceptual_hash(kind: PerceptualHashKind, image: &DynamicImage, crop: Option<Crop>) -> Result<PerceptualHash, Error> {
+    let image = match crop {
+        Some(crop) => crop.apply(image)?,
+        None => std::borrow::Cow::Borrowed(image),
+    };
+    let bits = match kind {
+        PerceptualHashKind::Dhash => dhash(&image),
+        PerceptualHashKind::Phash => phash(&image),
+    };
+    Ok(PerceptualHash { kind, value: format!("{:016x}", bits) })
+}
+
+fn dhash(image: &DynamicImage) -> u64 {
+    let gray = image.resize_exact(9, 8, FilterType::Triangle).to_luma8();
+    let mut bits = 0u64;
+    for y in 0..8 {
+        for x in 0..8 {
+            bits = bits << 1 | (gray.get_pixel(x, y)[0] < gray.get_pixel(x + 1, y)[0]) as u64;
+        }
+    }
+    bits
+}
+
+fn phash(image: &DynamicImage) -> u64 {
+    const SIZE: usize = 32;
+    let gray = image.resize_exact(SIZE as u32, SIZE as u32, FilterType::Triangle).to_luma8();
+    let pixels: Vec<f32> = gray.pixels().map(|pixel| pixel[0] as f32).collect();
+    // 2d dct-ii, only the 8x8 lowest frequencies are needed
+    let cosines: Vec<f32> = (0..8 * SIZE)
+        .map(|i| (PI / SIZE as f32 * (i / SIZE) as f32 * ((i % SIZE) as f32 + 0.5)).cos())
+        .collect();
+    let mut coefficients = [0f32; 64];
+    for v in 0..8 {
+        for u in 0..8 {
+            let mut sum = 0.;
+            for y in 0..SIZE {
+                for x in 0..SIZE {
+                    sum += pixels[x + y * SIZE] * cosines[u * SIZE + x] * cosines[v * SIZE + y];
+                }
+            }
+            coefficients[u + v * 8] = sum;
+        }
+    }
+    // the dc coefficient (average brightness) would dominate the median
+    let mut ac = coefficients[1..].to_vec();
+    ac.sort_by(|a, b| a.total_cmp(b));
+    let median = ac[ac.len() / 2];
+    coefficients.iter().fold(0u64, |bits, &coefficient| bits << 1 | (coefficient > median) as u64)
+}
@@ -0,0 +1,273 @@
+use crate::{
+    converter::webp::encode_webp_to,
+    sidecar::Crop,
+    utils::{linear_to_srgb, srgb_to_linear},
+    Error,
+};
+use image::{imageops::FilterType, DynamicImage, GenericImageView};
+use std::f32::consts::PI;
+
+/// Kind of low quality placeholder computed per image, for lazy loading frontends
+#[cfg_attr(feature = "cli", derive(clap::ValueEnum))]
+#[derive(serde::Serialize, serde::Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum PlaceholderKind {
+    /// BlurHash string of 4x3 components (https://blurha.sh).
+    Blurhash,
+    /// ThumbHash bytes, base64 encoded (keeps the aspect ratio and transparency).
+    Thumbhash,
+    /// Tiny (16 pixel) webp as a `data:` uri, usable directly as `src` of an `<img>`.
+    TinyWebp,
+}
+
+/// Low quality placeholder of an image
+#[derive(serde::Serialize, serde::Deserialize, Clone, Debug, PartialEq)]
+pub struct Placeholder {
+    /// Kind of the placeholder.
+    pub kind: PlaceholderKind,
+    /// Placeholder in the usual text form of its kind.
+    pub value: String,
+}
+
+/// Computes the placeholder of `image` (of its `crop` region if set)
+pub fn compute_placeholder(kind: PlaceholderKind, image: &DynamicImage, crop: Option<Crop>) -> Result<Placeholder, Error> {
+    let image = match crop {
+        Some(crop) => crop.apply(image)?,
+        None => std::borrow::Cow::Borrowed(image),
+    };
+    let value = match kind {
+        // the hashes only keep the lowest frequencies, small thumbnails give the same result much faster
+        PlaceholderKind::Blurhash => blurhash(&image.thumbnail(32, 32)),
+        PlaceholderKind::Thumbhash => base64(&thumbhash(&image.thumbnail(100, 100))),
+        PlaceholderKind::TinyWebp => {
+            let tiny = image.resize(16, 16, FilterType::Triangle);
+            let mut data = Vec::new();
+            encode_webp_to(&tiny, false, 40., None, &mut data)?;
+            format!("data:image/webp;base64,{}", base64(&data))
+        }
+    };
+    Ok(Placeholder { kind, value })
+}
+
+const BASE83_CHARS: &[u8] = b"0123456789ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz#$%*+,-.:;=?@[]^_{|}~";
+
+fn base83(value: u32, length: u32, hash: &mut String) {
+    for i in 1..=length {
+        let digit = (value / 83u32.pow(length - i)) % 83;
+        hash.push(BASE83_CHARS[digit as usize] as char);
+    }
+}
+
+// https://github.com/woltapp/blurhash/blob/master/Algorithm.md
+fn blurhash(image: &DynamicImage) -> String {
+    const COMPONENTS_X: usize = 4;
+    const COMPONENTS_Y: usize = 3;
+    let (width, height) = image.dimensions();
+    let rgb = image.to_rgb8();
+    let linear: Vec<[f32; 3]> = rgb.pixels()
+        .map(|pixel| [srgb_to_linear(pixel[0]), srgb_to_linear(pixel[1]), srgb_to_linear(pixel[2])])
+        .collect();
+
+    let mut factors = Vec::with_capacity(COMPONENTS_X * COMPONENTS_Y);
+    for cy in 0..COMPONENTS_Y {
+        for cx in 0..COMPONENTS_X {
+            let normalisation = if cx == 0 && cy == 0 { 1. } else { 2. };
+            let mut factor = [0f32; 3];
+            for y in 0..height as usize {
+                let basis_y = (PI * cy as f32 * y as f32 / height as f32).cos();
+                for x in 0..width as usize {
+                    let basis = normalisation * (PI * cx as f32 * x as f32 / width as f32).cos() * basis_y;
+                    let pixel = linear[x + y * width as usize];
+                    for channel in 0..3 {
+                        factor[channel] += basis * pixel[channel];
+                    }
+                }
+            }
+            let scale = 1. / (width * height) as f32;
+            factors.push(factor.map(|value| value * scale));
+        }
+    }
+
+    let mut hash = String::new();
+    base83(((COMPONENTS_X - 1) + (COMPONENTS_Y - 1) * 9) as u32, 1, &mut hash);
+    let (dc, ac) = factors.split_first().unwrap();
+    let actual_maximum = ac.iter().flatten().fold(0f32, |maximum, value| maximum.max(value.abs()));
+    let quantised_maximum = ((actual_maximum * 166. - 0.5).floor()).clamp(0., 82.) as u32;
+    let maximum = (quantised_maximum + 1) as f32 / 166.;
+    base83(quantised_maximum, 1, &mut hash);
+    base83(u32::from_be_bytes([0, linear_to_srgb(dc[0]), linear_to_srgb(dc[1]), linear_to_srgb(dc[2])]), 4, &mut hash);
+    for factor in ac {
+        let quantise = |value: f32| {
+            let normalised = value / maximum;
+            (normalised.signum() * normalised.abs().sqrt() * 9. + 9.5).floor().clamp(0., 18.) as u32
+        };
+        base83(quantise(factor[0]) * 19 * 19 + quantise(factor[1]) * 19 + quantise(factor[2]), 2, &mut hash);
+    }
+    hash
+}
+
+// port of `rgbaToThumbHash` of the reference implementation (https://github.com/evanw/thumbhash), images up to 100x100;
+//  in double precision and the operation order of the reference, coefficients close to 0.5 round the same way
+fn thumbhash(image: &DynamicImage) -> Vec<u8> {
+    use std::f64::consts::PI;
+    let (w, h) = (image.width() as usize, image.height() as usize);
+    let rgba = image.to_rgba8();
+    let pixels: Vec<[f64; 4]> = rgba.pixels()
+        .map(|pixel| [pixel[0] as f64, pixel[1] as f64, pixel[2] as f64, pixel[3] as f64 / 255.])
+        .collect();
+
+    // average color, weighted by alpha
+    let (mut avg_r, mut avg_g, mut avg_b, mut avg_a) = (0., 0., 0., 0.);
+    for [r, g, b, alpha] in &pixels {
+        avg_r += alpha / 255. * r;
+        avg_g += alpha / 255. * g;
+        avg_b += alpha / 255. * b;
+        avg_a += alpha;
+    }
+    if avg_a > 0. {
+        avg_r /= avg_a;
+        avg_g /= avg_a;
+        avg_b /= avg_a;
+    }
+
+    let has_alpha = avg_a < (w * h) as f64;
+    let l_limit = if has_alpha { 5. } else { 7. };
+    let longest = w.max(h) as f64;
+    let lx = ((l_limit * w as f64 / longest).round() as usize).max(1);
+    let ly = ((l_limit * h as f64 / longest).round() as usize).max(1);
+
+    // luminance, yellow-blue, red-green and alpha channels, transparent pixels blend with the average color
+    let mut l = Vec::with_capacity(w * h);
+    let mut p = Vec::with_capacity(w * h);
+    let mut q = Vec::with_capacity(w * h);
+    let mut a = Vec::with_capacity(w * h);
+    for [r, g, b, alpha] in &pixels {
+        let r = avg_r * (1. - alpha) + alpha / 255. * r;
+        let g = avg_g * (1. - alpha) + alpha / 255. * g;
+        let b = avg_b * (1. - alpha) + alpha / 255. * b;
+        l.push((r + g + b) / 3.);
+        p.push((r + g) / 2. - b);
+        q.push(r - g);
+        a.push(*alpha);
+    }
+
+    let encode_channel = |channel: &[f64], nx: usize, ny: usize| -> (f64, Vec<f64>, f64) {
+        let (mut dc, mut ac, mut scale) = (0., Vec::new(), 0f64);
+        let mut fx = vec![0f64; w];
+        for cy in 0..ny {
+            let mut cx = 0;
+            while cx * ny < nx * (ny - cy) {
+                for (x, f) in fx.iter_mut().enumerate() {
+                    *f = (PI / w as f64 * cx as f64 * (x as f64 + 0.5)).cos();
+                }
+                let mut f = 0.;
+                for y in 0..h {
+                    let fy = (PI / h as f64 * cy as f64 * (y as f64 + 0.5)).cos();
+                    for x in 0..w {
+                        f += channel[x + y * w] * fx[x] * fy;
+                    }
+                }
+                f /= (w * h) as f64;
+                if cx > 0 || cy > 0 {
+                    ac.push(f);
+                    scale = scale.max(f.abs());
+                } else {
+                    dc = f;
+                }
+                cx += 1;
+            }
+        }
+        if scale > 0. {
+            for value in &mut ac {
+                *value = 0.5 + 0.5 / scale * *value;
+            }
+        }
+        (dc, ac, scale)
+    };
+    let (l_dc, l_ac, l_scale) = encode_channel(&l, lx.max(3), ly.max(3));
+    let (p_dc, p_ac, p_scale) = encode_channel(&p, 3, 3);
+    let (q_dc, q_ac, q_scale) = encode_channel(&q, 3, 3);
+    let alpha_channel = has_alpha.then(|| encode_channel(&a, 5, 5));
+
+    let is_landscape = w > h;
+    let header24 = (63. * l_dc).round() as u32
+        | ((31.5 + 31.5 * p_dc).round() as u32) << 6
+        | ((31.5 + 31.5 * q_dc).round() as u32) << 12
+        | ((31. * l_scale).round() as u32) << 18
+        | (has_alpha as u32) << 23;
+    let header16 = (if is_landscape { ly } else { lx }) as u32
+        | ((63. * p_scale).round() as u32) << 3
+        | ((63. * q_scale).round() as u32) << 9
+        | (is_landscape as u32) << 15;
+    let mut hash = vec![
+        (header24 & 255) as u8, ((header24 >> 8) & 255) as u8, (header24 >> 16) as u8,
+        (header16 & 255) as u8, (header16 >> 8) as u8,
+    ];
+    let mut acs = vec![l_ac, p_ac, q_ac];
+    if let Some((a_dc, a_ac, a_scale)) = alpha_channel {
+        hash.push((15. * a_dc).round() as u8 | ((15. * a_scale).round() as u8) << 4);
+        acs.push(a_ac);
+    }
+    // two 4 bit coefficients per byte, low nibble first
+    for (i, value) in acs.iter().flatten().enumerate() {
+        if i % 2 == 0 {
+            hash.push(0);
+        }
+        *hash.last_mut().unwrap() |= ((15. * value).round() as u8) << ((i & 1) * 4);
+    }
+    hash
+}
+
+fn base64(data: &[u8]) -> String {
+    const ALPHABET: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+    let mut encoded = String::with_capacity(data.len().div_ceil(3) * 4);
+    for chunk in data.chunks(3) {
+        let bytes = [chunk[0], chunk.get(1).copied().unwrap_or(0), chunk.get(2).copied().unwrap_or(0)];
+        let triple = (bytes[0] as u32) << 16 | (bytes[1] as u32) << 8 | bytes[2] as u32;
+        for i in 0..4 {
+            if i <= chunk.len() {
+                encoded.push(ALPHABET[(triple >> (18 - 6 * i) & 63) as usize] as char);
+            } else {
+                encoded.push('=');
+            }
+        }
+    }
+    encoded
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::{Rgba, RgbaImage};
+
+    // 8x6 gradient, with alpha fading out to the right
+    fn fixture(alpha: bool) -> DynamicImage {
+        DynamicImage::ImageRgba8(RgbaImage::from_fn(8, 6, |x, y| {
+            Rgba([(x * 32) as u8, (y * 40 + 20) as u8, ((x + y) * 16) as u8, if alpha { (255 - x * 30) as u8 } else { 255 }])
+        }))
+    }
+
+    // expected hashes computed with the reference encoders (woltapp/blurhash, evanw/thumbhash) from the same pixels
+
+    #[test]
+    fn blurhash_matches_the_reference() {
+        assert_eq!(blurhash(&fixture(false)), "LjF?8#32a_xtzFNKfRnQeUf9fRf6");
+        let black = DynamicImage::ImageRgba8(RgbaImage::from_pixel(4, 4, Rgba([0, 0, 0, 255])));
+        assert_eq!(blurhash(&black), "L00000fQfQfQfQfQfQfQfQfQfQfQ");
+    }
+
+    #[test]
+    fn thumbhash_matches_the_reference() {
+        assert_eq!(base64(&thumbhash(&fixture(false))), "m/gNDZpwh3dxeHiHeHiIh3iAB+eI");
+        assert_eq!(base64(&thumbhash(&fixture(true))), "l6iJDI45QXdQd4d4hwh1Qa/4h4iIeIiIBw==");
+    }
+
+    #[test]
+    fn base64_matches_rfc_4648() {
+        let vectors = [("", ""), ("f", "Zg=="), ("fo", "Zm8="), ("foo", "Zm9v"), ("foob", "Zm9vYg=="), ("fooba", "Zm9vYmE="), ("foobar", "Zm9vYmFy")];
+        for (data, encoded) in vectors {
+            assert_eq!(base64(data.as_bytes()), encoded);
+        }
+        assert_eq!(base64(&[0xfb, 0xff]), "+/8=");
+    }
+}
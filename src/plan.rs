@@ -0,0 +1,533 @@
+use crate::{
+    animation::is_animated,
+    converter::{
+        base_from_pattern, normalize_prefix, output_is_current, output_metadata, CommonConfig, EncoderConfig, OutputNaming,
+        CONTENT_HASH_LENGTH,
+    },
+    dds::is_dds,
+    decode::{decoder_name, format_name, DecoderFilter},
+    format::ImageFormat,
+    manifest::sha256_hex,
+    priority::weight_of,
+    psd::is_psd,
+    sidecar::read_sidecar,
+    Error,
+};
+use rayon::prelude::*;
+use std::{collections::{BTreeMap, BTreeSet, HashMap}, fs, io::{self, Read}, path::{Path, PathBuf}, time::{SystemTime, UNIX_EPOCH}};
+
+/// Version of the plan file format, bumped on incompatible changes
+pub const PLAN_VERSION: u32 = 1;
+
+/// Order in which the inputs are converted
+#[cfg_attr(feature = "cli", derive(clap::ValueEnum))]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ProcessingOrder {
+    /// Lexicographical order of the paths (see `reverse_processing_order`).
+    #[default]
+    Name,
+    /// Shuffled, e.g. for sampling runs where the first results should not all come from one directory.
+    Random,
+}
+
+/// Conversions of a run, decided before any file is converted
+///
+/// Written by `imgc plan`, executed by `imgc apply` (later or on another machine, with the same relative paths).
+#[derive(serde::Serialize, serde::Deserialize, Clone, Debug)]
+pub struct Plan {
+    /// Plan file format version.
+    pub version: u32,
+    /// Output format.
+    pub format: ImageFormat,
+    /// Glob pattern the inputs were matched with.
+    pub pattern: String,
+    /// Output directory, empty for outputs next to their inputs.
+    pub output: String,
+    /// Output directories receiving copies of the outputs.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub mirror_outputs: Vec<String>,
+    /// Encoder settings of the run, for files without own `settings`.
+    pub settings: EncoderConfig,
+    /// Only convert the frames of animated inputs (`imgc frames`).
+    #[serde(default)]
+    pub extract_frames: bool,
+    /// Timestamps of the poster frames of video inputs (`imgc poster`).
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub poster_at: Vec<String>,
+    /// Seed of the shuffled processing order (`--order random`).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub order_seed: Option<u64>,
+    /// Planned conversions in processing order.
+    pub files: Vec<PlannedFile>,
+    /// Inputs left out by their sidecar files.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub skipped: Vec<PathBuf>,
+    /// Inputs whose outputs all exist, skipped while planning as no overwrite option was set.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub up_to_date: Vec<UpToDateInput>,
+    /// Outputs planned for several inputs, only the last written one survives.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub collisions: Vec<Collision>,
+    /// Files matched by the pattern that cannot be converted.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub unconvertible: Vec<UnconvertibleInput>,
+}
+
+/// One planned conversion
+#[derive(serde::Serialize, serde::Deserialize, Clone, Debug)]
+pub struct PlannedFile {
+    /// Input path.
+    pub input: PathBuf,
+    /// Size of the input (B), read when planning and again when a saved plan is read.
+    #[serde(default)]
+    pub input_size: usize,
+    /// Output path, content hash names and frame numbers are added when the output is written.
+    pub output: PathBuf,
+    /// Copies of the output in the mirror directories.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub mirrors: Vec<PathBuf>,
+    /// The output replaces the input (`--in-place`, or `--optimize` only if smaller).
+    #[serde(default)]
+    pub replace_input: bool,
+    /// Inputs already in the output format are re-optimized, discarding results that are not smaller (`--optimize`).
+    #[serde(default)]
+    pub reoptimize: bool,
+    /// Settings of this file if they differ from the ones of the run (`--when` overrides, sidecar files).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub settings: Option<EncoderConfig>,
+    /// The input is an animated gif, webp or png (see `animated` of [`CommonConfig`]).
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub animated: bool,
+}
+
+/// Which of the matched files are inputs, by the format of their extension (names like [`DecoderFilter`])
+#[derive(Clone, Debug, Default)]
+pub struct InputFormatFilter {
+    /// Only files of these formats are inputs, all files if empty.
+    pub only: Vec<String>,
+    /// Files of these formats are no inputs.
+    pub skip: Vec<String>,
+}
+
+impl InputFormatFilter {
+    /// Whether the file at `path` is an input
+    pub fn matches(&self, path: &Path) -> bool {
+        if self.only.is_empty() && self.skip.is_empty() {
+            return true;
+        }
+        let format = path.extension().and_then(|extension| decoder_name(&extension.to_string_lossy()));
+        (self.only.is_empty() || format.as_ref().is_some_and(|format| self.only.contains(format)))
+            && !format.is_some_and(|format| self.skip.contains(&format))
+    }
+}
+
+/// Several inputs planned to be written to the same output
+#[derive(serde::Serialize, serde::Deserialize, Clone, Debug)]
+pub struct Collision {
+    /// Shared output path.
+    pub output: PathBuf,
+    /// Inputs written to it.
+    pub inputs: Vec<PathBuf>,
+}
+
+/// Input whose outputs all exist (and are current with `if_stale`), it is not converted again
+#[derive(serde::Serialize, serde::Deserialize, Clone, Debug)]
+pub struct UpToDateInput {
+    /// Path of the input.
+    pub path: PathBuf,
+    /// Size of the input (B).
+    pub input_size: usize,
+    /// Size of its existing output (B).
+    pub output_size: usize,
+}
+
+/// Why a file matched by the pattern cannot be converted
+#[derive(serde::Serialize, serde::Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum InputProblem {
+    /// The file is empty.
+    ZeroByte,
+    /// The file has an image extension, but does not start with the signature of any readable format.
+    UnknownMagic,
+    /// The file is no image, or an image format without decoder (avif, heif, jpeg 2000 without the feature).
+    UnsupportedFormat,
+    /// The decoder of the format is disabled (`--enable-decoder`, `--disable-decoder`).
+    DisabledDecoder,
+}
+
+impl InputProblem {
+    /// Name of the problem as used in plan files and listings
+    pub fn name(&self) -> &'static str {
+        match self {
+            InputProblem::ZeroByte => "zero-byte",
+            InputProblem::UnknownMagic => "unknown-magic",
+            InputProblem::UnsupportedFormat => "unsupported-format",
+            InputProblem::DisabledDecoder => "disabled-decoder",
+        }
+    }
+}
+
+/// File matched by the pattern that cannot be converted
+#[derive(serde::Serialize, serde::Deserialize, Clone, Debug)]
+pub struct UnconvertibleInput {
+    /// Path of the file.
+    pub path: PathBuf,
+    /// Why it cannot be converted.
+    pub problem: InputProblem,
+}
+
+impl Plan {
+    /// Reads a plan file written by [`Plan::write`]
+    ///
+    /// The sizes of the inputs are read again, they may have changed since planning.
+    pub fn read(path: &Path) -> Result<Plan, Error> {
+        let mut plan: Plan = serde_json::from_slice(&fs::read(path)?)?;
+        if plan.version != PLAN_VERSION {
+            return Err(Error::from_string(format!(
+                "plan {} has version {}, this imgc reads version {}", path.display(), plan.version, PLAN_VERSION)));
+        }
+        plan.files.par_iter_mut().for_each(|file| file.input_size = input_size(&file.input));
+        Ok(plan)
+    }
+
+    /// Writes the plan as pretty json
+    pub fn write(&self, path: &Path) -> Result<(), Error> {
+        fs::write(path, self.to_json()?)?;
+        Ok(())
+    }
+
+    /// The plan as pretty json
+    pub fn to_json(&self) -> Result<String, Error> {
+        Ok(serde_json::to_string_pretty(self)?)
+    }
+
+    /// Settings `file` is converted with
+    pub fn settings_of<'a>(&'a self, file: &'a PlannedFile) -> &'a EncoderConfig {
+        file.settings.as_ref().unwrap_or(&self.settings)
+    }
+
+    /// Sets the files whose outputs all exist aside (see [`UpToDateInput`]) unless an overwrite option of `conf` is set,
+    /// so that re-runs over converted trees do not queue them at all.
+    ///
+    /// Saved plans keep all their files, the overwrite options are only known when they are applied.
+    pub fn set_aside_up_to_date(&mut self, conf: &CommonConfig) {
+        if conf.naming != OutputNaming::Plain || conf.writes_frame_sequences() || conf.overwrite_existing || conf.overwrite_if_smaller {
+            return;
+        }
+        // the outputs are looked up in parallel, network filesystems answer slowly but concurrently
+        let current: Vec<Option<UpToDateInput>> = self.files.par_iter().map(|file| up_to_date_input(conf, file)).collect();
+        let mut current = current.into_iter();
+        let up_to_date = &mut self.up_to_date;
+        self.files.retain(|_| match current.next().flatten() {
+            Some(input) => {
+                up_to_date.push(input);
+                false
+            }
+            None => true,
+        });
+    }
+
+    /// Number of unconvertible inputs with `problem`
+    pub fn count_unconvertible(&self, problem: InputProblem) -> usize {
+        self.unconvertible.iter().filter(|input| input.problem == problem).count()
+    }
+
+    /// Writes the unconvertible inputs to `path`, one `PROBLEM<tab>PATH` per line
+    pub fn write_unconvertible(&self, path: &Path) -> Result<(), Error> {
+        let listing: String = self.unconvertible.iter()
+            .map(|input| format!("{}\t{}\n", input.problem.name(), input.path.display()))
+            .collect();
+        fs::write(path, listing)?;
+        Ok(())
+    }
+    /// Creates and removes a file in every output directory of the files (or the closest existing directory above it),
+    /// failing on the first one no file can be created in (read-only mounts, missing permissions)
+    ///
+    /// Called right before converting, a long run would otherwise fail every single file of it.
+    pub fn check_writable(&self) -> Result<(), Error> {
+        let parents: BTreeSet<&Path> = self.files.iter()
+            .flat_map(|file| std::iter::once(&file.output).chain(&file.mirrors))
+            .map(|output| output.parent().unwrap_or_else(|| Path::new("")))
+            .collect();
+        // directories created while converting are probed at their closest existing ancestor
+        let directories: BTreeSet<&Path> = parents.into_iter()
+            .map(|parent| parent.ancestors().find(|ancestor| ancestor.is_dir()).unwrap_or_else(|| Path::new("")))
+            .collect();
+        let unwritable: Vec<(&Path, io::Error)> = directories.into_par_iter()
+            .filter_map(|directory| probe_write(directory).err().map(|err| (directory, err)))
+            .collect();
+        let Some(((directory, err), others)) = unwritable.split_first() else {
+            return Ok(());
+        };
+        let directory = if directory.as_os_str().is_empty() { Path::new(".") } else { directory };
+        let others = match others.len() {
+            0 => String::new(),
+            count => format!(" (and {} more output directories)", count),
+        };
+        Err(Error::from_string(format!("cannot write into the output directory {}: {}{}, no file was converted",
+                                       directory.display(), err, others)))
+    }
+}
+
+/// Plans the conversion of the files matched by `conf.pattern` into `img_format`
+///
+/// Matches and orders the inputs, reads their sidecar files, resolves their settings and output paths
+/// and detects outputs shared by several inputs. Nothing is written.
+pub fn plan_conversion(conf: &CommonConfig, img_format: &ImageFormat, options: &EncoderConfig) -> Result<Plan, Error> {
+    let paths: Vec<PathBuf> = glob::glob(&conf.pattern)?
+        .filter_map(|entry| entry.ok())
+        .collect();
+    plan_inputs(conf, img_format, options, paths)
+}
+
+/// Plans the conversion of `paths` into `img_format` like [`plan_conversion`], without matching the pattern
+///
+/// The pattern still decides the output paths (its fixed base), paths that are no files are left out.
+pub fn plan_inputs(conf: &CommonConfig, img_format: &ImageFormat, options: &EncoderConfig, paths: Vec<PathBuf>)
+    -> Result<Plan, Error> {
+    let mut unconvertible = Vec::new();
+    let mut paths: Vec<PathBuf> = paths.into_iter()
+        .filter(|path| conf.input_formats.matches(path) && path.is_file())
+        .filter(|path| {
+            // videos are read by ffmpeg
+            if !conf.poster_at.is_empty() {
+                return true;
+            }
+            match classify_input(path, &conf.decoders) {
+                Some(problem) => {
+                    unconvertible.push(UnconvertibleInput { path: path.clone(), problem });
+                    false
+                }
+                None => true,
+            }
+        })
+        .collect();
+    // sort paths lexicographically, not only filenames
+    paths.sort_by(|a, b| {
+        let dir_cmp = a.parent().cmp(&b.parent());
+        let cmp = if dir_cmp != std::cmp::Ordering::Equal {
+            dir_cmp
+        } else {
+            a.file_name().cmp(&b.file_name())
+        };
+
+        if conf.reverse_processing_order {
+            cmp.reverse()
+        } else {
+            cmp
+        }
+    });
+    let order_seed = match conf.order {
+        ProcessingOrder::Name => None,
+        ProcessingOrder::Random => {
+            // shuffled after sorting, so that the seed alone determines the order
+            let seed = conf.seed.unwrap_or_else(random_seed);
+            shuffle(&mut paths, seed);
+            Some(seed)
+        }
+    };
+    // stable, the order above is kept among inputs of the same weight
+    if !conf.priorities.is_empty() {
+        paths.sort_by_key(|path| std::cmp::Reverse(weight_of(&conf.priorities, path)));
+    }
+    if conf.group_by_directory {
+        group_by_directory(&mut paths);
+    }
+    let pattern_base = conf.relative_to.clone().unwrap_or_else(|| base_from_pattern(&conf.pattern));
+
+    let mut files = Vec::with_capacity(paths.len());
+    let mut skipped = Vec::new();
+    for input in paths {
+        // sidecar files are read up front, invalid ones fail the run before any file is converted
+        let sidecar = read_sidecar(&input)?;
+        if sidecar.as_ref().is_some_and(|sidecar| sidecar.skip) {
+            skipped.push(input);
+            continue;
+        }
+        let overridden = sidecar.is_some() || options.input_overrides.iter().any(|input_override| input_override.matches(&input));
+        let settings = options.for_input(&input, sidecar.as_ref());
+        let ext = settings.output_extension(img_format);
+        // inputs already in the target format are recompressed into themselves
+        let reoptimize = conf.optimize && ImageFormat::from(input.as_path()).extension() == img_format.extension();
+        let replace_input = conf.in_place || reoptimize;
+        if conf.relative_to.is_some() && !replace_input && !normalize_prefix(&input).starts_with(normalize_prefix(&pattern_base)) {
+            return Err(Error::from_string(format!("input {} is not inside --relative-to {}", input.display(), pattern_base)));
+        }
+        files.push(PlannedFile {
+            output: if replace_input { input.clone() } else { output_path(&input, ext, &conf.output, &pattern_base, conf) },
+            mirrors: if replace_input { Vec::new() } else {
+                conf.mirror_outputs.iter().map(|mirror| output_path(&input, ext, mirror, &pattern_base, conf)).collect()
+            },
+            replace_input,
+            reoptimize,
+            settings: overridden.then(|| settings.into_owned()),
+            // frames and posters are extracted from their inputs anyway
+            animated: !conf.extract_frames && conf.poster_at.is_empty() && is_animated(&input),
+            input_size: input_size(&input),
+            input,
+        });
+    }
+
+    // flattened outputs of inputs with the same name are told apart by a hash of their input path,
+    // content hash names already differ by their content
+    if conf.flatten && conf.naming == OutputNaming::Plain {
+        let mut name_counts: HashMap<PathBuf, usize> = HashMap::new();
+        for file in files.iter().filter(|file| !file.replace_input) {
+            *name_counts.entry(file.output.clone()).or_default() += 1;
+        }
+        for file in files.iter_mut().filter(|file| !file.replace_input && name_counts[&file.output] > 1) {
+            let hash = sha256_hex(normalize_prefix(&file.input).to_string_lossy().as_bytes());
+            // the planned extension, which depends on the settings of the input (pnm subtypes)
+            let ext = file.output.extension().unwrap_or_default().to_string_lossy().into_owned();
+            let unique = |path: &Path| path.with_extension(format!("{}.{}", &hash[..CONTENT_HASH_LENGTH], ext));
+            file.output = unique(&file.output);
+            file.mirrors = file.mirrors.iter().map(|mirror| unique(mirror)).collect();
+        }
+    }
+
+    let mut outputs: BTreeMap<&Path, Vec<PathBuf>> = BTreeMap::new();
+    for file in files.iter().filter(|file| !file.replace_input) {
+        outputs.entry(&file.output).or_default().push(file.input.clone());
+    }
+    let collisions = outputs.into_iter()
+        .filter(|(_, inputs)| inputs.len() > 1)
+        .map(|(output, inputs)| Collision { output: output.to_path_buf(), inputs })
+        .collect();
+
+    Ok(Plan {
+        version: PLAN_VERSION,
+        format: *img_format,
+        pattern: conf.pattern.clone(),
+        output: conf.output.clone(),
+        mirror_outputs: conf.mirror_outputs.clone(),
+        settings: options.clone(),
+        extract_frames: conf.extract_frames,
+        poster_at: conf.poster_at.clone(),
+        order_seed,
+        files,
+        skipped,
+        up_to_date: Vec::new(),
+        collisions,
+        unconvertible,
+    })
+}
+
+// the input of `file` if its output and all mirrors exist and count as done, like the check before converting a file
+fn up_to_date_input(conf: &CommonConfig, file: &PlannedFile) -> Option<UpToDateInput> {
+    if file.replace_input {
+        return None;
+    }
+    let input = fs::metadata(&file.input).ok()?;
+    let output = output_metadata(&file.output);
+    let input_modified = input.modified().ok();
+    let current = output_is_current(conf, input_modified, output.as_ref())
+        && file.mirrors.iter().all(|mirror| output_is_current(conf, input_modified, output_metadata(mirror).as_ref()));
+    current.then(|| UpToDateInput {
+        path: file.input.clone(),
+        input_size: input.len() as usize,
+        output_size: output.map_or(0, |output| output.len() as usize),
+    })
+}
+
+// size of the input at `path`, 0 if it cannot be read (its conversion reports why)
+fn input_size(path: &Path) -> usize {
+    fs::metadata(path).map_or(0, |metadata| metadata.len() as usize)
+}
+
+// creates and removes a file in `directory`
+fn probe_write(directory: &Path) -> io::Result<()> {
+    let directory = if directory.as_os_str().is_empty() { Path::new(".") } else { directory };
+    let probe = directory.join(format!(".imgc-write-probe-{}", std::process::id()));
+    fs::OpenOptions::new().write(true).create(true).truncate(true).open(&probe)?;
+    fs::remove_file(&probe)
+}
+
+// seed of runs without --seed, printed so that the order can be repeated
+fn random_seed() -> u64 {
+    let nanos = SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |elapsed| elapsed.as_nanos() as u64);
+    nanos ^ u64::from(std::process::id()).rotate_left(32)
+}
+
+// inputs of a directory follow each other, directories in the order of their first input,
+// so that the workers drain one directory at a time
+fn group_by_directory(paths: &mut [PathBuf]) {
+    let mut first_seen: HashMap<PathBuf, usize> = HashMap::new();
+    for path in paths.iter() {
+        let next = first_seen.len();
+        first_seen.entry(path.parent().map(Path::to_path_buf).unwrap_or_default()).or_insert(next);
+    }
+    paths.sort_by_key(|path| first_seen[path.parent().unwrap_or_else(|| Path::new(""))]);
+}
+
+// Fisher-Yates shuffle with splitmix64 random numbers, the same seed yields the same order on every platform
+fn shuffle<T>(items: &mut [T], seed: u64) {
+    let mut state = seed;
+    let mut next = || {
+        state = state.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^ (z >> 31)
+    };
+    for i in (1..items.len()).rev() {
+        // the modulo bias is negligible for file counts
+        let j = (next() % (i as u64 + 1)) as usize;
+        items.swap(i, j);
+    }
+}
+
+// inputs are told apart by their extension, their first bytes only reveal empty and mislabeled files
+fn classify_input(path: &Path, decoders: &DecoderFilter) -> Option<InputProblem> {
+    let format = ImageFormat::from(path);
+    if format == ImageFormat::Unknown
+        || format == ImageFormat::Avif // disable reading avif (FIXME: re-enable with reliable build+integration for reader)
+        || format == ImageFormat::Heif // no heif decoder
+        || (!cfg!(feature = "jpeg2000") && format == ImageFormat::Jpeg2000) {
+        return Some(InputProblem::UnsupportedFormat);
+    }
+    let mut header = Vec::with_capacity(32);
+    // unreadable files are reported by their conversion
+    fs::File::open(path).and_then(|file| file.take(32).read_to_end(&mut header)).ok()?;
+    if header.is_empty() {
+        return Some(InputProblem::ZeroByte);
+    }
+    if !decoders.is_empty()
+        && format_name(&header, &path.extension().unwrap_or_default().to_string_lossy()).is_some_and(|name| !decoders.allows(&name)) {
+        return Some(InputProblem::DisabledDecoder);
+    }
+    match image::guess_format(&header) {
+        Ok(image::ImageFormat::Avif) => Some(InputProblem::UnsupportedFormat),
+        Ok(_) => None,
+        // tga has no signature
+        Err(_) if format == ImageFormat::Tga || is_dds(&header) || is_psd(&header) => None,
+        #[cfg(feature = "jpeg2000")]
+        Err(_) if crate::jpeg2000::is_jpeg2000(&header) => None,
+        Err(_) => Some(InputProblem::UnknownMagic),
+    }
+}
+
+// output next to the input, or at the same path relative to the output directory as the input to the pattern base
+// (directly in the output directory if flattened), the names below the output directory are normalized
+fn output_path(input: &Path, ext: &str, output: &str, pattern_base: &str, conf: &CommonConfig) -> PathBuf {
+    let stem = Path::new(input.file_stem().unwrap());
+    let normalize = |path: &Path| match conf.normalize_filenames {
+        Some(normalization) => normalization.normalize_path(path),
+        None => path.to_path_buf(),
+    };
+    if output.is_empty() {
+        return input.with_file_name(normalize(stem)).with_extension(ext);
+    }
+    if conf.flatten {
+        return Path::new(output).join(normalize(stem)).with_extension(ext);
+    }
+    let pattern_base_norm = normalize_prefix(pattern_base);
+    let input_path_norm = normalize_prefix(input);
+    let rel_path = input_path_norm
+        .strip_prefix(&pattern_base_norm)
+        .unwrap_or_else(|_| Path::new(&input_path_norm));
+
+    Path::new(output)
+        .join(normalize(rel_path.parent().unwrap_or_else(|| Path::new(""))))
+        .join(normalize(stem))
+        .with_extension(ext)
+}
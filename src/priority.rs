@@ -0,0 +1,62 @@
+use crate::Error;
+use std::{fs, path::Path, str::FromStr};
+
+/// Weight of the inputs matching a glob pattern, inputs with higher weights are converted first
+///
+/// Written as `GLOB=WEIGHT` or just `GLOB` (weight 1), e.g. `uploads/2026-10-*/**=10`.
+/// Negative weights move the matching inputs behind all others.
+#[derive(Clone, Debug)]
+pub struct PathWeight {
+    /// Pattern matched against the input paths as expanded from the input pattern.
+    pub pattern: glob::Pattern,
+    /// Priority of the matching inputs, unmatched inputs have weight 0.
+    pub weight: i32,
+}
+
+/// Weight of the input at `path`, the highest of all matching `weights` (0 if none matches)
+pub fn weight_of(weights: &[PathWeight], path: &Path) -> i32 {
+    weights.iter()
+        .filter(|path_weight| path_weight.pattern.matches_path(path))
+        .map(|path_weight| path_weight.weight)
+        .max()
+        .unwrap_or(0)
+}
+
+/// Reads a weights file with one `GLOB = WEIGHT` per line (`#` starts a comment), e.g.
+/// ```text
+/// # this week's uploads first
+/// uploads/2026-10-*/** = 10
+/// archive/** = -1
+/// ```
+pub fn read_weights(path: &Path) -> Result<Vec<PathWeight>, Error> {
+    let text = fs::read_to_string(path)?;
+    let mut weights = Vec::new();
+    for (number, line) in text.lines().enumerate() {
+        let line = line.split_once('#').map_or(line, |(setting, _comment)| setting).trim();
+        if line.is_empty() {
+            continue;
+        }
+        let path_weight = line.parse().map_err(|err| Error::from_string(
+            format!("invalid weights file {}: line {}: {}", path.display(), number + 1, err)))?;
+        weights.push(path_weight);
+    }
+    Ok(weights)
+}
+
+impl FromStr for PathWeight {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        // globs may contain `=` themselves, the weight is always last
+        let (pattern, weight) = match s.rsplit_once('=') {
+            Some((pattern, weight)) => (pattern.trim(), weight.trim().parse::<i32>()
+                .map_err(|_| format!("weight has to be a whole number, got `{}`", weight.trim()))?),
+            None => (s.trim(), 1),
+        };
+        if pattern.is_empty() {
+            return Err(format!("expected GLOB or GLOB=WEIGHT (e.g. `uploads/**=10`), got `{}`", s));
+        }
+        let pattern = glob::Pattern::new(pattern).map_err(|err| format!("invalid glob `{}`: {}", pattern, err))?;
+        Ok(PathWeight { pattern, weight })
+    }
+}
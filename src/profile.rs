@@ -0,0 +1,139 @@
+use crate::Error;
+use serde_json::json;
+use std::{
+    cell::RefCell,
+    collections::BTreeMap,
+    fmt::Write as _,
+    fs,
+    path::Path,
+    sync::{
+        atomic::{AtomicBool, AtomicU64, Ordering},
+        Mutex,
+    },
+    time::{Duration, Instant},
+};
+
+// checked before any clock is read, spans cost nothing without --profile
+static ENABLED: AtomicBool = AtomicBool::new(false);
+static RECORDING: Mutex<Option<Recording>> = Mutex::new(None);
+static NEXT_THREAD: AtomicU64 = AtomicU64::new(1);
+
+thread_local! {
+    // small sequential ids, the trace viewers draw one row per thread
+    static THREAD: u64 = NEXT_THREAD.fetch_add(1, Ordering::Relaxed);
+    // open spans of the thread: name and time spent in their finished children
+    static STACK: RefCell<Vec<(&'static str, Duration)>> = const { RefCell::new(Vec::new()) };
+    // input of the outermost open span, labelling the spans below it
+    static FILE: RefCell<Option<String>> = const { RefCell::new(None) };
+}
+
+struct Recording {
+    started: Instant,
+    spans: Vec<SpanRecord>,
+}
+
+struct SpanRecord {
+    name: &'static str,
+    // names of the enclosing spans and this one, joined with `;`
+    stack: String,
+    file: Option<String>,
+    thread: u64,
+    start: Duration,
+    duration: Duration,
+    // duration without the children, the width of the span in flame graphs
+    self_time: Duration,
+}
+
+/// Open span of the pipeline of a file, recorded when dropped
+pub struct Span {
+    name: &'static str,
+    start: Instant,
+    // set if the span labelled the spans below it with its input
+    file: bool,
+}
+
+/// Starts recording spans (of all threads) for [`write_profile`]
+pub fn start_profile() {
+    *RECORDING.lock().unwrap_or_else(|e| e.into_inner()) = Some(Recording { started: Instant::now(), spans: Vec::new() });
+    ENABLED.store(true, Ordering::SeqCst);
+}
+
+/// Opens the span `name` (decode, transform, encode, write, ...) below the open spans of the thread,
+/// None if no profile is recorded
+pub fn span(name: &'static str) -> Option<Span> {
+    if !ENABLED.load(Ordering::Relaxed) {
+        return None;
+    }
+    STACK.with_borrow_mut(|stack| stack.push((name, Duration::ZERO)));
+    Some(Span { name, start: Instant::now(), file: false })
+}
+
+/// Opens the span `name` of the conversion of `input`, the spans below it are attributed to the input
+pub fn file_span(name: &'static str, input: &Path) -> Option<Span> {
+    let mut span = span(name)?;
+    FILE.with_borrow_mut(|file| if file.is_none() {
+        *file = Some(input.display().to_string());
+        span.file = true;
+    });
+    Some(span)
+}
+
+impl Drop for Span {
+    fn drop(&mut self) {
+        let duration = self.start.elapsed();
+        let (stack, children) = STACK.with_borrow_mut(|stack| {
+            let names = stack.iter().map(|(name, _)| *name).collect::<Vec<_>>().join(";");
+            let (_, children) = stack.pop().unwrap_or((self.name, Duration::ZERO));
+            if let Some((_, parent_children)) = stack.last_mut() {
+                *parent_children += duration;
+            }
+            (names, children)
+        });
+        let file = FILE.with_borrow_mut(|file| if self.file { file.take() } else { file.clone() });
+        let thread = THREAD.with(|thread| *thread);
+        let mut recording = RECORDING.lock().unwrap_or_else(|e| e.into_inner());
+        if let Some(recording) = recording.as_mut() {
+            let start = self.start.saturating_duration_since(recording.started);
+            recording.spans.push(SpanRecord {
+                name: self.name, stack, file, thread, start, duration, self_time: duration.saturating_sub(children),
+            });
+        }
+    }
+}
+
+/// Stops recording and writes the spans to `path`, returning their number
+///
+/// Paths ending in `.folded` get folded stacks with the self time of each stack in microseconds
+/// (the format of tracing-flame, for inferno-flamegraph or flamegraph.pl), all others a chrome trace
+/// (json for chrome://tracing, Perfetto or speedscope).
+pub fn write_profile(path: &Path) -> Result<usize, Error> {
+    ENABLED.store(false, Ordering::SeqCst);
+    let Some(recording) = RECORDING.lock().unwrap_or_else(|e| e.into_inner()).take() else {
+        return Err(Error::from_string("no profile was recorded".to_string()));
+    };
+    let contents = if path.extension().is_some_and(|ext| ext == "folded") {
+        let mut stacks = BTreeMap::new();
+        for span in &recording.spans {
+            *stacks.entry(&span.stack).or_insert(Duration::ZERO) += span.self_time;
+        }
+        let mut folded = String::new();
+        for (stack, time) in stacks {
+            writeln!(folded, "{} {}", stack, time.as_micros())?;
+        }
+        folded
+    } else {
+        let events = recording.spans.iter().map(|span| json!({
+            "name": span.name,
+            "cat": "imgc",
+            "ph": "X",
+            "ts": span.start.as_micros() as u64,
+            "dur": span.duration.as_micros() as u64,
+            "pid": std::process::id(),
+            "tid": span.thread,
+            "args": { "file": span.file },
+        })).collect::<Vec<_>>();
+        serde_json::to_string(&json!({ "traceEvents": events, "displayTimeUnit": "ms" }))?
+    };
+    fs::write(path, contents)?;
+    Ok(recording.spans.len())
+}
@@ -0,0 +1,128 @@
+// Progress bars of the conversion runs: indicatif with the `cli` feature, silent stand-ins with the same interface
+//  without it (applications embedding the library have no terminal to draw on).
+
+use std::{
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+#[cfg(feature = "cli")]
+pub(crate) use indicatif::{HumanDuration, MultiProgress, ProgressBar, ProgressStyle};
+#[cfg(not(feature = "cli"))]
+pub(crate) use silent::{HumanDuration, MultiProgress, ProgressBar, ProgressStyle};
+
+// finished files and time after which the progress bar is updated, formatting its message for every one of
+//  hundreds of thousands of small files shows up in profiles
+const BATCH_FILES: u64 = 64;
+const BATCH_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Collects the progress of finished files, releasing it to the progress bar in batches
+pub(crate) struct ProgressBatch {
+    // finished files and their input bytes since the last update, time of the last update
+    pending: Mutex<(u64, u64, Instant)>,
+}
+
+impl ProgressBatch {
+    pub(crate) fn new() -> Self {
+        ProgressBatch { pending: Mutex::new((0, 0, Instant::now())) }
+    }
+
+    /// Adds a finished file of `bytes` input size, returning the pending bytes once [`BATCH_FILES`] files were added
+    /// or [`BATCH_INTERVAL`] passed since the last update
+    pub(crate) fn add(&self, bytes: u64) -> Option<u64> {
+        let mut pending = self.pending.lock().unwrap_or_else(|e| e.into_inner());
+        pending.0 += 1;
+        pending.1 += bytes;
+        if pending.0 < BATCH_FILES && pending.2.elapsed() < BATCH_INTERVAL {
+            return None;
+        }
+        let bytes = pending.1;
+        *pending = (0, 0, Instant::now());
+        Some(bytes)
+    }
+
+    /// Takes the bytes not released yet, to complete the progress bar
+    pub(crate) fn flush(&self) -> u64 {
+        let mut pending = self.pending.lock().unwrap_or_else(|e| e.into_inner());
+        let bytes = pending.1;
+        *pending = (0, 0, Instant::now());
+        bytes
+    }
+}
+
+#[cfg(not(feature = "cli"))]
+mod silent {
+    use std::{borrow::Cow, convert::Infallible, fmt, time::Duration};
+
+    // like indicatif, the largest fitting unit, e.g. `3 minutes`
+    pub(crate) struct HumanDuration(pub Duration);
+
+    impl fmt::Display for HumanDuration {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            const UNITS: [(u64, &str); 5] = [(365 * 86400, "year"), (86400, "day"), (3600, "hour"), (60, "minute"), (1, "second")];
+            let seconds = self.0.as_secs();
+            let (length, name) = UNITS.iter().find(|&&(length, _)| seconds >= length).unwrap_or(&UNITS[4]);
+            let count = seconds / length;
+            write!(f, "{} {}{}", count, name, if count == 1 { "" } else { "s" })
+        }
+    }
+
+    #[derive(Clone)]
+    pub(crate) struct ProgressStyle;
+
+    impl ProgressStyle {
+        pub(crate) fn with_template(_template: &str) -> Result<Self, Infallible> {
+            Ok(ProgressStyle)
+        }
+
+        pub(crate) fn progress_chars(self, _chars: &str) -> Self {
+            self
+        }
+
+        pub(crate) fn tick_chars(self, _chars: &str) -> Self {
+            self
+        }
+    }
+
+    pub(crate) struct ProgressBar;
+
+    impl ProgressBar {
+        pub(crate) fn new(_length: u64) -> Self {
+            ProgressBar
+        }
+
+        pub(crate) fn new_spinner() -> Self {
+            ProgressBar
+        }
+
+        pub(crate) fn set_style(&self, _style: ProgressStyle) {}
+
+        pub(crate) fn set_message(&self, _message: impl Into<Cow<'static, str>>) {}
+
+        pub(crate) fn enable_steady_tick(&self, _interval: Duration) {}
+
+        pub(crate) fn reset_elapsed(&self) {}
+
+        pub(crate) fn inc(&self, _delta: u64) {}
+
+        pub(crate) fn finish_and_clear(&self) {}
+
+        pub(crate) fn finish_with_message(&self, _message: impl Into<Cow<'static, str>>) {}
+    }
+
+    pub(crate) struct MultiProgress;
+
+    impl MultiProgress {
+        pub(crate) fn new() -> Self {
+            MultiProgress
+        }
+
+        pub(crate) fn add(&self, bar: ProgressBar) -> ProgressBar {
+            bar
+        }
+
+        pub(crate) fn suspend<F: FnOnce() -> R, R>(&self, f: F) -> R {
+            f()
+        }
+    }
+}
@@ -0,0 +1,344 @@
+use crate::{decode::check_pixel_limit, Error};
+use image::{DynamicImage, ImageBuffer, Luma, LumaA, Rgb, RgbImage, Rgba};
+
+const MAGIC: &[u8] = b"8BPS";
+const HEADER_LEN: usize = 26;
+
+// color modes
+const MODE_GRAYSCALE: u16 = 1;
+const MODE_INDEXED: u16 = 2;
+const MODE_RGB: u16 = 3;
+const MODE_CMYK: u16 = 4;
+const MODE_DUOTONE: u16 = 8;
+
+/// Whether `ext` (lowercase) is a photoshop document extension (psd, or psb for large documents)
+pub fn is_psd_extension(ext: &str) -> bool {
+    matches!(ext, "psd" | "psb")
+}
+
+/// Whether `data` starts with the photoshop document signature
+pub fn is_psd(data: &[u8]) -> bool {
+    data.starts_with(MAGIC)
+}
+
+// big endian reader over the document sections
+struct Reader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, len: usize) -> Result<&'a [u8], Error> {
+        let end = self.pos.checked_add(len).filter(|&end| end <= self.data.len())
+            .ok_or_else(|| Error::from_string("truncated psd file".to_string()))?;
+        let bytes = &self.data[self.pos..end];
+        self.pos = end;
+        Ok(bytes)
+    }
+
+    fn u16(&mut self) -> Result<u16, Error> {
+        Ok(u16::from_be_bytes(self.take(2)?.try_into().unwrap()))
+    }
+
+    fn u32(&mut self) -> Result<u32, Error> {
+        Ok(u32::from_be_bytes(self.take(4)?.try_into().unwrap()))
+    }
+
+    fn u64(&mut self) -> Result<u64, Error> {
+        Ok(u64::from_be_bytes(self.take(8)?.try_into().unwrap()))
+    }
+
+    // length prefixed section, 64 bit lengths for some sections of large documents
+    fn section(&mut self, wide: bool) -> Result<&'a [u8], Error> {
+        let len = if wide { self.u64()? } else { self.u32()? as u64 };
+        self.take(usize::try_from(len).map_err(|_| Error::from_string("psd section too large".to_string()))?)
+    }
+}
+
+/// Reads the canvas dimensions (width, height) from the header of a photoshop document, None if it is no psd or psb
+pub fn psd_dimensions(data: &[u8]) -> Option<(u32, u32)> {
+    if !is_psd(data) || data.len() < HEADER_LEN {
+        return None;
+    }
+    let u32_at = |offset: usize| u32::from_be_bytes(data[offset..offset + 4].try_into().unwrap());
+    Some((u32_at(18), u32_at(14)))
+}
+
+/// Decodes the merged composite image of an in-memory photoshop document (psd or psb).
+///
+/// The composite is only stored if the document was saved with "Maximize Compatibility" (the default).
+/// Supports 8 and 16 bit rgb, grayscale (and duotone), indexed and cmyk documents,
+/// the first extra channel of rgb and grayscale documents is read as transparency.
+/// Documents with more than `max_pixels` pixels are rejected before their planes are read.
+pub fn read_psd_from_memory(data: &[u8], max_pixels: Option<u64>) -> Result<DynamicImage, Error> {
+    if !is_psd(data) || data.len() < HEADER_LEN {
+        return Err(Error::from_string("not a psd file".to_string()));
+    }
+    let mut reader = Reader { data, pos: 4 };
+    let large = match reader.u16()? {
+        1 => false,
+        2 => true,
+        version => return Err(Error::from_string(format!("unsupported psd version {}", version))),
+    };
+    reader.take(6)?; // reserved
+    let channels = reader.u16()? as usize;
+    let height = reader.u32()?;
+    let width = reader.u32()?;
+    let depth = reader.u16()?;
+    let mode = reader.u16()?;
+    if width == 0 || height == 0 {
+        return Err(Error::from_string("psd without a composite image (empty canvas)".to_string()));
+    }
+    check_pixel_limit(width, height, max_pixels)?;
+    if depth != 8 && depth != 16 {
+        return Err(Error::from_string(format!("unsupported psd bit depth {}", depth)));
+    }
+
+    let color_mode_data = reader.section(false)?;
+    reader.section(false)?; // image resources
+    reader.section(large)?; // layers, masks
+
+    let planes = read_planes(&mut reader, channels, width as usize, height as usize, depth as usize / 8, large)?;
+    // samples scaled to 16 bit
+    let sample = |plane: usize, i: usize| -> u16 {
+        match depth {
+            8 => planes[plane][i] as u16 * 257,
+            _ => u16::from_be_bytes([planes[plane][2 * i], planes[plane][2 * i + 1]]),
+        }
+    };
+    let index = |x: u32, y: u32| y as usize * width as usize + x as usize;
+
+    let image = match mode {
+        MODE_RGB if channels >= 4 => DynamicImage::ImageRgba16(ImageBuffer::from_fn(width, height, |x, y| {
+            let i = index(x, y);
+            let alpha = sample(3, i);
+            Rgba([unmatte(sample(0, i), alpha), unmatte(sample(1, i), alpha), unmatte(sample(2, i), alpha), alpha])
+        })),
+        MODE_RGB if channels == 3 => DynamicImage::ImageRgb16(ImageBuffer::from_fn(width, height, |x, y| {
+            let i = index(x, y);
+            Rgb([sample(0, i), sample(1, i), sample(2, i)])
+        })),
+        MODE_GRAYSCALE | MODE_DUOTONE if channels >= 2 => DynamicImage::ImageLumaA16(ImageBuffer::from_fn(width, height, |x, y| {
+            let i = index(x, y);
+            let alpha = sample(1, i);
+            LumaA([unmatte(sample(0, i), alpha), alpha])
+        })),
+        MODE_GRAYSCALE | MODE_DUOTONE if channels == 1 => DynamicImage::ImageLuma16(ImageBuffer::from_fn(width, height, |x, y| {
+            Luma([sample(0, index(x, y))])
+        })),
+        MODE_CMYK if channels >= 4 => DynamicImage::ImageRgb16(ImageBuffer::from_fn(width, height, |x, y| {
+            // samples are stored inverted (full intensity = no ink)
+            let i = index(x, y);
+            let key = sample(3, i) as u32;
+            Rgb([0, 1, 2].map(|c| (sample(c, i) as u32 * key / 65535) as u16))
+        })),
+        MODE_INDEXED if depth == 8 && channels >= 1 && color_mode_data.len() >= 768 => {
+            // 256 reds, greens and blues
+            let palette = |value: u8, c: usize| color_mode_data[c * 256 + value as usize];
+            DynamicImage::ImageRgb8(RgbImage::from_fn(width, height, |x, y| {
+                let value = planes[0][index(x, y)];
+                Rgb([palette(value, 0), palette(value, 1), palette(value, 2)])
+            }))
+        }
+        _ => return Err(Error::from_string(format!("unsupported psd color mode {} with {} channels", mode, channels))),
+    };
+    if depth == 16 {
+        return Ok(image);
+    }
+    // 8 bit documents stay 8 bit
+    Ok(match image {
+        DynamicImage::ImageRgba16(_) => DynamicImage::ImageRgba8(image.to_rgba8()),
+        DynamicImage::ImageRgb16(_) => DynamicImage::ImageRgb8(image.to_rgb8()),
+        DynamicImage::ImageLumaA16(_) => DynamicImage::ImageLumaA8(image.to_luma_alpha8()),
+        DynamicImage::ImageLuma16(_) => DynamicImage::ImageLuma8(image.to_luma8()),
+        image => image,
+    })
+}
+
+// the composite of transparent documents is blended onto white, which is removed again
+fn unmatte(value: u16, alpha: u16) -> u16 {
+    if alpha == 0 {
+        return 0;
+    }
+    let white = 65535 - alpha as i64;
+    ((value as i64 - white).max(0) * 65535 / alpha as i64).min(65535) as u16
+}
+
+// reads the planar channels of the composite, raw or PackBits compressed
+fn read_planes(reader: &mut Reader, channels: usize, width: usize, height: usize, bytes: usize, large: bool) -> Result<Vec<Vec<u8>>, Error> {
+    let row_len = width * bytes;
+    match reader.u16()? {
+        0 => (0..channels)
+            .map(|_| Ok(reader.take(row_len * height)?.to_vec()))
+            .collect(),
+        1 => {
+            let mut row_sizes = Vec::new();
+            for _ in 0..channels * height {
+                row_sizes.push(if large { reader.u32()? as usize } else { reader.u16()? as usize });
+            }
+            row_sizes.chunks(height)
+                .map(|sizes| {
+                    // not preallocated, the header dimensions are not checked against the data yet
+                    let mut plane = Vec::new();
+                    for &size in sizes {
+                        unpack_bits(reader.take(size)?, row_len, &mut plane)?;
+                    }
+                    Ok(plane)
+                })
+                .collect()
+        }
+        compression => Err(Error::from_string(format!("unsupported psd compression {}", compression))),
+    }
+}
+
+// PackBits: n < 128 copies n + 1 literal bytes, n > 128 repeats the next byte 257 - n times
+fn unpack_bits(mut packed: &[u8], row_len: usize, out: &mut Vec<u8>) -> Result<(), Error> {
+    let end = out.len() + row_len;
+    while out.len() < end {
+        let Some((&n, rest)) = packed.split_first() else {
+            return Err(Error::from_string("truncated psd row".to_string()));
+        };
+        match n {
+            0..=127 => {
+                let literal = rest.get(..n as usize + 1)
+                    .ok_or_else(|| Error::from_string("truncated psd row".to_string()))?;
+                out.extend_from_slice(literal);
+                packed = &rest[literal.len()..];
+            }
+            128 => packed = rest, // no-op
+            _ => {
+                let &value = rest.first()
+                    .ok_or_else(|| Error::from_string("truncated psd row".to_string()))?;
+                out.extend(std::iter::repeat_n(value, 257 - n as usize));
+                packed = &rest[1..];
+            }
+        }
+    }
+    // corrupt rows must not shift the following ones
+    out.truncate(end);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // document with a composite of `planes` (one per channel, samples of `depth` bits, big endian),
+    //  PackBits compressed with `rle` (runs for rows of one value, literals otherwise)
+    fn document(mode: u16, depth: u16, width: u32, height: u32, planes: &[&[u8]], color_mode_data: &[u8], rle: bool) -> Vec<u8> {
+        let mut data = MAGIC.to_vec();
+        data.extend(1u16.to_be_bytes());
+        data.extend([0; 6]);
+        data.extend((planes.len() as u16).to_be_bytes());
+        data.extend(height.to_be_bytes());
+        data.extend(width.to_be_bytes());
+        data.extend(depth.to_be_bytes());
+        data.extend(mode.to_be_bytes());
+        data.extend((color_mode_data.len() as u32).to_be_bytes());
+        data.extend(color_mode_data);
+        data.extend(0u32.to_be_bytes()); // image resources
+        data.extend(0u32.to_be_bytes()); // layers, masks
+        if !rle {
+            data.extend(0u16.to_be_bytes());
+            planes.iter().for_each(|plane| data.extend(*plane));
+            return data;
+        }
+        data.extend(1u16.to_be_bytes());
+        let row_len = planes[0].len() / height as usize;
+        let rows: Vec<Vec<u8>> = planes.iter().flat_map(|plane| plane.chunks(row_len)).map(|row| {
+            if row.len() > 1 && row.iter().all(|&value| value == row[0]) {
+                vec![(257 - row.len()) as u8, row[0]]
+            } else {
+                [&[(row.len() - 1) as u8], row].concat()
+            }
+        }).collect();
+        rows.iter().for_each(|row| data.extend((row.len() as u16).to_be_bytes()));
+        rows.iter().for_each(|row| data.extend(row));
+        data
+    }
+
+    #[test]
+    fn reads_raw_and_rle_rows() {
+        let planes: [&[u8]; 3] = [&[10, 20, 7, 7], &[30, 40, 8, 8], &[50, 60, 9, 9]];
+        for rle in [false, true] {
+            let image = read_psd_from_memory(&document(MODE_RGB, 8, 2, 2, &planes, &[], rle), None).unwrap();
+            let DynamicImage::ImageRgb8(image) = image else { panic!("expected 8 bit rgb") };
+            assert_eq!(image.as_raw(), &[10, 30, 50, 20, 40, 60, 7, 8, 9, 7, 8, 9]);
+        }
+    }
+
+    #[test]
+    fn reads_16_bit_rgb_and_alpha() {
+        let planes: [&[u8]; 3] = [&[0x12, 0x34], &[0xff, 0xff], &[0, 1]];
+        let image = read_psd_from_memory(&document(MODE_RGB, 16, 1, 1, &planes, &[], true), None).unwrap();
+        let DynamicImage::ImageRgb16(image) = image else { panic!("expected 16 bit rgb") };
+        assert_eq!(image.as_raw(), &[0x1234, 0xffff, 1]);
+        // the first extra channel is the transparency, transparent pixels lose the white matte
+        let planes: [&[u8]; 4] = [&[200, 255], &[100, 255], &[50, 255], &[255, 0]];
+        let image = read_psd_from_memory(&document(MODE_RGB, 8, 2, 1, &planes, &[], false), None).unwrap();
+        let DynamicImage::ImageRgba8(image) = image else { panic!("expected 8 bit rgba") };
+        assert_eq!(image.as_raw(), &[200, 100, 50, 255, 0, 0, 0, 0]);
+    }
+
+    #[test]
+    fn reads_gray() {
+        let image = read_psd_from_memory(&document(MODE_GRAYSCALE, 8, 2, 1, &[&[0, 128]], &[], false), None).unwrap();
+        let DynamicImage::ImageLuma8(image) = image else { panic!("expected 8 bit gray") };
+        assert_eq!(image.as_raw(), &[0, 128]);
+        let image = read_psd_from_memory(&document(MODE_GRAYSCALE, 16, 2, 1, &[&[0, 1, 0xab, 0xcd]], &[], true), None).unwrap();
+        let DynamicImage::ImageLuma16(image) = image else { panic!("expected 16 bit gray") };
+        assert_eq!(image.as_raw(), &[1, 0xabcd]);
+    }
+
+    #[test]
+    fn reads_cmyk() {
+        // inverted samples: 255 is no ink, a key of 0 is full black
+        let planes: [&[u8]; 4] = [&[255, 255], &[0, 255], &[255, 255], &[255, 0]];
+        for depth in [8, 16] {
+            let planes: Vec<Vec<u8>> = planes.iter()
+                .map(|plane| plane.iter().flat_map(|&value| vec![value; depth as usize / 8]).collect())
+                .collect();
+            let planes: Vec<&[u8]> = planes.iter().map(Vec::as_slice).collect();
+            let image = read_psd_from_memory(&document(MODE_CMYK, depth, 2, 1, &planes, &[], false), None).unwrap();
+            assert_eq!(image.to_rgb8().as_raw(), &[255, 0, 255, 0, 0, 0]);
+            assert_eq!(image.color().bytes_per_pixel(), 3 * depth as u8 / 8);
+        }
+    }
+
+    #[test]
+    fn reads_indexed() {
+        // 256 reds, greens and blues
+        let mut palette = vec![0; 768];
+        (palette[1], palette[256 + 1], palette[512 + 1]) = (200, 100, 50);
+        let image = read_psd_from_memory(&document(MODE_INDEXED, 8, 2, 1, &[&[1, 0]], &palette, true), None).unwrap();
+        let DynamicImage::ImageRgb8(image) = image else { panic!("expected 8 bit rgb") };
+        assert_eq!(image.as_raw(), &[200, 100, 50, 0, 0, 0]);
+        // the palette is required
+        assert!(read_psd_from_memory(&document(MODE_INDEXED, 8, 2, 1, &[&[1, 0]], &[], true), None).is_err());
+    }
+
+    #[test]
+    fn rejects_truncated_documents() {
+        let planes: [&[u8]; 3] = [&[10, 20, 7, 7], &[30, 40, 8, 8], &[50, 60, 9, 9]];
+        for rle in [false, true] {
+            let data = document(MODE_RGB, 8, 2, 2, &planes, &[], rle);
+            for len in [HEADER_LEN - 1, HEADER_LEN + 4, data.len() - 1] {
+                assert!(read_psd_from_memory(&data[..len], None).is_err(), "{} of {} bytes, rle {}", len, data.len(), rle);
+            }
+        }
+        // a row holding fewer samples than the width
+        let mut data = document(MODE_GRAYSCALE, 8, 2, 1, &[&[5, 6]], &[], true);
+        let len = data.len();
+        (data[len - 4], data[len - 3]) = (2, 0);
+        data.truncate(len - 1);
+        assert!(read_psd_from_memory(&data, None).is_err());
+    }
+
+    #[test]
+    fn checks_the_pixel_limit_first() {
+        let data = document(MODE_GRAYSCALE, 8, 2, 2, &[&[1, 2, 3, 4]], &[], false);
+        assert_eq!(psd_dimensions(&data), Some((2, 2)));
+        assert!(read_psd_from_memory(&data, Some(4)).is_ok());
+        assert!(read_psd_from_memory(&data, Some(3)).is_err());
+    }
+}
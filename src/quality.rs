@@ -0,0 +1,89 @@
+use crate::format::ImageFormat;
+use std::{fmt, str::FromStr};
+
+// quality settings of (about) equal visual quality: jpeg (the scale of `VisualQuality`), webp, avif/heic,
+//  interpolated linearly in between
+const ANCHORS: &[[f32; 3]] = &[
+    [0., 0., 0.],
+    [60., 60., 50.],
+    [75., 78., 64.],
+    [88., 90., 78.],
+    [100., 100., 100.],
+];
+
+/// Encoder independent quality scale, mapped onto the quality (and avif speed) settings of each lossy encoder
+#[derive(serde::Serialize, serde::Deserialize, Clone, Copy, PartialEq, Eq, Debug)]
+#[serde(rename_all = "kebab-case")]
+pub enum VisualQuality {
+    /// Visible artifacts, smallest files (thumbnails, previews).
+    Low,
+    /// Artifacts only on close inspection (general web images).
+    Medium,
+    /// No visible artifacts (photography, hero images).
+    High,
+    /// Level 0 - 100 on the scale of (moz)jpeg quality, `low`, `medium` and `high` are 60, 75 and 88.
+    Level(u8),
+}
+
+impl VisualQuality {
+    /// Level on the (moz)jpeg quality scale
+    pub fn level(self) -> u8 {
+        match self {
+            VisualQuality::Low => 60,
+            VisualQuality::Medium => 75,
+            VisualQuality::High => 88,
+            VisualQuality::Level(level) => level.min(100),
+        }
+    }
+
+    /// Quality setting of `img_format` (webp, avif, heic, jpeg) for this visual quality, `None` for other formats
+    pub fn quality_for(self, img_format: &ImageFormat) -> Option<f32> {
+        let column = match img_format {
+            ImageFormat::Jpeg => 0,
+            ImageFormat::Webp => 1,
+            ImageFormat::Avif | ImageFormat::Heif => 2,
+            _ => return None,
+        };
+        let level = self.level() as f32;
+        let upper = ANCHORS.iter().position(|anchor| anchor[0] >= level).unwrap_or(ANCHORS.len() - 1).max(1);
+        let (low, high) = (ANCHORS[upper - 1], ANCHORS[upper]);
+        let t = (level - low[0]) / (high[0] - low[0]);
+        Some((low[column] + t * (high[column] - low[column])).round())
+    }
+
+    /// Avif encoding speed for this visual quality, higher qualities spend more effort
+    pub fn avif_speed(self) -> u8 {
+        match self.level() {
+            0..=67 => 8,
+            68..=81 => 6,
+            _ => 4,
+        }
+    }
+}
+
+impl FromStr for VisualQuality {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "low" => Ok(VisualQuality::Low),
+            "medium" => Ok(VisualQuality::Medium),
+            "high" => Ok(VisualQuality::High),
+            level => match level.parse::<u8>() {
+                Ok(level) if level <= 100 => Ok(VisualQuality::Level(level)),
+                _ => Err(format!("expected low, medium, high or a level from 0 to 100, got `{}`", s)),
+            },
+        }
+    }
+}
+
+impl fmt::Display for VisualQuality {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            VisualQuality::Low => write!(f, "low"),
+            VisualQuality::Medium => write!(f, "medium"),
+            VisualQuality::High => write!(f, "high"),
+            VisualQuality::Level(level) => write!(f, "{}", level),
+        }
+    }
+}
@@ -0,0 +1,36 @@
+use std::{ffi::OsString, fs, io, path::{Path, PathBuf}};
+
+/// How undecodable inputs get into the quarantine directory
+#[cfg_attr(feature = "cli", derive(clap::ValueEnum))]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum QuarantineMode {
+    /// Copy the input, leaving it in place.
+    #[default]
+    Copy,
+    /// Move the input out of the input directory.
+    Move,
+}
+
+/// Puts the undecodable `input` into `directory` at its `relative` path, next to a `.error.txt` note of `error`
+///
+/// Returns the quarantined path. Existing files in the quarantine directory are overwritten.
+pub fn quarantine_input(input: &Path, relative: &Path, directory: &Path, mode: QuarantineMode, error: &str)
+    -> io::Result<PathBuf> {
+    let target = directory.join(relative);
+    fs::create_dir_all(target.parent().unwrap_or(directory))?;
+    match mode {
+        QuarantineMode::Copy => {
+            fs::copy(input, &target)?;
+        }
+        // renaming fails across filesystems
+        QuarantineMode::Move => if fs::rename(input, &target).is_err() {
+            fs::copy(input, &target)?;
+            fs::remove_file(input)?;
+        },
+    }
+    let mut note_name = OsString::from(target.file_name().unwrap_or_default());
+    note_name.push(".error.txt");
+    fs::write(target.with_file_name(note_name), format!(
+        "input: {}\nerror: {}\nimgc: {}\n", input.display(), error, env!("CARGO_PKG_VERSION")))?;
+    Ok(target)
+}
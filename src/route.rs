@@ -0,0 +1,128 @@
+use crate::format::{ImageFormat, OutputFormat};
+use image::{DynamicImage, GenericImageView};
+use std::{collections::HashSet, str::FromStr};
+
+// pixels looked at by the classification, larger images are sampled on a grid
+const SAMPLED_PIXELS: u32 = 256 * 256;
+// more distinct colors than a palette holds
+const PALETTE_COLORS: usize = 256;
+// share of neighbouring pixels with the same color above which an image counts as a graphic
+const FLAT_SHARE: f32 = 0.5;
+
+/// Content traits of an image, cheap to compute from a sample of its pixels
+#[derive(serde::Serialize, serde::Deserialize, Clone, Debug, PartialEq)]
+pub struct ImageTraits {
+    /// Channels and bit depth of the decoded image, e.g. `rgba8` or `l16`.
+    pub color_type: String,
+    /// Some pixels are not fully opaque.
+    pub has_alpha: bool,
+    /// Distinct colors of the sampled pixels, capped at one more than a palette holds (257).
+    pub colors: usize,
+    /// Share of sampled pixels with the same color as their right neighbour (0 - 1).
+    pub flat_share: f32,
+}
+
+impl ImageTraits {
+    /// Classifies `image` by its alpha channel, colors and flat areas
+    pub fn of(image: &DynamicImage) -> Self {
+        let (width, height) = image.dimensions();
+        // a grid of at most SAMPLED_PIXELS, keeping horizontal neighbours next to each other
+        let step = ((width as u64 * height as u64 / SAMPLED_PIXELS as u64) as f64).sqrt().max(1.) as u32;
+        let has_alpha = image.color().has_alpha();
+        let mut colors = HashSet::new();
+        let (mut pairs, mut flat) = (0usize, 0usize);
+        let mut transparent = false;
+        for y in (0..height).step_by(step as usize) {
+            for x in (0..width).step_by(step as usize) {
+                let pixel = image.get_pixel(x, y).0;
+                transparent |= has_alpha && pixel[3] < u8::MAX;
+                if colors.len() <= PALETTE_COLORS {
+                    colors.insert(pixel);
+                }
+                if x + 1 < width {
+                    pairs += 1;
+                    flat += (image.get_pixel(x + 1, y).0 == pixel) as usize;
+                }
+            }
+        }
+        ImageTraits {
+            color_type: format!("{:?}", image.color()).to_ascii_lowercase(),
+            has_alpha: transparent,
+            colors: colors.len(),
+            flat_share: if pairs > 0 { flat as f32 / pairs as f32 } else { 1. },
+        }
+    }
+
+    /// Few colors or large flat areas (logos, icons, screenshots)
+    pub fn is_graphic(&self) -> bool {
+        self.colors <= PALETTE_COLORS || self.flat_share >= FLAT_SHARE
+    }
+}
+
+/// Class of images a route applies to
+#[derive(serde::Serialize, serde::Deserialize, Clone, Copy, PartialEq, Eq, Debug)]
+#[serde(rename_all = "kebab-case")]
+pub enum ImageClass {
+    /// Images with transparent pixels.
+    HasAlpha,
+    /// Images without transparent pixels.
+    Opaque,
+    /// Many colors without large flat areas.
+    Photo,
+    /// Few colors or large flat areas.
+    Graphic,
+}
+
+impl ImageClass {
+    /// Whether an image of `traits` belongs to the class
+    pub fn matches(&self, traits: &ImageTraits) -> bool {
+        match self {
+            ImageClass::HasAlpha => traits.has_alpha,
+            ImageClass::Opaque => !traits.has_alpha,
+            ImageClass::Photo => !traits.is_graphic(),
+            ImageClass::Graphic => traits.is_graphic(),
+        }
+    }
+}
+
+/// Output format of a class of images, written as `CLASS=>FORMAT`, e.g. `has-alpha=>png` or `photo=>avif`
+#[derive(serde::Serialize, serde::Deserialize, Clone, Copy, PartialEq, Eq, Debug)]
+pub struct RouteRule {
+    /// Images the rule applies to.
+    pub class: ImageClass,
+    /// Format of their outputs.
+    pub format: OutputFormat,
+}
+
+impl FromStr for RouteRule {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (class, format) = s.split_once("=>")
+            .ok_or_else(|| format!("expected CLASS=>FORMAT (e.g. `photo=>avif`), got `{}`", s))?;
+        let class = match class.trim() {
+            "has-alpha" | "alpha" => ImageClass::HasAlpha,
+            "opaque" => ImageClass::Opaque,
+            "photo" => ImageClass::Photo,
+            "graphic" => ImageClass::Graphic,
+            class => return Err(format!("unknown image class `{}`, expected has-alpha, opaque, photo or graphic", class)),
+        };
+        let format = match format.trim() {
+            "webp" => OutputFormat::Webp,
+            "webp-lossless" | "webp-image" => OutputFormat::WebpImage,
+            "avif" => OutputFormat::Avif,
+            "png" => OutputFormat::Png,
+            "jpeg" | "jpg" => OutputFormat::Jpeg,
+            "pnm" => OutputFormat::Pnm,
+            "farbfeld" => OutputFormat::Farbfeld,
+            format => return Err(format!(
+                "unknown format `{}`, expected webp, webp-lossless, avif, png, jpeg, pnm or farbfeld", format)),
+        };
+        Ok(RouteRule { class, format })
+    }
+}
+
+/// Format of the first rule matching an image of `traits`, None if no rule matches (the target format of the run applies)
+pub fn route_format(rules: &[RouteRule], traits: &ImageTraits) -> Option<ImageFormat> {
+    rules.iter().find(|rule| rule.class.matches(traits)).map(|rule| rule.format.into())
+}
@@ -0,0 +1,301 @@
+use crate::{output, Error};
+#[cfg(not(windows))]
+use std::fs;
+use std::{
+    env,
+    path::{Path, PathBuf},
+};
+
+/// Context menu entries: label and the subcommand run on the selected image with its default settings
+const PRESETS: &[(&str, &str)] = &[
+    ("Convert to WebP", "webp"),
+    ("Convert to AVIF", "avif"),
+];
+
+// formats offered the entries, the ones decoded without optional features
+#[cfg(not(any(windows, target_os = "macos")))]
+const MIME_TYPES: &[&str] = &[
+    "image/png", "image/jpeg", "image/gif", "image/webp", "image/bmp", "image/tiff", "image/x-tga", "image/x-icon",
+    "image/x-portable-anymap", "image/vnd.radiance", "image/x-exr", "image/qoi", "image/vnd.adobe.photoshop",
+];
+
+// the running binary, so that the entries keep working without imgc on the PATH of the file manager
+fn imgc_binary() -> Result<PathBuf, Error> {
+    Ok(env::current_exe()?)
+}
+
+#[cfg(not(windows))]
+fn home_dir() -> Result<PathBuf, Error> {
+    env::var_os("HOME")
+        .map(PathBuf::from)
+        .ok_or_else(|| Error::from_string("the home directory is not set".to_string()))
+}
+
+// $XDG_DATA_HOME, ~/.local/share by default
+#[cfg(not(any(windows, target_os = "macos")))]
+fn data_home() -> Result<PathBuf, Error> {
+    match env::var_os("XDG_DATA_HOME").filter(|dir| !dir.is_empty()) {
+        Some(dir) => Ok(PathBuf::from(dir)),
+        None => Ok(home_dir()?.join(".local/share")),
+    }
+}
+
+// files written by `install_shell_integration`, removed again by `uninstall_shell_integration`
+#[cfg(not(any(windows, target_os = "macos")))]
+fn installed_files() -> Result<Vec<PathBuf>, Error> {
+    let data_home = data_home()?;
+    let mut files = vec![data_home.join("kio/servicemenus/imgc.desktop")];
+    files.extend(PRESETS.iter().map(|(label, _)| data_home.join("nautilus/scripts").join(label)));
+    Ok(files)
+}
+
+#[cfg(target_os = "macos")]
+fn installed_files() -> Result<Vec<PathBuf>, Error> {
+    let services = home_dir()?.join("Library/Services");
+    Ok(PRESETS.iter().map(|(label, _)| services.join(format!("{} (imgc).workflow", label))).collect())
+}
+
+#[cfg(windows)]
+const REGISTRY_BASE: &str = r"HKCU\Software\Classes\SystemFileAssociations\image\shell";
+
+#[cfg(windows)]
+fn reg(args: &[&str]) -> Result<(), Error> {
+    let output = std::process::Command::new("reg").args(args).output()?;
+    if !output.status.success() {
+        return Err(Error::from_string(format!("reg {} failed: {}", args[0], String::from_utf8_lossy(&output.stderr).trim())));
+    }
+    Ok(())
+}
+
+#[cfg(windows)]
+fn install(binary: &Path) -> Result<Vec<String>, Error> {
+    let mut installed = Vec::new();
+    for (label, command) in PRESETS {
+        let key = format!(r"{}\imgc.{}", REGISTRY_BASE, command);
+        reg(&["add", &key, "/ve", "/d", label, "/f"])?;
+        reg(&["add", &key, "/v", "Icon", "/d", &binary.display().to_string(), "/f"])?;
+        let command_line = format!("\"{}\" \"%1\" {}", binary.display(), command);
+        reg(&["add", &format!(r"{}\command", key), "/ve", "/d", &command_line, "/f"])?;
+        installed.push(key);
+    }
+    Ok(installed)
+}
+
+#[cfg(windows)]
+fn uninstall() -> Result<Vec<String>, Error> {
+    let mut removed = Vec::new();
+    for (_, command) in PRESETS {
+        let key = format!(r"{}\imgc.{}", REGISTRY_BASE, command);
+        // missing keys were never installed
+        if reg(&["delete", &key, "/f"]).is_ok() {
+            removed.push(key);
+        }
+    }
+    Ok(removed)
+}
+
+// KDE (Dolphin) service menu with one action per preset, and a Nautilus (GNOME Files) script per preset
+#[cfg(not(any(windows, target_os = "macos")))]
+fn install(binary: &Path) -> Result<Vec<String>, Error> {
+    let files = installed_files()?;
+    let actions: Vec<String> = PRESETS.iter().map(|(_, command)| format!("imgc-{}", command)).collect();
+    let mut service_menu = format!(
+        "[Desktop Entry]\nType=Service\nMimeType={};\nActions={};\nX-KDE-Submenu=imgc\n",
+        MIME_TYPES.join(";"), actions.join(";"));
+    for ((label, command), action) in PRESETS.iter().zip(&actions) {
+        service_menu.push_str(&format!(
+            "\n[Desktop Action {}]\nName={}\nIcon=image-x-generic\nExec=\"{}\" %f {}\n", action, label, binary.display(), command));
+    }
+    let mut contents = vec![service_menu];
+    contents.extend(PRESETS.iter().map(|(_, command)| format!(
+        "#!/bin/sh\n# installed by imgc install-shell-integration\nset -f\nIFS='\n'\nfor file in $NAUTILUS_SCRIPT_SELECTED_FILE_PATHS; do\n  \"{}\" \"$file\" {}\ndone\n",
+        binary.display(), command)));
+    for (file, content) in files.iter().zip(contents) {
+        fs::create_dir_all(file.parent().unwrap_or_else(|| Path::new("")))?;
+        fs::write(file, content)?;
+        // service menus are only trusted if executable (KDE Plasma 5.24+), scripts have to be
+        #[cfg(unix)]
+        fs::set_permissions(file, std::os::unix::fs::PermissionsExt::from_mode(0o755))?;
+    }
+    Ok(files.iter().map(|file| file.display().to_string()).collect())
+}
+
+// Finder quick actions: Automator workflows running the binary on the selected files
+#[cfg(target_os = "macos")]
+fn install(binary: &Path) -> Result<Vec<String>, Error> {
+    let files = installed_files()?;
+    for ((label, command), workflow) in PRESETS.iter().zip(&files) {
+        let contents = workflow.join("Contents");
+        fs::create_dir_all(&contents)?;
+        fs::write(contents.join("Info.plist"), SERVICE_INFO_PLIST.replace("{label}", &xml_escape(label)))?;
+        let script = format!("for f in \"$@\"; do \"{}\" \"$f\" {}; done", binary.display(), command);
+        fs::write(contents.join("document.wflow"), WORKFLOW_DOCUMENT.replace("{script}", &xml_escape(&script)))?;
+    }
+    Ok(files.iter().map(|file| file.display().to_string()).collect())
+}
+
+// service offered by Finder for images
+#[cfg(target_os = "macos")]
+const SERVICE_INFO_PLIST: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<!DOCTYPE plist PUBLIC "-//Apple//DTD PLIST 1.0//EN" "http://www.apple.com/DTDs/PropertyList-1.0.dtd">
+<plist version="1.0">
+<dict>
+	<key>NSServices</key>
+	<array>
+		<dict>
+			<key>NSMenuItem</key>
+			<dict>
+				<key>default</key>
+				<string>{label}</string>
+			</dict>
+			<key>NSMessage</key>
+			<string>runWorkflowAsService</string>
+			<key>NSRequiredContext</key>
+			<dict>
+				<key>NSApplicationIdentifier</key>
+				<string>com.apple.finder</string>
+			</dict>
+			<key>NSSendFileTypes</key>
+			<array>
+				<string>public.image</string>
+			</array>
+		</dict>
+	</array>
+</dict>
+</plist>
+"#;
+
+// quick action with a single "Run Shell Script" action, getting the selected files as arguments
+#[cfg(target_os = "macos")]
+const WORKFLOW_DOCUMENT: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<!DOCTYPE plist PUBLIC "-//Apple//DTD PLIST 1.0//EN" "http://www.apple.com/DTDs/PropertyList-1.0.dtd">
+<plist version="1.0">
+<dict>
+	<key>AMApplicationBuild</key>
+	<string>523</string>
+	<key>AMApplicationVersion</key>
+	<string>2.10</string>
+	<key>AMDocumentVersion</key>
+	<string>2</string>
+	<key>actions</key>
+	<array>
+		<dict>
+			<key>action</key>
+			<dict>
+				<key>AMAccepts</key>
+				<dict>
+					<key>Container</key>
+					<string>List</string>
+					<key>Optional</key>
+					<true/>
+					<key>Types</key>
+					<array>
+						<string>com.apple.cocoa.path</string>
+					</array>
+				</dict>
+				<key>AMActionVersion</key>
+				<string>2.0.3</string>
+				<key>AMApplication</key>
+				<array>
+					<string>Automator</string>
+				</array>
+				<key>AMProvides</key>
+				<dict>
+					<key>Container</key>
+					<string>List</string>
+					<key>Types</key>
+					<array>
+						<string>com.apple.cocoa.string</string>
+					</array>
+				</dict>
+				<key>ActionBundlePath</key>
+				<string>/System/Library/Automator/Run Shell Script.action</string>
+				<key>ActionName</key>
+				<string>Run Shell Script</string>
+				<key>ActionParameters</key>
+				<dict>
+					<key>COMMAND_STRING</key>
+					<string>{script}</string>
+					<key>CheckedForUserDefaultShell</key>
+					<true/>
+					<key>inputMethod</key>
+					<integer>1</integer>
+					<key>shell</key>
+					<string>/bin/sh</string>
+					<key>source</key>
+					<string></string>
+				</dict>
+				<key>BundleIdentifier</key>
+				<string>com.apple.RunShellScript</string>
+				<key>CFBundleVersion</key>
+				<string>2.0.3</string>
+				<key>Class Name</key>
+				<string>RunShellScriptAction</string>
+			</dict>
+		</dict>
+	</array>
+	<key>connectors</key>
+	<dict/>
+	<key>workflowMetaData</key>
+	<dict>
+		<key>serviceApplicationBundleID</key>
+		<string>com.apple.finder</string>
+		<key>serviceInputTypeIdentifier</key>
+		<string>com.apple.Automator.fileSystemObject</string>
+		<key>serviceOutputTypeIdentifier</key>
+		<string>com.apple.Automator.nothing</string>
+		<key>workflowTypeIdentifier</key>
+		<string>com.apple.Automator.servicesMenu</string>
+	</dict>
+</dict>
+</plist>
+"#;
+
+#[cfg(target_os = "macos")]
+fn xml_escape(text: &str) -> String {
+    text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;").replace('"', "&quot;")
+}
+
+#[cfg(not(windows))]
+fn uninstall() -> Result<Vec<String>, Error> {
+    let mut removed = Vec::new();
+    for file in installed_files()? {
+        if file.is_dir() {
+            fs::remove_dir_all(&file)?;
+        } else if file.exists() {
+            fs::remove_file(&file)?;
+        } else {
+            continue;
+        }
+        removed.push(file.display().to_string());
+    }
+    Ok(removed)
+}
+
+/// Registers "Convert to WebP/AVIF" context menu entries for images in the file manager of the current user
+/// (Windows Explorer registry keys, KDE service menu and Nautilus scripts, macOS Finder quick actions),
+/// running this binary on the selected files with the default settings of the format.
+///
+/// Installing again updates the entries, e.g. after moving the binary.
+pub fn install_shell_integration() -> Result<(), Error> {
+    let binary = imgc_binary()?;
+    let glyphs = output::glyphs();
+    for entry in install(&binary)? {
+        println!("{} {}", glyphs.ok, entry);
+    }
+    println!("Context menu entries run {}, remove them with `imgc uninstall-shell-integration`", binary.display());
+    Ok(())
+}
+
+/// Removes the context menu entries of [`install_shell_integration`]
+pub fn uninstall_shell_integration() -> Result<(), Error> {
+    let removed = uninstall()?;
+    let glyphs = output::glyphs();
+    for entry in &removed {
+        println!("{} removed {}", glyphs.ok, entry);
+    }
+    if removed.is_empty() {
+        println!("No context menu entries of imgc installed");
+    }
+    Ok(())
+}
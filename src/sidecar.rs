@@ -0,0 +1,118 @@
+use crate::{overrides::InputOverride, Error};
+use image::{DynamicImage, GenericImageView};
+use std::{borrow::Cow, fmt, fs, io::ErrorKind, path::{Path, PathBuf}, str::FromStr};
+
+/// Extension appended to the input filename (`hero.jpg.imgc`)
+pub const SIDECAR_EXTENSION: &str = "imgc";
+
+/// Region of an image, written like imagemagick geometry: `WIDTHxHEIGHT+X+Y`
+#[derive(serde::Serialize, serde::Deserialize, Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Crop {
+    /// Left edge of the region.
+    pub x: u32,
+    /// Top edge of the region.
+    pub y: u32,
+    /// Width of the region.
+    pub width: u32,
+    /// Height of the region.
+    pub height: u32,
+}
+
+impl Crop {
+    /// Cuts the region out of `image`, failing if it does not fit into the image
+    pub fn apply<'a>(&self, image: &'a DynamicImage) -> Result<Cow<'a, DynamicImage>, Error> {
+        let (width, height) = image.dimensions();
+        let fits = self.x.checked_add(self.width).is_some_and(|right| right <= width)
+            && self.y.checked_add(self.height).is_some_and(|bottom| bottom <= height);
+        if !fits {
+            return Err(Error::from_string(format!("crop {} is outside of the {}x{} image", self, width, height)));
+        }
+        if (self.x, self.y, self.width, self.height) == (0, 0, width, height) {
+            return Ok(Cow::Borrowed(image));
+        }
+        Ok(Cow::Owned(image.crop_imm(self.x, self.y, self.width, self.height)))
+    }
+}
+
+impl FromStr for Crop {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || format!("expected WIDTHxHEIGHT+X+Y (e.g. `1200x630+0+40`), got `{}`", s);
+        let (size, offset) = s.split_once('+').unwrap_or((s, "0+0"));
+        let (width, height) = size.split_once('x').ok_or_else(invalid)?;
+        let (x, y) = offset.split_once('+').ok_or_else(invalid)?;
+        let number = |value: &str| value.trim().parse::<u32>().map_err(|_| invalid());
+        let crop = Crop { x: number(x)?, y: number(y)?, width: number(width)?, height: number(height)? };
+        if crop.width == 0 || crop.height == 0 {
+            return Err(invalid());
+        }
+        Ok(crop)
+    }
+}
+
+impl fmt::Display for Crop {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}x{}+{}+{}", self.width, self.height, self.x, self.y)
+    }
+}
+
+/// Per-image settings of a sidecar file next to the input
+///
+/// Sidecar files contain one `key = value` per line (`#` starts a comment), e.g.
+/// ```text
+/// # hero image, keep it sharp
+/// quality = 95
+/// crop = 1200x630+0+40
+/// ```
+/// Keys are `skip`, `crop` and the encoder settings of `--when` (quality, visual-quality, speed, lossless, alpha-quality),
+/// a key without a value sets it to true.
+#[derive(Clone, PartialEq, Debug, Default)]
+pub struct Sidecar {
+    /// Leave the image out of the run.
+    pub skip: bool,
+    /// Region of the image to convert.
+    pub crop: Option<Crop>,
+    /// Encoder settings, replacing the ones of the run (and of `--when`).
+    pub settings: InputOverride,
+}
+
+/// Path of the sidecar file of `input_path`
+pub fn sidecar_path(input_path: &Path) -> PathBuf {
+    let mut path = input_path.as_os_str().to_owned();
+    path.push(".");
+    path.push(SIDECAR_EXTENSION);
+    PathBuf::from(path)
+}
+
+/// Reads the sidecar file of `input_path`, `None` if there is none
+pub fn read_sidecar(input_path: &Path) -> Result<Option<Sidecar>, Error> {
+    let path = sidecar_path(input_path);
+    let text = match fs::read_to_string(&path) {
+        Ok(text) => text,
+        Err(err) if err.kind() == ErrorKind::NotFound => return Ok(None),
+        Err(err) => return Err(err.into()),
+    };
+    parse_sidecar(&text)
+        .map(Some)
+        .map_err(|err| Error::from_string(format!("invalid sidecar file {}: {}", path.display(), err)))
+}
+
+fn parse_sidecar(text: &str) -> Result<Sidecar, String> {
+    let mut sidecar = Sidecar::default();
+    for (number, line) in text.lines().enumerate() {
+        let line = line.split_once('#').map_or(line, |(setting, _comment)| setting).trim();
+        if line.is_empty() {
+            continue;
+        }
+        let (key, value) = line.split_once('=').unwrap_or((line, "true"));
+        let (key, value) = (key.trim(), value.trim());
+        match key {
+            "skip" => sidecar.skip = value.parse()
+                .map_err(|_| format!("line {}: skip has to be true or false, got `{}`", number + 1, value))?,
+            "crop" => sidecar.crop = Some(value.parse().map_err(|err| format!("line {}: {}", number + 1, err))?),
+            key => sidecar.settings.set(key, value).map_err(|err| format!("line {}: {}", number + 1, err))?,
+        }
+    }
+    Ok(sidecar)
+}
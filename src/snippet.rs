@@ -0,0 +1,110 @@
+use crate::{format::ImageFormat, Error};
+use std::{fs, path::{Path, PathBuf}};
+
+/// Kind of the snippet written next to each output
+#[cfg_attr(feature = "cli", derive(clap::ValueEnum))]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SnippetFormat {
+    /// `<picture>` element with a `<source>` per variant and an `<img>` fallback (`hero.picture.html`).
+    Html,
+    /// Json object with the input, its dimensions and the variants (`hero.picture.json`), e.g. for static site generators.
+    Json,
+}
+
+// formats browsers can show, most efficient first (order of the `<source>` elements)
+const WEB_FORMATS: [ImageFormat; 5] = [ImageFormat::Avif, ImageFormat::Webp, ImageFormat::Jpeg, ImageFormat::Png, ImageFormat::Gif];
+
+/// Output of an input in one format, found next to the output of the current run
+#[derive(serde::Serialize, Debug)]
+pub struct Variant {
+    /// Filename, relative to the snippet.
+    pub src: String,
+    /// Mime type.
+    #[serde(rename = "type")]
+    pub mime_type: &'static str,
+}
+
+#[derive(serde::Serialize)]
+struct PictureSnippet<'a> {
+    input: String,
+    width: u32,
+    height: u32,
+    variants: &'a [Variant],
+}
+
+/// Variants of `output_path` (plain name) in its directory: the same name in other web formats, with or without content hash
+///
+/// Variants written by earlier runs into other formats are included, all of them are assumed to have the same dimensions.
+pub fn find_variants(output_path: &Path) -> Result<Vec<Variant>, Error> {
+    let directory = output_path.parent().filter(|dir| !dir.as_os_str().is_empty()).unwrap_or_else(|| Path::new("."));
+    let Some(stem) = output_path.file_stem().and_then(|stem| stem.to_str()) else {
+        return Ok(Vec::new());
+    };
+    let mut variants = Vec::new();
+    for entry in fs::read_dir(directory)? {
+        let name = entry?.file_name().to_string_lossy().to_string();
+        let Some(rest) = name.strip_prefix(stem).and_then(|rest| rest.strip_prefix('.')) else {
+            continue;
+        };
+        // `webp` or `3fa2b1c9.webp`
+        let (hash, ext) = rest.rsplit_once('.').unwrap_or(("", rest));
+        if !hash.chars().all(|c| c.is_ascii_hexdigit()) {
+            continue;
+        }
+        let format = ImageFormat::from_extension(ext);
+        if let Some(rank) = WEB_FORMATS.iter().position(|web_format| *web_format == format) {
+            variants.push((rank, Variant { src: name, mime_type: mime_type(&format) }));
+        }
+    }
+    variants.sort_by(|(a_rank, a), (b_rank, b)| a_rank.cmp(b_rank).then_with(|| a.src.cmp(&b.src)));
+    Ok(variants.into_iter().map(|(_, variant)| variant).collect())
+}
+
+fn mime_type(format: &ImageFormat) -> &'static str {
+    match format {
+        ImageFormat::Avif => "image/avif",
+        ImageFormat::Webp => "image/webp",
+        ImageFormat::Jpeg => "image/jpeg",
+        ImageFormat::Png => "image/png",
+        ImageFormat::Gif => "image/gif",
+        _ => "application/octet-stream",
+    }
+}
+
+/// Writes the snippet of `input` next to its `output_path` (plain name), listing all its variants
+pub fn write_snippet(format: SnippetFormat, input: &Path, output_path: &Path, width: u32, height: u32) -> Result<PathBuf, Error> {
+    let variants = find_variants(output_path)?;
+    let (snippet_path, text) = match format {
+        SnippetFormat::Html => (output_path.with_extension("picture.html"), picture_html(&variants, width, height)),
+        SnippetFormat::Json => (output_path.with_extension("picture.json"), serde_json::to_string_pretty(&PictureSnippet {
+            input: input.display().to_string(),
+            width,
+            height,
+            variants: &variants,
+        })?),
+    };
+    fs::write(&snippet_path, text)?;
+    Ok(snippet_path)
+}
+
+// the last jpeg, png or gif variant is the `<img>` fallback, all others are sources
+fn picture_html(variants: &[Variant], width: u32, height: u32) -> String {
+    let fallback = variants.iter()
+        .rposition(|variant| matches!(variant.mime_type, "image/jpeg" | "image/png" | "image/gif"))
+        .unwrap_or(variants.len().saturating_sub(1));
+    let mut html = String::from("<picture>\n");
+    for variant in variants.iter().enumerate().filter(|(i, _)| *i != fallback).map(|(_, variant)| variant) {
+        html.push_str(&format!("  <source type=\"{}\" srcset=\"{}\">\n", variant.mime_type, url_attribute(&variant.src)));
+    }
+    if let Some(variant) = variants.get(fallback) {
+        html.push_str(&format!("  <img src=\"{}\" width=\"{}\" height=\"{}\" alt=\"\">\n", url_attribute(&variant.src), width, height));
+    }
+    html.push_str("</picture>\n");
+    html
+}
+
+// spaces and commas separate the candidates of `srcset`
+fn url_attribute(name: &str) -> String {
+    name.replace('%', "%25").replace(' ', "%20").replace(',', "%2C")
+        .replace('&', "&amp;").replace('"', "&quot;").replace('<', "&lt;")
+}
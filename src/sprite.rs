@@ -0,0 +1,261 @@
+use crate::{
+    converter::{base_from_pattern, encode_image, EncoderConfig},
+    decode::read_image,
+    format::ImageFormat,
+    output, Error,
+};
+use image::{DynamicImage, RgbaImage};
+use rayon::prelude::*;
+use serde::Serialize;
+use std::{fs, path::{Path, PathBuf}};
+
+/// Settings of `imgc sprite`
+#[derive(Clone, Debug)]
+pub struct SpriteConfig {
+    /// Glob pattern of the images to pack.
+    pub pattern: String,
+    /// Directory of the sheets and the atlas, defaults to the fixed base of the pattern if empty.
+    pub output: String,
+    /// Name of the atlas (`<name>.json`) and the sheets (`<name>.0001.png`, ...).
+    pub name: String,
+    /// Maximum width and height of a sheet.
+    pub max_size: (u32, u32),
+    /// Empty pixels between the sprites.
+    pub padding: u32,
+    /// Cut fully transparent borders off the images, recording their offsets in the atlas.
+    pub trim: bool,
+    /// Repeat the edge pixels of each sprite this many times around it (inside the padding).
+    pub extrude: u32,
+}
+
+/// Coordinates of a sprite in its sheet
+#[derive(Serialize, Debug)]
+pub struct AtlasSprite {
+    /// Path of the input, relative to the fixed base of the pattern.
+    pub name: String,
+    /// Left edge in the sheet.
+    pub x: u32,
+    /// Top edge in the sheet.
+    pub y: u32,
+    /// Width of the sprite.
+    pub width: u32,
+    /// Height of the sprite.
+    pub height: u32,
+    /// Where the sprite was cut out of the input (`--trim`).
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub trim: Option<SpriteTrim>,
+}
+
+/// Bounds of a trimmed sprite in its input, to reconstruct the original bounds
+/// (e.g. for nine-slice borders or pivots defined on the untrimmed image)
+#[derive(Serialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub struct SpriteTrim {
+    /// Left edge of the sprite in the input.
+    pub offset_x: u32,
+    /// Top edge of the sprite in the input.
+    pub offset_y: u32,
+    /// Width of the input.
+    pub source_width: u32,
+    /// Height of the input.
+    pub source_height: u32,
+}
+
+/// Sheet of an atlas
+#[derive(Serialize, Debug)]
+pub struct AtlasSheet {
+    /// Filename of the sheet, relative to the atlas.
+    pub file: String,
+    /// Width of the sheet.
+    pub width: u32,
+    /// Height of the sheet.
+    pub height: u32,
+    /// Sprites packed into the sheet, in packing order.
+    pub sprites: Vec<AtlasSprite>,
+}
+
+/// Json atlas of the sprite sheets written by [`pack_sprites`]
+#[derive(Serialize, Debug)]
+pub struct Atlas {
+    /// Version of imgc that packed the sheets.
+    pub imgc_version: String,
+    /// Packed sheets.
+    pub sheets: Vec<AtlasSheet>,
+}
+
+// rows of sprites of similar height
+struct Shelf {
+    y: u32,
+    height: u32,
+    used_width: u32,
+}
+
+#[derive(Default)]
+struct Sheet {
+    shelves: Vec<Shelf>,
+    used_height: u32,
+    // (input index, x, y)
+    placements: Vec<(usize, u32, u32)>,
+}
+
+impl Sheet {
+    // first shelf with room for the sprite, opening a new one below the last if needed
+    fn place(&mut self, (width, height): (u32, u32), (max_width, max_height): (u32, u32), padding: u32) -> Option<(u32, u32)> {
+        let shelf = self.shelves.iter_mut()
+            .find(|shelf| height <= shelf.height && shelf.used_width + width <= max_width);
+        let shelf = match shelf {
+            Some(shelf) => shelf,
+            None => {
+                let y = self.used_height;
+                if y + height > max_height {
+                    return None;
+                }
+                self.used_height = y + height + padding;
+                self.shelves.push(Shelf { y, height, used_width: 0 });
+                self.shelves.last_mut()?
+            }
+        };
+        let x = shelf.used_width;
+        shelf.used_width = x + width + padding;
+        Some((x, shelf.y))
+    }
+}
+
+// decoded input, trimmed with `--trim`
+struct Sprite {
+    path: PathBuf,
+    pixels: RgbaImage,
+    trim: Option<SpriteTrim>,
+}
+
+// cuts the fully transparent borders off `pixels`, fully transparent images keep a single pixel
+fn trim_transparent(pixels: RgbaImage) -> (RgbaImage, SpriteTrim) {
+    let (source_width, source_height) = pixels.dimensions();
+    let opaque = pixels.enumerate_pixels().filter(|(_, _, pixel)| pixel[3] > 0);
+    let (left, top, right, bottom) = opaque.fold((u32::MAX, u32::MAX, 0, 0), |(left, top, right, bottom), (x, y, _)| {
+        (left.min(x), top.min(y), right.max(x + 1), bottom.max(y + 1))
+    });
+    let (left, top, right, bottom) = if left == u32::MAX { (0, 0, 1, 1) } else { (left, top, right, bottom) };
+    let trimmed = image::imageops::crop_imm(&pixels, left, top, right - left, bottom - top).to_image();
+    (trimmed, SpriteTrim { offset_x: left, offset_y: top, source_width, source_height })
+}
+
+// copies `pixels` to `x`, `y` of `sheet`, repeating its edge pixels `extrude` times around it
+fn draw_extruded(sheet: &mut RgbaImage, pixels: &RgbaImage, x: u32, y: u32, extrude: u32) {
+    let (width, height) = pixels.dimensions();
+    for sheet_y in y - extrude..y + height + extrude {
+        for sheet_x in x - extrude..x + width + extrude {
+            let source_x = sheet_x.clamp(x, x + width - 1) - x;
+            let source_y = sheet_y.clamp(y, y + height - 1) - y;
+            sheet.put_pixel(sheet_x, sheet_y, *pixels.get_pixel(source_x, source_y));
+        }
+    }
+}
+
+/// Packs the images matched by the pattern into as few sheets of at most `max_size` as possible,
+/// writing the sheets in `img_format` and a json atlas of the sprite coordinates.
+///
+/// Sprites are packed onto shelves, tallest first. Images larger than a sheet or not decodable are reported and left out.
+/// The atlas records the sprites without their extruded edges, trimmed sprites with their bounds in the input.
+pub fn pack_sprites(conf: &SpriteConfig, img_format: &ImageFormat, options: &EncoderConfig) -> Result<Atlas, Error> {
+    let glyphs = output::glyphs();
+    let base = base_from_pattern(&conf.pattern);
+    let directory = PathBuf::from(if conf.output.is_empty() { &base } else { &conf.output });
+    // sheets of an earlier run matched by the pattern are not packed again
+    let is_sheet = |path: &Path| path.parent() == Some(directory.as_path())
+        && path.file_name().is_some_and(|name| name.to_string_lossy().starts_with(&format!("{}.", conf.name)));
+    let mut paths: Vec<PathBuf> = glob::glob(&conf.pattern)?
+        .filter_map(|entry| entry.ok())
+        .filter(|path| path.is_file() && !is_sheet(path))
+        .collect();
+    paths.sort();
+
+    let decoded: Vec<(PathBuf, Result<DynamicImage, Error>)> = paths.into_par_iter()
+        .map(|path| {
+            let image = read_image(&path);
+            (path, image)
+        })
+        .collect();
+    let mut sprites = Vec::new();
+    for (path, image) in decoded {
+        let pixels = match image {
+            Ok(image) => image.to_rgba8(),
+            Err(err) => {
+                println!("{} {}: could not be decoded, skipped: {}", glyphs.error, path.display(), err);
+                continue;
+            }
+        };
+        let (pixels, trim) = match conf.trim {
+            true => {
+                let (pixels, trim) = trim_transparent(pixels);
+                (pixels, Some(trim))
+            }
+            false => (pixels, None),
+        };
+        let (width, height) = (pixels.width() + 2 * conf.extrude, pixels.height() + 2 * conf.extrude);
+        if width > conf.max_size.0 || height > conf.max_size.1 {
+            println!("{} {}: larger than a sheet ({}x{}), skipped", glyphs.error, path.display(), width, height);
+            continue;
+        }
+        sprites.push(Sprite { path, pixels, trim });
+    }
+    if sprites.is_empty() {
+        return Err(Error::from_string(format!("no images to pack matched by {}", conf.pattern)));
+    }
+
+    // extruded edges are packed as part of the sprites
+    let packed_size = |sprite: &Sprite| (sprite.pixels.width() + 2 * conf.extrude, sprite.pixels.height() + 2 * conf.extrude);
+    let mut order: Vec<usize> = (0..sprites.len()).collect();
+    order.sort_by_key(|&i| {
+        let (width, height) = packed_size(&sprites[i]);
+        std::cmp::Reverse((height, width))
+    });
+    let mut sheets: Vec<Sheet> = Vec::new();
+    for i in order {
+        let size = packed_size(&sprites[i]);
+        let placed = sheets.iter_mut()
+            .find_map(|sheet| sheet.place(size, conf.max_size, conf.padding).map(|(x, y)| (sheet, x, y)));
+        match placed {
+            Some((sheet, x, y)) => sheet.placements.push((i, x, y)),
+            None => {
+                let mut sheet = Sheet::default();
+                // fits, the image is not larger than a sheet
+                let (x, y) = sheet.place(size, conf.max_size, conf.padding).unwrap_or_default();
+                sheet.placements.push((i, x, y));
+                sheets.push(sheet);
+            }
+        }
+    }
+
+    fs::create_dir_all(&directory)?;
+    let mut atlas = Atlas { imgc_version: env!("CARGO_PKG_VERSION").to_string(), sheets: Vec::new() };
+    for (index, sheet) in sheets.iter().enumerate() {
+        let (width, height) = sheet.placements.iter()
+            .map(|&(i, x, y)| {
+                let (width, height) = packed_size(&sprites[i]);
+                (x + width, y + height)
+            })
+            .fold((0, 0), |(w, h), (right, bottom)| (w.max(right), h.max(bottom)));
+        let mut pixels = RgbaImage::new(width, height);
+        let mut atlas_sprites = Vec::new();
+        for &(i, x, y) in &sheet.placements {
+            let sprite = &sprites[i];
+            // the atlas points at the sprite itself, inside its extruded edges
+            let (x, y) = (x + conf.extrude, y + conf.extrude);
+            draw_extruded(&mut pixels, &sprite.pixels, x, y, conf.extrude);
+            let name = sprite.path.strip_prefix(&base).unwrap_or(&sprite.path);
+            atlas_sprites.push(AtlasSprite {
+                name: name.to_string_lossy().replace('\\', "/"),
+                x, y, width: sprite.pixels.width(), height: sprite.pixels.height(),
+                trim: sprite.trim,
+            });
+        }
+        let file = format!("{}.{:04}.{}", conf.name, index + 1, options.output_extension(img_format));
+        fs::write(directory.join(&file), encode_image(&DynamicImage::ImageRgba8(pixels), img_format, options)?)?;
+        println!("{} {} ({}x{}, {} sprites)", glyphs.ok, directory.join(&file).display(), width, height, atlas_sprites.len());
+        atlas.sheets.push(AtlasSheet { file, width, height, sprites: atlas_sprites });
+    }
+    let atlas_path = directory.join(format!("{}.json", conf.name));
+    fs::write(&atlas_path, serde_json::to_string_pretty(&atlas)?)?;
+    println!("Packed {} images into {} sheets, atlas written to {}", sprites.len(), atlas.sheets.len(), atlas_path.display());
+    Ok(atlas)
+}
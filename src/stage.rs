@@ -0,0 +1,117 @@
+// Staging of outputs for slow destinations (`--stage-dir`): the workers write the outputs into a local directory,
+//  a fixed number of transfer threads moves them to their destinations while the workers encode the next files.
+
+use crate::{
+    utils::{move_file, set_owner, Owner},
+    xattr::{write_xattrs, Xattr},
+};
+use std::{
+    ffi::OsString,
+    fs, io,
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicU64, AtomicUsize, Ordering},
+        mpsc, Arc, Mutex,
+    },
+    thread::JoinHandle,
+    time::Duration,
+};
+
+/// Output written into the staging directory, waiting to be moved to its destination
+pub(crate) struct Transfer {
+    pub(crate) staged: PathBuf,
+    pub(crate) output: PathBuf,
+    // the conversion counted as converted, taken back if the transfer fails
+    pub(crate) input: PathBuf,
+    pub(crate) input_size: usize,
+    pub(crate) output_size: usize,
+    pub(crate) elapsed: Duration,
+    // applied at the destination, copies across filesystems do not keep them
+    pub(crate) xattrs: Vec<Xattr>,
+    pub(crate) owner: Option<Owner>,
+}
+
+/// Staging directory and the threads transferring its outputs
+pub(crate) struct Stage {
+    directory: PathBuf,
+    // prefix of the staged names, outputs of the same name in different directories stay apart
+    next: AtomicU64,
+    sender: Mutex<Option<mpsc::Sender<Transfer>>>,
+    transfers: Mutex<Vec<JoinHandle<()>>>,
+    transferred: Arc<AtomicUsize>,
+    failures: Arc<Mutex<Vec<(Transfer, io::Error)>>>,
+}
+
+impl Stage {
+    /// Creates the staging `directory` and starts `transfers` threads moving the staged outputs
+    pub(crate) fn start(directory: &Path, transfers: usize, durable: bool) -> io::Result<Stage> {
+        fs::create_dir_all(directory)?;
+        let (sender, receiver) = mpsc::channel::<Transfer>();
+        let receiver = Arc::new(Mutex::new(receiver));
+        let transferred = Arc::new(AtomicUsize::new(0));
+        let failures = Arc::new(Mutex::new(Vec::new()));
+        let transfers = (0..transfers.max(1)).map(|i| {
+            let (receiver, transferred, failures) = (receiver.clone(), transferred.clone(), failures.clone());
+            std::thread::Builder::new()
+                .name(format!("imgc-transfer-{}", i))
+                .spawn(move || loop {
+                    // the lock is released before the transfer, the others pick up the next outputs meanwhile
+                    let next = receiver.lock().unwrap_or_else(|e| e.into_inner()).recv();
+                    let Ok(transfer) = next else {
+                        break;
+                    };
+                    match finish_transfer(&transfer, durable) {
+                        Ok(()) => {
+                            transferred.fetch_add(1, Ordering::SeqCst);
+                        }
+                        Err(err) => failures.lock().unwrap_or_else(|e| e.into_inner()).push((transfer, err)),
+                    }
+                })
+        }).collect::<io::Result<Vec<_>>>()?;
+        Ok(Stage {
+            directory: directory.to_path_buf(),
+            next: AtomicU64::new(0),
+            sender: Mutex::new(Some(sender)),
+            transfers: Mutex::new(transfers),
+            transferred,
+            failures,
+        })
+    }
+
+    /// Path in the staging directory the output at `output` is written to
+    pub(crate) fn staging_path(&self, output: &Path) -> PathBuf {
+        let mut name = OsString::from(format!("{}-", self.next.fetch_add(1, Ordering::SeqCst)));
+        name.push(output.file_name().unwrap_or_default());
+        self.directory.join(name)
+    }
+
+    /// Queues the written `transfer`, it is moved once a transfer thread is free
+    pub(crate) fn transfer(&self, transfer: Transfer) {
+        if let Some(sender) = self.sender.lock().unwrap_or_else(|e| e.into_inner()).as_ref() {
+            let _ = sender.send(transfer);
+        }
+    }
+
+    /// Waits for the queued transfers, returning the number of moved outputs and the failed transfers
+    /// (their outputs stay in the staging directory)
+    pub(crate) fn finish(&self) -> (usize, Vec<(Transfer, io::Error)>) {
+        // the threads end once the queue is drained
+        self.sender.lock().unwrap_or_else(|e| e.into_inner()).take();
+        for transfer in std::mem::take(&mut *self.transfers.lock().unwrap_or_else(|e| e.into_inner())) {
+            let _ = transfer.join();
+        }
+        let failures = std::mem::take(&mut *self.failures.lock().unwrap_or_else(|e| e.into_inner()));
+        (self.transferred.load(Ordering::SeqCst), failures)
+    }
+}
+
+fn finish_transfer(transfer: &Transfer, durable: bool) -> io::Result<()> {
+    move_file(&transfer.staged, &transfer.output, durable)?;
+    if !transfer.xattrs.is_empty() {
+        write_xattrs(&transfer.output, &transfer.xattrs)?;
+    }
+    if let Some(owner) = transfer.owner {
+        set_owner(&transfer.output, owner)?;
+    }
+    Ok(())
+}
@@ -0,0 +1,97 @@
+use crate::Error;
+use std::{borrow::Cow, fs, path::{Path, PathBuf}};
+
+/// Change of a conversion run, reverted by its undo script
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum UndoStep {
+    /// A new output was written, removing it reverts the conversion.
+    Created {
+        /// Path of the output.
+        output: PathBuf,
+    },
+    /// An existing output was overwritten, its previous content is lost.
+    Overwritten {
+        /// Path of the output.
+        output: PathBuf,
+    },
+    /// The input was replaced by its output, the original is kept as `backup` (`--backup-ext`).
+    Replaced {
+        /// Path of the input and output.
+        path: PathBuf,
+        /// Path of the original input.
+        backup: PathBuf,
+    },
+    /// The input was replaced by its output without backup, it cannot be restored.
+    ReplacedWithoutBackup {
+        /// Path of the input and output.
+        path: PathBuf,
+    },
+    /// The input was moved, e.g. into the quarantine directory.
+    Moved {
+        /// Original path of the input.
+        from: PathBuf,
+        /// Current path of the input.
+        to: PathBuf,
+    },
+}
+
+// raw bytes of `path`, names that are no valid unicode are kept unchanged on unix
+#[cfg(unix)]
+fn path_bytes(path: &Path) -> Cow<'_, [u8]> {
+    Cow::Borrowed(std::os::unix::ffi::OsStrExt::as_bytes(path.as_os_str()))
+}
+
+#[cfg(not(unix))]
+fn path_bytes(path: &Path) -> Cow<'_, [u8]> {
+    Cow::Owned(path.to_string_lossy().into_owned().into_bytes())
+}
+
+// single quoted for sh, quotes inside end the quoting for an escaped quote
+fn quote(path: &Path) -> Vec<u8> {
+    let mut quoted = vec![b'\''];
+    for &byte in path_bytes(path).iter() {
+        match byte {
+            b'\'' => quoted.extend_from_slice(br"'\''"),
+            byte => quoted.push(byte),
+        }
+    }
+    quoted.push(b'\'');
+    quoted
+}
+
+// appends a command of space separated words
+fn push_command(script: &mut Vec<u8>, words: &[&[u8]]) {
+    script.extend_from_slice(&words.join(&b' '));
+    script.push(b'\n');
+}
+
+/// Writes a shell script reverting `steps` to `path`: new outputs are removed, replaced and moved inputs restored.
+///
+/// Steps that cannot be reverted (overwritten outputs, inputs replaced without backup) are reported on stderr
+/// when the script runs. They are echoed rather than listed as comments, a newline in a name would end the comment.
+pub fn write_undo_script(path: &Path, steps: &[UndoStep]) -> Result<(), Error> {
+    let mut script = format!(
+        "#!/bin/sh\n# reverts the conversion run of imgc {}, run it from the directory the run was started in\nset -e\n",
+        env!("CARGO_PKG_VERSION")).into_bytes();
+    let mut steps = steps.to_vec();
+    steps.sort();
+    for step in &steps {
+        match step {
+            UndoStep::Created { output } => push_command(&mut script, &[b"rm -f --", &quote(output)]),
+            UndoStep::Overwritten { output } =>
+                push_command(&mut script, &[b"echo 'cannot restore (an existing output was overwritten):'", &quote(output), b">&2"]),
+            UndoStep::Replaced { path, backup } => push_command(&mut script, &[b"mv -f --", &quote(backup), &quote(path)]),
+            UndoStep::ReplacedWithoutBackup { path } =>
+                push_command(&mut script, &[b"echo 'cannot restore (replaced without --backup-ext):'", &quote(path), b">&2"]),
+            UndoStep::Moved { from, to } => {
+                let directory = from.parent().filter(|dir| !dir.as_os_str().is_empty()).unwrap_or(Path::new("."));
+                push_command(&mut script, &[b"mkdir -p --", &quote(directory)]);
+                push_command(&mut script, &[b"mv -f --", &quote(to), &quote(from)]);
+            }
+        }
+    }
+    fs::write(path, script)?;
+    #[cfg(unix)]
+    fs::set_permissions(path, std::os::unix::fs::PermissionsExt::from_mode(0o755))?;
+    Ok(())
+}
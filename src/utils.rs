@@ -1,7 +1,14 @@
 use glob::glob;
-use std::{fs, path::Path};
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    ffi::{OsStr, OsString},
+    fs,
+    io::{self, Write},
+    path::{Path, PathBuf},
+    time::{Duration, SystemTime},
+};
 use humansize::{format_size, FormatSizeOptions, BINARY};
-use crate::{format::ImageFormat, Error};
+use crate::{format::ImageFormat, profile, Error};
 
 /// Checks if the image format of the given path is supported, ignoring a specific format.
 ///
@@ -14,10 +21,9 @@ use crate::{format::ImageFormat, Error};
 ///
 /// Returns `true` if the image format is supported and not ignored, `false` otherwise.
 pub fn is_supported(path: &Path, ignore_format: &ImageFormat) -> bool {
-    if let Some(extension) = path.extension() {
-        if extension == ignore_format.extension() {
-            return false;
-        }
+    if let Some(extension) = path.extension()
+        && extension == ignore_format.extension() {
+        return false;
     }
 
     match fs::read(path) {
@@ -26,28 +32,232 @@ pub fn is_supported(path: &Path, ignore_format: &ImageFormat) -> bool {
     }
 }
 
-/// Removes files that match the given pattern.
+/// Owner of a file: user and group id
+pub type Owner = (u32, u32);
+
+/// Owner of the file at `path`, None on platforms without unix ownership
+pub fn file_owner(path: &Path) -> io::Result<Option<Owner>> {
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::MetadataExt;
+        let metadata = fs::metadata(path)?;
+        Ok(Some((metadata.uid(), metadata.gid())))
+    }
+    #[cfg(not(unix))]
+    {
+        let _ = path;
+        Ok(None)
+    }
+}
+
+/// Gives the file at `path` to `owner` (requires root for files of other users)
+pub fn set_owner(path: &Path, owner: Owner) -> io::Result<()> {
+    #[cfg(unix)]
+    std::os::unix::fs::chown(path, Some(owner.0), Some(owner.1))?;
+    #[cfg(not(unix))]
+    let _ = (path, owner);
+    Ok(())
+}
+
+/// Creates `directory` and its missing parents like [`fs::create_dir_all`], giving the created directories to `owner`
+pub fn create_dir_all_owned(directory: &Path, owner: Option<Owner>) -> io::Result<()> {
+    let Some(owner) = owner else {
+        return fs::create_dir_all(directory);
+    };
+    let missing: Vec<&Path> = directory.ancestors()
+        .take_while(|ancestor| !ancestor.as_os_str().is_empty() && !ancestor.exists())
+        .collect();
+    fs::create_dir_all(directory)?;
+    for created in missing {
+        set_owner(created, owner)?;
+    }
+    Ok(())
+}
+
+/// Flushes the entries of a directory (created, renamed and linked files) to disk.
+///
+/// Directories cannot be opened for syncing on Windows, where this does nothing.
+pub fn sync_directory(directory: &Path) -> io::Result<()> {
+    #[cfg(unix)]
+    fs::File::open(if directory.as_os_str().is_empty() { Path::new(".") } else { directory })?.sync_all()?;
+    #[cfg(not(unix))]
+    let _ = directory;
+    Ok(())
+}
+
+/// Writes `data` to the file at `path` like [`fs::write`].
+///
+/// With `durable`, the file and its directory entry are synced to disk before returning,
+/// so that a power loss cannot leave a truncated file behind that was reported as written.
+pub fn write_file(path: &Path, data: &[u8], durable: bool) -> io::Result<()> {
+    let _span = profile::span("write");
+    if !durable {
+        return fs::write(path, data);
+    }
+    let mut file = fs::File::create(path)?;
+    file.write_all(data)?;
+    file.sync_all()?;
+    sync_directory(path.parent().unwrap_or_else(|| Path::new("")))
+}
+
+/// Path of the backup of `path` kept by [`replace_file`]: `backup_ext` appended to the file name
+pub fn backup_path(path: &Path, backup_ext: &str) -> PathBuf {
+    let mut backup_name = OsString::from(path.file_name().unwrap_or_default());
+    backup_name.push(backup_ext);
+    path.with_file_name(backup_name)
+}
+
+/// Atomically replaces the file at `path` with `data`, optionally keeping the original as `path` + `backup_ext`.
+///
+/// The data is written to a temporary file next to `path` which is renamed over it,
+/// so `path` always refers to either the complete original or the complete new file.
+/// An existing backup is never overwritten (it may be the only copy of the true original).
+/// With `durable`, the new file is synced to disk before and the directory after the rename (see [`write_file`]).
+pub fn replace_file(path: &Path, data: &[u8], backup_ext: Option<&str>, durable: bool) -> io::Result<()> {
+    let file_name = path.file_name().ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "not a file path"))?;
+    if let Some(backup_ext) = backup_ext {
+        let backup_path = backup_path(path, backup_ext);
+        // a hard link keeps the original reachable under its path, copy if the filesystem does not support links
+        let backed_up = match fs::hard_link(path, &backup_path) {
+            Err(err) if err.kind() != io::ErrorKind::AlreadyExists => copy_to_new(path, &backup_path),
+            linked => linked,
+        };
+        // the copy does not overwrite a backup created in the meantime either
+        backed_up.map_err(|err| match err.kind() {
+            io::ErrorKind::AlreadyExists => io::Error::new(err.kind(), format!("backup {} already exists", backup_path.display())),
+            _ => err,
+        })?;
+    }
+
+    let tmp_path = temporary_path(path, file_name);
+    write_file(&tmp_path, data, durable)?;
+    fs::rename(&tmp_path, path).inspect_err(|_| {
+        let _ = fs::remove_file(&tmp_path);
+    })?;
+    if durable {
+        sync_directory(path.parent().unwrap_or_else(|| Path::new("")))?;
+    }
+    Ok(())
+}
+
+// copies `from` to the new file `to` with the same permissions, failing if `to` exists and removing it again on errors
+fn copy_to_new(from: &Path, to: &Path) -> io::Result<()> {
+    let mut source = fs::File::open(from)?;
+    let mut target = fs::OpenOptions::new().write(true).create_new(true).open(to)?;
+    io::copy(&mut source, &mut target)
+        .and_then(|_| target.set_permissions(source.metadata()?.permissions()))
+        .inspect_err(|_| {
+            let _ = fs::remove_file(to);
+        })?;
+    Ok(())
+}
+
+/// Moves the file at `from` to `to`, copying it if they are on different filesystems.
+///
+/// Copies go to a temporary file next to `to` which is renamed over it (see [`replace_file`]),
+/// so `to` never refers to a partial file. With `durable`, the file and the directory of `to` are synced to disk.
+pub fn move_file(from: &Path, to: &Path, durable: bool) -> io::Result<()> {
+    let file_name = to.file_name().ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "not a file path"))?;
+    if durable {
+        fs::File::open(from)?.sync_all()?;
+    }
+    if fs::rename(from, to).is_err() {
+        let tmp_path = temporary_path(to, file_name);
+        fs::copy(from, &tmp_path).and_then(|_| if durable { fs::File::open(&tmp_path)?.sync_all() } else { Ok(()) })
+            .and_then(|_| fs::rename(&tmp_path, to))
+            .inspect_err(|_| {
+                let _ = fs::remove_file(&tmp_path);
+            })?;
+        fs::remove_file(from)?;
+    }
+    if durable {
+        sync_directory(to.parent().unwrap_or_else(|| Path::new("")))?;
+    }
+    Ok(())
+}
+
+// hidden file next to `path` that is renamed over it once complete
+fn temporary_path(path: &Path, file_name: &OsStr) -> PathBuf {
+    let mut tmp_name = OsString::from(".");
+    tmp_name.push(file_name);
+    tmp_name.push(".imgc-tmp");
+    path.with_file_name(tmp_name)
+}
+
+/// Which of the matched files `imgc clean` removes
+#[derive(Clone, Debug, Default)]
+pub struct CleanFilter {
+    /// Only remove files larger than this (B).
+    pub larger_than: Option<u64>,
+    /// Only remove files last modified longer ago than this.
+    pub older_than: Option<Duration>,
+    /// Only remove files with one of these extensions (case-insensitive, without dot), all files if empty.
+    pub extensions: Vec<String>,
+}
+
+impl CleanFilter {
+    fn matches(&self, path: &Path, metadata: &fs::Metadata) -> bool {
+        let extension = path.extension().map(|extension| extension.to_string_lossy().to_lowercase()).unwrap_or_default();
+        let age = metadata.modified().ok().and_then(|modified| SystemTime::now().duration_since(modified).ok());
+        (self.extensions.is_empty() || self.extensions.iter().any(|wanted| wanted.trim_start_matches('.').eq_ignore_ascii_case(&extension)))
+            && self.larger_than.is_none_or(|size| metadata.len() > size)
+            && self.older_than.is_none_or(|older_than| age.is_some_and(|age| age > older_than))
+    }
+}
+
+/// Removes the files matching any of the given patterns and the filter.
 ///
 /// # Arguments
 ///
-/// * `pattern` - The glob pattern to match files.
+/// * `patterns` - The glob patterns to match files.
+/// * `filter` - Size, age and extension the matched files need to be removed.
 ///
 /// # Returns
 ///
 /// Returns `Ok(())` if the files are successfully removed, or an `Error` if an error occurs.
-pub fn remove_files(pattern: &str) -> Result<(), Error> {
+pub fn remove_files(patterns: &[String], filter: &CleanFilter) -> Result<(), Error> {
     let mut total_deleted_bytes: usize = 0;
-    for entry in glob(pattern)? {
-        let path = entry?;
-        if path.is_file() {
-            total_deleted_bytes += fs::metadata(&path)?.len() as usize;
+    // files matched by several patterns are removed once, counts and sizes are grouped by directory
+    let mut seen = BTreeSet::new();
+    let mut by_directory: BTreeMap<PathBuf, (usize, usize)> = BTreeMap::new();
+    for pattern in patterns {
+        for entry in glob(pattern)? {
+            let path = entry?;
+            let Ok(metadata) = fs::metadata(&path) else {
+                continue;
+            };
+            if !metadata.is_file() || !filter.matches(&path, &metadata) || !seen.insert(path.clone()) {
+                continue;
+            }
             fs::remove_file(&path)?;
+            total_deleted_bytes += metadata.len() as usize;
+            let directory = by_directory.entry(path.parent().map(Path::to_path_buf).unwrap_or_default()).or_default();
+            directory.0 += 1;
+            directory.1 += metadata.len() as usize;
             println!("Deleted: {}", path.display());
         }
     }
     let format_option_binary_two_nospace = FormatSizeOptions::from(BINARY)
         .decimal_places(2).decimal_zeroes(2).space_after_value(false);
+    for (directory, (count, bytes)) in &by_directory {
+        let directory = if directory.as_os_str().is_empty() { Path::new(".") } else { directory };
+        println!("  {}: {} files, {}", directory.display(), count, format_size(*bytes, format_option_binary_two_nospace));
+    }
     println!("Deleted {}.", format_size(total_deleted_bytes, format_option_binary_two_nospace));
 
     Ok(())
 }
+
+/// Linear light value (0 - 1) of an 8 bit sRGB sample
+pub fn srgb_to_linear(value: u8) -> f32 {
+    let v = value as f32 / 255.;
+    if v <= 0.04045 { v / 12.92 } else { ((v + 0.055) / 1.055).powf(2.4) }
+}
+
+/// 8 bit sRGB sample of a linear light value, clamped to 0 - 1
+pub fn linear_to_srgb(value: f32) -> u8 {
+    // NaN ends up as 0
+    let value = value.clamp(0., 1.);
+    let encoded = if value <= 0.003_130_8 { value * 12.92 } else { 1.055 * value.powf(1. / 2.4) - 0.055 };
+    (encoded * 255. + 0.5) as u8
+}
@@ -0,0 +1,108 @@
+use crate::{
+    decode::read_image_from_memory,
+    metadata::{read_exif, ExifData},
+    Error,
+};
+use image::{metadata::Orientation, DynamicImage, GenericImageView, ImageDecoder, ImageReader, RgbaImage};
+use moxcms::{ColorProfile, Layout, TransformOptions};
+use std::io::Cursor;
+
+// side of the windows the SSIM is computed in
+const WINDOW: u32 = 8;
+// stabilizing constants of the SSIM formula, for 8 bit values
+const C1: f64 = (0.01 * 255.) * (0.01 * 255.);
+const C2: f64 = (0.03 * 255.) * (0.03 * 255.);
+
+/// The image as a viewer displays it: its EXIF orientation applied and its ICC profile converted to sRGB,
+/// composited onto white
+fn displayed(image: &DynamicImage, data: &[u8]) -> RgbaImage {
+    let mut image = image.clone();
+    let orientation = read_exif(data).and_then(|exif| ExifData::parse(&exif)).and_then(|exif| exif.orientation());
+    if let Some(orientation) = orientation.and_then(|orientation| Orientation::from_exif(orientation as u8)) {
+        image.apply_orientation(orientation);
+    }
+    let mut pixels = image.to_rgba8();
+    if let Some(profile) = icc_profile(data) {
+        let transform = ColorProfile::new_from_slice(&profile).ok().and_then(|profile| profile
+            .create_transform_8bit(Layout::Rgba, &ColorProfile::new_srgb(), Layout::Rgba, TransformOptions::default()).ok());
+        if let Some(transform) = transform {
+            let mut converted = vec![0; pixels.len()];
+            if transform.transform(&pixels, &mut converted).is_ok() {
+                pixels.copy_from_slice(&converted);
+            }
+        }
+    }
+    for pixel in pixels.pixels_mut() {
+        let alpha = pixel[3] as u32;
+        for channel in &mut pixel.0[..3] {
+            *channel = ((*channel as u32 * alpha + 255 * (255 - alpha)) / 255) as u8;
+        }
+        pixel[3] = u8::MAX;
+    }
+    pixels
+}
+
+// embedded ICC profile of an encoded image, None for sRGB (and formats the `image` crate cannot read)
+fn icc_profile(data: &[u8]) -> Option<Vec<u8>> {
+    ImageReader::new(Cursor::new(data)).with_guessed_format().ok()?.into_decoder().ok()?.icc_profile().ok()?
+}
+
+/// Mean structural similarity of the luma of two images of the same size (1 for identical images),
+/// computed in non-overlapping 8x8 windows
+pub fn ssim(a: &RgbaImage, b: &RgbaImage) -> f64 {
+    let luma = |image: &RgbaImage, x: u32, y: u32| {
+        let [r, g, b, _] = image.get_pixel(x, y).0;
+        0.299 * r as f64 + 0.587 * g as f64 + 0.114 * b as f64
+    };
+    let (width, height) = a.dimensions();
+    let (mut total, mut windows) = (0., 0usize);
+    for wy in (0..height).step_by(WINDOW as usize) {
+        for wx in (0..width).step_by(WINDOW as usize) {
+            let (mut sum_a, mut sum_b, mut sum_aa, mut sum_bb, mut sum_ab) = (0., 0., 0., 0., 0.);
+            let mut n = 0.;
+            for y in wy..(wy + WINDOW).min(height) {
+                for x in wx..(wx + WINDOW).min(width) {
+                    let (la, lb) = (luma(a, x, y), luma(b, x, y));
+                    sum_a += la;
+                    sum_b += lb;
+                    sum_aa += la * la;
+                    sum_bb += lb * lb;
+                    sum_ab += la * lb;
+                    n += 1.;
+                }
+            }
+            let (mean_a, mean_b) = (sum_a / n, sum_b / n);
+            let variance_a = sum_aa / n - mean_a * mean_a;
+            let variance_b = sum_bb / n - mean_b * mean_b;
+            let covariance = sum_ab / n - mean_a * mean_b;
+            total += ((2. * mean_a * mean_b + C1) * (2. * covariance + C2))
+                / ((mean_a * mean_a + mean_b * mean_b + C1) * (variance_a + variance_b + C2));
+            windows += 1;
+        }
+    }
+    if windows == 0 { 1. } else { total / windows as f64 }
+}
+
+/// Decodes `output_data` and compares it with the `input` it was encoded from (cropped like the output, `input_data`
+/// holding its metadata) as both are displayed: dimensions, orientation and an SSIM of at least `min_ssim`.
+///
+/// Returns the problem of an output that does not look like its input.
+pub fn check_visual(input: &DynamicImage, input_data: &[u8], output_data: &[u8], min_ssim: f64) -> Result<Option<String>, Error> {
+    let output = read_image_from_memory(output_data)
+        .map_err(|err| Error::from_string(format!("the output cannot be decoded for --validate-visual: {}", err)))?;
+    let (expected, actual) = (displayed(input, input_data), displayed(&output, output_data));
+    if expected.dimensions() != actual.dimensions() {
+        let (width, height) = expected.dimensions();
+        let rotated = actual.dimensions() == (height, width);
+        return Ok(Some(format!("the output is displayed at {}x{} instead of {}x{}{}", actual.width(), actual.height(),
+                               width, height, if rotated { " (rotated, EXIF orientation lost)" } else { "" })));
+    }
+    let similarity = ssim(&expected, &actual);
+    if similarity < min_ssim {
+        // the pixels match without orientation, e.g. a lost 180° rotation
+        let upright = output.dimensions() == input.dimensions() && ssim(&input.to_rgba8(), &output.to_rgba8()) >= min_ssim;
+        return Ok(Some(format!("the output has an SSIM of {:.4} to the input, below {}{}", similarity, min_ssim,
+                               if upright { " (rotated, EXIF orientation lost)" } else { "" })));
+    }
+    Ok(None)
+}
@@ -0,0 +1,44 @@
+use crate::{animation::Frame, decode::read_image_from_memory, Error};
+use image::DynamicImage;
+use std::{
+    env,
+    ffi::OsString,
+    path::Path,
+    process::{Command, Stdio},
+};
+
+// overrides the ffmpeg binary (looked up on the PATH by default)
+const FFMPEG_ENV: &str = "IMGC_FFMPEG";
+
+fn ffmpeg_binary() -> OsString {
+    env::var_os(FFMPEG_ENV).unwrap_or_else(|| OsString::from("ffmpeg"))
+}
+
+/// Grabs the frame at `time` (ffmpeg time syntax, e.g. `00:00:03` or `3.5` seconds) of the video at `path`.
+///
+/// The frame is piped losslessly (png) out of the ffmpeg binary, set `IMGC_FFMPEG` to use another one than on the PATH.
+pub fn read_poster(path: &Path, time: &str) -> Result<DynamicImage, Error> {
+    let output = Command::new(ffmpeg_binary())
+        // seeking before the input is fast (keyframe based) and still frame accurate
+        .args(["-hide_banner", "-loglevel", "error", "-ss", time, "-i"])
+        .arg(path)
+        .args(["-frames:v", "1", "-f", "image2pipe", "-c:v", "png", "-"])
+        .stdin(Stdio::null())
+        .output()
+        .map_err(|e| Error::from_string(format!("running ffmpeg failed: {}", e)))?;
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        return Err(Error::from_string(format!("ffmpeg failed ({}): {}", output.status, stderr.trim())));
+    }
+    if output.stdout.is_empty() {
+        return Err(Error::from_string(format!("no frame at {} (after the end of the video?)", time)));
+    }
+    read_image_from_memory(&output.stdout)
+}
+
+/// Grabs the frames at `times` of the video at `path`, see [`read_poster`]
+pub fn read_posters(path: &Path, times: &[String]) -> Result<Vec<Frame>, Error> {
+    times.iter()
+        .map(|time| Ok(Frame { image: read_poster(path, time)?, delay_ms: 0 }))
+        .collect()
+}
@@ -0,0 +1,145 @@
+use crate::{
+    converter::{apply_plan, base_from_pattern, normalize_prefix, stop_signal, CommonConfig, EncoderConfig},
+    format::ImageFormat,
+    i18n::tr,
+    plan::{plan_conversion, plan_inputs, Plan, PlannedFile},
+    Error,
+};
+use notify::{
+    event::{AccessKind, AccessMode},
+    EventKind, RecursiveMode, Watcher,
+};
+use std::{
+    collections::{BTreeSet, VecDeque},
+    fs,
+    path::{Path, PathBuf},
+    sync::{atomic::Ordering, mpsc::{self, RecvTimeoutError}},
+    time::{Duration, Instant},
+};
+
+/// Settings of watch mode (`--watch`)
+#[derive(Clone, Debug)]
+pub struct WatchConfig {
+    /// Quiet time after the last file event before the collected files are converted as one batch.
+    pub debounce: Duration,
+    /// Also convert the files matching the pattern when the watch starts (like a run without watch mode),
+    /// in chunks between which new files are converted first.
+    pub backlog: bool,
+}
+
+// how often waiting for events checks for Ctrl+C
+const STOP_POLL_INTERVAL: Duration = Duration::from_millis(200);
+
+// backlog files per worker thread converted before checking for new files again
+const BACKLOG_CHUNK_PER_THREAD: usize = 4;
+
+// next files of the backlog, its skipped and unconvertible inputs are reported with the first chunk
+fn next_backlog_chunk(backlog: &mut VecDeque<PlannedFile>, backlog_plan: &mut Plan) -> Plan {
+    let count = backlog.len().min(rayon::current_num_threads() * BACKLOG_CHUNK_PER_THREAD);
+    Plan {
+        files: backlog.drain(..count).collect(),
+        skipped: std::mem::take(&mut backlog_plan.skipped),
+        up_to_date: std::mem::take(&mut backlog_plan.up_to_date),
+        collisions: std::mem::take(&mut backlog_plan.collisions),
+        unconvertible: std::mem::take(&mut backlog_plan.unconvertible),
+        ..backlog_plan.clone()
+    }
+}
+
+/// Converts the inputs matched by the pattern as they are created or changed, until Ctrl+C.
+///
+/// Events are collected until none arrived for the debounce time (e.g. while a camera import copies hundreds of files),
+/// then the collected inputs are converted as one batch with full parallelism.
+/// Changed inputs replace their existing outputs, files in the output and mirror directories are never inputs.
+///
+/// With `backlog`, the files existing at the start are converted as well (existing outputs are handled like in a regular run),
+/// but new files are converted first once their debounce time is over, so they are not stuck behind a long backlog.
+pub fn watch_images(conf: CommonConfig, img_format: &ImageFormat, options: &EncoderConfig, watch: &WatchConfig) -> Result<(), Error> {
+    let pattern = glob::Pattern::new(&conf.pattern)?;
+    let base = base_from_pattern(&conf.pattern);
+    // events carry absolute paths on some platforms, they are mapped back onto the base of the pattern
+    let root = fs::canonicalize(if base.is_empty() { "." } else { &base })?;
+    let (sender, events) = mpsc::channel();
+    let mut watcher = notify::recommended_watcher(sender)?;
+    watcher.watch(&root, RecursiveMode::Recursive)?;
+    let output_dirs: Vec<PathBuf> = std::iter::once(&conf.output).chain(&conf.mirror_outputs)
+        .filter(|output| !output.is_empty())
+        .map(normalize_prefix)
+        .collect();
+    // planned before the watch starts, files created meanwhile are no backlog
+    let (mut backlog, mut backlog_plan) = match watch.backlog {
+        true => {
+            let mut plan = plan_conversion(&conf, img_format, options)?;
+            plan.set_aside_up_to_date(&conf);
+            let files = std::mem::take(&mut plan.files);
+            let has_files = !files.is_empty();
+            (VecDeque::from(files), has_files.then_some(plan))
+        }
+        false => (VecDeque::new(), None),
+    };
+    let backlog_conf = conf.clone();
+    let conf = CommonConfig { overwrite_existing: true, ..conf };
+    let stop = stop_signal();
+    println!("{}", tr!("watch-started", path = root.display().to_string(), pattern = conf.pattern.as_str()));
+    if !backlog.is_empty() {
+        println!("{}", tr!("watch-backlog", count = backlog.len()));
+    }
+
+    let mut batch = BTreeSet::new();
+    // outputs of the last batch, whose events are no new inputs
+    let mut written = BTreeSet::new();
+    let mut last_event: Option<Instant> = None;
+    while !stop.load(Ordering::Relaxed) {
+        // pending backlog files are converted while no new files are due
+        let timeout = match last_event {
+            _ if !backlog.is_empty() => Duration::ZERO,
+            Some(last) => watch.debounce.saturating_sub(last.elapsed()).min(STOP_POLL_INTERVAL),
+            None => STOP_POLL_INTERVAL,
+        };
+        match events.recv_timeout(timeout) {
+            Ok(Ok(event)) => {
+                if !matches!(event.kind, EventKind::Create(_) | EventKind::Modify(_) | EventKind::Access(AccessKind::Close(AccessMode::Write))) {
+                    continue;
+                }
+                for path in event.paths {
+                    let Ok(relative) = path.strip_prefix(&root) else {
+                        continue;
+                    };
+                    let input = Path::new(&base).join(relative);
+                    let normalized = normalize_prefix(&input);
+                    if pattern.matches_path(&input) && !written.contains(&normalized)
+                        && !output_dirs.iter().any(|output| normalized.starts_with(output)) {
+                        batch.insert(input);
+                        last_event = Some(Instant::now());
+                    }
+                }
+            }
+            Ok(Err(err)) => eprintln!("{}", tr!("watch-failed", path = root.display().to_string(), error = err.to_string())),
+            Err(RecvTimeoutError::Timeout) => {
+                let (plan, run_conf) = if last_event.is_some_and(|last| last.elapsed() >= watch.debounce) {
+                    last_event = None;
+                    // new files of the backlog are converted now
+                    backlog.retain(|file| !batch.contains(&file.input));
+                    (plan_inputs(&conf, img_format, options, std::mem::take(&mut batch).into_iter().collect())?, &conf)
+                } else if let Some(backlog_plan) = backlog_plan.as_mut().filter(|_| !backlog.is_empty()) {
+                    (next_backlog_chunk(&mut backlog, backlog_plan), &backlog_conf)
+                } else {
+                    continue;
+                };
+                written = plan.files.iter()
+                    .flat_map(|file| std::iter::once(&file.output).chain(&file.mirrors))
+                    .map(normalize_prefix)
+                    .collect();
+                if !plan.files.is_empty() || !plan.up_to_date.is_empty() || !plan.unconvertible.is_empty() {
+                    apply_plan(run_conf.clone(), &plan)?;
+                }
+                if backlog_plan.is_some() && backlog.is_empty() {
+                    backlog_plan = None;
+                    println!("{}", tr!("watch-backlog-done"));
+                }
+            }
+            Err(RecvTimeoutError::Disconnected) => break,
+        }
+    }
+    Ok(())
+}
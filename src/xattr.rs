@@ -0,0 +1,90 @@
+use std::{ffi::OsString, io, path::Path};
+
+/// Extended attribute of a file: name and value
+pub type Xattr = (OsString, Vec<u8>);
+
+// the quarantine flag of downloads and resource forks describe the original file, not the user's labels
+#[cfg(target_os = "macos")]
+fn is_copied(name: &[u8]) -> bool {
+    name != b"com.apple.quarantine" && name != b"com.apple.ResourceFork"
+}
+
+// security.* and trusted.* attributes need privileges and describe the file rather than its content
+#[cfg(target_os = "linux")]
+fn is_copied(name: &[u8]) -> bool {
+    name.starts_with(b"user.")
+}
+
+// filesystems without extended attributes (ENOTSUP, the same as EOPNOTSUPP on Linux)
+#[cfg(any(target_os = "linux", target_os = "macos"))]
+fn unsupported(err: rustix::io::Errno) -> bool {
+    err == rustix::io::Errno::NOTSUP || err == rustix::io::Errno::OPNOTSUPP
+}
+
+/// Reads the extended attributes of `path` that are copied to the outputs
+/// (macOS Finder tags and color labels, `user.*` attributes on Linux).
+///
+/// Files on filesystems without extended attributes and on other platforms have none.
+#[cfg(any(target_os = "linux", target_os = "macos"))]
+pub fn read_xattrs(path: &Path) -> io::Result<Vec<Xattr>> {
+    use rustix::{fs::{getxattr, listxattr}, io::Errno};
+    use std::{ffi::OsStr, os::unix::ffi::OsStrExt};
+
+    let names = loop {
+        let size = match listxattr(path, &mut [0u8; 0][..]) {
+            Ok(size) => size,
+            Err(err) if unsupported(err) => return Ok(Vec::new()),
+            Err(err) => return Err(err.into()),
+        };
+        let mut names = vec![0; size];
+        match listxattr(path, &mut names[..]) {
+            Ok(size) => {
+                names.truncate(size);
+                break names;
+            }
+            // attributes were added in between
+            Err(Errno::RANGE) => continue,
+            Err(err) => return Err(err.into()),
+        }
+    };
+    let mut xattrs = Vec::new();
+    for name in names.split(|&byte| byte == 0).filter(|name| !name.is_empty() && is_copied(name)) {
+        let name = OsStr::from_bytes(name);
+        let mut value = vec![0; getxattr(path, name, &mut [0u8; 0][..])?];
+        let size = getxattr(path, name, &mut value[..])?;
+        value.truncate(size);
+        xattrs.push((name.to_os_string(), value));
+    }
+    Ok(xattrs)
+}
+
+/// Reads the extended attributes of `path` that are copied to the outputs
+/// (macOS Finder tags and color labels, `user.*` attributes on Linux).
+///
+/// Files on filesystems without extended attributes and on other platforms have none.
+#[cfg(not(any(target_os = "linux", target_os = "macos")))]
+pub fn read_xattrs(_path: &Path) -> io::Result<Vec<Xattr>> {
+    Ok(Vec::new())
+}
+
+/// Sets the extended attributes read by [`read_xattrs`] on `path`, skipped on filesystems without extended attributes
+#[cfg(any(target_os = "linux", target_os = "macos"))]
+pub fn write_xattrs(path: &Path, xattrs: &[Xattr]) -> io::Result<()> {
+    use rustix::fs::{setxattr, XattrFlags};
+
+    for (name, value) in xattrs {
+        match setxattr(path, name.as_os_str(), value, XattrFlags::empty()) {
+            Ok(()) => {}
+            Err(err) if unsupported(err) => return Ok(()),
+            Err(err) => return Err(io::Error::other(
+                format!("setting the extended attribute {} failed: {}", name.to_string_lossy(), io::Error::from(err)))),
+        }
+    }
+    Ok(())
+}
+
+/// Sets the extended attributes read by [`read_xattrs`] on `path`, skipped on filesystems without extended attributes
+#[cfg(not(any(target_os = "linux", target_os = "macos")))]
+pub fn write_xattrs(_path: &Path, _xattrs: &[Xattr]) -> io::Result<()> {
+    Ok(())
+}